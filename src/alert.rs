@@ -0,0 +1,348 @@
+//! Regression alerts for repeated speed tests.
+//!
+//! `dnstest monitor` and the periodic tests of `dnstest serve` can watch
+//! for servers getting worse. The first rounds of a session form each
+//! server's baseline; afterwards the latest rounds are compared against it,
+//! and when average latency or loss grows by more than the configured
+//! delta an alert is raised. A second event follows once the server is
+//! back within the limits.
+//!
+//! ```toml
+//! [alerts]
+//! latency_ms = 30       # alert when 30 ms slower than the baseline
+//! loss = 0.2            # or when 20 points more rounds are lost
+//! baseline_rounds = 5
+//! window = 5
+//! webhook = "https://hooks.example/dnstest"
+//! ```
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::dns::{DnsServer, SpeedTestResult};
+use crate::http;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Timeout for one webhook call.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// When to raise alerts (`[alerts]` table).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AlertRules {
+    /// Alert when the recent average latency exceeds the baseline by more
+    /// than this many milliseconds
+    pub latency_ms: Option<f64>,
+    /// Alert when the recent loss ratio exceeds the baseline by more than
+    /// this (0.0 - 1.0)
+    pub loss: Option<f64>,
+    /// Rounds forming each server's baseline
+    pub baseline_rounds: usize,
+    /// Recent rounds compared against the baseline
+    pub window: usize,
+    /// URL each alert event is sent to as a JSON POST body
+    pub webhook: Option<String>,
+}
+
+impl Default for AlertRules {
+    fn default() -> Self {
+        Self {
+            latency_ms: None,
+            loss: None,
+            baseline_rounds: 5,
+            window: 5,
+            webhook: None,
+        }
+    }
+}
+
+impl AlertRules {
+    /// Whether any threshold is set.
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.latency_ms.is_some() || self.loss.is_some()
+    }
+}
+
+/// Whether a server got worse or recovered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    Degraded,
+    Recovered,
+}
+
+/// Which threshold a degraded server crossed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertReason {
+    Latency,
+    Loss,
+}
+
+/// A change of a server's state, as logged and sent to the webhook.
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertEvent {
+    pub timestamp: DateTime<Utc>,
+    pub kind: AlertKind,
+    pub server: DnsServer,
+    /// Thresholds crossed, empty for `recovered`
+    pub reasons: Vec<AlertReason>,
+    pub baseline_ms: Option<f64>,
+    pub current_ms: Option<f64>,
+    pub baseline_loss: f64,
+    pub current_loss: f64,
+}
+
+impl std::fmt::Display for AlertEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms =
+            |value: Option<f64>| value.map_or_else(|| "-".to_string(), |ms| format!("{ms:.1}ms"));
+        write!(
+            f,
+            "{} ({}) {}: latency {} -> {}, loss {:.0}% -> {:.0}%",
+            self.server.name,
+            self.server.ip,
+            match self.kind {
+                AlertKind::Degraded => "degraded",
+                AlertKind::Recovered => "recovered",
+            },
+            ms(self.baseline_ms),
+            ms(self.current_ms),
+            self.baseline_loss * 100.0,
+            self.current_loss * 100.0
+        )
+    }
+}
+
+/// Average latency and loss over a run of rounds.
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    avg_ms: Option<f64>,
+    loss: f64,
+}
+
+#[allow(clippy::cast_precision_loss)]
+impl Level {
+    fn of<'a>(samples: impl Iterator<Item = &'a Option<f64>> + Clone) -> Self {
+        let total = samples.clone().count();
+        let answered: Vec<f64> = samples.flatten().copied().collect();
+        Self {
+            avg_ms: (!answered.is_empty())
+                .then(|| answered.iter().sum::<f64>() / answered.len() as f64),
+            loss: if total == 0 {
+                0.0
+            } else {
+                1.0 - answered.len() as f64 / total as f64
+            },
+        }
+    }
+}
+
+/// Per-server state of a [`Detector`].
+#[derive(Debug, Clone, Default)]
+struct ServerState {
+    /// Rounds collected for the baseline, until it is complete
+    warmup: Vec<Option<f64>>,
+    baseline: Option<Level>,
+    recent: VecDeque<Option<f64>>,
+    degraded: bool,
+}
+
+/// Turns rounds of results into alert events.
+#[derive(Debug, Clone)]
+pub struct Detector {
+    rules: AlertRules,
+    servers: HashMap<String, ServerState>,
+}
+
+impl Detector {
+    #[must_use]
+    pub fn new(rules: AlertRules) -> Self {
+        Self {
+            rules,
+            servers: HashMap::new(),
+        }
+    }
+
+    /// Add a round of results, returning servers that changed state.
+    pub fn observe(&mut self, results: &[SpeedTestResult]) -> Vec<AlertEvent> {
+        results
+            .iter()
+            .filter_map(|result| self.observe_result(result))
+            .collect()
+    }
+
+    fn observe_result(&mut self, result: &SpeedTestResult) -> Option<AlertEvent> {
+        let rules = &self.rules;
+        let state = self.servers.entry(result.server.ip.clone()).or_default();
+
+        let Some(baseline) = state.baseline else {
+            state.warmup.push(result.latency_ms);
+            if state.warmup.len() >= rules.baseline_rounds.max(1) {
+                state.baseline = Some(Level::of(state.warmup.iter()));
+                state.warmup = Vec::new();
+            }
+            return None;
+        };
+
+        state.recent.push_back(result.latency_ms);
+        if state.recent.len() > rules.window.max(1) {
+            state.recent.pop_front();
+        }
+        if state.recent.len() < rules.window.max(1) {
+            return None;
+        }
+
+        let current = Level::of(state.recent.iter());
+        let mut reasons = Vec::new();
+        if let (Some(delta), Some(base), Some(now)) =
+            (rules.latency_ms, baseline.avg_ms, current.avg_ms)
+        {
+            if now - base > delta {
+                reasons.push(AlertReason::Latency);
+            }
+        }
+        if let Some(delta) = rules.loss {
+            if current.loss - baseline.loss > delta {
+                reasons.push(AlertReason::Loss);
+            }
+        }
+
+        let degraded = !reasons.is_empty();
+        if degraded == state.degraded {
+            return None;
+        }
+        state.degraded = degraded;
+        Some(AlertEvent {
+            timestamp: Utc::now(),
+            kind: if degraded {
+                AlertKind::Degraded
+            } else {
+                AlertKind::Recovered
+            },
+            server: result.server.clone(),
+            reasons,
+            baseline_ms: baseline.avg_ms,
+            current_ms: current.avg_ms,
+            baseline_loss: baseline.loss,
+            current_loss: current.loss,
+        })
+    }
+}
+
+/// A [`Detector`] that also reports its events.
+#[derive(Debug, Clone)]
+pub struct Alerter {
+    detector: Detector,
+    webhook: Option<String>,
+    raised: bool,
+}
+
+impl Alerter {
+    /// Create an alerter, or `None` if no threshold is set.
+    #[must_use]
+    pub fn new(rules: AlertRules) -> Option<Self> {
+        rules.enabled().then(|| Self {
+            webhook: rules.webhook.clone(),
+            detector: Detector::new(rules),
+            raised: false,
+        })
+    }
+
+    /// Add a round of results, logging each event and sending it to the
+    /// webhook. Webhook failures are logged, not returned.
+    pub async fn observe(&mut self, results: &[SpeedTestResult]) -> Vec<AlertEvent> {
+        let events = self.detector.observe(results);
+        for event in &events {
+            match event.kind {
+                AlertKind::Degraded => {
+                    self.raised = true;
+                    tracing::warn!("Alert: {event}");
+                }
+                AlertKind::Recovered => tracing::info!("Alert cleared: {event}"),
+            }
+            if let Some(url) = &self.webhook {
+                if let Err(e) = http::post_json(url, event, None, WEBHOOK_TIMEOUT).await {
+                    tracing::warn!("Failed to send alert to {url}: {e}");
+                }
+            }
+        }
+        events
+    }
+
+    /// Whether any server was reported degraded so far.
+    #[must_use]
+    pub const fn raised(&self) -> bool {
+        self.raised
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round(latency: Option<f64>) -> Vec<SpeedTestResult> {
+        let server = DnsServer::new("Cloudflare", "1.1.1.1");
+        vec![match latency {
+            Some(ms) => SpeedTestResult::success(server, ms, 0.0),
+            None => SpeedTestResult::failure(server, "timeout"),
+        }]
+    }
+
+    #[test]
+    fn test_detector_latency() {
+        let mut detector = Detector::new(AlertRules {
+            latency_ms: Some(10.0),
+            baseline_rounds: 2,
+            window: 2,
+            ..AlertRules::default()
+        });
+
+        // Baseline 10 ms, then the window fills
+        for ms in [10.0, 10.0, 12.0, 15.0] {
+            assert!(detector.observe(&round(Some(ms))).is_empty());
+        }
+        // Window average 27.5 ms
+        let events = detector.observe(&round(Some(40.0)));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, AlertKind::Degraded);
+        assert_eq!(events[0].reasons, [AlertReason::Latency]);
+        assert_eq!(events[0].baseline_ms, Some(10.0));
+
+        // Still degraded: no repeated event
+        assert!(detector.observe(&round(Some(40.0))).is_empty());
+
+        detector.observe(&round(Some(11.0)));
+        let events = detector.observe(&round(Some(11.0)));
+        assert_eq!(events[0].kind, AlertKind::Recovered);
+        assert!(events[0].reasons.is_empty());
+    }
+
+    #[test]
+    fn test_detector_loss() {
+        let mut detector = Detector::new(AlertRules {
+            loss: Some(0.4),
+            baseline_rounds: 2,
+            window: 2,
+            ..AlertRules::default()
+        });
+        detector.observe(&round(Some(10.0)));
+        detector.observe(&round(Some(10.0)));
+        assert!(detector.observe(&round(None)).is_empty());
+        let events = detector.observe(&round(None));
+        assert_eq!(events[0].reasons, [AlertReason::Loss]);
+        assert!((events[0].current_loss - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_rules_enabled() {
+        assert!(!AlertRules::default().enabled());
+        let rules: AlertRules = toml::from_str("latency_ms = 20.0\nwindow = 3").unwrap();
+        assert!(rules.enabled());
+        assert_eq!(rules.window, 3);
+        assert_eq!(rules.baseline_rounds, 5);
+    }
+}
@@ -0,0 +1,260 @@
+//! Blocking wrappers of the async testers.
+//!
+//! [`SpeedTester`] and [`PollutionChecker`] own a single-threaded tokio
+//! runtime and block on it for each call, so programs and scripts without
+//! an async runtime can use the library from plain functions. They must not
+//! be used from within an async runtime: tokio panics when a runtime is
+//! blocked on from inside another one.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dnstest::blocking::{PollutionChecker, SpeedTester};
+//! use dnstest::DnsServer;
+//!
+//! let tester = SpeedTester::new()?;
+//! let result = tester.test_latency(&DnsServer::new("Cloudflare", "1.1.1.1"));
+//!
+//! let checker = PollutionChecker::new()?;
+//! let result = checker.check("google.com")?;
+//! ```
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::dns;
+use crate::dns::icmp::{IcmpMethod, Probe};
+use crate::dns::pollution::Lookup;
+use crate::dns::progress::ProgressListener;
+use crate::dns::query::Exchange;
+use crate::dns::types::{DnsServer, PollutionResult, SpeedTestResult};
+use crate::error::Result;
+use hickory_resolver::proto::rr::RecordType;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// Runtime driving one wrapper.
+fn runtime() -> Result<Runtime> {
+    Ok(tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?)
+}
+
+/// Blocking [`dns::SpeedTester`].
+pub struct SpeedTester {
+    // Dropped before the runtime its sockets are registered with
+    inner: dns::SpeedTester,
+    runtime: Runtime,
+}
+
+impl SpeedTester {
+    /// Create a tester with default settings.
+    ///
+    /// See [`dns::SpeedTester::new`].
+    pub fn new() -> Result<Self> {
+        Self::build(dns::SpeedTester::new)
+    }
+
+    /// Create a tester with custom settings.
+    ///
+    /// See [`dns::SpeedTester::with_settings`].
+    pub fn with_settings(timeout: Duration, ping_count: usize) -> Result<Self> {
+        Self::build(|| dns::SpeedTester::with_settings(timeout, ping_count))
+    }
+
+    /// Create a tester whose DNS-query probe asks for `query_type`
+    /// records.
+    ///
+    /// See [`dns::SpeedTester::with_query_type`].
+    pub fn with_query_type(
+        timeout: Duration,
+        ping_count: usize,
+        query_type: RecordType,
+    ) -> Result<Self> {
+        Self::build(|| dns::SpeedTester::with_query_type(timeout, ping_count, query_type))
+    }
+
+    /// Create a tester sending echo requests through `probe`.
+    ///
+    /// See [`dns::SpeedTester::with_probe`].
+    pub fn with_probe(
+        probe: impl Probe + 'static,
+        timeout: Duration,
+        ping_count: usize,
+    ) -> Result<Self> {
+        Self::build(|| Ok(dns::SpeedTester::with_probe(probe, timeout, ping_count)))
+    }
+
+    /// Create the async tester within the runtime, which its ICMP client
+    /// spawns a task on.
+    fn build(create: impl FnOnce() -> Result<dns::SpeedTester>) -> Result<Self> {
+        let runtime = runtime()?;
+        let inner = {
+            let _guard = runtime.enter();
+            create()?
+        };
+        Ok(Self { inner, runtime })
+    }
+
+    /// How this tester sends echo requests.
+    #[must_use]
+    pub fn icmp_method(&self) -> IcmpMethod {
+        self.inner.icmp_method()
+    }
+
+    /// Test latency to a single DNS server.
+    ///
+    /// See [`dns::SpeedTester::test_latency`].
+    pub fn test_latency(&self, server: &DnsServer) -> SpeedTestResult {
+        self.runtime.block_on(self.inner.test_latency(server))
+    }
+
+    /// Test multiple DNS servers one after another.
+    ///
+    /// See [`dns::SpeedTester::test_all`].
+    pub fn test_all(
+        &self,
+        servers: &[DnsServer],
+        progress: Option<&dyn ProgressListener>,
+    ) -> Vec<SpeedTestResult> {
+        self.runtime
+            .block_on(self.inner.test_all(servers, progress))
+    }
+}
+
+/// Blocking [`dns::PollutionChecker`].
+pub struct PollutionChecker {
+    // Dropped before the runtime its resolvers are registered with
+    inner: dns::PollutionChecker,
+    runtime: Runtime,
+}
+
+impl PollutionChecker {
+    /// Create a checker comparing the system resolver with public DNS.
+    ///
+    /// See [`dns::PollutionChecker::new`].
+    pub fn new() -> Result<Self> {
+        let runtime = runtime()?;
+        let inner = {
+            let _guard = runtime.enter();
+            dns::PollutionChecker::new()?
+        };
+        Ok(Self { inner, runtime })
+    }
+
+    /// Create a checker resolving through the given resolvers.
+    ///
+    /// See [`dns::PollutionChecker::with_resolvers`].
+    pub fn with_resolvers(
+        system: impl Lookup + 'static,
+        public: impl Lookup + 'static,
+    ) -> Result<Self> {
+        Ok(Self {
+            inner: dns::PollutionChecker::with_resolvers(system, public),
+            runtime: runtime()?,
+        })
+    }
+
+    /// Compare against other public resolvers than Google and Cloudflare.
+    ///
+    /// See [`dns::PollutionChecker::with_reference_resolvers`].
+    pub fn with_reference_resolvers(self, ips: &[IpAddr]) -> Result<Self> {
+        let inner = {
+            let _guard = self.runtime.enter();
+            self.inner.with_reference_resolvers(ips)?
+        };
+        Ok(Self {
+            inner,
+            runtime: self.runtime,
+        })
+    }
+
+    /// Set the answer overlap below which a domain counts as polluted.
+    ///
+    /// See [`dns::PollutionChecker::with_overlap_threshold`].
+    #[must_use]
+    pub fn with_overlap_threshold(self, threshold: f64) -> Self {
+        Self {
+            inner: self.inner.with_overlap_threshold(threshold),
+            runtime: self.runtime,
+        }
+    }
+
+    /// Send the queries of the active probes through `exchange`.
+    ///
+    /// See [`dns::PollutionChecker::with_exchange`].
+    #[must_use]
+    pub fn with_exchange(self, exchange: impl Exchange + 'static) -> Self {
+        Self {
+            inner: self.inner.with_exchange(exchange),
+            runtime: self.runtime,
+        }
+    }
+
+    /// Look up mismatched addresses in RDAP.
+    ///
+    /// See [`dns::PollutionChecker::with_rdap`].
+    #[must_use]
+    pub fn with_rdap(self, enabled: bool) -> Self {
+        Self {
+            inner: self.inner.with_rdap(enabled),
+            runtime: self.runtime,
+        }
+    }
+
+    /// Check one domain for pollution.
+    ///
+    /// See [`dns::PollutionChecker::check`].
+    pub fn check(&self, domain: &str) -> Result<PollutionResult> {
+        self.runtime.block_on(self.inner.check(domain))
+    }
+
+    /// Check several domains.
+    ///
+    /// See [`dns::PollutionChecker::check_batch`].
+    pub fn check_batch(&self, domains: &[String]) -> Vec<PollutionResult> {
+        self.runtime.block_on(self.inner.check_batch(domains))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::icmp::{Echo, PingError};
+    use futures::future::BoxFuture;
+
+    /// Answers every echo request after a millisecond.
+    struct FakeProbe;
+
+    impl Probe for FakeProbe {
+        fn method(&self) -> IcmpMethod {
+            IcmpMethod::Datagram
+        }
+
+        fn ping<'a>(
+            &'a self,
+            _target: std::net::SocketAddr,
+            _ident: u16,
+            _seq: u16,
+            _payload: &'a [u8],
+            _timeout: Duration,
+        ) -> BoxFuture<'a, std::result::Result<Echo, PingError>> {
+            Box::pin(async {
+                Ok(Echo {
+                    rtt: Duration::from_millis(1),
+                    ttl: None,
+                    elapsed: None,
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_blocking_speed_test() {
+        let tester = SpeedTester::with_probe(FakeProbe, Duration::from_secs(1), 2).unwrap();
+        let server = DnsServer::new("Cloudflare", "1.1.1.1");
+        let results = tester.test_all(&[server], None);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].latency_ms, Some(1.0));
+    }
+}
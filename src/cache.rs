@@ -0,0 +1,105 @@
+//! Generated state in the data directory.
+//!
+//! Files dnstest writes itself live under the platform data directory
+//! (`~/.local/share/dnstest` on Linux), apart from the hand-edited files
+//! in the config directory:
+//!
+//! - `history.ndjson` - recorded speed test runs
+//! - `speed-checkpoint.ndjson` - results of an unfinished speed test
+//! - `dnslist.json`, `dnslist-v6.json` - lists downloaded by `dnstest update`
+//! - `cache/` - disposable caches (update check, reference answers of
+//!   pollution checks, RDAP registrations, downloaded databases)
+//!
+//! `dnstest cache clean` removes `cache/`, or the whole data directory
+//! with `--all`.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::config::ConfigLoader;
+use crate::error::Result;
+use std::path::Path;
+
+/// What a clean removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CleanReport {
+    /// Number of files removed
+    pub files: u64,
+    /// Total size of the removed files in bytes
+    pub bytes: u64,
+}
+
+/// Remove the disposable caches, keeping history and downloaded lists.
+pub fn clean() -> Result<CleanReport> {
+    remove_dir(&ConfigLoader::cache_dir())
+}
+
+/// Remove everything in the data directory, including the history.
+pub fn clean_all() -> Result<CleanReport> {
+    remove_dir(&ConfigLoader::data_dir())
+}
+
+/// Write a file atomically: write a temporary sibling, then rename it.
+///
+/// Readers such as `node_exporter`'s textfile collector never see a
+/// partially written file, and a crash leaves the previous content.
+pub fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{file_name}.tmp"));
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Size of the files under a directory.
+pub fn usage(dir: &Path) -> Result<CleanReport> {
+    let mut report = CleanReport::default();
+    if !dir.exists() {
+        return Ok(report);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            let sub = usage(&entry.path())?;
+            report.files += sub.files;
+            report.bytes += sub.bytes;
+        } else {
+            report.files += 1;
+            report.bytes += metadata.len();
+        }
+    }
+    Ok(report)
+}
+
+/// Remove a directory tree, reporting what was in it.
+fn remove_dir(dir: &Path) -> Result<CleanReport> {
+    let report = usage(dir)?;
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("cache");
+        std::fs::create_dir_all(cache.join("geoip")).unwrap();
+        std::fs::write(cache.join("update-check.json"), "{}").unwrap();
+        std::fs::write(cache.join("geoip").join("db"), "1234").unwrap();
+
+        let report = remove_dir(&cache).unwrap();
+        assert_eq!(report, CleanReport { files: 2, bytes: 6 });
+        assert!(!cache.exists());
+
+        // Cleaning again is not an error
+        assert_eq!(remove_dir(&cache).unwrap(), CleanReport::default());
+    }
+}
@@ -0,0 +1,423 @@
+//! Static chart rendering.
+//!
+//! This module draws speed test results as SVG or PNG images using
+//! `plotters`: a per-server latency bar chart and a latency distribution
+//! histogram. Unlike the HTML report, the output can be embedded anywhere
+//! an image is accepted (READMEs, chat, slides). [`sparkline`] draws a
+//! series as one line of block characters for the terminal.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::dns::SpeedTestResult;
+use crate::error::{Error, Result};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::register_font;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Font family used for all chart text.
+const FONT_FAMILY: &str = "sans-serif";
+
+/// Fonts tried in order when rendering. CJK fonts come first so Chinese
+/// server names render instead of showing as boxes.
+const FONT_CANDIDATES: &[&str] = &[
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/usr/share/fonts/wenquanyi/wqy-microhei/wqy-microhei.ttc",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/Helvetica.ttc",
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+/// Chart width in pixels.
+const WIDTH: u32 = 1000;
+
+/// Height of a single bar (including spacing) in the bar chart.
+const BAR_HEIGHT: u32 = 22;
+
+/// Target number of histogram buckets.
+const HISTOGRAM_BUCKETS: f64 = 20.0;
+
+/// Kind of chart to render.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChartKind {
+    /// One horizontal bar per responding server, fastest first
+    #[default]
+    Bars,
+    /// Number of servers per latency bucket
+    Histogram,
+}
+
+impl ChartKind {
+    /// Get all available chart kind names.
+    #[must_use]
+    pub fn names() -> &'static [&'static str] {
+        &["bars", "histogram"]
+    }
+}
+
+impl std::str::FromStr for ChartKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bars" | "bar" => Ok(Self::Bars),
+            "histogram" | "hist" => Ok(Self::Histogram),
+            _ => Err(format!(
+                "Unknown chart kind: {}. Valid options are: {:?}",
+                s,
+                Self::names()
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ChartKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bars => write!(f, "bars"),
+            Self::Histogram => write!(f, "histogram"),
+        }
+    }
+}
+
+/// Render a chart to an SVG document.
+///
+/// # Arguments
+///
+/// * `results` - Speed test results
+/// * `kind` - Kind of chart to render
+///
+/// # Example
+///
+/// ```ignore
+/// let svg = chart::render_svg(&results, ChartKind::Histogram)?;
+/// ```
+pub fn render_svg(results: &[SpeedTestResult], kind: ChartKind) -> Result<String> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, chart_size(results, kind)).into_drawing_area();
+        draw(&root, results, kind)?;
+        root.present().map_err(chart_error)?;
+    }
+    Ok(svg)
+}
+
+/// Render a chart to a PNG file.
+///
+/// # Arguments
+///
+/// * `results` - Speed test results
+/// * `kind` - Kind of chart to render
+/// * `path` - Output file path
+pub fn render_png(results: &[SpeedTestResult], kind: ChartKind, path: &Path) -> Result<()> {
+    let root = BitMapBackend::new(path, chart_size(results, kind)).into_drawing_area();
+    draw(&root, results, kind)?;
+    root.present().map_err(chart_error)
+}
+
+/// Render a chart to a file, choosing SVG or PNG from the extension.
+///
+/// # Arguments
+///
+/// * `results` - Speed test results
+/// * `kind` - Kind of chart to render
+/// * `path` - Output file path ending in `.svg` or `.png`
+pub fn render_file(results: &[SpeedTestResult], kind: ChartKind, path: &Path) -> Result<()> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+
+    match extension.as_deref() {
+        Some("svg") => Ok(std::fs::write(path, render_svg(results, kind)?)?),
+        Some("png") => render_png(results, kind, path),
+        _ => Err(Error::chart(format!(
+            "Unsupported chart file type: {} (expected .svg or .png)",
+            path.display()
+        ))),
+    }
+}
+
+/// Levels of a sparkline, lowest first.
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Draw values as a line of block characters scaled between the lowest
+/// and highest value. Missing values (failed runs) are drawn as dots.
+#[must_use]
+#[allow(clippy::cast_sign_loss)] // the level is between 0 and 7
+pub fn sparkline(values: &[Option<f64>]) -> String {
+    let present = values.iter().flatten().copied();
+    let min = present.clone().fold(f64::INFINITY, f64::min);
+    let max = present.fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+
+    values
+        .iter()
+        .map(|value| match value {
+            None => '·',
+            Some(_) if range <= 0.0 => SPARK_LEVELS[0],
+            Some(v) => {
+                let level = ((v - min) / range * (SPARK_LEVELS.len() - 1) as f64).round();
+                SPARK_LEVELS[level as usize]
+            }
+        })
+        .collect()
+}
+
+/// Convert a plotters error into a crate error.
+fn chart_error(e: impl std::fmt::Display) -> Error {
+    Error::chart(e.to_string())
+}
+
+/// Register a system font with plotters, once per process.
+fn ensure_font() -> Result<()> {
+    static LOADED: OnceLock<bool> = OnceLock::new();
+
+    let loaded = *LOADED.get_or_init(|| {
+        FONT_CANDIDATES.iter().any(|path| {
+            std::fs::read(path).is_ok_and(|bytes| {
+                // plotters keeps fonts for the lifetime of the process
+                let bytes: &'static [u8] = Box::leak(bytes.into_boxed_slice());
+                register_font(FONT_FAMILY, FontStyle::Normal, bytes).is_ok()
+            })
+        })
+    });
+
+    if loaded {
+        Ok(())
+    } else {
+        Err(Error::chart("No usable TrueType font found on this system"))
+    }
+}
+
+/// Successful results sorted by latency, fastest first.
+fn responding(results: &[SpeedTestResult]) -> Vec<(&SpeedTestResult, f64)> {
+    let mut ok: Vec<_> = results
+        .iter()
+        .filter(|r| r.success)
+        .filter_map(|r| r.latency_ms.map(|latency| (r, latency)))
+        .collect();
+    ok.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    ok
+}
+
+/// Image size for a chart; the bar chart grows with the number of servers.
+fn chart_size(results: &[SpeedTestResult], kind: ChartKind) -> (u32, u32) {
+    match kind {
+        ChartKind::Bars => {
+            let bars = responding(results).len() as u32;
+            (WIDTH, (bars * BAR_HEIGHT + 100).max(300))
+        }
+        ChartKind::Histogram => (WIDTH, 500),
+    }
+}
+
+/// Pick a "nice" bucket width (1, 2 or 5 times a power of ten).
+fn bucket_width(max_latency: f64) -> f64 {
+    let raw = (max_latency / HISTOGRAM_BUCKETS).max(0.1);
+    let magnitude = 10f64.powf(raw.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|step| step * magnitude)
+        .find(|&width| width >= raw)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// Index of the bucket a latency falls into.
+#[allow(clippy::cast_sign_loss)] // latencies are never negative
+fn bucket_index(latency: f64, width: f64) -> usize {
+    (latency / width) as usize
+}
+
+/// Count results per latency bucket.
+fn histogram(latencies: &[f64], width: f64) -> Vec<usize> {
+    let buckets = latencies
+        .iter()
+        .map(|&l| bucket_index(l, width) + 1)
+        .max()
+        .unwrap_or(0);
+    let mut counts = vec![0; buckets];
+    for &latency in latencies {
+        counts[bucket_index(latency, width)] += 1;
+    }
+    counts
+}
+
+fn draw<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    results: &[SpeedTestResult],
+    kind: ChartKind,
+) -> Result<()> {
+    ensure_font()?;
+    root.fill(&WHITE).map_err(chart_error)?;
+
+    let ok = responding(results);
+    if ok.is_empty() {
+        return Err(Error::chart("No successful results to chart"));
+    }
+
+    match kind {
+        ChartKind::Bars => draw_bars(root, &ok, results.len()),
+        ChartKind::Histogram => draw_histogram(root, &ok),
+    }
+}
+
+fn draw_bars<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    ok: &[(&SpeedTestResult, f64)],
+    total: usize,
+) -> Result<()> {
+    let count = ok.len();
+    let max = ok.iter().map(|(_, l)| *l).fold(0.0, f64::max);
+    // Fastest server on top: row `i` is drawn at position `count - 1 - i`
+    let label = |position: usize| {
+        count
+            .checked_sub(position + 1)
+            .and_then(|i| ok.get(i))
+            .map(|(r, _)| format!("{} ({})", r.server.name, r.server.ip))
+            .unwrap_or_default()
+    };
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(
+            format!("DNS latency ({count} of {total} servers responded)"),
+            (FONT_FAMILY, 20),
+        )
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(260)
+        .build_cartesian_2d(0.0..max * 1.1, (0..count).into_segmented())
+        .map_err(chart_error)?;
+
+    chart
+        .configure_mesh()
+        .disable_y_mesh()
+        .y_labels(count)
+        .y_label_formatter(&|v| match v {
+            SegmentValue::CenterOf(position) => label(*position),
+            _ => String::new(),
+        })
+        .x_label_formatter(&|v| format!("{v:.0}"))
+        .x_desc("Latency (ms)")
+        .label_style((FONT_FAMILY, 12))
+        .draw()
+        .map_err(chart_error)?;
+
+    chart
+        .draw_series(ok.iter().enumerate().map(|(i, (_, latency))| {
+            let position = count - 1 - i;
+            let mut bar = Rectangle::new(
+                [
+                    (0.0, SegmentValue::Exact(position)),
+                    (*latency, SegmentValue::Exact(position + 1)),
+                ],
+                RGBColor(79, 157, 222).filled(),
+            );
+            bar.set_margin(3, 3, 0, 0);
+            bar
+        }))
+        .map_err(chart_error)?;
+
+    Ok(())
+}
+
+fn draw_histogram<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    ok: &[(&SpeedTestResult, f64)],
+) -> Result<()> {
+    let latencies: Vec<f64> = ok.iter().map(|(_, l)| *l).collect();
+    let max = latencies.iter().copied().fold(0.0, f64::max);
+    let width = bucket_width(max);
+    let counts = histogram(&latencies, width);
+    let highest = counts.iter().copied().max().unwrap_or(0);
+
+    let mut chart = ChartBuilder::on(root)
+        .caption("DNS latency distribution", (FONT_FAMILY, 20))
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(50)
+        .build_cartesian_2d(0.0..counts.len() as f64 * width, 0..highest + 1)
+        .map_err(chart_error)?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_label_formatter(&|v| format!("{v:.precision$}", precision = usize::from(width < 1.0)))
+        .x_desc("Latency (ms)")
+        .y_desc("Servers")
+        .label_style((FONT_FAMILY, 12))
+        .draw()
+        .map_err(chart_error)?;
+
+    chart
+        .draw_series(
+            counts
+                .iter()
+                .enumerate()
+                .filter(|(_, &n)| n > 0)
+                .map(|(bucket, &n)| {
+                    let start = bucket as f64 * width;
+                    let mut bar = Rectangle::new(
+                        [(start, 0), (start + width, n)],
+                        RGBColor(79, 157, 222).filled(),
+                    );
+                    bar.set_margin(0, 0, 1, 1);
+                    bar
+                }),
+        )
+        .map_err(chart_error)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::DnsServer;
+
+    #[test]
+    fn test_chart_kind_parse() {
+        assert_eq!("bars".parse::<ChartKind>(), Ok(ChartKind::Bars));
+        assert_eq!("histogram".parse::<ChartKind>(), Ok(ChartKind::Histogram));
+        assert!("pie".parse::<ChartKind>().is_err());
+    }
+
+    #[test]
+    fn test_bucket_width() {
+        assert!((bucket_width(100.0) - 5.0).abs() < f64::EPSILON);
+        assert!((bucket_width(350.0) - 20.0).abs() < f64::EPSILON);
+        assert!((bucket_width(1.0) - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_histogram_counts() {
+        assert_eq!(histogram(&[1.0, 4.0, 5.0, 12.0], 5.0), vec![2, 1, 1]);
+        assert!(histogram(&[], 5.0).is_empty());
+    }
+
+    #[test]
+    fn test_render_file_rejects_unknown_extension() {
+        let results = vec![SpeedTestResult::success(
+            DnsServer::new("Cloudflare", "1.1.1.1"),
+            12.5,
+            0.0,
+        )];
+        assert!(render_file(&results, ChartKind::Bars, Path::new("chart.gif")).is_err());
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[Some(1.0), Some(8.0), None, Some(4.5)]), "▁█·▅");
+        assert_eq!(sparkline(&[Some(3.0), Some(3.0)]), "▁▁");
+        assert_eq!(sparkline(&[None]), "·");
+        assert_eq!(sparkline(&[]), "");
+    }
+}
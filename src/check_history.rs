@@ -0,0 +1,370 @@
+//! Pollution check history store.
+//!
+//! Every `dnstest check` appends its outcome as one JSON line to
+//! `check-history.ndjson` in the data directory, in the same append-only
+//! format as the speed test [`crate::history`].
+//!
+//! [`CheckHistory::last`] finds the previous check of a domain and
+//! [`changes`] what differs from it, for `dnstest check --diff`, so that a
+//! scheduled check notices when a domain starts or stops being polluted.
+//! Addresses are compared by network, since CDNs hand out other addresses
+//! of the same networks from one check to the next.
+//!
+//! Once the file grows past [`MAX_FILE_SIZE`], it is compacted to the last
+//! [`KEEP_PER_DOMAIN`] checks of each domain.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::cache::write_atomic;
+use crate::config::ConfigLoader;
+use crate::dns::{CheckVerdict, PollutionResult};
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// History file name inside the data directory.
+const HISTORY_FILE: &str = "check-history.ndjson";
+
+/// Size in bytes past which the history is compacted.
+pub const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Checks kept of each domain when the history is compacted.
+pub const KEEP_PER_DOMAIN: usize = 50;
+
+/// Prefix lengths of the networks that addresses are compared by: a /24
+/// for IPv4 and a /48 for IPv6, the smallest routed blocks.
+const V4_PREFIX: u32 = 24;
+const V6_PREFIX: u32 = 48;
+
+/// The outcome of a single recorded check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckRecord {
+    /// When the check finished
+    pub timestamp: DateTime<Utc>,
+    /// Domain checked
+    pub domain: String,
+    /// Overall outcome
+    pub verdict: CheckVerdict,
+    /// Addresses returned by the system resolver, sorted
+    pub system_ips: Vec<IpAddr>,
+    /// Addresses returned by the reference resolvers, sorted
+    pub public_ips: Vec<IpAddr>,
+}
+
+impl CheckRecord {
+    /// Record a check finishing now.
+    #[must_use]
+    pub fn from_result(result: &PollutionResult) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            domain: result.domain.clone(),
+            verdict: result.verdict(),
+            system_ips: sorted(&result.system_ips),
+            public_ips: sorted(&result.public_ips),
+        }
+    }
+}
+
+/// A difference between two checks of a domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckChange {
+    /// The overall outcome changed
+    Verdict {
+        /// Outcome of the previous check
+        from: CheckVerdict,
+        /// Outcome of this check
+        to: CheckVerdict,
+    },
+    /// The system resolver returned addresses of other networks
+    SystemIps {
+        /// Addresses of networks returned only now
+        added: Vec<IpAddr>,
+        /// Addresses of networks returned only before
+        removed: Vec<IpAddr>,
+    },
+    /// The reference resolvers returned addresses of other networks
+    PublicIps {
+        /// Addresses of networks returned only now
+        added: Vec<IpAddr>,
+        /// Addresses of networks returned only before
+        removed: Vec<IpAddr>,
+    },
+}
+
+/// What changed from the `previous` check of a domain to the `current` one,
+/// in the order outcome, system addresses, reference addresses.
+///
+/// Addresses only count as changed when their network, a /24 or /48, was
+/// not returned by the other check.
+#[must_use]
+pub fn changes(previous: &CheckRecord, current: &CheckRecord) -> Vec<CheckChange> {
+    let mut changes = Vec::new();
+    if previous.verdict != current.verdict {
+        changes.push(CheckChange::Verdict {
+            from: previous.verdict,
+            to: current.verdict,
+        });
+    }
+    let (added, removed) = set_diff(&previous.system_ips, &current.system_ips);
+    if !added.is_empty() || !removed.is_empty() {
+        changes.push(CheckChange::SystemIps { added, removed });
+    }
+    let (added, removed) = set_diff(&previous.public_ips, &current.public_ips);
+    if !added.is_empty() || !removed.is_empty() {
+        changes.push(CheckChange::PublicIps { added, removed });
+    }
+    changes
+}
+
+/// Append-only store of pollution checks.
+///
+/// # Example
+///
+/// ```ignore
+/// let history = CheckHistory::open_default();
+/// let current = CheckRecord::from_result(&result);
+/// if let Some(previous) = history.last(&current.domain)? {
+///     for change in changes(&previous, &current) {
+///         println!("{change:?}");
+///     }
+/// }
+/// history.append(&current)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct CheckHistory {
+    path: PathBuf,
+}
+
+impl CheckHistory {
+    /// Use a history file at a custom path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Use the default history file in the data directory.
+    #[must_use]
+    pub fn open_default() -> Self {
+        Self::new(ConfigLoader::data_dir().join(HISTORY_FILE))
+    }
+
+    /// Path of the history file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record a check, compacting the history once it grows past
+    /// [`MAX_FILE_SIZE`].
+    pub fn append(&self, record: &CheckRecord) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        if file.metadata()?.len() > MAX_FILE_SIZE {
+            drop(file);
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Keep only the last [`KEEP_PER_DOMAIN`] checks of each domain.
+    fn compact(&self) -> Result<()> {
+        let records = self.load()?;
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut kept: Vec<&CheckRecord> = records
+            .iter()
+            .rev()
+            .filter(|record| {
+                let count = seen.entry(normalize(&record.domain)).or_default();
+                *count += 1;
+                *count <= KEEP_PER_DOMAIN
+            })
+            .collect();
+        kept.reverse();
+
+        let mut content = String::new();
+        for record in kept {
+            content.push_str(&serde_json::to_string(record)?);
+            content.push('\n');
+        }
+        write_atomic(&self.path, &content)
+    }
+
+    /// Load all recorded checks, oldest first.
+    ///
+    /// A missing file yields an empty history; malformed lines are skipped.
+    pub fn load(&self) -> Result<Vec<CheckRecord>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| tracing::debug!("Skipping malformed check history line: {e}"))
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// The latest recorded check of `domain`, ignoring case and a trailing
+    /// dot.
+    ///
+    /// Lines are read from the end and only parsed until one matches.
+    pub fn last(&self, domain: &str) -> Result<Option<CheckRecord>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let domain = normalize(domain);
+        Ok(content
+            .lines()
+            .rev()
+            .filter_map(|line| serde_json::from_str::<CheckRecord>(line).ok())
+            .find(|record| normalize(&record.domain) == domain))
+    }
+}
+
+fn normalize(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn sorted(ips: &[IpAddr]) -> Vec<IpAddr> {
+    ips.iter()
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// The network an address is compared by.
+fn network(ip: IpAddr) -> (bool, u128) {
+    match ip {
+        IpAddr::V4(ip) => (false, u128::from(u32::from(ip) >> (32 - V4_PREFIX))),
+        IpAddr::V6(ip) => (true, u128::from(ip) >> (128 - V6_PREFIX)),
+    }
+}
+
+/// Addresses of networks only in `new`, and only in `old`.
+fn set_diff(old: &[IpAddr], new: &[IpAddr]) -> (Vec<IpAddr>, Vec<IpAddr>) {
+    let only_in = |ips: &[IpAddr], other: &[IpAddr]| {
+        let other: BTreeSet<_> = other.iter().copied().map(network).collect();
+        sorted(ips)
+            .into_iter()
+            .filter(|&ip| !other.contains(&network(ip)))
+            .collect()
+    };
+    (only_in(new, old), only_in(old, new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_check_and_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = CheckHistory::new(dir.path().join("nested").join(HISTORY_FILE));
+        assert_eq!(history.last("example.com").unwrap(), None);
+
+        let ip = |last: u8| IpAddr::from([93, 184, 215, last]);
+        let record = |domain: &str, verdict, system_ips: Vec<IpAddr>| CheckRecord {
+            timestamp: Utc::now(),
+            domain: domain.to_string(),
+            verdict,
+            system_ips,
+            public_ips: vec![ip(14)],
+        };
+        let first = record("example.com", CheckVerdict::Clean, vec![ip(14)]);
+        history.append(&first).unwrap();
+        history
+            .append(&record("example.org", CheckVerdict::Clean, vec![ip(1)]))
+            .unwrap();
+
+        let previous = history.last("Example.com.").unwrap().unwrap();
+        assert_eq!(previous, first);
+        assert!(changes(&previous, &first).is_empty());
+
+        let forged = IpAddr::from([59, 24, 3, 173]);
+        let current = record("example.com", CheckVerdict::Polluted, vec![ip(14), forged]);
+        assert_eq!(
+            changes(&previous, &current),
+            [
+                CheckChange::Verdict {
+                    from: CheckVerdict::Clean,
+                    to: CheckVerdict::Polluted,
+                },
+                CheckChange::SystemIps {
+                    added: vec![forged],
+                    removed: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_changes_by_network() {
+        let record = |system_ips: &[&str]| CheckRecord {
+            timestamp: Utc::now(),
+            domain: "example.com".to_string(),
+            verdict: CheckVerdict::Clean,
+            system_ips: system_ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+            public_ips: Vec::new(),
+        };
+        // A CDN rotating addresses within its networks
+        let before = record(&["23.215.0.136", "2600:1406:3a00:21::173e:2e65"]);
+        let rotated = record(&["23.215.0.138", "2600:1406:3a00:22::173e:2e66"]);
+        assert!(changes(&before, &rotated).is_empty());
+
+        let moved = record(&["23.215.0.136", "2600:1406:bc00:53::b81e:94ce"]);
+        assert_eq!(
+            changes(&before, &moved),
+            [CheckChange::SystemIps {
+                added: vec!["2600:1406:bc00:53::b81e:94ce".parse().unwrap()],
+                removed: vec!["2600:1406:3a00:21::173e:2e65".parse().unwrap()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = CheckHistory::new(dir.path().join(HISTORY_FILE));
+        let record = |domain: &str, last: u8| CheckRecord {
+            timestamp: Utc::now(),
+            domain: domain.to_string(),
+            verdict: CheckVerdict::Clean,
+            system_ips: vec![IpAddr::from([192, 0, 2, last])],
+            public_ips: Vec::new(),
+        };
+        for i in 0..=KEEP_PER_DOMAIN {
+            history.append(&record("example.com", i as u8)).unwrap();
+        }
+        history.append(&record("example.org", 1)).unwrap();
+
+        history.compact().unwrap();
+        let records = history.load().unwrap();
+        assert_eq!(records.len(), KEEP_PER_DOMAIN + 1);
+        assert_eq!(records[0].system_ips, [IpAddr::from([192, 0, 2, 1])]);
+        assert_eq!(records[KEEP_PER_DOMAIN].domain, "example.org");
+        let last = history.last("example.com").unwrap().unwrap();
+        assert_eq!(last.system_ips, [IpAddr::from([192, 0, 2, 50])]);
+    }
+}
@@ -0,0 +1,219 @@
+//! Checkpoint of an in-progress speed test.
+//!
+//! While `dnstest speed` runs, every finished result is appended as one
+//! JSON line to `speed-checkpoint.ndjson` in the data directory, and the
+//! file is removed once the run completes. If the run is interrupted,
+//! `dnstest speed --resume` reuses the saved results and tests only the
+//! remaining servers. As with the history, a partially written last line
+//! is skipped.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::config::ConfigLoader;
+use crate::dns::{DnsServer, SpeedTestResult};
+use crate::error::Result;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Checkpoint file name inside the data directory.
+const CHECKPOINT_FILE: &str = "speed-checkpoint.ndjson";
+
+/// Results saved so far by an interrupted speed test.
+///
+/// # Example
+///
+/// ```ignore
+/// let checkpoint = Checkpoint::open_default();
+/// let done = checkpoint.resume(&servers)?;
+/// let mut writer = checkpoint.writer(true)?;
+/// for result in remaining_results {
+///     writer.record(&result)?;
+/// }
+/// checkpoint.remove()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    /// Use a checkpoint file at a custom path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Use the default checkpoint file in the data directory.
+    #[must_use]
+    pub fn open_default() -> Self {
+        Self::new(ConfigLoader::data_dir().join(CHECKPOINT_FILE))
+    }
+
+    /// Path of the checkpoint file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load the saved results of servers in `servers`, in list order.
+    ///
+    /// Results of servers no longer in the list are ignored, and a later
+    /// result of the same server replaces an earlier one. A missing file
+    /// yields no results.
+    pub fn resume(&self, servers: &[DnsServer]) -> Result<Vec<SpeedTestResult>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut saved: HashMap<String, SpeedTestResult> = HashMap::new();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str::<SpeedTestResult>(line) {
+                Ok(result) => {
+                    saved.insert(result.server.spec(), result);
+                }
+                Err(e) => tracing::debug!("Skipping malformed checkpoint line: {e}"),
+            }
+        }
+
+        Ok(servers
+            .iter()
+            .filter_map(|server| saved.remove(&server.spec()))
+            .collect())
+    }
+
+    /// Open the checkpoint for writing, keeping saved results when
+    /// `append` is set and starting over otherwise.
+    pub fn writer(&self, append: bool) -> Result<CheckpointWriter> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&self.path)?;
+        let mut writer = CheckpointWriter { file };
+        // Do not continue a line cut off by the interruption
+        if append && ends_mid_line(&self.path)? {
+            writer.file.write_all(b"\n")?;
+        }
+        Ok(writer)
+    }
+
+    /// Remove the checkpoint after a completed run.
+    pub fn remove(&self) -> Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether the file's last line lacks its newline.
+fn ends_mid_line(path: &Path) -> Result<bool> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(false);
+    }
+    let mut last = [0u8];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] != b'\n')
+}
+
+/// Appends results to a [`Checkpoint`] as they finish.
+#[derive(Debug)]
+pub struct CheckpointWriter {
+    file: File,
+}
+
+impl CheckpointWriter {
+    /// Save one finished result.
+    pub fn record(&mut self, result: &SpeedTestResult) -> Result<()> {
+        let mut line = serde_json::to_string(result)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let checkpoint = Checkpoint::new(dir.path().join("nested").join(CHECKPOINT_FILE));
+        let servers = [
+            DnsServer::new("Cloudflare", "1.1.1.1"),
+            DnsServer::new("Google", "8.8.8.8"),
+            DnsServer::new("Quad9", "9.9.9.9"),
+        ];
+        assert!(checkpoint.resume(&servers).unwrap().is_empty());
+
+        let mut writer = checkpoint.writer(false).unwrap();
+        writer
+            .record(&SpeedTestResult::success(servers[2].clone(), 30.0, 0.0))
+            .unwrap();
+        writer
+            .record(&SpeedTestResult::failure(
+                DnsServer::new("Gone", "4.4.4.4"),
+                "timeout",
+            ))
+            .unwrap();
+        drop(writer);
+
+        let mut writer = checkpoint.writer(true).unwrap();
+        writer
+            .record(&SpeedTestResult::success(servers[0].clone(), 10.0, 0.0))
+            .unwrap();
+        writer.file.write_all(b"{\"server\":").unwrap();
+        drop(writer);
+
+        // Resuming again starts a fresh line after the cut-off one
+        let mut writer = checkpoint.writer(true).unwrap();
+        writer
+            .record(&SpeedTestResult::success(servers[1].clone(), 20.0, 0.0))
+            .unwrap();
+
+        let done = checkpoint.resume(&servers).unwrap();
+        let ips: Vec<_> = done.iter().map(|r| r.server.ip.as_str()).collect();
+        assert_eq!(ips, ["1.1.1.1", "8.8.8.8", "9.9.9.9"]);
+
+        // Starting over drops the saved results
+        drop(checkpoint.writer(false).unwrap());
+        assert!(checkpoint.resume(&servers).unwrap().is_empty());
+
+        checkpoint.remove().unwrap();
+        checkpoint.remove().unwrap();
+        assert!(!checkpoint.path().exists());
+    }
+
+    #[test]
+    fn test_resume_same_ip() {
+        let dir = tempfile::tempdir().unwrap();
+        let checkpoint = Checkpoint::new(dir.path().join(CHECKPOINT_FILE));
+        let mut alternate = DnsServer::new("Local 5353", "127.0.0.1");
+        alternate.port = Some(5353);
+        let servers = [DnsServer::new("Local", "127.0.0.1"), alternate];
+
+        let mut writer = checkpoint.writer(false).unwrap();
+        writer
+            .record(&SpeedTestResult::success(servers[1].clone(), 20.0, 0.0))
+            .unwrap();
+        writer
+            .record(&SpeedTestResult::success(servers[0].clone(), 10.0, 0.0))
+            .unwrap();
+        drop(writer);
+
+        let done = checkpoint.resume(&servers).unwrap();
+        let specs: Vec<_> = done.iter().map(|r| r.server.spec()).collect();
+        assert_eq!(specs, ["127.0.0.1", "127.0.0.1:5353"]);
+        assert_eq!(done[1].latency_ms, Some(20.0));
+    }
+}
@@ -4,8 +4,15 @@
 //! It supports multiple commands: interactive mode, speed test, pollution check,
 //! listing DNS servers, and exporting DNS lists.
 
-use clap::{Parser, Subcommand};
+use crate::chart::ChartKind;
+use crate::config::{MergePolicy, Preset};
+use crate::dns::TestMethod;
+use crate::http::HttpVersion;
+use clap::builder::BoolishValueParser;
+use clap::{Args, Parser, Subcommand};
+use hickory_resolver::proto::rr::RecordType;
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 
 /// CLI argument parser using clap derive macro.
@@ -30,16 +37,77 @@ use std::path::PathBuf;
 )]
 pub struct Cli {
     /// Enable verbose logging
-    #[arg(short, long, global = true)]
+    #[arg(short, long, global = true, env = "DNSTEST_VERBOSE", value_parser = BoolishValueParser::new())]
     pub verbose: bool,
 
     /// Quiet mode (only errors)
-    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    #[arg(
+        short,
+        long,
+        global = true,
+        conflicts_with = "verbose",
+        env = "DNSTEST_QUIET", value_parser = BoolishValueParser::new()
+    )]
     pub quiet: bool,
 
-    /// Output format
-    #[arg(long, global = true, default_value = "table")]
-    pub format: OutputFormat,
+    /// Output format [default: table]
+    #[arg(long, global = true, env = "DNSTEST_FORMAT")]
+    pub format: Option<OutputFormat>,
+
+    /// Export traces and metrics to this OTLP/HTTP collector
+    #[cfg(feature = "otel")]
+    #[arg(long, global = true, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Use a named profile from config.toml
+    #[arg(long, global = true, value_name = "NAME", env = "DNSTEST_PROFILE")]
+    pub profile: Option<String>,
+
+    /// POST speed/check results as JSON to this URL
+    #[arg(long, global = true, value_name = "URL", env = "DNSTEST_PUSH_URL")]
+    pub push: Option<String>,
+
+    /// Bearer token for --push
+    #[arg(
+        long,
+        global = true,
+        value_name = "TOKEN",
+        env = "DNSTEST_PUSH_TOKEN",
+        hide_env_values = true
+    )]
+    pub push_token: Option<String>,
+
+    /// Resolve host names of HTTP requests (DNS lists, DNS-over-HTTPS
+    /// endpoints, updates) through this DNS server instead of the system
+    /// resolver
+    #[arg(long, global = true, value_name = "IP", env = "DNSTEST_BOOTSTRAP")]
+    pub bootstrap: Option<IpAddr>,
+
+    /// Send probes through this network interface (Linux only)
+    #[arg(long, global = true, value_name = "NAME", env = "DNSTEST_INTERFACE")]
+    pub interface: Option<String>,
+
+    /// Send probes from this local address
+    #[arg(long, global = true, value_name = "IP", env = "DNSTEST_SOURCE")]
+    pub source: Option<IpAddr>,
+
+    /// Mark probe packets for policy routing, e.g. 0x100 (Linux only)
+    #[arg(
+        long,
+        global = true,
+        value_name = "MARK",
+        env = "DNSTEST_FWMARK",
+        value_parser = crate::dns::source::parse_mark
+    )]
+    pub fwmark: Option<u32>,
+
+    /// Send HTTP requests through this proxy (e.g. `socks5://127.0.0.1:1080`)
+    #[arg(long, global = true, value_name = "URL", env = "DNSTEST_PROXY")]
+    pub proxy: Option<String>,
+
+    /// Check daily for new releases and DNS lists
+    #[arg(long, global = true, env = "DNSTEST_CHECK_UPDATES", value_parser = BoolishValueParser::new())]
+    pub check_updates: bool,
 
     #[command(subcommand)]
     pub command: Option<Commands>,
@@ -61,13 +129,17 @@ pub enum OutputFormat {
     Csv,
     /// TSV format (tab-separated)
     Tsv,
+    /// YAML format
+    Yaml,
+    /// `OpenMetrics` text format (Prometheus, `node_exporter` textfile)
+    OpenMetrics,
 }
 
 impl OutputFormat {
     /// Get all available output format names.
     #[must_use]
     pub fn names() -> &'static [&'static str] {
-        &["table", "json", "csv", "tsv"]
+        &["table", "json", "csv", "tsv", "yaml", "openmetrics"]
     }
 }
 
@@ -80,6 +152,8 @@ impl std::str::FromStr for OutputFormat {
             "json" => Ok(Self::Json),
             "csv" => Ok(Self::Csv),
             "tsv" => Ok(Self::Tsv),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "openmetrics" | "prometheus" => Ok(Self::OpenMetrics),
             _ => Err(format!(
                 "Unknown format: {}. Valid options are: {:?}",
                 s,
@@ -96,6 +170,161 @@ impl std::fmt::Display for OutputFormat {
             Self::Json => write!(f, "json"),
             Self::Csv => write!(f, "csv"),
             Self::Tsv => write!(f, "tsv"),
+            Self::Yaml => write!(f, "yaml"),
+            Self::OpenMetrics => write!(f, "openmetrics"),
+        }
+    }
+}
+
+/// How `speed` groups its results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// One row per provider, summarizing all of its addresses
+    Provider,
+}
+
+impl GroupBy {
+    /// Get all available grouping names.
+    #[must_use]
+    pub fn names() -> &'static [&'static str] {
+        &["provider"]
+    }
+}
+
+impl std::str::FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "provider" => Ok(Self::Provider),
+            _ => Err(format!(
+                "Unknown grouping: {}. Valid options are: {:?}",
+                s,
+                Self::names()
+            )),
+        }
+    }
+}
+
+/// Format of the statistics block after `speed` results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryFormat {
+    /// Human-readable lines
+    Table,
+    /// One JSON object
+    Json,
+}
+
+impl SummaryFormat {
+    /// Get all available summary format names.
+    #[must_use]
+    pub fn names() -> &'static [&'static str] {
+        &["table", "json"]
+    }
+}
+
+impl std::str::FromStr for SummaryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "Unknown summary format: {}. Valid options are: {:?}",
+                s,
+                Self::names()
+            )),
+        }
+    }
+}
+
+/// Saved DNS list that `list --saved` shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListSource {
+    /// `dnslist.json`
+    V4,
+    /// `dnslist-v6.json`
+    V6,
+}
+
+impl ListSource {
+    /// Get all available list names.
+    #[must_use]
+    pub fn names() -> &'static [&'static str] {
+        &["v4", "v6"]
+    }
+
+    /// File name of the list.
+    #[must_use]
+    pub const fn file_name(self) -> &'static str {
+        match self {
+            Self::V4 => crate::config::LIST_FILE,
+            Self::V6 => crate::config::LIST_V6_FILE,
+        }
+    }
+}
+
+impl std::str::FromStr for ListSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "v4" | "ipv4" => Ok(Self::V4),
+            "v6" | "ipv6" => Ok(Self::V6),
+            _ => Err(format!(
+                "Unknown list: {}. Valid options are: {:?}",
+                s,
+                Self::names()
+            )),
+        }
+    }
+}
+
+/// How `export` writes IPv6 servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Ipv6Export {
+    /// In the same file as the IPv4 servers
+    #[default]
+    Include,
+    /// In a separate `-v6` file next to the output, like `dnslist-v6.json`
+    /// next to `dnslist.json`
+    Split,
+    /// Not at all
+    Exclude,
+}
+
+impl Ipv6Export {
+    /// Get all available mode names.
+    #[must_use]
+    pub fn names() -> &'static [&'static str] {
+        &["include", "split", "exclude"]
+    }
+}
+
+impl std::str::FromStr for Ipv6Export {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "include" => Ok(Self::Include),
+            "split" => Ok(Self::Split),
+            "exclude" => Ok(Self::Exclude),
+            _ => Err(format!(
+                "Unknown IPv6 export mode: {}. Valid options are: {:?}",
+                s,
+                Self::names()
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Ipv6Export {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Include => write!(f, "include"),
+            Self::Split => write!(f, "split"),
+            Self::Exclude => write!(f, "exclude"),
         }
     }
 }
@@ -107,11 +336,24 @@ pub enum Commands {
     ///
     /// Launch the interactive terminal user interface (TUI).
     /// This provides a menu-based interface for DNS testing.
-    #[command(alias = "i")]
+    #[command(aliases = ["i", "tui"])]
     Interactive {
         /// Load custom DNS list file (JSON format)
-        #[arg(short, long)]
+        #[arg(short, long, env = "DNSTEST_DNS_LIST")]
         file: Option<PathBuf>,
+
+        /// Browse speed test results from a JSON file (from `speed --format
+        /// json`) instead of starting empty; `-` reads them from stdin
+        #[arg(long, value_name = "PATH", conflicts_with = "file")]
+        from: Option<PathBuf>,
+
+        /// Test only IPv4 servers
+        #[arg(long = "ipv4", conflicts_with = "ipv6_only")]
+        ipv4_only: bool,
+
+        /// Test only IPv6 servers
+        #[arg(long = "ipv6")]
+        ipv6_only: bool,
     },
 
     /// DNS测速
@@ -121,24 +363,180 @@ pub enum Commands {
     #[command(alias = "s")]
     Speed {
         /// DNS list file (JSON format)
-        #[arg(short, long)]
+        #[arg(short, long, env = "DNSTEST_DNS_LIST")]
         file: Option<PathBuf>,
 
-        /// Number of pings per server
-        #[arg(short, long, default_value = "3")]
-        count: usize,
+        /// Number of pings per server (default: 3)
+        #[arg(short, long, env = "DNSTEST_COUNT")]
+        count: Option<usize>,
+
+        /// Timeout in seconds (default: 5)
+        #[arg(short, long, env = "DNSTEST_TIMEOUT")]
+        timeout: Option<u64>,
+
+        /// Timeout in milliseconds, for finer control than `--timeout`
+        #[arg(
+            long,
+            value_name = "MS",
+            env = "DNSTEST_TIMEOUT_MS",
+            conflicts_with = "timeout",
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        timeout_ms: Option<u64>,
+
+        /// Number of servers tested at the same time (default: 1), or the
+        /// most with --adaptive (default: 64)
+        #[arg(short = 'j', long, env = "DNSTEST_CONCURRENCY")]
+        concurrency: Option<usize>,
+
+        /// Ramp the number of servers tested at the same time up while few
+        /// time out and back off when many do
+        #[arg(long, env = "DNSTEST_ADAPTIVE", value_parser = BoolishValueParser::new())]
+        adaptive: bool,
+
+        /// Test the servers in a random order; results are still listed in
+        /// list order
+        #[arg(long, env = "DNSTEST_SHUFFLE", value_parser = BoolishValueParser::new())]
+        shuffle: bool,
+
+        /// Shuffle the servers in the order given by SEED, the same for the
+        /// same SEED (implies --shuffle)
+        #[arg(long, value_name = "SEED", env = "DNSTEST_SHUFFLE_SEED")]
+        shuffle_seed: Option<u64>,
+
+        /// Record type queried where ICMP is not permitted and latency is
+        /// measured with DNS queries instead (default: NS)
+        #[arg(
+            long,
+            value_name = "TYPE",
+            env = "DNSTEST_QTYPE",
+            value_parser = crate::dns::query::parse_record_type
+        )]
+        qtype: Option<RecordType>,
 
-        /// Timeout in seconds
-        #[arg(short, long, default_value = "5")]
-        timeout: u64,
+        /// Measure with ICMP ping (icmp) or the round trip of a DNS query
+        /// over UDP (udp) or TCP (tcp), which need no privileges
+        /// [default: icmp]
+        #[arg(long, value_name = "METHOD", env = "DNSTEST_METHOD")]
+        method: Option<TestMethod>,
 
-        /// Custom DNS servers (format: IP#Name)
-        #[arg(long = "dns")]
+        /// Measure servers that answer no ping with DNS queries over TCP
+        /// instead, for networks that drop ICMP
+        #[arg(long, env = "DNSTEST_TCP_FALLBACK", value_parser = BoolishValueParser::new())]
+        tcp_fallback: bool,
+
+        /// Name asked for by DNS queries (default: the root zone)
+        #[arg(long, value_name = "DOMAIN", env = "DNSTEST_QUERY_NAME")]
+        query_name: Option<String>,
+
+        /// Custom DNS servers (format: [scheme://]IP[:port]#Name)
+        #[arg(long = "dns", env = "DNSTEST_DNS", value_delimiter = ',')]
         dns_servers: Vec<String>,
 
+        /// Test a built-in server list instead: root (the 13 root servers)
+        /// or tld[:com,net,...] (the nameservers of top-level domains)
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["file", "dns_servers"])]
+        preset: Option<Preset>,
+
+        /// Test only servers hosted in these countries (e.g. CN,US)
+        #[arg(
+            long,
+            value_name = "CODE",
+            env = "DNSTEST_COUNTRY",
+            value_delimiter = ','
+        )]
+        country: Vec<String>,
+
+        /// Test only IPv4 servers
+        #[arg(long = "ipv4", conflicts_with = "ipv6_only")]
+        ipv4_only: bool,
+
+        /// Test only IPv6 servers
+        #[arg(long = "ipv6")]
+        ipv6_only: bool,
+
         /// Sort by latency (fastest first)
-        #[arg(long = "sort")]
+        #[arg(long = "sort", env = "DNSTEST_SORT", value_parser = BoolishValueParser::new())]
         sort_by_latency: bool,
+
+        /// Print only the servers that answered
+        #[arg(long, env = "DNSTEST_ONLY_SUCCESS", value_parser = BoolishValueParser::new())]
+        only_success: bool,
+
+        /// Print only the servers whose latency is at most this
+        #[arg(long, value_name = "MS", env = "DNSTEST_MAX_LATENCY")]
+        max_latency: Option<f64>,
+
+        /// Print only the servers losing at least this percentage of
+        /// probes
+        #[arg(long, value_name = "PERCENT", env = "DNSTEST_MIN_LOSS")]
+        min_loss: Option<f64>,
+
+        /// Do not record this run in the history
+        #[arg(long, env = "DNSTEST_NO_HISTORY", value_parser = BoolishValueParser::new())]
+        no_history: bool,
+
+        /// Leave the round-trip time of every ping out of the results
+        #[arg(long, env = "DNSTEST_NO_SAMPLES", value_parser = BoolishValueParser::new())]
+        no_samples: bool,
+
+        /// Write each server's latency and status back into the DNS list
+        /// file it was loaded from
+        #[arg(
+            long,
+            env = "DNSTEST_SAVE_RESULTS",
+            value_parser = BoolishValueParser::new(),
+            conflicts_with_all = ["dns_servers", "preset", "from"]
+        )]
+        save_results: bool,
+
+        /// Write the formatted results to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Summarize the results per provider instead of per server
+        #[arg(long, value_name = "GROUP", env = "DNSTEST_GROUP_BY")]
+        group_by: Option<GroupBy>,
+
+        /// Leave out the statistics after the results
+        #[arg(long, conflicts_with = "summary_only")]
+        no_summary: bool,
+
+        /// Print only the statistics, not the results
+        #[arg(long)]
+        summary_only: bool,
+
+        /// Format of the statistics: table or json (default: json with
+        /// `--format json`, table otherwise)
+        #[arg(long, value_name = "FORMAT", conflicts_with = "no_summary")]
+        summary_format: Option<SummaryFormat>,
+
+        /// Exit with status 3 if the fastest server is slower than this
+        #[arg(long, value_name = "MS", env = "DNSTEST_FAIL_OVER_MS")]
+        fail_over_ms: Option<f64>,
+
+        /// Exit with status 3 if fewer servers than this percentage answer
+        #[arg(long, value_name = "PERCENT", env = "DNSTEST_MIN_SUCCESS")]
+        min_success: Option<f64>,
+
+        /// Retest only the servers that failed in the `--from` results
+        #[arg(long, requires = "from")]
+        only_failed: bool,
+
+        /// Previous results JSON file (from `speed --format json`); the
+        /// merged results are written back to it unless `--output` is given
+        #[arg(long, requires = "only_failed", conflicts_with_all = ["file", "dns_servers", "preset", "country", "ipv4_only", "ipv6_only"])]
+        from: Option<PathBuf>,
+
+        /// Continue an interrupted run, testing only the servers it did not
+        /// reach
+        #[arg(long, conflicts_with = "from")]
+        resume: bool,
+
+        /// Also look up where each resolver's queries leave for the
+        /// internet: its egress address, ASN and country
+        #[arg(long, env = "DNSTEST_EGRESS", value_parser = BoolishValueParser::new())]
+        egress: bool,
     },
 
     /// DNS污染检测
@@ -154,8 +552,213 @@ pub enum Commands {
         /// Check multiple domains from file
         #[arg(short, long)]
         file: Option<PathBuf>,
+
+        /// Look up RDAP (WHOIS) registration data of mismatched IPs
+        #[arg(long, env = "DNSTEST_WHOIS", value_parser = BoolishValueParser::new())]
+        whois: bool,
+
+        /// Take the reference answer from the Google and Cloudflare DNS
+        /// JSON APIs over HTTPS, for networks that intercept plain DNS
+        #[arg(long, env = "DNSTEST_DOH_JSON", value_parser = BoolishValueParser::new())]
+        doh_json: bool,
+
+        /// Take the reference answer from an oblivious DNS-over-HTTPS target
+        /// through this relay, hiding the domain from the local network
+        /// and the relay
+        #[arg(
+            long,
+            value_name = "URL",
+            env = "DNSTEST_ODOH_RELAY",
+            conflicts_with = "doh_json"
+        )]
+        odoh_relay: Option<String>,
+
+        /// Oblivious DNS target host (default: odoh.cloudflare-dns.com)
+        #[arg(long, value_name = "HOST", env = "DNSTEST_ODOH_TARGET")]
+        odoh_target: Option<String>,
+
+        /// Query the reference resolvers even for domains whose cached
+        /// answer is still within its TTL
+        #[arg(long, env = "DNSTEST_NO_CACHE", value_parser = BoolishValueParser::new())]
+        no_cache: bool,
+
+        /// Seconds to wait for each query of the system and reference
+        /// resolvers, so a dead resolver fails the check quickly
+        #[arg(long, value_name = "SECS", env = "DNSTEST_LOOKUP_TIMEOUT")]
+        lookup_timeout: Option<u64>,
+
+        /// Times to try each query of the system and reference resolvers
+        /// (default: 2)
+        #[arg(long, value_name = "N", env = "DNSTEST_LOOKUP_ATTEMPTS")]
+        lookup_attempts: Option<usize>,
+
+        /// Exit with the polluted status (4) for suspicious results too
+        #[arg(long, conflicts_with = "lenient")]
+        strict: bool,
+
+        /// Exit with status 0 for suspicious results, not 5
+        #[arg(long)]
+        lenient: bool,
+
+        /// Report how the verdict and the returned addresses changed since
+        /// the last check of the domain
+        #[arg(long, env = "DNSTEST_DIFF", value_parser = BoolishValueParser::new())]
+        diff: bool,
+
+        /// Do not record this check in the check history
+        #[arg(long, env = "DNSTEST_NO_HISTORY", value_parser = BoolishValueParser::new())]
+        no_history: bool,
+    },
+
+    /// 生成测试报告
+    ///
+    /// Generate a self-contained HTML report with an interactive latency
+    /// chart, a sortable server table and a pollution summary.
+    #[command(alias = "r")]
+    Report {
+        /// Output HTML file path
+        #[arg(long)]
+        html: PathBuf,
+
+        /// Use speed test results from a JSON file instead of testing
+        #[arg(long)]
+        from: Option<PathBuf>,
+
+        /// DNS list file (JSON format)
+        #[arg(short, long, env = "DNSTEST_DNS_LIST")]
+        file: Option<PathBuf>,
+
+        /// Custom DNS servers (format: [scheme://]IP[:port]#Name)
+        #[arg(long = "dns", env = "DNSTEST_DNS", value_delimiter = ',')]
+        dns_servers: Vec<String>,
+
+        /// Domains to check for pollution
+        #[arg(short, long, default_value = "google.com")]
+        domain: Vec<String>,
+
+        /// Skip the pollution check
+        #[arg(long)]
+        no_check: bool,
+    },
+
+    /// 生成延迟图表
+    ///
+    /// Render speed test results from a JSON file as an SVG or PNG chart:
+    /// a per-server latency bar chart or a latency distribution histogram.
+    Chart {
+        /// Speed test results JSON file (from `speed --format json`)
+        from: PathBuf,
+
+        /// Output image path (.svg or .png)
+        #[arg(short, long, default_value = "latency.svg")]
+        output: PathBuf,
+
+        /// Chart kind (bars, histogram)
+        #[arg(short, long, default_value = "bars")]
+        kind: ChartKind,
     },
 
+    /// 启动HTTP服务
+    ///
+    /// Serve the speed test history over HTTP as a Grafana JSON datasource
+    /// (`/search`, `/query`). Optionally runs a speed test periodically and
+    /// records it, so the server doubles as a measurement daemon.
+    Serve {
+        /// Address to listen on
+        #[arg(
+            short,
+            long,
+            default_value = crate::serve::DEFAULT_LISTEN,
+            env = "DNSTEST_LISTEN"
+        )]
+        listen: SocketAddr,
+
+        /// Run a speed test every N seconds and record it
+        #[arg(short, long, env = "DNSTEST_INTERVAL")]
+        interval: Option<u64>,
+
+        /// DNS list file for periodic tests (JSON format)
+        #[arg(short, long, env = "DNSTEST_DNS_LIST")]
+        file: Option<PathBuf>,
+
+        /// Custom DNS servers for periodic tests (format: [scheme://]IP[:port]#Name)
+        #[arg(long = "dns", env = "DNSTEST_DNS", value_delimiter = ',')]
+        dns_servers: Vec<String>,
+    },
+
+    /// 诊断网络环境
+    ///
+    /// Diagnose the local network environment: ICMP availability, whether
+    /// port-53 traffic is transparently intercepted by the ISP, and whether
+    /// the system resolver redirects names that do not exist.
+    #[command(alias = "d")]
+    Doctor,
+
+    /// 持续监测
+    ///
+    /// Test the servers round after round and show rolling per-server
+    /// statistics over the session (loss, last, average, best, worst,
+    /// standard deviation), like mtr. With --format json, prints one JSON
+    /// line per round.
+    #[command(alias = "m")]
+    Monitor(MonitorArgs),
+
+    /// 路由追踪
+    ///
+    /// Trace the route to a DNS server with per-hop latency, to see where
+    /// along the path latency is introduced. Needs raw ICMP access on
+    /// Linux and macOS.
+    Route(RouteArgs),
+
+    /// 扫描网段中的DNS服务器
+    ///
+    /// Find DNS servers in an address range, e.g. the local network, by
+    /// sending every address a DNS query. Servers that resolve queries can
+    /// be added to the saved DNS lists right away with --add.
+    Scan(ScanArgs),
+
+    /// 检测开放递归解析器
+    ///
+    /// Check whether servers resolve arbitrary names for this host, i.e.
+    /// are open recursive resolvers: what a public resolver should be, and
+    /// what a router or self-hosted server reachable from the internet
+    /// should not.
+    OpenResolver(OpenResolverArgs),
+
+    /// 解析器特性
+    ///
+    /// Probe how resolvers behave beyond latency, one row per resolver:
+    /// whether they cache NXDOMAIN answers, whether they keep them no
+    /// longer than the zone's SOA minimum allows, whether they pass
+    /// their clients' subnet on to authoritative servers (ECS), and
+    /// whether they block ad, malware and adult domains or enforce safe
+    /// search.
+    Features(FeaturesArgs),
+
+    /// 检查域名的NS委派
+    ///
+    /// Check the delegation of a domain: follow referrals from the root to
+    /// the parent zone, then ask every delegated nameserver for the
+    /// domain's NS set. Reports lame and unreachable nameservers and NS
+    /// sets that differ between parent and child.
+    NsCheck(NsCheckArgs),
+
+    /// 检查DNS记录的传播情况
+    ///
+    /// Ask every resolver of the DNS list for a record and report which
+    /// ones already answer with the expected values, e.g. after changing a
+    /// domain's address. Resolvers still answering with the old record
+    /// show how long they keep it cached.
+    Propagate(PropagateArgs),
+
+    /// DNS-over-HTTPS 测速
+    ///
+    /// Benchmark DNS-over-HTTPS endpoints over HTTP/1.1, HTTP/2 and HTTP/3
+    /// separately, to see whether a provider's newer protocol versions are
+    /// actually faster from this network. HTTP/3 needs a curl built with
+    /// HTTP/3 support.
+    Doh(DohArgs),
+
     /// 列出可用的DNS服务器
     ///
     /// List all available DNS servers from the default list or a custom file.
@@ -163,16 +766,87 @@ pub enum Commands {
     #[command(alias = "l")]
     List {
         /// DNS list file
-        #[arg(short, long)]
+        #[arg(short, long, env = "DNSTEST_DNS_LIST")]
         file: Option<PathBuf>,
 
+        /// Show only one of the saved lists: v4 (`dnslist.json`) or v6
+        /// (`dnslist-v6.json`)
+        #[arg(long, value_name = "LIST", conflicts_with = "file")]
+        saved: Option<ListSource>,
+
+        /// Show a built-in server list instead: root (the 13 root servers)
+        /// or tld[:com,net,...] (the nameservers of top-level domains)
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["file", "saved"])]
+        preset: Option<Preset>,
+
         /// Show only IPv4 servers
-        #[arg(long = "ipv4")]
+        #[arg(long = "ipv4", conflicts_with = "ipv6_only")]
         ipv4_only: bool,
 
         /// Show only IPv6 servers
         #[arg(long = "ipv6")]
         ipv6_only: bool,
+
+        /// Show only servers hosted in these countries (e.g. CN,US)
+        #[arg(
+            long,
+            value_name = "CODE",
+            env = "DNSTEST_COUNTRY",
+            value_delimiter = ','
+        )]
+        country: Vec<String>,
+    },
+
+    /// 添加DNS服务器
+    ///
+    /// Add servers to the saved DNS lists. IPv4 servers go to
+    /// `dnslist.json` and IPv6 servers to `dnslist-v6.json`.
+    Add {
+        /// Servers to add (format: [scheme://]IP[:port]#Name)
+        #[arg(required = true, value_name = "IP#NAME")]
+        servers: Vec<String>,
+
+        /// Operator of the servers (e.g. Google)
+        #[arg(long)]
+        provider: Option<String>,
+
+        /// Two-letter country code where the servers are hosted
+        #[arg(long, value_name = "CODE")]
+        country: Option<String>,
+    },
+
+    /// 从保存的列表中删除DNS服务器
+    ///
+    /// Remove servers, by address or name, from whichever saved DNS list
+    /// they are in. An address removes only the server with the same port
+    /// and protocol, e.g. `127.0.0.1:5353` or `tls://1.1.1.1`.
+    Remove {
+        /// Addresses or names of the servers
+        #[arg(required = true, value_name = "SERVER|NAME")]
+        servers: Vec<String>,
+    },
+
+    /// 移除持续失败的DNS服务器
+    ///
+    /// Remove servers that failed the latest speed test runs in a row, as
+    /// recorded in the history, from the saved DNS lists.
+    Prune {
+        /// DNS list file (default: the saved lists)
+        #[arg(short, long, env = "DNSTEST_DNS_LIST")]
+        file: Option<PathBuf>,
+
+        /// Remove servers that failed this many runs in a row
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 3,
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        after: u64,
+
+        /// Only show the servers that would be removed
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// 从网络更新 DNS 列表
@@ -182,27 +856,364 @@ pub enum Commands {
     #[command(alias = "u")]
     Update {
         /// URL to download DNS list from (default: GitHub Pages)
-        #[arg(short, long)]
+        #[arg(short, long, env = "DNSTEST_LIST_URL", conflicts_with = "sources")]
         url: Option<String>,
 
-        /// Output file path (default: dnslist.json in current directory)
+        /// Download these lists in parallel and merge them (repeatable)
+        #[arg(long = "source", value_name = "URL")]
+        sources: Vec<String>,
+
+        /// How merged sources combine with the local list: replace, union
+        #[arg(long, env = "DNSTEST_MERGE", default_value_t, requires = "sources")]
+        merge: MergePolicy,
+
+        /// Output file path (default: dnslist.json in the data directory)
         #[arg(short, long)]
         output: Option<PathBuf>,
     },
 
+    /// 更新 dnstest 程序
+    ///
+    /// Update dnstest itself to the latest GitHub release. The downloaded
+    /// archive is verified against the release's SHA256SUMS before the
+    /// running binary is replaced.
+    SelfUpdate {
+        /// Only check whether a newer release is available
+        #[arg(long)]
+        check: bool,
+
+        /// Reinstall even if already up to date
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// 管理缓存与数据目录
+    ///
+    /// Show or clean the data directory, where history, downloaded DNS
+    /// lists and caches are stored.
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
+    /// 对比两次测速
+    ///
+    /// Compare two speed test runs server by server. Per-ping samples are
+    /// tested with Welch's t-test, so each change is labeled as
+    /// significant or noise. Without files, the last two runs in the
+    /// history are compared.
+    Compare {
+        /// Older results JSON file (from `speed --format json`)
+        old: Option<PathBuf>,
+
+        /// Newer results JSON file
+        #[arg(requires = "old")]
+        new: Option<PathBuf>,
+    },
+
+    /// 查看测速历史
+    ///
+    /// Inspect the recorded speed test history.
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+
     /// 导出DNS列表
     ///
     /// Export the merged DNS server list to a JSON file.
-    /// Includes both IPv4 and IPv6 servers by default.
+    /// Includes both IPv4 and IPv6 servers by default; `--ipv6 split`
+    /// writes them to `dnslist.json` and `dnslist-v6.json` like the saved
+    /// lists.
     #[command(alias = "e")]
     Export {
         /// Output file path
         #[arg(short, long, default_value = "dnslist.json")]
         output: PathBuf,
 
-        /// Include IPv6 servers in export
-        #[arg(long = "ipv6")]
-        include_ipv6: bool,
+        /// IPv6 servers: include, split (into a `-v6` file), exclude
+        #[arg(
+            long,
+            value_name = "MODE",
+            default_value_t,
+            num_args = 0..=1,
+            default_missing_value = "include"
+        )]
+        ipv6: Ipv6Export,
+    },
+}
+
+/// Options of the `monitor` command.
+#[derive(Debug, Clone, Args)]
+pub struct MonitorArgs {
+    /// DNS list file (JSON format)
+    #[arg(short, long, env = "DNSTEST_DNS_LIST")]
+    pub file: Option<PathBuf>,
+
+    /// Custom DNS servers (format: [scheme://]IP[:port]#Name)
+    #[arg(long = "dns", env = "DNSTEST_DNS", value_delimiter = ',')]
+    pub dns_servers: Vec<String>,
+
+    /// Number of pings per server in each round (default: 3)
+    #[arg(short, long, env = "DNSTEST_COUNT")]
+    pub count: Option<usize>,
+
+    /// Timeout in seconds (default: 5)
+    #[arg(short, long, env = "DNSTEST_TIMEOUT")]
+    pub timeout: Option<u64>,
+
+    /// Timeout in milliseconds, for finer control than `--timeout`
+    #[arg(
+        long,
+        value_name = "MS",
+        env = "DNSTEST_TIMEOUT_MS",
+        conflicts_with = "timeout",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub timeout_ms: Option<u64>,
+
+    /// Number of servers tested at the same time (default: 1)
+    #[arg(short = 'j', long, env = "DNSTEST_CONCURRENCY")]
+    pub concurrency: Option<usize>,
+
+    /// Record type queried where ICMP is not permitted and latency is
+    /// measured with DNS queries instead (default: NS)
+    #[arg(
+        long,
+        value_name = "TYPE",
+        env = "DNSTEST_QTYPE",
+        value_parser = crate::dns::query::parse_record_type
+    )]
+    pub qtype: Option<RecordType>,
+
+    /// Seconds between the starts of two rounds (default: 10)
+    #[arg(short, long, env = "DNSTEST_INTERVAL")]
+    pub interval: Option<u64>,
+
+    /// Stop after this many rounds (default: run until interrupted)
+    #[arg(short = 'n', long)]
+    pub rounds: Option<u64>,
+
+    /// Alert when a server gets this many milliseconds slower than its
+    /// baseline (overrides `[alerts] latency_ms`)
+    #[arg(long, value_name = "MS", env = "DNSTEST_ALERT_LATENCY")]
+    pub alert_latency: Option<f64>,
+
+    /// Alert when a server's loss grows by this ratio, 0.0 - 1.0
+    /// (overrides `[alerts] loss`)
+    #[arg(long, value_name = "RATIO", env = "DNSTEST_ALERT_LOSS")]
+    pub alert_loss: Option<f64>,
+
+    /// Do not record the rounds in the history
+    #[arg(long, env = "DNSTEST_NO_HISTORY", value_parser = BoolishValueParser::new())]
+    pub no_history: bool,
+
+    /// Remove servers that failed this many rounds in a row from the DNS
+    /// list file and stop testing them
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with = "dns_servers",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub prune_after: Option<u64>,
+}
+
+/// Options of the `route` command.
+#[derive(Debug, Clone, Args)]
+pub struct RouteArgs {
+    /// Server address to trace
+    pub target: IpAddr,
+
+    /// Maximum number of hops (default: 30)
+    #[arg(short, long)]
+    pub max_hops: Option<u8>,
+
+    /// Probes sent to each hop (default: 3)
+    #[arg(short, long)]
+    pub probes: Option<usize>,
+
+    /// Timeout in seconds for each probe (default: 2)
+    #[arg(short, long)]
+    pub timeout: Option<u64>,
+}
+
+/// Options of the `scan` command.
+#[derive(Debug, Clone, Args)]
+pub struct ScanArgs {
+    /// Address range in CIDR notation, e.g. 192.168.1.0/24
+    pub range: String,
+
+    /// Timeout in milliseconds for each address (default: 500)
+    #[arg(short, long, value_name = "MS")]
+    pub timeout_ms: Option<u64>,
+
+    /// Addresses queried at the same time (default: 64)
+    #[arg(short = 'j', long)]
+    pub concurrency: Option<usize>,
+
+    /// Port to query (default: 53)
+    #[arg(short, long)]
+    pub port: Option<u16>,
+
+    /// Add the found resolvers to the saved DNS lists
+    #[arg(long)]
+    pub add: bool,
+}
+
+/// Options of the `open-resolver` command.
+#[derive(Debug, Clone, Args)]
+pub struct OpenResolverArgs {
+    /// Servers to check (format: IP[:port])
+    #[arg(required = true, value_name = "IP")]
+    pub servers: Vec<String>,
+
+    /// Timeout in seconds for each query (default: 2)
+    #[arg(short, long)]
+    pub timeout: Option<u64>,
+
+    /// Servers checked at the same time (default: 32)
+    #[arg(short = 'j', long)]
+    pub concurrency: Option<usize>,
+}
+
+/// Options of the `features` command.
+#[derive(Debug, Clone, Args)]
+pub struct FeaturesArgs {
+    /// DNS list file (JSON format)
+    #[arg(short, long, env = "DNSTEST_DNS_LIST")]
+    pub file: Option<PathBuf>,
+
+    /// Custom DNS servers (format: IP[:port]#Name)
+    #[arg(long = "dns", env = "DNSTEST_DNS", value_delimiter = ',')]
+    pub dns_servers: Vec<String>,
+
+    /// Timeout in seconds for each query (default: 3)
+    #[arg(short, long)]
+    pub timeout: Option<u64>,
+
+    /// Resolvers probed at the same time (default: 8)
+    #[arg(short = 'j', long)]
+    pub concurrency: Option<usize>,
+}
+
+/// Options of the `ns-check` command.
+#[derive(Debug, Clone, Args)]
+pub struct NsCheckArgs {
+    /// Domain to check
+    pub domain: String,
+
+    /// Timeout in seconds for each query (default: 3)
+    #[arg(short, long)]
+    pub timeout: Option<u64>,
+
+    /// Resolver for the addresses of nameservers without glue
+    /// (default: 1.1.1.1)
+    #[arg(long, value_name = "IP")]
+    pub resolver: Option<IpAddr>,
+}
+
+/// Options of the `propagate` command.
+#[derive(Debug, Clone, Args)]
+pub struct PropagateArgs {
+    /// Domain whose record changed
+    pub domain: String,
+
+    /// Values the record should have now, e.g. an address
+    #[arg(
+        short,
+        long,
+        required = true,
+        value_delimiter = ',',
+        value_name = "VALUE"
+    )]
+    pub expect: Vec<String>,
+
+    /// Record type to query (default: A)
+    #[arg(
+        long = "type",
+        value_name = "TYPE",
+        value_parser = crate::dns::query::parse_record_type
+    )]
+    pub record_type: Option<RecordType>,
+
+    /// DNS list file (JSON format)
+    #[arg(short, long, env = "DNSTEST_DNS_LIST")]
+    pub file: Option<PathBuf>,
+
+    /// Custom DNS servers (format: IP[:port]#Name)
+    #[arg(long = "dns", env = "DNSTEST_DNS", value_delimiter = ',')]
+    pub dns_servers: Vec<String>,
+
+    /// Timeout in seconds for each query (default: 3)
+    #[arg(short, long)]
+    pub timeout: Option<u64>,
+
+    /// Resolvers queried at the same time (default: 16)
+    #[arg(short = 'j', long)]
+    pub concurrency: Option<usize>,
+}
+
+/// Options of the `doh` command.
+#[derive(Debug, Clone, Args)]
+pub struct DohArgs {
+    /// Endpoint URLs (default: Google and Cloudflare)
+    #[arg(value_name = "URL")]
+    pub urls: Vec<String>,
+
+    /// HTTP versions to compare (default: 1.1,2,3)
+    #[arg(
+        long,
+        value_name = "VERSION",
+        env = "DNSTEST_HTTP",
+        value_delimiter = ','
+    )]
+    pub http: Vec<HttpVersion>,
+
+    /// Queries per endpoint and version (default: 3)
+    #[arg(short, long)]
+    pub count: Option<usize>,
+
+    /// Timeout in seconds for each query (default: 5)
+    #[arg(short, long)]
+    pub timeout: Option<u64>,
+
+    /// Domain to query (default: example.com)
+    #[arg(short, long)]
+    pub domain: Option<String>,
+}
+
+/// Actions of the `cache` command.
+#[derive(Debug, Subcommand)]
+pub enum CacheAction {
+    /// 显示数据目录及占用空间
+    Info,
+
+    /// 清理缓存
+    ///
+    /// Remove disposable caches. History and downloaded DNS lists are kept
+    /// unless --all is given.
+    Clean {
+        /// Also remove the history and downloaded DNS lists
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+/// Actions of the `history` command.
+#[derive(Debug, Subcommand)]
+pub enum HistoryAction {
+    /// 显示某个服务器的延迟趋势
+    ///
+    /// Show the latency of one server across recorded runs as a sparkline
+    /// and a table, to spot regressions, e.g. after an ISP change.
+    Show {
+        /// Server IP address or name
+        server: String,
+
+        /// Only show the most recent N runs
+        #[arg(short = 'n', long)]
+        last: Option<usize>,
     },
 }
 
@@ -239,6 +1250,11 @@ mod tests {
         assert_eq!("json".parse::<OutputFormat>(), Ok(OutputFormat::Json));
         assert_eq!("csv".parse::<OutputFormat>(), Ok(OutputFormat::Csv));
         assert_eq!("tsv".parse::<OutputFormat>(), Ok(OutputFormat::Tsv));
+        assert_eq!("yml".parse::<OutputFormat>(), Ok(OutputFormat::Yaml));
+        assert_eq!(
+            "openmetrics".parse::<OutputFormat>(),
+            Ok(OutputFormat::OpenMetrics)
+        );
         assert!("invalid".parse::<OutputFormat>().is_err());
     }
 
@@ -248,10 +1264,68 @@ mod tests {
         assert_eq!(OutputFormat::Json.to_string(), "json");
         assert_eq!(OutputFormat::Csv.to_string(), "csv");
         assert_eq!(OutputFormat::Tsv.to_string(), "tsv");
+        assert_eq!(OutputFormat::Yaml.to_string(), "yaml");
+        assert_eq!(OutputFormat::OpenMetrics.to_string(), "openmetrics");
     }
 
     #[test]
     fn test_output_format_default() {
         assert_eq!(OutputFormat::default(), OutputFormat::Table);
     }
+
+    #[test]
+    fn test_export_ipv6_mode() {
+        let ipv6 = |args: &[&str]| match Cli::parse_from(args).command {
+            Some(Commands::Export { ipv6, .. }) => ipv6,
+            _ => unreachable!(),
+        };
+        assert_eq!(ipv6(&["dnstest", "export"]), Ipv6Export::Include);
+        // The bare flag of older versions still includes them
+        assert_eq!(ipv6(&["dnstest", "export", "--ipv6"]), Ipv6Export::Include);
+        assert_eq!(
+            ipv6(&["dnstest", "export", "--ipv6", "split"]),
+            Ipv6Export::Split
+        );
+        assert!("both".parse::<Ipv6Export>().is_err());
+    }
+
+    #[test]
+    fn test_dns_list_delimiter() {
+        let cli = Cli::try_parse_from([
+            "dnstest",
+            "speed",
+            "--dns",
+            "1.1.1.1#Cloudflare,8.8.8.8",
+            "--dns",
+            "9.9.9.9",
+        ])
+        .unwrap();
+        let Some(Commands::Speed { dns_servers, .. }) = cli.command else {
+            panic!("expected speed command");
+        };
+        assert_eq!(dns_servers, ["1.1.1.1#Cloudflare", "8.8.8.8", "9.9.9.9"]);
+        assert_eq!(cli.format, None);
+    }
+
+    #[test]
+    fn test_shuffle_flags() {
+        let shuffle = |args: &[&str]| match Cli::try_parse_from(args).map(|cli| cli.command) {
+            Ok(Some(Commands::Speed {
+                shuffle,
+                shuffle_seed,
+                ..
+            })) => Some((shuffle, shuffle_seed)),
+            _ => None,
+        };
+        assert_eq!(shuffle(&["dnstest", "speed"]), Some((false, None)));
+        assert_eq!(
+            shuffle(&["dnstest", "speed", "--shuffle"]),
+            Some((true, None))
+        );
+        assert_eq!(
+            shuffle(&["dnstest", "speed", "--shuffle-seed", "42"]),
+            Some((false, Some(42)))
+        );
+        assert_eq!(shuffle(&["dnstest", "speed", "--shuffle", "42"]), None);
+    }
 }
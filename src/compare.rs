@@ -0,0 +1,202 @@
+//! Comparison of two speed test runs.
+//!
+//! A few milliseconds between two runs is often just jitter. For each
+//! server tested in both runs, [`compare`] runs Welch's t-test on the
+//! individual ping round-trip times and labels the change as significant
+//! (at 95% confidence) or noise, instead of reporting raw deltas only.
+
+use crate::dns::{DnsServer, SpeedTestResult};
+use serde::Serialize;
+
+/// Two-sided 95% critical values of Student's t distribution for 1 to 30
+/// degrees of freedom.
+const T_CRITICAL_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// How a server's latency changed between two runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// Significantly faster in the new run
+    Faster,
+    /// Significantly slower in the new run
+    Slower,
+    /// The difference is within the noise of the samples
+    Noise,
+    /// Fewer than two ping samples on a side, e.g. results saved by older
+    /// versions or a server that stopped answering
+    Insufficient,
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Faster => write!(f, "faster"),
+            Self::Slower => write!(f, "slower"),
+            Self::Noise => write!(f, "noise"),
+            Self::Insufficient => write!(f, "insufficient"),
+        }
+    }
+}
+
+/// Change of one server between two runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub server: DnsServer,
+    /// Average latency in the old run
+    pub old_ms: Option<f64>,
+    /// Average latency in the new run
+    pub new_ms: Option<f64>,
+    /// `new_ms - old_ms`
+    pub delta_ms: Option<f64>,
+    /// Welch's t statistic, if it could be computed
+    pub t: Option<f64>,
+    pub verdict: Verdict,
+}
+
+/// Compare the servers tested in both runs, in the new run's order.
+#[must_use]
+pub fn compare(old: &[SpeedTestResult], new: &[SpeedTestResult]) -> Vec<Comparison> {
+    new.iter()
+        .filter_map(|new| {
+            let old = old.iter().find(|old| old.server.ip == new.server.ip)?;
+            let delta_ms = old.latency_ms.zip(new.latency_ms).map(|(o, n)| n - o);
+            let (t, verdict) = match welch(&old.samples_ms, &new.samples_ms) {
+                Some(test) if test.significant() && test.t < 0.0 => (Some(test.t), Verdict::Faster),
+                Some(test) if test.significant() => (Some(test.t), Verdict::Slower),
+                Some(test) => (Some(test.t), Verdict::Noise),
+                None => (None, Verdict::Insufficient),
+            };
+            Some(Comparison {
+                server: new.server.clone(),
+                old_ms: old.latency_ms,
+                new_ms: new.latency_ms,
+                delta_ms,
+                t: t.filter(|t| t.is_finite()),
+                verdict,
+            })
+        })
+        .collect()
+}
+
+/// Result of Welch's t-test.
+#[derive(Debug, Clone, Copy)]
+struct TTest {
+    /// t statistic, positive when the second sample is larger
+    t: f64,
+    /// Welch-Satterthwaite degrees of freedom
+    df: f64,
+}
+
+impl TTest {
+    /// Whether the means differ at 95% confidence.
+    fn significant(self) -> bool {
+        self.t.abs() > t_critical_95(self.df)
+    }
+}
+
+/// Welch's t-test of `b` against `a`, `None` with fewer than two samples
+/// on a side.
+#[allow(clippy::cast_precision_loss)]
+fn welch(a: &[f64], b: &[f64]) -> Option<TTest> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let stats = |xs: &[f64]| {
+        let n = xs.len() as f64;
+        let mean = xs.iter().sum::<f64>() / n;
+        let variance = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        (n, mean, variance / n)
+    };
+    let (na, mean_a, se2_a) = stats(a);
+    let (nb, mean_b, se2_b) = stats(b);
+    let se2 = se2_a + se2_b;
+
+    if se2 == 0.0 {
+        // Identical samples on both sides: any difference is exact
+        let t = match (mean_b - mean_a).partial_cmp(&0.0) {
+            Some(std::cmp::Ordering::Greater) => f64::INFINITY,
+            Some(std::cmp::Ordering::Less) => f64::NEG_INFINITY,
+            _ => 0.0,
+        };
+        return Some(TTest {
+            t,
+            df: na + nb - 2.0,
+        });
+    }
+
+    Some(TTest {
+        t: (mean_b - mean_a) / se2.sqrt(),
+        df: se2.powi(2) / (se2_a.powi(2) / (na - 1.0) + se2_b.powi(2) / (nb - 1.0)),
+    })
+}
+
+/// Two-sided 95% critical t value, rounding the degrees of freedom down.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn t_critical_95(df: f64) -> f64 {
+    match df.floor() as usize {
+        0 => f64::INFINITY,
+        df @ 1..=30 => T_CRITICAL_95[df - 1],
+        31..=40 => 2.021,
+        41..=60 => 2.000,
+        61..=120 => 1.980,
+        _ => 1.960,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(ip: &str, samples: &[f64]) -> SpeedTestResult {
+        let avg = samples.iter().sum::<f64>() / samples.len() as f64;
+        SpeedTestResult::success(DnsServer::new(ip, ip), avg, 0.0).with_samples(samples.to_vec())
+    }
+
+    #[test]
+    fn test_welch() {
+        // Means 20.8 and 23.0, variances 1.7 and 1.0
+        let a = [19.0, 21.0, 20.0, 22.0, 22.0];
+        let b = [24.0, 22.0, 23.0, 22.0, 24.0];
+        let test = welch(&a, &b).unwrap();
+        assert!((test.t - 2.2 / 0.54f64.sqrt()).abs() < 1e-9);
+        assert!((test.df - 0.2916 / 0.0389).abs() < 1e-9);
+        assert!(test.significant());
+
+        assert!(welch(&[1.0], &b).is_none());
+        assert!(!welch(&[10.0, 10.0], &[10.0, 10.0]).unwrap().significant());
+        assert!(welch(&[10.0, 10.0], &[12.0, 12.0]).unwrap().significant());
+    }
+
+    #[test]
+    fn test_t_critical() {
+        assert!((t_critical_95(2.7) - 4.303).abs() < f64::EPSILON);
+        assert!((t_critical_95(1000.0) - 1.960).abs() < f64::EPSILON);
+        assert!(t_critical_95(0.5).is_infinite());
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = vec![
+            result("1.1.1.1", &[10.0, 11.0, 10.5]),
+            result("8.8.8.8", &[30.0, 31.0, 29.0]),
+            result("9.9.9.9", &[20.0, 21.0, 19.0]),
+        ];
+        let new = vec![
+            result("8.8.8.8", &[20.0, 21.0, 19.0]),
+            result("1.1.1.1", &[10.2, 11.3, 10.1]),
+            SpeedTestResult::success(DnsServer::new("Old", "9.9.9.9"), 20.0, 0.0),
+            result("223.5.5.5", &[5.0, 5.0, 5.0]),
+        ];
+
+        let comparisons = compare(&old, &new);
+        assert_eq!(comparisons.len(), 3);
+        assert_eq!(comparisons[0].verdict, Verdict::Faster);
+        assert_eq!(comparisons[0].delta_ms, Some(-10.0));
+        assert_eq!(comparisons[1].verdict, Verdict::Noise);
+        assert_eq!(comparisons[2].verdict, Verdict::Insufficient);
+    }
+}
@@ -3,10 +3,70 @@
 //! This module provides functionality to load DNS server lists
 //! from JSON files, command-line arguments, or default locations.
 
-use crate::dns::types::{DnsList, DnsServer};
+use crate::dns::types::{DnsList, DnsProtocol, DnsServer, SpeedTestResult};
 use crate::error::{Error, Result};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::path::Path;
 
+/// Default download URL of the IPv4 DNS list.
+pub const DEFAULT_LIST_URL: &str = "https://wjsoj.github.io/dnstest/dnslist.json";
+
+/// Default download URL of the IPv6 DNS list.
+pub const DEFAULT_LIST_V6_URL: &str = "https://wjsoj.github.io/dnstest/dnslist-v6.json";
+
+/// File name of the IPv4 DNS list.
+pub const LIST_FILE: &str = "dnslist.json";
+
+/// File name of the IPv6 DNS list.
+pub const LIST_V6_FILE: &str = "dnslist-v6.json";
+
+/// How `dnstest update --source` combines downloaded lists with the
+/// local list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Replace the local list with the merged sources
+    #[default]
+    Replace,
+    /// Keep the local list and add servers from the sources; local entries
+    /// win for addresses present in both
+    Union,
+}
+
+impl MergePolicy {
+    /// Get all available merge policy names.
+    #[must_use]
+    pub fn names() -> &'static [&'static str] {
+        &["replace", "union"]
+    }
+}
+
+impl std::str::FromStr for MergePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "replace" => Ok(Self::Replace),
+            "union" => Ok(Self::Union),
+            _ => Err(format!(
+                "Unknown merge policy: {}. Valid options are: {:?}",
+                s,
+                Self::names()
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for MergePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Replace => write!(f, "replace"),
+            Self::Union => write!(f, "union"),
+        }
+    }
+}
+
 /// DNS list configuration loader.
 ///
 /// Provides various methods to load and merge DNS server lists
@@ -34,36 +94,34 @@ impl ConfigLoader {
     /// ```
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<DnsList> {
         let content = std::fs::read_to_string(path.as_ref())?;
-        let list: DnsList = serde_json::from_str(&content)?;
+        let mut list: DnsList = serde_json::from_str(&content)?;
+        list.normalize();
+        list.disambiguate_names();
         Ok(list)
     }
 
     /// Load DNS list from the default location.
     ///
     /// Searches in the following order:
-    /// 1. `$CONFIG_DIR/dnstest/dnslist.json`
-    /// 2. `dnslist.json` in current directory
+    /// 1. `$DATA_DIR/dnstest/dnslist.json` (downloaded by `dnstest update`)
+    /// 2. `$CONFIG_DIR/dnstest/dnslist.json`
+    /// 3. `dnslist.json` in current directory
     ///
     /// # Errors
     ///
     /// Returns an error if no default file is found or cannot be parsed.
     #[allow(dead_code)]
     pub fn load_default() -> Result<DnsList> {
-        Self::load_from_file(
-            dirs::config_dir()
-                .unwrap_or_else(|| std::path::PathBuf::from("."))
-                .join("dnstest")
-                .join("dnslist.json"),
-        )
-        .or_else(|_| {
+        Self::load_from_file(Self::list_path(LIST_FILE)).or_else(|_| {
             // Try to load from current directory
-            Self::load_from_file("dnslist.json")
+            Self::load_from_file(LIST_FILE)
         })
     }
 
-    /// Load both IPv4 and IPv6 DNS lists from user config directory.
+    /// Load both IPv4 and IPv6 DNS lists.
     ///
-    /// Loads from `~/.config/dnstest/dnslist.json` and `dnslist-v6.json`.
+    /// Loads `dnslist.json` and `dnslist-v6.json` from the data directory,
+    /// where `dnstest update` saves them, or from the config directory.
     ///
     /// # Errors
     ///
@@ -76,23 +134,12 @@ impl ConfigLoader {
     /// let merged = ConfigLoader::merge(lists);
     /// ```
     pub fn load_all() -> Result<Vec<DnsList>> {
-        // Get user config directory
-        let config_dir = dirs::config_dir()
-            .unwrap_or_else(|| std::path::PathBuf::from("."))
-            .join("dnstest");
-
         let mut lists = Vec::new();
 
-        // Try to load IPv4 list from config directory
-        let ipv4_path = config_dir.join("dnslist.json");
-        if let Ok(list) = Self::load_from_file(&ipv4_path) {
-            lists.push(list);
-        }
-
-        // Try to load IPv6 list from config directory
-        let ipv6_path = config_dir.join("dnslist-v6.json");
-        if let Ok(list) = Self::load_from_file(&ipv6_path) {
-            lists.push(list);
+        for name in [LIST_FILE, LIST_V6_FILE] {
+            if let Ok(list) = Self::load_from_file(Self::list_path(name)) {
+                lists.push(list);
+            }
         }
 
         if lists.is_empty() {
@@ -112,10 +159,81 @@ impl ConfigLoader {
             .join("dnstest")
     }
 
+    /// Get the data directory path, for history, downloaded lists and
+    /// other generated state.
+    #[must_use]
+    pub fn data_dir() -> std::path::PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| std::path::PathBuf::from("."))
+            .join("dnstest")
+    }
+
+    /// Get the cache directory path, for files that can be deleted at any
+    /// time.
+    #[must_use]
+    pub fn cache_dir() -> std::path::PathBuf {
+        Self::data_dir().join("cache")
+    }
+
+    /// Path of the IPv6 list that goes with the list at `path`, e.g.
+    /// `dnslist-v6.json` for `dnslist.json`.
+    #[must_use]
+    pub fn v6_path(path: &Path) -> std::path::PathBuf {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = match path.extension() {
+            Some(ext) => format!("{stem}-v6.{}", ext.to_string_lossy()),
+            None => format!("{stem}-v6"),
+        };
+        path.with_file_name(name)
+    }
+
+    /// Path of a DNS list file: the downloaded copy in the data directory,
+    /// or a hand-placed one in the config directory.
+    #[must_use]
+    pub fn list_path(name: &str) -> std::path::PathBuf {
+        let downloaded = Self::data_dir().join(name);
+        let configured = Self::config_dir().join(name);
+        if !downloaded.exists() && configured.exists() {
+            configured
+        } else {
+            downloaded
+        }
+    }
+
+    /// Move a file written by older versions from the config directory to
+    /// the data directory.
+    ///
+    /// Failures are logged and otherwise ignored: the old file stays where
+    /// it was and a new one is started.
+    pub fn migrate_to_data_dir(name: &str) {
+        let old = Self::config_dir().join(name);
+        let new = Self::data_dir().join(name);
+        if new.exists() || !old.exists() {
+            return;
+        }
+        let moved = std::fs::create_dir_all(Self::data_dir()).and_then(|()| {
+            std::fs::rename(&old, &new).or_else(|_| {
+                // Different file systems
+                std::fs::copy(&old, &new)?;
+                std::fs::remove_file(&old)
+            })
+        });
+        match moved {
+            Ok(()) => tracing::info!("Moved {} to {}", old.display(), new.display()),
+            Err(e) => tracing::warn!("Failed to move {} to data directory: {e}", old.display()),
+        }
+    }
+
     /// Merge multiple DNS lists into one.
     ///
     /// Combines all servers from the input lists and removes duplicates
-    /// based on IP address.
+    /// based on address, port and protocol (see [`DnsServer::spec`]),
+    /// keeping the first occurrence. Addresses are normalized first, so
+    /// differently written IPv6 addresses match.
+    /// Servers from different lists that share a name are numbered.
     ///
     /// # Arguments
     ///
@@ -135,55 +253,730 @@ impl ConfigLoader {
     #[must_use]
     pub fn merge(lists: Vec<DnsList>) -> DnsList {
         let mut servers = Vec::new();
-        for list in lists {
+        for mut list in lists {
+            list.normalize();
             servers.extend(list.servers);
         }
-        // Remove duplicates by IP
-        servers.sort_by(|a, b| a.ip.cmp(&b.ip));
-        servers.dedup_by(|a, b| a.ip == b.ip);
-        DnsList { servers }
+        // Remove duplicates by spec; the sort is stable, so the first
+        // occurrence is kept
+        servers.sort_by_cached_key(|server| (server.ip.clone(), server.spec()));
+        servers.dedup_by(|a, b| a.spec() == b.spec());
+        let mut list = DnsList { servers };
+        list.disambiguate_names();
+        list
+    }
+
+    /// Check that a downloaded list is usable.
+    ///
+    /// Normalize the list first (see [`DnsList::normalize`]), so addresses
+    /// with surrounding whitespace are accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the list is empty or an address does not parse.
+    pub fn validate(list: &DnsList) -> Result<()> {
+        if list.is_empty() {
+            return Err(Error::parse("DNS list is empty"));
+        }
+        if let Some(server) = list.servers.iter().find(|s| s.ip_addr().is_none()) {
+            return Err(Error::parse(format!(
+                "Invalid IP address for {}: {}",
+                server.name, server.ip
+            )));
+        }
+        Ok(())
     }
 
     /// Create a custom DNS list from command-line arguments.
     ///
+    /// Each server is given as `[scheme://]address[:port][#Name]`:
+    ///
+    /// * `8.8.8.8` or `2001:4860:4860::8888` - plain DNS on port 53
+    /// * `127.0.0.1:5353#Local` - another port
+    /// * `[2001:db8::1]:5353#Local` - IPv6 with a port, in brackets
+    /// * `tls://1.1.1.1#Cloudflare` - DNS over TLS, port 853 by default
+    /// * `https://1.1.1.1/dns-query` - DNS over HTTPS, port 443 by default
+    /// * `192.168.1.0/28#LAN` - every host of a range, see [`expand_cidr`]
+    /// * `192.168.1.0/28:5353` - every host of a range on another port
+    ///
+    /// Without a name the server is named after its spec.
+    ///
     /// # Arguments
     ///
-    /// * `dns_servers` - Vector of strings in format "IP#Name"
+    /// * `dns_servers` - Server specs as above
     ///
     /// # Errors
     ///
-    /// Returns an error if any IP address is invalid.
+    /// Returns an error if any spec is invalid, or if the ranges expand to
+    /// more than [`MAX_RANGE_HOSTS`] addresses together.
     ///
     /// # Example
     ///
     /// ```ignore
-    /// let args = vec!["8.8.8.8#Google".to_string(), "1.1.1.1#Cloudflare".to_string()];
+    /// let args = vec!["8.8.8.8#Google".to_string(), "tls://1.1.1.1#Cloudflare".to_string()];
     /// let list = ConfigLoader::from_args(args)?;
     /// ```
     pub fn from_args(dns_servers: Vec<String>) -> Result<DnsList> {
-        let mut servers = Vec::new();
-        for s in dns_servers {
-            let parts: Vec<&str> = s.splitn(2, '#').collect();
-            let ip = parts[0].trim().to_string();
-            let name = parts
-                .get(1)
-                .map(|s| s.trim().to_string())
-                .unwrap_or_else(|| ip.clone());
-
-            // Validate IP address
-            if ip.parse::<std::net::IpAddr>().is_err() {
-                return Err(Error::Parse(format!("Invalid IP address: {ip}")));
+        let servers = dns_servers
+            .iter()
+            .map(|spec| parse_spec(spec))
+            .collect::<Result<Vec<_>>>()?;
+        let range_hosts: usize = servers
+            .iter()
+            .map(Vec::len)
+            .filter(|&hosts| hosts > 1)
+            .sum();
+        if range_hosts > MAX_RANGE_HOSTS {
+            return Err(Error::parse(format!(
+                "Ranges expand to more than {MAX_RANGE_HOSTS} addresses in total"
+            )));
+        }
+        let servers = servers.concat();
+        let mut list = DnsList { servers };
+        list.disambiguate_names();
+        Ok(list)
+    }
+
+    /// Record measured latencies and statuses in a DNS list file.
+    ///
+    /// Sets `delay` and `status` of the servers in the file that `results`
+    /// tested, matched by address, port and protocol (see
+    /// [`DnsServer::spec`]), and keeps everything else in the file
+    /// as it was. The file is replaced atomically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not a DNS list, or
+    /// cannot be written.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of updated servers.
+    pub fn save_results<P: AsRef<Path>>(path: P, results: &[SpeedTestResult]) -> Result<usize> {
+        let by_spec: HashMap<String, &SpeedTestResult> = results
+            .iter()
+            .map(|r| (canonical_spec(&r.server), r))
+            .collect();
+        edit_list(path.as_ref(), |servers| {
+            let mut updated = 0;
+            for server in servers.iter_mut().filter_map(Value::as_object_mut) {
+                let Some(result) = entry_spec(server).and_then(|spec| by_spec.get(&spec)) else {
+                    continue;
+                };
+                server.insert("delay".into(), result.latency_ms.into());
+                server.insert("status".into(), serde_json::to_value(result.status())?);
+                updated += 1;
+            }
+            Ok(updated)
+        })
+    }
+
+    /// Append servers to a DNS list file, creating the file if missing.
+    ///
+    /// Servers already in the file, with the same address, port and
+    /// protocol (see [`DnsServer::spec`]), are skipped, and
+    /// everything else in the file is kept as it was. The file is replaced
+    /// atomically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not a DNS list, or
+    /// cannot be written.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of added servers.
+    pub fn add_servers<P: AsRef<Path>>(path: P, servers: &[DnsServer]) -> Result<usize> {
+        let path = path.as_ref();
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, "{\"list\": []}\n")?;
+        }
+        edit_list(path, |entries| {
+            let mut present: HashSet<String> = entries
+                .iter()
+                .filter_map(Value::as_object)
+                .filter_map(entry_spec)
+                .collect();
+            let mut added = 0;
+            for server in servers {
+                if present.insert(canonical_spec(server)) {
+                    entries.push(serde_json::to_value(server)?);
+                    added += 1;
+                }
+            }
+            Ok(added)
+        })
+    }
+
+    /// Remove servers from a DNS list file by address, whatever their
+    /// port and protocol; see [`remove_specs`](Self::remove_specs) to
+    /// remove only one of them.
+    ///
+    /// Keeps everything else in the file as it was. The file is replaced
+    /// atomically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not a DNS list, or
+    /// cannot be written.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of removed servers.
+    pub fn remove_servers<P: AsRef<Path>>(path: P, ips: &HashSet<String>) -> Result<usize> {
+        edit_list(path.as_ref(), |servers| {
+            let before = servers.len();
+            servers.retain(|server| {
+                server
+                    .as_object()
+                    .and_then(entry_ip)
+                    .map_or(true, |ip| !ips.contains(&ip))
+            });
+            Ok(before - servers.len())
+        })
+    }
+
+    /// Remove servers from a DNS list file by [`DnsServer::spec`], so that
+    /// `127.0.0.1:5353` is removed but `127.0.0.1` kept.
+    ///
+    /// Keeps everything else in the file as it was. The file is replaced
+    /// atomically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not a DNS list, or
+    /// cannot be written.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of removed servers.
+    pub fn remove_specs<P: AsRef<Path>>(path: P, specs: &HashSet<String>) -> Result<usize> {
+        edit_list(path.as_ref(), |servers| {
+            let before = servers.len();
+            servers.retain(|server| {
+                server
+                    .as_object()
+                    .and_then(entry_spec)
+                    .map_or(true, |spec| !specs.contains(&spec))
+            });
+            Ok(before - servers.len())
+        })
+    }
+
+    /// Apply edits to servers of a DNS list file.
+    ///
+    /// Each edit pairs a server as loaded with the server it became. The
+    /// entry with the loaded spec (address, port and protocol, see
+    /// [`DnsServer::spec`]) takes the fields that differ between
+    /// the two, so names numbered when lists were merged stay as written
+    /// unless renamed. Everything else in the file is kept as it was. The
+    /// file is replaced atomically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not a DNS list, or
+    /// cannot be written.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of updated servers.
+    pub fn update_servers<P: AsRef<Path>>(
+        path: P,
+        edits: &[(DnsServer, DnsServer)],
+    ) -> Result<usize> {
+        let mut changes = HashMap::new();
+        for (loaded, edited) in edits {
+            let (Value::Object(before), Value::Object(after)) =
+                (serde_json::to_value(loaded)?, serde_json::to_value(edited)?)
+            else {
+                continue;
+            };
+            let mut fields: Vec<(String, Option<Value>)> = after
+                .iter()
+                .filter(|(key, value)| before.get(*key) != Some(value))
+                .map(|(key, value)| (key.clone(), Some(value.clone())))
+                .collect();
+            fields.extend(
+                before
+                    .keys()
+                    .filter(|key| !after.contains_key(*key))
+                    .map(|key| (key.clone(), None)),
+            );
+            if !fields.is_empty() {
+                changes.insert(loaded.spec(), fields);
+            }
+        }
+
+        edit_list(path.as_ref(), |servers| {
+            let mut updated = 0;
+            for server in servers.iter_mut().filter_map(Value::as_object_mut) {
+                let Some(fields) = entry_spec(server).and_then(|spec| changes.get(&spec)) else {
+                    continue;
+                };
+                for (key, value) in fields {
+                    match value {
+                        Some(value) => server.insert(key.clone(), value.clone()),
+                        None => server.remove(key),
+                    };
+                }
+                updated += 1;
             }
+            Ok(updated)
+        })
+    }
+}
+
+/// Apply `edit` to the servers of a DNS list file and replace the file
+/// atomically, keeping fields this crate does not know.
+fn edit_list(path: &Path, edit: impl FnOnce(&mut Vec<Value>) -> Result<usize>) -> Result<usize> {
+    let mut list: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let servers = list
+        .get_mut("list")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| Error::parse(format!("{} is not a DNS list", path.display())))?;
+    let changed = edit(servers)?;
+
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{file_name}.tmp"));
+    std::fs::write(&tmp, serde_json::to_string_pretty(&list)? + "\n")?;
+    std::fs::rename(&tmp, path)?;
+    Ok(changed)
+}
+
+/// Address of a server entry of a list file, in the canonical form that
+/// loaded lists use.
+fn entry_ip(server: &serde_json::Map<String, Value>) -> Option<String> {
+    let ip = server.get("IP")?.as_str()?;
+    Some(
+        ip.trim()
+            .parse::<IpAddr>()
+            .map_or_else(|_| ip.to_string(), |ip| ip.to_string()),
+    )
+}
+
+/// [`DnsServer::spec`] of a server entry of a list file, with the address
+/// in canonical form.
+fn entry_spec(server: &serde_json::Map<String, Value>) -> Option<String> {
+    let mut server: DnsServer = serde_json::from_value(Value::Object(server.clone())).ok()?;
+    server.normalize_ip();
+    Some(server.spec())
+}
+
+/// [`DnsServer::spec`] of `server` with the address in canonical form, to
+/// compare with [`entry_spec`].
+fn canonical_spec(server: &DnsServer) -> String {
+    let mut server = server.clone();
+    server.normalize_ip();
+    server.spec()
+}
+
+/// Most addresses a range, or all ranges of
+/// [`ConfigLoader::from_args`] together, may expand to.
+pub const MAX_RANGE_HOSTS: usize = 1024;
+
+/// Expand an address range in CIDR notation, e.g. `192.168.1.0/28`, into
+/// its host addresses.
+///
+/// The network and broadcast addresses of IPv4 ranges larger than /31 are
+/// left out. A single address without a prefix length expands to itself.
+///
+/// # Errors
+///
+/// Returns an error if the range is invalid or has more than
+/// [`MAX_RANGE_HOSTS`] addresses.
+pub fn expand_cidr(range: &str) -> Result<Vec<IpAddr>> {
+    let (ip, len) = range
+        .split_once('/')
+        .map_or((range, None), |(ip, len)| (ip, Some(len)));
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return Err(Error::Parse(format!("Invalid IP address: {ip}")));
+    };
+    let bits = if ip.is_ipv4() { 32 } else { 128 };
+    let len = match len.map(str::parse::<u32>) {
+        None => bits,
+        Some(Ok(len)) if len <= bits => len,
+        Some(_) => return Err(Error::parse(format!("Invalid prefix length in {range}"))),
+    };
+    let host_bits = bits - len;
+    if host_bits > MAX_RANGE_HOSTS.trailing_zeros() {
+        return Err(Error::parse(format!(
+            "{range} has more than {MAX_RANGE_HOSTS} addresses"
+        )));
+    }
+
+    let hosts = 1u32 << host_bits;
+    Ok(match ip {
+        IpAddr::V4(ip) => {
+            let network = u32::from(ip) >> host_bits << host_bits;
+            let hosts = if host_bits >= 2 {
+                1..hosts - 1
+            } else {
+                0..hosts
+            };
+            hosts
+                .map(|host| IpAddr::from(std::net::Ipv4Addr::from(network | host)))
+                .collect()
+        }
+        IpAddr::V6(ip) => {
+            let network = u128::from(ip) >> host_bits << host_bits;
+            (0..hosts)
+                .map(|host| IpAddr::from(std::net::Ipv6Addr::from(network | u128::from(host))))
+                .collect()
+        }
+    })
+}
+
+/// Whether `address` is a range in CIDR notation.
+fn is_cidr(address: &str) -> bool {
+    address.split_once('/').is_some_and(|(ip, len)| {
+        ip.parse::<IpAddr>().is_ok() && !len.is_empty() && len.bytes().all(|b| b.is_ascii_digit())
+    })
+}
 
-            servers.push(DnsServer::new(name, ip));
+/// Parse one server spec of [`ConfigLoader::from_args`], expanding a
+/// range into one server per address.
+fn parse_spec(spec: &str) -> Result<Vec<DnsServer>> {
+    let (address, name) = match spec.split_once('#') {
+        Some((address, name)) => (address.trim(), Some(name.trim())),
+        None => (spec.trim(), None),
+    };
+
+    let (protocol, mut address) = if let Some(rest) = address.strip_prefix("tls://") {
+        (DnsProtocol::Tls, rest)
+    } else if let Some(rest) = address.strip_prefix("https://") {
+        (DnsProtocol::Https, rest)
+    } else {
+        (DnsProtocol::Plain, address)
+    };
+    if protocol == DnsProtocol::Https {
+        // Only the path of RFC 8484's example template, which servers
+        // addressed by IP use
+        if let Some(host) = address
+            .strip_suffix("/dns-query")
+            .or_else(|| address.strip_suffix('/'))
+        {
+            address = host;
+        }
+        let host = address.strip_prefix('[').map_or(address, |rest| {
+            rest.split_once(']').map_or(rest, |(host, _)| host)
+        });
+        if let Some((_, path)) = address.split_once('/').filter(|_| !is_cidr(host)) {
+            return Err(Error::parse(format!(
+                "Unsupported path /{path} in {spec}, use `dnstest doh` for other endpoints"
+            )));
         }
-        Ok(DnsList { servers })
     }
+
+    let (ip, port) = if let Some(rest) = address.strip_prefix('[') {
+        let Some((ip, rest)) = rest.split_once(']') else {
+            return Err(Error::parse(format!("Missing ']' in {spec}")));
+        };
+        match rest.strip_prefix(':') {
+            Some(port) => (ip, Some(port)),
+            None if rest.is_empty() => (ip, None),
+            None => return Err(Error::parse(format!("Invalid server {spec}"))),
+        }
+    } else if address.parse::<IpAddr>().is_ok() || is_cidr(address) {
+        (address, None)
+    } else {
+        // An IPv6 address with a port needs brackets, so the port follows
+        // the only colon
+        match address.split_once(':') {
+            Some((ip, port)) => (ip, Some(port)),
+            None => (address, None),
+        }
+    };
+
+    // Validate IP addresses, storing them in canonical form
+    let addrs = expand_cidr(ip)?;
+    let port = match port.map(str::parse::<u16>) {
+        Some(Ok(0) | Err(_)) => return Err(Error::parse(format!("Invalid port in {spec}"))),
+        Some(Ok(port)) => Some(port).filter(|&port| port != protocol.default_port()),
+        None => None,
+    };
+
+    Ok(addrs
+        .into_iter()
+        .map(|addr| {
+            let mut server = DnsServer::new("", addr.to_string());
+            server.port = port;
+            server.protocol = protocol;
+            server.name = name.map_or_else(|| server.spec(), str::to_string);
+            server
+        })
+        .collect())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dns::types::IpFamily;
+
+    #[test]
+    fn test_save_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LIST_FILE);
+        std::fs::write(
+            &path,
+            r#"{"version": 2, "list": [
+                {"name": "Google", "IP": "2001:4860:4860:0:0:0:0:8888", "note": "kept"},
+                {"name": "Cloudflare", "IP": "1.1.1.1"},
+                {"name": "Quad9", "IP": "9.9.9.9", "delay": 30.0}
+            ]}"#,
+        )
+        .unwrap();
+
+        let results = [
+            SpeedTestResult::success(DnsServer::new("Google", "2001:4860:4860::8888"), 12.5, 0.0),
+            SpeedTestResult::failure(DnsServer::new("Cloudflare", "1.1.1.1"), "timeout"),
+        ];
+        assert_eq!(ConfigLoader::save_results(&path, &results).unwrap(), 2);
+
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], 2);
+        let servers = &saved["list"];
+        assert_eq!(servers[0]["delay"], 12.5);
+        assert_eq!(servers[0]["status"], "success");
+        assert_eq!(servers[0]["note"], "kept");
+        assert_eq!(servers[1]["delay"], Value::Null);
+        assert_eq!(servers[1]["status"], "timeout");
+        assert_eq!(servers[2]["delay"], 30.0);
+        assert!(servers[2].get("status").is_none());
+
+        // Still loads as a list
+        let list = ConfigLoader::load_from_file(&path).unwrap();
+        assert_eq!(list.servers[0].delay, Some(12.5));
+
+        let removed: HashSet<String> = ["2001:4860:4860::8888".to_string()].into();
+        assert_eq!(ConfigLoader::remove_servers(&path, &removed).unwrap(), 1);
+        let list = ConfigLoader::load_from_file(&path).unwrap();
+        let ips: Vec<_> = list.servers.iter().map(|s| s.ip.as_str()).collect();
+        assert_eq!(ips, ["1.1.1.1", "9.9.9.9"]);
+
+        std::fs::write(&path, "[]").unwrap();
+        assert!(ConfigLoader::save_results(&path, &results).is_err());
+    }
+
+    #[test]
+    fn test_save_results_same_ip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LIST_FILE);
+        std::fs::write(
+            &path,
+            r#"{"list": [
+                {"name": "Local", "IP": "127.0.0.1"},
+                {"name": "Local", "IP": "127.0.0.1", "port": 5353}
+            ]}"#,
+        )
+        .unwrap();
+
+        let mut alternate = DnsServer::new("Local #2", "127.0.0.1");
+        alternate.port = Some(5353);
+        let results = [SpeedTestResult::success(alternate, 3.0, 0.0)];
+        assert_eq!(ConfigLoader::save_results(&path, &results).unwrap(), 1);
+
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(saved["list"][0].get("delay").is_none());
+        assert_eq!(saved["list"][1]["delay"], 3.0);
+    }
+
+    #[test]
+    fn test_add_servers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(LIST_V6_FILE);
+        let google = DnsServer::new("Google", "2001:4860:4860::8888");
+        let cloudflare =
+            DnsServer::new("Cloudflare", "2606:4700:4700::1111").with_provider("Cloudflare");
+
+        assert_eq!(
+            ConfigLoader::add_servers(&path, std::slice::from_ref(&google)).unwrap(),
+            1
+        );
+        // Already present servers are skipped
+        assert_eq!(
+            ConfigLoader::add_servers(&path, &[cloudflare, google]).unwrap(),
+            1
+        );
+
+        let list = ConfigLoader::load_from_file(&path).unwrap();
+        let ips: Vec<_> = list.servers.iter().map(|s| s.ip.as_str()).collect();
+        assert_eq!(ips, ["2001:4860:4860::8888", "2606:4700:4700::1111"]);
+        assert_eq!(list.servers[1].provider.as_deref(), Some("Cloudflare"));
+
+        // The same address on another port is another server
+        let alternate = ConfigLoader::from_args(vec!["[2001:4860:4860::8888]:5353".into()])
+            .unwrap()
+            .servers;
+        assert_eq!(ConfigLoader::add_servers(&path, &alternate).unwrap(), 1);
+        assert_eq!(ConfigLoader::add_servers(&path, &alternate).unwrap(), 0);
+
+        let removed: HashSet<String> = ["[2001:4860:4860::8888]:5353".to_string()].into();
+        assert_eq!(ConfigLoader::remove_specs(&path, &removed).unwrap(), 1);
+        let list = ConfigLoader::load_from_file(&path).unwrap();
+        assert_eq!(list.servers.len(), 2);
+        assert_eq!(list.servers[0].port, None);
+    }
+
+    #[test]
+    fn test_update_servers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LIST_FILE);
+        std::fs::write(
+            &path,
+            r#"{"list": [
+                {"name": "Google", "IP": "8.8.8.8", "note": "kept"},
+                {"name": "Google", "IP": "8.8.4.4"},
+                {"name": "Google", "IP": "8.8.4.4", "port": 5353}
+            ]}"#,
+        )
+        .unwrap();
+
+        // As numbered by merge
+        let loaded = DnsServer::new("Google #2", "8.8.4.4");
+        let disabled = DnsServer {
+            disabled: true,
+            ..loaded.clone()
+        };
+        let renamed = DnsServer::new("Quad8", "8.8.8.9");
+        let edits = [
+            (loaded, disabled),
+            (DnsServer::new("Google", "8.8.8.8"), renamed),
+        ];
+        assert_eq!(ConfigLoader::update_servers(&path, &edits).unwrap(), 2);
+
+        let list: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            list["list"],
+            serde_json::json!([
+                {"name": "Quad8", "IP": "8.8.8.9", "note": "kept"},
+                {"name": "Google", "IP": "8.8.4.4", "disabled": true},
+                {"name": "Google", "IP": "8.8.4.4", "port": 5353}
+            ])
+        );
+
+        // Only the entry with the same port goes
+        let removed = HashSet::from(["8.8.4.4:5353".to_string()]);
+        assert_eq!(ConfigLoader::remove_specs(&path, &removed).unwrap(), 1);
+        let list = ConfigLoader::load_from_file(&path).unwrap();
+        assert_eq!(list.servers[1].spec(), "8.8.4.4");
+    }
+
+    #[test]
+    fn test_v6_path() {
+        assert_eq!(
+            ConfigLoader::v6_path(Path::new(LIST_FILE)),
+            Path::new(LIST_V6_FILE)
+        );
+        assert_eq!(
+            ConfigLoader::v6_path(Path::new("/tmp/servers")),
+            Path::new("/tmp/servers-v6")
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = DnsList::from_servers(vec![DnsServer::new("Google", "8.8.8.8")]);
+        assert!(ConfigLoader::validate(&valid).is_ok());
+        assert!(ConfigLoader::validate(&DnsList::new()).is_err());
+
+        let invalid = DnsList::from_servers(vec![DnsServer::new("Bad", "8.8.8")]);
+        assert!(ConfigLoader::validate(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_merge_normalizes_ipv6() {
+        let short = DnsList::from_servers(vec![DnsServer::new("Google", "2001:4860:4860::8888")]);
+        let long = DnsList::from_servers(vec![
+            DnsServer::new("Google long", "2001:4860:4860:0000:0000:0000:0000:8888"),
+            DnsServer::new("Padded", " 2606:4700:4700::1111 "),
+            DnsServer::new("Cloudflare", "2606:4700:4700:0::1111"),
+        ]);
+        let merged = ConfigLoader::merge(vec![short, long]);
+        let ips: Vec<_> = merged.servers.iter().map(|s| s.ip.as_str()).collect();
+        assert_eq!(ips, ["2001:4860:4860::8888", "2606:4700:4700::1111"]);
+        assert_eq!(merged.servers[0].name, "Google");
+
+        let list = ConfigLoader::from_args(vec!["2001:DB8:0::1#Doc".to_string()]).unwrap();
+        assert_eq!(list.servers[0].ip, "2001:db8::1");
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let list = ConfigLoader::from_args(vec![
+            "8.8.8.8#Google".to_string(),
+            "1.1.1.1#Cloudflare".to_string(),
+            "8.8.4.4#Google".to_string(),
+        ])
+        .unwrap();
+        let names: Vec<_> = list.servers.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Google #1", "Cloudflare", "Google #2"]);
+
+        // Merged lists are sorted by address before numbering
+        let merged = ConfigLoader::merge(vec![
+            DnsList::from_servers(vec![DnsServer::new("Google", "8.8.8.8")]),
+            DnsList::from_servers(vec![DnsServer::new("Google", "8.8.4.4")]),
+        ]);
+        assert_eq!(merged.servers[0].name, "Google #1");
+        assert_eq!(merged.servers[0].ip, "8.8.4.4");
+
+        // Lists numbered when loaded are not numbered twice
+        let merged = ConfigLoader::merge(vec![
+            list,
+            DnsList::from_servers(vec![DnsServer::new("Google", "8.8.4.4")]),
+            DnsList::from_servers(vec![DnsServer::new("Google", "2001:4860:4860::8888")]),
+        ]);
+        let names: Vec<_> = merged.servers.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Cloudflare", "Google #1", "Google #2", "Google #3"]);
+        assert!(merged
+            .servers
+            .iter()
+            .skip(1)
+            .all(|s| s.provider_name() == "Google"));
+    }
+
+    #[test]
+    fn test_merge_keeps_ports() {
+        let merged = ConfigLoader::merge(vec![
+            ConfigLoader::from_args(vec!["127.0.0.1#Local".into(), "tls://1.1.1.1".into()])
+                .unwrap(),
+            ConfigLoader::from_args(vec!["127.0.0.1:5353#Local".into(), "1.1.1.1".into()]).unwrap(),
+            ConfigLoader::from_args(vec!["127.0.0.1#Other".into()]).unwrap(),
+        ]);
+        let servers: Vec<_> = merged
+            .servers
+            .iter()
+            .map(|s| (s.name.as_str(), s.spec()))
+            .collect();
+        assert_eq!(
+            servers,
+            [
+                ("1.1.1.1", "1.1.1.1".to_string()),
+                ("tls://1.1.1.1", "tls://1.1.1.1".to_string()),
+                ("Local #1", "127.0.0.1".to_string()),
+                ("Local #2", "127.0.0.1:5353".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_first() {
+        let local = DnsList::from_servers(vec![DnsServer::new("Local name", "8.8.8.8")]);
+        let remote = DnsList::from_servers(vec![
+            DnsServer::new("Remote name", "8.8.8.8"),
+            DnsServer::new("Cloudflare", "1.1.1.1"),
+        ]);
+        let merged = ConfigLoader::merge(vec![local, remote]);
+        assert_eq!(merged.len(), 2);
+        let google = merged.servers.iter().find(|s| s.ip == "8.8.8.8").unwrap();
+        assert_eq!(google.name, "Local name");
+        assert_eq!("union".parse::<MergePolicy>(), Ok(MergePolicy::Union));
+    }
 
     #[test]
     fn test_dns_server_creation() {
@@ -206,6 +999,16 @@ mod tests {
         assert!(ip_v6.unwrap().is_ipv6());
     }
 
+    #[test]
+    fn test_in_country() {
+        let mut server = DnsServer::new("AliDNS", "223.5.5.5");
+        let codes = vec!["us".to_string(), "CN".to_string()];
+        assert!(!server.in_country(&codes));
+        server.country = Some("cn".to_string());
+        assert!(server.in_country(&codes));
+        assert!(!server.in_country(&["JP".to_string()]));
+    }
+
     #[test]
     fn test_dns_server_is_ipv4_ipv6() {
         let server_v4 = DnsServer::new("Test", "8.8.8.8");
@@ -215,6 +1018,12 @@ mod tests {
         let server_v6 = DnsServer::new("Test", "::1");
         assert!(!server_v6.is_ipv4());
         assert!(server_v6.is_ipv6());
+
+        assert!(server_v4.in_family(IpFamily::V4));
+        assert!(!server_v6.in_family(IpFamily::V4));
+        assert!(server_v6.in_family(IpFamily::V6));
+        assert_eq!(IpFamily::from_flags(false, true), Some(IpFamily::V6));
+        assert_eq!(IpFamily::from_flags(false, false), None);
     }
 
     #[test]
@@ -250,4 +1059,122 @@ mod tests {
         let result = ConfigLoader::from_args(args);
         assert!(result.is_err());
     }
+
+    fn parse_one(spec: &str) -> Result<DnsServer> {
+        let mut servers = parse_spec(spec)?;
+        assert_eq!(servers.len(), 1);
+        Ok(servers.remove(0))
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        let server = parse_one("127.0.0.1:5353#Local").unwrap();
+        assert_eq!(
+            (server.name.as_str(), server.ip.as_str(), server.port),
+            ("Local", "127.0.0.1", Some(5353))
+        );
+        assert_eq!(server.protocol, DnsProtocol::Plain);
+        assert_eq!(
+            server.socket_addr(),
+            Some("127.0.0.1:5353".parse().unwrap())
+        );
+
+        let server = parse_one("[2001:DB8::1]:5353#Local v6").unwrap();
+        assert_eq!(server.ip, "2001:db8::1");
+        assert_eq!(server.port, Some(5353));
+        assert_eq!(server.spec(), "[2001:db8::1]:5353");
+        assert_eq!(parse_one("[::1]").unwrap().port, None);
+        assert_eq!(parse_one("2001:db8::1").unwrap().ip, "2001:db8::1");
+
+        let server = parse_one("tls://1.1.1.1").unwrap();
+        assert_eq!(server.protocol, DnsProtocol::Tls);
+        assert_eq!(server.port(), 853);
+        assert_eq!(server.name, "tls://1.1.1.1");
+
+        // Default ports are not stored
+        let server = parse_one("https://[2606:4700:4700::1111]:443/dns-query#Cloudflare").unwrap();
+        assert_eq!(server.protocol, DnsProtocol::Https);
+        assert_eq!(server.port, None);
+        assert_eq!(parse_one("8.8.8.8:53").unwrap().port, None);
+        assert_eq!(
+            parse_one("https://8.8.8.8:8443").unwrap().spec(),
+            "https://8.8.8.8:8443"
+        );
+
+        for invalid in [
+            "1.1.1.1:0",
+            "1.1.1.1:dns",
+            "2001:db8::1:5353x",
+            "[2001:db8::1:5353",
+            "[2001:db8::1]5353",
+            "https://1.1.1.1/resolve",
+            "quic://1.1.1.1",
+        ] {
+            assert!(parse_one(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_expand_cidr() {
+        let ips = |range| -> Vec<String> {
+            expand_cidr(range)
+                .unwrap()
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        };
+        // Host bits are ignored, network and broadcast left out
+        assert_eq!(
+            ips("192.168.1.5/29"),
+            [
+                "192.168.1.1",
+                "192.168.1.2",
+                "192.168.1.3",
+                "192.168.1.4",
+                "192.168.1.5",
+                "192.168.1.6"
+            ]
+        );
+        assert_eq!(ips("10.0.0.0/31"), ["10.0.0.0", "10.0.0.1"]);
+        assert_eq!(ips("10.0.0.7"), ["10.0.0.7"]);
+        assert_eq!(ips("fd00::/127"), ["fd00::", "fd00::1"]);
+        assert_eq!(expand_cidr("10.0.0.0/22").unwrap().len(), 1022);
+        assert!(expand_cidr("10.0.0.0/21").is_err());
+        assert!(expand_cidr("fd00::/64").is_err());
+        assert!(expand_cidr("10.0.0.0/33").is_err());
+        assert!(expand_cidr("10.0.0.0/").is_err());
+
+        let list = ConfigLoader::from_args(vec![
+            "192.168.1.0/30#LAN".to_string(),
+            "tls://[fd00::/127]:8853".to_string(),
+        ])
+        .unwrap();
+        let specs: Vec<_> = list.servers.iter().map(DnsServer::spec).collect();
+        assert_eq!(
+            specs,
+            [
+                "192.168.1.1",
+                "192.168.1.2",
+                "tls://[fd00::]:8853",
+                "tls://[fd00::1]:8853"
+            ]
+        );
+        assert_eq!(list.servers[0].name, "LAN #1");
+        assert_eq!(list.servers[3].name, "tls://[fd00::1]:8853");
+        assert_eq!(parse_spec("https://10.0.0.0/31").unwrap().len(), 2);
+        assert_eq!(parse_spec("10.0.0.0/31:5353").unwrap()[1].port, Some(5353));
+
+        // The cap applies to all ranges together
+        let ranges = |specs: &[&str]| {
+            ConfigLoader::from_args(specs.iter().map(ToString::to_string).collect())
+        };
+        assert_eq!(
+            ranges(&["10.0.0.0/23", "10.0.4.0/24"])
+                .unwrap()
+                .servers
+                .len(),
+            764
+        );
+        assert!(ranges(&["10.0.0.0/22", "10.0.4.0/24"]).is_err());
+    }
 }
@@ -1,8 +1,16 @@
 //! Configuration module.
 //!
 //! This module provides functionality for loading and managing
-//! DNS server configuration from various sources.
+//! DNS server configuration from various sources, the built-in server
+//! presets, and the user settings file.
 
 pub mod loader;
+pub mod presets;
+pub mod settings;
 
-pub use loader::ConfigLoader;
+pub use loader::{
+    expand_cidr, ConfigLoader, MergePolicy, DEFAULT_LIST_URL, DEFAULT_LIST_V6_URL, LIST_FILE,
+    LIST_V6_FILE, MAX_RANGE_HOSTS,
+};
+pub use presets::Preset;
+pub use settings::{Profile, PushSettings, Settings};
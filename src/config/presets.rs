@@ -0,0 +1,386 @@
+//! Built-in server lists.
+//!
+//! A preset is a well-known set of servers that is tested as a baseline
+//! with `--preset NAME` instead of a hand-built DNS list. The root servers
+//! are built in; the nameservers of top-level domains change more often
+//! and are looked up at a root server when the preset is used.
+
+use crate::dns::query::{self, Exchange, NetworkExchange};
+use crate::dns::DnsServer;
+use crate::error::{Error, Result};
+use hickory_resolver::proto::rr::{Name, RecordType};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+/// Root server letters with their operator and IPv4 and IPv6 addresses,
+/// from <https://root-servers.org>.
+const ROOT_SERVERS: &[(char, &str, &str, &str)] = &[
+    ('a', "Verisign", "198.41.0.4", "2001:503:ba3e::2:30"),
+    ('b', "USC-ISI", "170.247.170.2", "2801:1b8:10::b"),
+    ('c', "Cogent", "192.33.4.12", "2001:500:2::c"),
+    ('d', "UMD", "199.7.91.13", "2001:500:2d::d"),
+    ('e', "NASA Ames", "192.203.230.10", "2001:500:a8::e"),
+    ('f', "ISC", "192.5.5.241", "2001:500:2f::f"),
+    ('g', "DISA", "192.112.36.4", "2001:500:12::d0d"),
+    ('h', "US Army (ARL)", "198.97.190.53", "2001:500:1::53"),
+    ('i', "Netnod", "192.36.148.17", "2001:7fe::53"),
+    ('j', "Verisign", "192.58.128.30", "2001:503:c27::2:30"),
+    ('k', "RIPE NCC", "193.0.14.129", "2001:7fd::1"),
+    ('l', "ICANN", "199.7.83.42", "2001:500:9f::42"),
+    ('m', "WIDE Project", "202.12.27.33", "2001:dc3::35"),
+];
+
+/// Top-level domains whose nameservers `tld` tests when none are given.
+pub const DEFAULT_TLDS: &[&str] = &["com", "net", "org", "cn"];
+
+/// Timeout for looking up the nameservers of one TLD at one root server.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Resolver asked for the addresses of nameservers without glue.
+const RESOLVER: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)), query::DNS_PORT);
+
+/// Built-in server list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preset {
+    /// The 13 root server letters over IPv4 and IPv6
+    Root,
+    /// The authoritative nameservers of these top-level domains
+    Tld(Vec<String>),
+}
+
+impl Preset {
+    /// Get all available preset names.
+    #[must_use]
+    pub fn names() -> &'static [&'static str] {
+        &["root", "tld", "tld:com,net,..."]
+    }
+
+    /// Servers of the preset.
+    ///
+    /// Root servers are named after their letter's host name, e.g.
+    /// `a.root-servers.net`, and carry their operator as provider. TLD
+    /// nameservers are named after their host name and carry the TLDs
+    /// they serve as provider, e.g. `com/net` for `a.gtld-servers.net`.
+    /// IPv6 addresses are labeled as such.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no root server tells the nameservers of a TLD,
+    /// or the TLD does not exist.
+    pub async fn servers(&self) -> Result<Vec<DnsServer>> {
+        self.servers_with(&NetworkExchange).await
+    }
+
+    /// Servers of the preset, looking up TLD nameservers through
+    /// `exchange`.
+    ///
+    /// # Errors
+    ///
+    /// See [`servers`](Self::servers).
+    pub async fn servers_with(&self, exchange: &dyn Exchange) -> Result<Vec<DnsServer>> {
+        match self {
+            Self::Root => Ok(root_servers()),
+            Self::Tld(tlds) => {
+                let mut servers: Vec<DnsServer> = Vec::new();
+                for tld in tlds {
+                    for server in tld_servers(exchange, tld).await? {
+                        // TLDs sharing nameservers, like com and net, are
+                        // tested once
+                        match servers.iter_mut().find(|s| s.ip == server.ip) {
+                            Some(existing) => {
+                                if let Some(provider) = &mut existing.provider {
+                                    provider.push('/');
+                                    provider.push_str(tld);
+                                }
+                            }
+                            None => servers.push(server),
+                        }
+                    }
+                }
+                Ok(servers)
+            }
+        }
+    }
+}
+
+/// IPv4 addresses of the root servers, a to m: where iterative lookups
+/// start.
+#[must_use]
+pub fn root_hints() -> Vec<IpAddr> {
+    ROOT_SERVERS
+        .iter()
+        .filter_map(|(_, _, v4, _)| v4.parse().ok())
+        .collect()
+}
+
+/// The root servers, IPv4 and IPv6 address of each letter in turn.
+fn root_servers() -> Vec<DnsServer> {
+    ROOT_SERVERS
+        .iter()
+        .flat_map(|&(letter, operator, v4, v6)| {
+            let name = format!("{letter}.root-servers.net");
+            [
+                DnsServer::new(name.clone(), v4).with_provider(operator),
+                DnsServer::new(format!("{name} (IPv6)"), v6).with_provider(operator),
+            ]
+        })
+        .collect()
+}
+
+/// Ask the root servers, one after another until one answers, for the
+/// delegation of `tld`.
+///
+/// The referral lists the nameservers with their addresses as glue. It
+/// rarely fits into a plain UDP response, so it is fetched over TCP.
+/// Nameservers without glue, which live under another TLD, are looked up
+/// at [`RESOLVER`].
+async fn tld_servers(exchange: &dyn Exchange, tld: &str) -> Result<Vec<DnsServer>> {
+    let mut last_error = None;
+    for &(letter, _, v4, _) in ROOT_SERVERS {
+        let Ok(ip) = v4.parse::<IpAddr>() else {
+            continue;
+        };
+        let root = SocketAddr::new(ip, query::DNS_PORT);
+        match exchange
+            .tcp(root, &format!("{tld}."), RecordType::NS, LOOKUP_TIMEOUT)
+            .await
+        {
+            Ok(response) => {
+                let mut nameservers = query::nameservers(&response.message);
+                if nameservers.is_empty() {
+                    return Err(Error::config(format!(
+                        "No nameservers found for TLD '{tld}'"
+                    )));
+                }
+                let lookups = nameservers
+                    .iter_mut()
+                    .filter(|(_, glue)| glue.is_empty())
+                    .map(|(host, glue)| async move { *glue = resolve(exchange, host).await });
+                futures::future::join_all(lookups).await;
+                let servers = delegation(nameservers, tld);
+                if servers.is_empty() {
+                    return Err(Error::config(format!(
+                        "No addresses found for the nameservers of TLD '{tld}'"
+                    )));
+                }
+                return Ok(servers);
+            }
+            Err(e) => {
+                tracing::debug!("{letter}.root-servers.net did not answer for {tld}: {e}");
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.unwrap_or(Error::Timeout))
+}
+
+/// Addresses of a nameserver the referral has no glue for.
+async fn resolve(exchange: &dyn Exchange, host: &Name) -> Vec<IpAddr> {
+    let name = host.to_string();
+    let lookups: [_; 2] = futures::join!(
+        exchange.udp(RESOLVER, &name, RecordType::A, LOOKUP_TIMEOUT, None),
+        exchange.udp(RESOLVER, &name, RecordType::AAAA, LOOKUP_TIMEOUT, None)
+    )
+    .into();
+    lookups
+        .into_iter()
+        .filter_map(|responses| {
+            responses
+                .map_err(|e| tracing::debug!("Looking up {name} at {RESOLVER} failed: {e}"))
+                .ok()
+        })
+        .filter_map(|responses| responses.into_iter().next())
+        .flat_map(|response| response.answer_ips())
+        .collect()
+}
+
+/// Servers of `tld` from its nameservers and their addresses, in the
+/// order of the referral's NS records, IPv4 before IPv6.
+fn delegation(nameservers: Vec<(Name, Vec<IpAddr>)>, tld: &str) -> Vec<DnsServer> {
+    let mut servers = Vec::new();
+    for (host, glue) in nameservers {
+        let name = host.to_string().trim_end_matches('.').to_string();
+        for ip in glue {
+            let name = if ip.is_ipv6() {
+                format!("{name} (IPv6)")
+            } else {
+                name.clone()
+            };
+            servers.push(DnsServer::new(name, ip.to_string()).with_provider(tld));
+        }
+    }
+    servers.sort_by_key(DnsServer::is_ipv6);
+    servers
+}
+
+impl std::str::FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        match s.split_once(':') {
+            None if s == "root" || s == "roots" => Ok(Self::Root),
+            None if s == "tld" => Ok(Self::Tld(
+                DEFAULT_TLDS.iter().map(ToString::to_string).collect(),
+            )),
+            Some(("tld", tlds)) => {
+                let tlds: Vec<String> = tlds
+                    .split(',')
+                    .map(|tld| tld.trim().trim_matches('.').to_string())
+                    .filter(|tld| !tld.is_empty())
+                    .collect();
+                if tlds.is_empty() {
+                    return Err("No TLDs given, expected e.g. tld:com,net".to_string());
+                }
+                Ok(Self::Tld(tlds))
+            }
+            _ => Err(format!(
+                "Unknown preset: {}. Valid options are: {:?}",
+                s,
+                Self::names()
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Preset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Root => write!(f, "root"),
+            Self::Tld(tlds) => write!(f, "tld:{}", tlds.join(",")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::query::fake::{record, FakeExchange, Query, Reply};
+    use hickory_resolver::proto::rr::rdata::{A, NS};
+    use hickory_resolver::proto::rr::{Name, RData};
+
+    #[tokio::test]
+    async fn test_root_preset() {
+        let servers = Preset::Root.servers().await.unwrap();
+        assert_eq!(servers.len(), 26);
+        assert!(servers.iter().all(|s| s.ip_addr().is_some()));
+        assert_eq!(servers.iter().filter(|s| s.is_ipv6()).count(), 13);
+        assert_eq!(servers[0].name, "a.root-servers.net");
+        assert_eq!(servers[1].name, "a.root-servers.net (IPv6)");
+        assert_eq!(servers[25].provider.as_deref(), Some("WIDE Project"));
+    }
+
+    #[test]
+    fn test_parse_preset() {
+        assert_eq!("Root".parse::<Preset>(), Ok(Preset::Root));
+        assert_eq!(
+            "tld".parse::<Preset>().unwrap().to_string(),
+            "tld:com,net,org,cn"
+        );
+        assert_eq!(
+            "tld:.COM, de".parse::<Preset>(),
+            Ok(Preset::Tld(vec!["com".to_string(), "de".to_string()]))
+        );
+        assert!("tld:".parse::<Preset>().is_err());
+        assert!("gtld".parse::<Preset>().is_err());
+    }
+
+    /// A root server delegating com and net to the same two nameservers,
+    /// one of them without IPv6 glue, and org and example to nameservers
+    /// without any glue, of which only org's resolve.
+    fn fake_root() -> FakeExchange {
+        let referral = |zone| {
+            let ns = |host| {
+                record(
+                    zone,
+                    172_800,
+                    RData::NS(NS(Name::from_ascii(host).unwrap())),
+                )
+            };
+            Reply::empty()
+                .with_authority([ns("a.gtld-servers.net."), ns("b.gtld-servers.net.")])
+                .with_additional([
+                    record(
+                        "a.gtld-servers.net.",
+                        172_800,
+                        RData::A(A::new(192, 5, 6, 30)),
+                    ),
+                    record(
+                        "a.gtld-servers.net.",
+                        172_800,
+                        RData::AAAA("2001:503:a83e::2:30".parse().unwrap()),
+                    ),
+                    record(
+                        "b.gtld-servers.net.",
+                        172_800,
+                        RData::A(A::new(192, 33, 14, 30)),
+                    ),
+                ])
+        };
+        let glueless = |zone, host| {
+            Reply::empty().with_authority([record(
+                zone,
+                172_800,
+                RData::NS(NS(Name::from_ascii(host).unwrap())),
+            )])
+        };
+        FakeExchange::new()
+            .on(Query::any().name("com.").tcp(), referral("com."))
+            .on(Query::any().name("net.").tcp(), referral("net."))
+            .on(
+                Query::any().name("org.").tcp(),
+                glueless("org.", "a0.org.afilias-nst.info."),
+            )
+            .on(
+                Query::any().name("example.").tcp(),
+                glueless("example.", "ns.example.net."),
+            )
+            .on(Query::any().tcp(), Reply::empty())
+            .on(
+                Query::any()
+                    .server(RESOLVER.ip())
+                    .name("a0.org.afilias-nst.info.")
+                    .record_type(RecordType::A),
+                Reply::answers([record(
+                    "a0.org.afilias-nst.info.",
+                    300,
+                    RData::A(A::new(199, 19, 56, 1)),
+                )]),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_tld_preset() {
+        let preset: Preset = "tld:com,net".parse().unwrap();
+        let servers = preset.servers_with(&fake_root()).await.unwrap();
+        let listed: Vec<_> = servers
+            .iter()
+            .map(|s| (s.name.as_str(), s.ip.as_str(), s.provider.as_deref()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("a.gtld-servers.net", "192.5.6.30", Some("com/net")),
+                ("b.gtld-servers.net", "192.33.14.30", Some("com/net")),
+                (
+                    "a.gtld-servers.net (IPv6)",
+                    "2001:503:a83e::2:30",
+                    Some("com/net")
+                ),
+            ]
+        );
+
+        let unknown = Preset::Tld(vec!["invalid".to_string()]);
+        assert!(unknown.servers_with(&fake_root()).await.is_err());
+
+        // Nameservers without glue are looked up at the resolver
+        let org = Preset::Tld(vec!["org".to_string()]);
+        let servers = org.servers_with(&fake_root()).await.unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].name, "a0.org.afilias-nst.info");
+        assert_eq!(servers[0].ip, "199.19.56.1");
+        let unresolved = Preset::Tld(vec!["example".to_string()]);
+        assert!(unresolved.servers_with(&fake_root()).await.is_err());
+    }
+}
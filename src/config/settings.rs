@@ -0,0 +1,326 @@
+//! User settings file.
+//!
+//! Options that do not fit on the command line live in `config.toml` in
+//! the config directory. Every section is optional and a missing file
+//! means defaults.
+//!
+//! Options are layered, later ones winning: top-level keys of the file,
+//! the selected `[profile.<name>]`, `DNSTEST_*` environment variables and
+//! finally command-line flags.
+//!
+//! ```toml
+//! timeout = 3          # or timeout_ms = 500
+//!
+//! [push]
+//! url = "https://collector.example/dnstest"
+//!
+//! [alerts]
+//! latency_ms = 30
+//!
+//! [watch]
+//! domains = ["google.com"]
+//!
+//! [profile.office]
+//! servers = ["10.0.0.53#Office DNS"]
+//! reference_resolvers = ["9.9.9.9"]
+//!
+//! [[plugin]]
+//! name = "corp-blocklist"
+//! command = "/usr/local/bin/check-blocklist"
+//! scope = "domain"
+//! ```
+
+use crate::alert::AlertRules;
+use crate::cli::OutputFormat;
+use crate::config::ConfigLoader;
+use crate::error::{Error, Result};
+use crate::plugin::PluginConfig;
+use crate::serve::watch::Watchlist;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Settings file name inside the config directory.
+const SETTINGS_FILE: &str = "config.toml";
+
+/// Contents of `config.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Top-level options, shared by all profiles
+    #[serde(flatten)]
+    pub defaults: Profile,
+    /// Remote collection endpoint (`[push]` table)
+    pub push: PushSettings,
+    /// Regression alerts of `monitor` and `serve` (`[alerts]` table)
+    pub alerts: AlertRules,
+    /// Domains `serve` keeps checking for pollution (`[watch]` table)
+    pub watch: Watchlist,
+    /// External check plugins (`[[plugin]]` tables)
+    #[serde(rename = "plugin")]
+    pub plugins: Vec<PluginConfig>,
+    /// Named profiles (`[profile.<name>]` tables)
+    #[serde(rename = "profile")]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Network-specific settings selected with `--profile`.
+///
+/// Results depend on the network a machine is on, so each profile can
+/// bring its own servers, reference resolvers and thresholds. Unset
+/// fields fall back to the built-in defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// DNS list file to test instead of the default lists
+    pub dns_list: Option<PathBuf>,
+    /// Servers to test (format: IP#Name), instead of any list
+    pub servers: Vec<String>,
+    /// Public resolvers pollution checks compare against
+    pub reference_resolvers: Vec<IpAddr>,
+    /// Overlap ratio at or above which answers count as agreeing
+    pub overlap_threshold: Option<f64>,
+    /// DNS JSON API endpoints pollution checks take the reference answer
+    /// from, instead of the reference resolvers
+    pub doh_json: Vec<String>,
+    /// Oblivious DNS relay URL; when set, pollution checks take the
+    /// reference answer from `odoh_target` through it
+    pub odoh_relay: Option<String>,
+    /// Oblivious DNS target host (default: `odoh.cloudflare-dns.com`)
+    pub odoh_target: Option<String>,
+    /// Whether pollution checks reuse reference answers until their TTL
+    /// runs out (default: true)
+    pub reference_cache: Option<bool>,
+    /// Seconds pollution checks wait for each lookup query
+    pub lookup_timeout: Option<u64>,
+    /// Times pollution checks try each lookup query
+    pub lookup_attempts: Option<usize>,
+    /// Pings per server
+    pub count: Option<usize>,
+    /// Ping timeout in seconds
+    pub timeout: Option<u64>,
+    /// Ping timeout in milliseconds, taking precedence over `timeout`
+    pub timeout_ms: Option<u64>,
+    /// Servers tested at the same time (TUI default: 20)
+    pub concurrency: Option<usize>,
+    /// Whether `speed` adapts the servers tested at the same time to the
+    /// timeout rate, up to `concurrency`
+    pub adaptive: Option<bool>,
+    /// Seconds a TUI test run may take before the servers not tested yet
+    /// are given up (default: 120)
+    pub run_timeout: Option<u64>,
+    /// Output format
+    pub format: Option<OutputFormat>,
+}
+
+impl Profile {
+    /// Layer `other` on top of this profile: fields set in `other` win.
+    #[must_use]
+    pub fn overlay(self, other: &Self) -> Self {
+        fn list<T: Clone>(base: Vec<T>, over: &[T]) -> Vec<T> {
+            if over.is_empty() {
+                base
+            } else {
+                over.to_vec()
+            }
+        }
+
+        // The two timeout keys are one setting: a layer setting either
+        // replaces both
+        let (timeout, timeout_ms) = if other.timeout.is_some() || other.timeout_ms.is_some() {
+            (other.timeout, other.timeout_ms)
+        } else {
+            (self.timeout, self.timeout_ms)
+        };
+
+        Self {
+            dns_list: other.dns_list.clone().or(self.dns_list),
+            servers: list(self.servers, &other.servers),
+            reference_resolvers: list(self.reference_resolvers, &other.reference_resolvers),
+            doh_json: list(self.doh_json, &other.doh_json),
+            odoh_relay: other.odoh_relay.clone().or(self.odoh_relay),
+            odoh_target: other.odoh_target.clone().or(self.odoh_target),
+            overlap_threshold: other.overlap_threshold.or(self.overlap_threshold),
+            reference_cache: other.reference_cache.or(self.reference_cache),
+            lookup_timeout: other.lookup_timeout.or(self.lookup_timeout),
+            lookup_attempts: other.lookup_attempts.or(self.lookup_attempts),
+            count: other.count.or(self.count),
+            timeout,
+            timeout_ms,
+            concurrency: other.concurrency.or(self.concurrency),
+            adaptive: other.adaptive.or(self.adaptive),
+            run_timeout: other.run_timeout.or(self.run_timeout),
+            format: other.format.or(self.format),
+        }
+    }
+
+    /// Ping timeout, if set in either unit.
+    #[must_use]
+    pub fn ping_timeout(&self) -> Option<Duration> {
+        self.timeout_ms
+            .map(Duration::from_millis)
+            .or_else(|| self.timeout.map(Duration::from_secs))
+    }
+}
+
+/// Where to push run results.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PushSettings {
+    /// Endpoint to POST results to
+    pub url: Option<String>,
+    /// Bearer token sent with each push
+    pub token: Option<String>,
+}
+
+impl Settings {
+    /// Path of the default settings file.
+    #[must_use]
+    pub fn path() -> PathBuf {
+        ConfigLoader::config_dir().join(SETTINGS_FILE)
+    }
+
+    /// Load the default settings file, or defaults if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read or parsed.
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        if path.exists() {
+            Self::load_from_file(&path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Look up a profile by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing the available profiles if `name` is not
+    /// defined.
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            Error::config(format!(
+                "Unknown profile '{name}' (available: {})",
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            ))
+        })
+    }
+
+    /// The effective options: top-level keys with the named profile, if
+    /// any, layered on top.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` is not a defined profile.
+    pub fn resolve(&self, name: Option<&str>) -> Result<Profile> {
+        let base = self.defaults.clone();
+        match name {
+            Some(name) => Ok(base.overlay(self.profile(name)?)),
+            None => Ok(base),
+        }
+    }
+
+    /// Load settings from a TOML file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| Error::config(format!("{}: {e}", path.display())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::PluginScope;
+
+    #[test]
+    fn test_load_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+
+        std::fs::write(&path, "").unwrap();
+        assert!(Settings::load_from_file(&path).unwrap().plugins.is_empty());
+
+        std::fs::write(
+            &path,
+            r#"
+[[plugin]]
+name = "blocklist"
+command = "check-blocklist"
+args = ["--strict"]
+scope = "domain"
+"#,
+        )
+        .unwrap();
+        let settings = Settings::load_from_file(&path).unwrap();
+        assert_eq!(settings.push.url, None);
+        assert_eq!(settings.plugins.len(), 1);
+        assert_eq!(settings.plugins[0].scope, PluginScope::Domain);
+        assert_eq!(settings.plugins[0].args, ["--strict"]);
+
+        std::fs::write(&path, "[push]\nurl = \"http://collector\"\n").unwrap();
+        let settings = Settings::load_from_file(&path).unwrap();
+        assert_eq!(settings.push.url.as_deref(), Some("http://collector"));
+
+        std::fs::write(
+            &path,
+            r#"
+timeout = 3
+format = "json"
+
+[profile.home]
+servers = ["192.168.1.1#Router"]
+timeout = 1
+
+[profile.office]
+reference_resolvers = ["9.9.9.9"]
+overlap_threshold = 0.3
+
+[profile.lan]
+timeout_ms = 300
+"#,
+        )
+        .unwrap();
+        let settings = Settings::load_from_file(&path).unwrap();
+        assert_eq!(
+            settings.profile("home").unwrap().servers,
+            ["192.168.1.1#Router"]
+        );
+        let office = settings.profile("office").unwrap();
+        assert_eq!(
+            office.reference_resolvers,
+            ["9.9.9.9".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(office.overlap_threshold, Some(0.3));
+        assert!(settings.profile("cafe").is_err());
+
+        let home = settings.resolve(Some("home")).unwrap();
+        assert_eq!(home.timeout, Some(1));
+        assert_eq!(home.format, Some(OutputFormat::Json));
+        assert_eq!(settings.resolve(None).unwrap().timeout, Some(3));
+        assert_eq!(
+            settings.resolve(None).unwrap().ping_timeout(),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            settings.resolve(Some("lan")).unwrap().ping_timeout(),
+            Some(Duration::from_millis(300))
+        );
+
+        std::fs::write(&path, "[[plugin]]\nname = 1\n").unwrap();
+        assert!(Settings::load_from_file(&path).is_err());
+    }
+}
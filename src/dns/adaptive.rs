@@ -0,0 +1,144 @@
+//! Adaptive concurrency.
+//!
+//! Large scans go fastest with many servers in flight, but on a weak
+//! uplink too many pings at once queue behind each other: latencies rise
+//! and replies arrive after the timeout. [`Aimd`] adjusts the number of
+//! servers tested at the same time the way TCP adjusts its congestion
+//! window. It judges the results in windows of as many results as the
+//! current limit: the limit doubles after each window until the first
+//! back-off, then grows by one per window, and halves after a window in
+//! which more than the threshold of servers timed out.
+//!
+//! Servers that are simply down time out too, so the threshold is set well
+//! above the share of dead servers in a typical list.
+
+/// Servers tested at the same time when a run starts.
+pub const DEFAULT_INITIAL: usize = 4;
+
+/// Most servers tested at the same time, unless configured.
+pub const DEFAULT_MAX: usize = 64;
+
+/// Share of timeouts in a window above which the limit is halved.
+pub const DEFAULT_TIMEOUT_THRESHOLD: f64 = 0.5;
+
+/// Additive-increase, multiplicative-decrease concurrency limit.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut limit = Aimd::new(64);
+/// while in_flight.len() < limit.limit() {
+///     // start another server
+/// }
+/// limit.record(result.is_timeout());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Aimd {
+    limit: usize,
+    max: usize,
+    threshold: f64,
+    /// Whether the limit still doubles per window
+    slow_start: bool,
+    /// Results seen in the current window
+    seen: usize,
+    /// Timeouts among them
+    timeouts: usize,
+    /// Highest limit reached
+    peak: usize,
+}
+
+impl Aimd {
+    /// Start at [`DEFAULT_INITIAL`] servers and never exceed `max`.
+    #[must_use]
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        let limit = DEFAULT_INITIAL.min(max);
+        Self {
+            limit,
+            max,
+            threshold: DEFAULT_TIMEOUT_THRESHOLD,
+            slow_start: true,
+            seen: 0,
+            timeouts: 0,
+            peak: limit,
+        }
+    }
+
+    /// Halve the limit when more than `threshold` (0.0 to 1.0) of a
+    /// window's results are timeouts.
+    #[must_use]
+    pub const fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Servers that may be tested at the same time now.
+    #[must_use]
+    pub const fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Highest limit reached so far.
+    #[must_use]
+    pub const fn peak(&self) -> usize {
+        self.peak
+    }
+
+    /// Count a finished server, adjusting the limit when it completes a
+    /// window.
+    pub fn record(&mut self, timed_out: bool) {
+        self.seen += 1;
+        self.timeouts += usize::from(timed_out);
+        if self.seen < self.limit {
+            return;
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let rate = self.timeouts as f64 / self.seen as f64;
+        if rate > self.threshold {
+            self.limit = (self.limit / 2).max(1);
+            self.slow_start = false;
+        } else if self.slow_start {
+            self.limit = (self.limit * 2).min(self.max);
+        } else {
+            self.limit = (self.limit + 1).min(self.max);
+        }
+        self.peak = self.peak.max(self.limit);
+        self.seen = 0;
+        self.timeouts = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aimd() {
+        let mut aimd = Aimd::new(20);
+        let window = |aimd: &mut Aimd, timeouts: usize| {
+            let size = aimd.limit();
+            for i in 0..size {
+                aimd.record(i < timeouts);
+            }
+            aimd.limit()
+        };
+
+        // Slow start doubles, up to the ceiling
+        assert_eq!(aimd.limit(), 4);
+        assert_eq!(window(&mut aimd, 2), 8);
+        assert_eq!(window(&mut aimd, 0), 16);
+        assert_eq!(window(&mut aimd, 0), 20);
+
+        // Mostly timeouts: halve, then grow by one
+        assert_eq!(window(&mut aimd, 15), 10);
+        assert_eq!(window(&mut aimd, 5), 11);
+        assert_eq!(aimd.peak(), 20);
+
+        // Never below one
+        for _ in 0..5 {
+            window(&mut aimd, usize::MAX);
+        }
+        assert_eq!(aimd.limit(), 1);
+    }
+}
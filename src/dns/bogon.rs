@@ -0,0 +1,131 @@
+//! Bogon (reserved address) detection.
+//!
+//! Public domains never legitimately resolve to private, loopback,
+//! documentation or otherwise reserved addresses. When a resolver hands
+//! one out, it is filtering or tampering with the answer, which makes a
+//! bogon a far stronger signal than a plain mismatch.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Reserved IPv4 ranges (network address and prefix length).
+const BOGON_V4: &[(Ipv4Addr, u8)] = &[
+    (Ipv4Addr::UNSPECIFIED, 8),           // "this" network
+    (Ipv4Addr::new(10, 0, 0, 0), 8),      // private
+    (Ipv4Addr::new(100, 64, 0, 0), 10),   // carrier-grade NAT
+    (Ipv4Addr::new(127, 0, 0, 0), 8),     // loopback
+    (Ipv4Addr::new(169, 254, 0, 0), 16),  // link local
+    (Ipv4Addr::new(172, 16, 0, 0), 12),   // private
+    (Ipv4Addr::new(192, 0, 0, 0), 24),    // IETF protocol assignments
+    (Ipv4Addr::new(192, 0, 2, 0), 24),    // TEST-NET-1
+    (Ipv4Addr::new(192, 168, 0, 0), 16),  // private
+    (Ipv4Addr::new(198, 18, 0, 0), 15),   // benchmarking
+    (Ipv4Addr::new(198, 51, 100, 0), 24), // TEST-NET-2
+    (Ipv4Addr::new(203, 0, 113, 0), 24),  // TEST-NET-3
+    (Ipv4Addr::new(224, 0, 0, 0), 4),     // multicast
+    (Ipv4Addr::new(240, 0, 0, 0), 4),     // reserved, broadcast
+];
+
+/// Reserved IPv6 ranges (network address and prefix length).
+const BOGON_V6: &[(Ipv6Addr, u8)] = &[
+    (Ipv6Addr::UNSPECIFIED, 128),                         // unspecified
+    (Ipv6Addr::LOCALHOST, 128),                           // loopback
+    (Ipv6Addr::new(0x100, 0, 0, 0, 0, 0, 0, 0), 64),      // discard only
+    (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32), // documentation
+    (Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7),      // unique local
+    (Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10),     // link local
+    (Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0), 8),      // multicast
+];
+
+/// Check whether an IPv4 address lies inside `network/prefix`.
+pub(crate) fn in_v4_range(ip: Ipv4Addr, network: Ipv4Addr, prefix: u8) -> bool {
+    let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+    u32::from(ip) & mask == u32::from(network) & mask
+}
+
+/// Check whether an IPv6 address lies inside `network/prefix`.
+pub(crate) fn in_v6_range(ip: Ipv6Addr, network: Ipv6Addr, prefix: u8) -> bool {
+    let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+    u128::from(ip) & mask == u128::from(network) & mask
+}
+
+/// Check whether an address is reserved and never valid in a public answer.
+///
+/// IPv4-mapped IPv6 addresses are checked against the IPv4 table.
+#[must_use]
+pub fn is_bogon(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => BOGON_V4
+            .iter()
+            .any(|&(network, prefix)| in_v4_range(*v4, network, prefix)),
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or_else(
+            || {
+                BOGON_V6
+                    .iter()
+                    .any(|&(network, prefix)| in_v6_range(*v6, network, prefix))
+            },
+            |v4| is_bogon(&IpAddr::V4(v4)),
+        ),
+    }
+}
+
+/// Return the bogons in `ips` that the reference answer does not contain.
+///
+/// A bogon that the trusted resolver also returns is the domain's real
+/// record (some names deliberately point at 127.0.0.1), not tampering.
+///
+/// # Arguments
+///
+/// * `ips` - Addresses returned by the resolver under test
+/// * `reference_ips` - Addresses returned by a trusted resolver
+#[must_use]
+pub fn unexpected_bogons(ips: &[IpAddr], reference_ips: &[IpAddr]) -> Vec<IpAddr> {
+    ips.iter()
+        .filter(|ip| is_bogon(ip) && !reference_ips.contains(ip))
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_bogon_v4() {
+        for bogon in [
+            "0.0.0.0",
+            "10.1.2.3",
+            "100.64.0.1",
+            "127.0.0.1",
+            "172.31.255.255",
+            "192.168.1.1",
+            "198.51.100.7",
+            "255.255.255.255",
+        ] {
+            assert!(is_bogon(&ip(bogon)), "{bogon} should be a bogon");
+        }
+        for public in ["1.1.1.1", "8.8.8.8", "100.128.0.1", "172.32.0.1"] {
+            assert!(!is_bogon(&ip(public)), "{public} should not be a bogon");
+        }
+    }
+
+    #[test]
+    fn test_bogon_v6() {
+        assert!(is_bogon(&ip("::")));
+        assert!(is_bogon(&ip("::1")));
+        assert!(is_bogon(&ip("2001:db8::1")));
+        assert!(is_bogon(&ip("fd00::1")));
+        assert!(is_bogon(&ip("::ffff:127.0.0.1")));
+        assert!(!is_bogon(&ip("2001:4860:4860::8888")));
+    }
+
+    #[test]
+    fn test_unexpected_bogons() {
+        let system = [ip("127.0.0.1"), ip("93.184.216.34")];
+        assert_eq!(unexpected_bogons(&system, &[]), vec![ip("127.0.0.1")]);
+        assert!(unexpected_bogons(&system, &[ip("127.0.0.1")]).is_empty());
+    }
+}
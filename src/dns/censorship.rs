@@ -0,0 +1,380 @@
+//! GFW-specific censorship detection.
+//!
+//! Generic pollution detection only notices that the system resolver
+//! disagrees with a public one. This module looks for fingerprints that
+//! are specific to the Great Firewall's DNS injection:
+//!
+//! - forged answers drawn from a small, well-known pool of addresses
+//! - forged answers pointing into unrelated foreign services' ranges
+//! - several replies racing for a single UDP query (duplicate injection)
+//! - NXDOMAIN replies without the SOA record a real server would attach
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::dns::bogon::in_v4_range;
+use crate::dns::query::{DnsResponse, Exchange, NetworkExchange, DNS_PORT};
+use crate::error::Result;
+use hickory_resolver::proto::op::ResponseCode;
+use hickory_resolver::proto::rr::{RData, RecordType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Addresses historically returned by GFW DNS injection.
+///
+/// None of these host the domains they are returned for.
+const GFW_INJECTED_IPS: &[Ipv4Addr] = &[
+    Ipv4Addr::new(4, 36, 66, 178),
+    Ipv4Addr::new(8, 7, 198, 45),
+    Ipv4Addr::new(37, 61, 54, 158),
+    Ipv4Addr::new(46, 82, 174, 68),
+    Ipv4Addr::new(59, 24, 3, 173),
+    Ipv4Addr::new(64, 33, 88, 161),
+    Ipv4Addr::new(64, 33, 99, 47),
+    Ipv4Addr::new(64, 66, 163, 251),
+    Ipv4Addr::new(65, 104, 202, 252),
+    Ipv4Addr::new(65, 160, 219, 113),
+    Ipv4Addr::new(66, 45, 252, 237),
+    Ipv4Addr::new(72, 14, 205, 99),
+    Ipv4Addr::new(72, 14, 205, 104),
+    Ipv4Addr::new(78, 16, 49, 15),
+    Ipv4Addr::new(93, 46, 8, 89),
+    Ipv4Addr::new(128, 121, 126, 139),
+    Ipv4Addr::new(159, 106, 121, 75),
+    Ipv4Addr::new(169, 132, 13, 103),
+    Ipv4Addr::new(192, 67, 198, 6),
+    Ipv4Addr::new(202, 106, 1, 2),
+    Ipv4Addr::new(202, 181, 7, 85),
+    Ipv4Addr::new(203, 98, 7, 65),
+    Ipv4Addr::new(203, 161, 230, 171),
+    Ipv4Addr::new(207, 12, 88, 98),
+    Ipv4Addr::new(208, 56, 31, 43),
+    Ipv4Addr::new(209, 36, 73, 33),
+    Ipv4Addr::new(209, 145, 54, 50),
+    Ipv4Addr::new(209, 220, 30, 174),
+    Ipv4Addr::new(211, 94, 66, 147),
+    Ipv4Addr::new(213, 169, 251, 35),
+    Ipv4Addr::new(216, 221, 188, 182),
+    Ipv4Addr::new(216, 234, 179, 13),
+    Ipv4Addr::new(243, 185, 187, 39),
+    Ipv4Addr::new(253, 157, 14, 165),
+];
+
+/// Foreign service ranges the GFW has injected since around 2019
+/// (Facebook, Twitter and Dropbox address space).
+///
+/// Each entry is a network address and prefix length.
+const GFW_FOREIGN_RANGES: &[(Ipv4Addr, u8)] = &[
+    (Ipv4Addr::new(31, 13, 64, 0), 18),
+    (Ipv4Addr::new(69, 171, 224, 0), 19),
+    (Ipv4Addr::new(157, 240, 0, 0), 16),
+    (Ipv4Addr::new(104, 244, 40, 0), 21),
+    (Ipv4Addr::new(199, 59, 148, 0), 22),
+    (Ipv4Addr::new(108, 160, 160, 0), 20),
+    (Ipv4Addr::new(162, 125, 0, 0), 16),
+];
+
+/// Resolver queried by the active probe. The path to it leaves the
+/// country, so injected replies race the genuine one.
+const DEFAULT_PROBE_RESOLVER: Ipv4Addr = Ipv4Addr::new(8, 8, 8, 8);
+
+/// Default time to wait for the first reply of the active probe.
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Default time to keep listening for injected duplicates.
+const DEFAULT_DUPLICATE_WINDOW: Duration = Duration::from_millis(500);
+
+/// A single piece of evidence for GFW-style censorship.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CensorshipSignal {
+    /// The answer contains an address from the known injection pool
+    KnownInjectedIp {
+        /// The injected address
+        ip: IpAddr,
+    },
+    /// The answer points into an unrelated foreign service's range
+    ForeignServiceRange {
+        /// The suspicious address
+        ip: IpAddr,
+    },
+    /// More than one reply arrived for a single UDP query
+    DuplicateAnswers {
+        /// Number of replies received
+        count: usize,
+    },
+    /// An NXDOMAIN reply carried no SOA record in its authority section
+    SoaLessNxdomain,
+}
+
+impl std::fmt::Display for CensorshipSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::KnownInjectedIp { ip } => write!(f, "known injected IP {ip}"),
+            Self::ForeignServiceRange { ip } => write!(f, "foreign service range {ip}"),
+            Self::DuplicateAnswers { count } => write!(f, "{count} replies to one query"),
+            Self::SoaLessNxdomain => write!(f, "NXDOMAIN without SOA"),
+        }
+    }
+}
+
+/// Find the foreign service range containing `ip`, if any.
+fn foreign_range_of(ip: &IpAddr) -> Option<(Ipv4Addr, u8)> {
+    let IpAddr::V4(v4) = ip else {
+        return None;
+    };
+    GFW_FOREIGN_RANGES
+        .iter()
+        .copied()
+        .find(|&(network, prefix)| in_v4_range(*v4, network, prefix))
+}
+
+/// Inspect answer addresses for GFW injection fingerprints.
+///
+/// A foreign service range only counts as suspicious when the reference
+/// answer does not point into the same range, so genuinely resolving
+/// `facebook.com` is not flagged.
+///
+/// # Arguments
+///
+/// * `ips` - Addresses returned by the resolver under test
+/// * `reference_ips` - Addresses returned by a trusted resolver
+#[must_use]
+pub fn ip_signals(ips: &[IpAddr], reference_ips: &[IpAddr]) -> Vec<CensorshipSignal> {
+    let reference_ranges: HashSet<_> = reference_ips.iter().filter_map(foreign_range_of).collect();
+
+    ips.iter()
+        .filter_map(|ip| {
+            let known = matches!(ip, IpAddr::V4(v4) if GFW_INJECTED_IPS.contains(v4));
+            if known {
+                return Some(CensorshipSignal::KnownInjectedIp { ip: *ip });
+            }
+            match foreign_range_of(ip) {
+                Some(range) if !reference_ranges.contains(&range) => {
+                    Some(CensorshipSignal::ForeignServiceRange { ip: *ip })
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Inspect raw replies to a single query for injection fingerprints.
+///
+/// # Arguments
+///
+/// * `responses` - Every reply received for one UDP query
+#[must_use]
+pub fn response_signals(responses: &[DnsResponse]) -> Vec<CensorshipSignal> {
+    let mut signals = Vec::new();
+
+    // Genuine servers answer once; differing extra replies mean a racer
+    let distinct: HashSet<Vec<IpAddr>> = responses
+        .iter()
+        .map(|r| {
+            let mut ips = r.answer_ips();
+            ips.sort();
+            ips
+        })
+        .collect();
+    if responses.len() > 1 && distinct.len() > 1 {
+        signals.push(CensorshipSignal::DuplicateAnswers {
+            count: responses.len(),
+        });
+    }
+
+    let soa_less = responses.iter().any(|r| {
+        r.message.response_code() == ResponseCode::NXDomain
+            && !r
+                .message
+                .name_servers()
+                .iter()
+                .any(|ns| matches!(ns.data(), Some(RData::SOA(_))))
+    });
+    if soa_less {
+        signals.push(CensorshipSignal::SoaLessNxdomain);
+    }
+
+    signals
+}
+
+/// Active GFW injection probe.
+///
+/// Sends a single UDP query across the border and inspects every reply
+/// that comes back.
+///
+/// # Example
+///
+/// ```ignore
+/// let detector = CensorshipDetector::new();
+/// let signals = detector.probe("google.com", &[]).await?;
+/// ```
+pub struct CensorshipDetector {
+    resolver: SocketAddr,
+    timeout: Duration,
+    window: Duration,
+    exchange: Arc<dyn Exchange>,
+}
+
+impl CensorshipDetector {
+    /// Create a detector probing through Google Public DNS.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_resolver(SocketAddr::new(
+            IpAddr::V4(DEFAULT_PROBE_RESOLVER),
+            DNS_PORT,
+        ))
+    }
+
+    /// Create a detector probing through a specific resolver.
+    #[must_use]
+    pub fn with_resolver(resolver: SocketAddr) -> Self {
+        Self {
+            resolver,
+            timeout: DEFAULT_PROBE_TIMEOUT,
+            window: DEFAULT_DUPLICATE_WINDOW,
+            exchange: Arc::new(NetworkExchange),
+        }
+    }
+
+    /// Send the probe through `exchange` instead of the network.
+    #[must_use]
+    pub fn with_exchange(mut self, exchange: Arc<dyn Exchange>) -> Self {
+        self.exchange = exchange;
+        self
+    }
+
+    /// Probe a domain and return all censorship signals found.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - Domain name to probe
+    /// * `reference_ips` - Addresses considered genuine for this domain
+    #[tracing::instrument(level = "debug", skip(self, reference_ips))]
+    pub async fn probe(
+        &self,
+        domain: &str,
+        reference_ips: &[IpAddr],
+    ) -> Result<Vec<CensorshipSignal>> {
+        let responses = self
+            .exchange
+            .udp(
+                self.resolver,
+                domain,
+                RecordType::A,
+                self.timeout,
+                Some(self.window),
+            )
+            .await?;
+
+        let mut signals = response_signals(&responses);
+        for response in &responses {
+            for signal in ip_signals(&response.answer_ips(), reference_ips) {
+                if !signals.contains(&signal) {
+                    signals.push(signal);
+                }
+            }
+        }
+
+        Ok(signals)
+    }
+}
+
+impl Default for CensorshipDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_resolver::proto::op::Message;
+    use hickory_resolver::proto::rr::rdata::A;
+    use hickory_resolver::proto::rr::rdata::SOA;
+    use hickory_resolver::proto::rr::{Name, Record};
+
+    fn response(code: ResponseCode, answers: &[Ipv4Addr], with_soa: bool) -> DnsResponse {
+        let name = Name::from_ascii("example.com.").unwrap();
+        let mut message = Message::new();
+        message.set_response_code(code);
+        for ip in answers {
+            message.add_answer(Record::from_rdata(name.clone(), 60, RData::A(A(*ip))));
+        }
+        if with_soa {
+            let soa = SOA::new(name.clone(), name.clone(), 1, 3600, 600, 86400, 300);
+            message.add_name_server(Record::from_rdata(name, 300, RData::SOA(soa)));
+        }
+        DnsResponse {
+            message,
+            elapsed: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn test_known_injected_ip() {
+        let ips = vec!["93.46.8.89".parse().unwrap(), "1.2.3.5".parse().unwrap()];
+        let signals = ip_signals(&ips, &[]);
+        assert_eq!(
+            signals,
+            vec![CensorshipSignal::KnownInjectedIp {
+                ip: "93.46.8.89".parse().unwrap()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_foreign_range_respects_reference() {
+        let ips: Vec<IpAddr> = vec!["157.240.3.35".parse().unwrap()];
+
+        // Not flagged when the reference answer lives in the same range
+        let reference: Vec<IpAddr> = vec!["157.240.7.35".parse().unwrap()];
+        assert!(ip_signals(&ips, &reference).is_empty());
+
+        // Flagged when the reference answer is elsewhere
+        let reference: Vec<IpAddr> = vec!["142.250.1.100".parse().unwrap()];
+        assert_eq!(ip_signals(&ips, &reference).len(), 1);
+    }
+
+    #[test]
+    fn test_duplicate_answers() {
+        let responses = vec![
+            response(
+                ResponseCode::NoError,
+                &[Ipv4Addr::new(59, 24, 3, 173)],
+                false,
+            ),
+            response(
+                ResponseCode::NoError,
+                &[Ipv4Addr::new(142, 250, 1, 1)],
+                false,
+            ),
+        ];
+        let signals = response_signals(&responses);
+        assert_eq!(
+            signals,
+            vec![CensorshipSignal::DuplicateAnswers { count: 2 }]
+        );
+
+        // Identical retransmissions are not injection
+        let responses = vec![
+            response(ResponseCode::NoError, &[Ipv4Addr::new(1, 1, 1, 1)], false),
+            response(ResponseCode::NoError, &[Ipv4Addr::new(1, 1, 1, 1)], false),
+        ];
+        assert!(response_signals(&responses).is_empty());
+    }
+
+    #[test]
+    fn test_soa_less_nxdomain() {
+        let forged = vec![response(ResponseCode::NXDomain, &[], false)];
+        assert_eq!(
+            response_signals(&forged),
+            vec![CensorshipSignal::SoaLessNxdomain]
+        );
+
+        let genuine = vec![response(ResponseCode::NXDomain, &[], true)];
+        assert!(response_signals(&genuine).is_empty());
+    }
+}
@@ -0,0 +1,557 @@
+//! Delegation health check.
+//!
+//! When a domain resolves inconsistently the cause is often not a resolver
+//! but the domain's own nameservers. The parent zone (e.g. `com` for
+//! `example.com`) delegates the domain to a set of nameservers, and each of
+//! those has to answer authoritatively and list the same set itself. This
+//! module follows referrals from the root servers to the parent's
+//! delegation, then asks every delegated nameserver for the domain's NS
+//! set and reports:
+//!
+//! - lame delegations: nameservers that answer, but not authoritatively
+//! - unreachable nameservers, or names without an address
+//! - nameservers listed only at the parent or only at the child
+
+use crate::config::presets;
+use crate::dns::query::{self, Exchange, NetworkExchange, DNS_PORT};
+use crate::error::{Error, Result};
+use futures::future::join_all;
+use hickory_resolver::proto::op::{Message, ResponseCode};
+use hickory_resolver::proto::rr::{Name, RecordType};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default per-query timeout.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Resolver asked for the addresses of nameservers without glue.
+const DEFAULT_RESOLVER: SocketAddr =
+    SocketAddr::new(IpAddr::V4(std::net::Ipv4Addr::new(1, 1, 1, 1)), DNS_PORT);
+
+/// Referrals followed from the root before giving up.
+const MAX_REFERRALS: usize = 16;
+
+/// How a nameserver answered for the domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NsState {
+    /// Answered authoritatively with the domain's NS set
+    Ok,
+    /// Answered, but not authoritatively
+    Lame,
+    /// Did not answer
+    Unreachable,
+}
+
+impl std::fmt::Display for NsState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok => write!(f, "ok"),
+            Self::Lame => write!(f, "lame"),
+            Self::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+/// One address of a delegated nameserver.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NameserverCheck {
+    /// Host name of the nameserver, without the trailing dot
+    pub host: String,
+    /// Address that was queried
+    pub ip: IpAddr,
+    /// How it answered
+    pub state: NsState,
+    /// Response code, e.g. `NoError` or `Refused`
+    pub rcode: Option<String>,
+    /// Time until the response arrived in milliseconds
+    pub latency_ms: Option<f64>,
+    /// NS set the nameserver returned, sorted
+    pub ns: Vec<String>,
+}
+
+/// Something wrong with a delegation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Issue {
+    /// A nameserver does not answer authoritatively for the domain
+    Lame { host: String, ip: IpAddr },
+    /// A nameserver does not answer at all
+    Unreachable { host: String, ip: IpAddr },
+    /// A nameserver's name has no address
+    Unresolvable { host: String },
+    /// The parent delegates to a nameserver the child does not list
+    OnlyAtParent { host: String },
+    /// The child lists a nameserver the parent does not delegate to
+    OnlyAtChild { host: String },
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lame { host, ip } => write!(f, "{host} ({ip}) is lame"),
+            Self::Unreachable { host, ip } => write!(f, "{host} ({ip}) does not answer"),
+            Self::Unresolvable { host } => write!(f, "{host} has no address"),
+            Self::OnlyAtParent { host } => write!(f, "{host} is only listed at the parent"),
+            Self::OnlyAtChild { host } => write!(f, "{host} is only listed at the child"),
+        }
+    }
+}
+
+/// Outcome of checking a domain's delegation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DelegationReport {
+    /// Domain checked, without the trailing dot
+    pub domain: String,
+    /// Zone that delegates the domain, e.g. `com`
+    pub parent_zone: String,
+    /// Nameservers the parent delegates to, in the referral's order
+    pub parent_ns: Vec<String>,
+    /// Every address of every delegated nameserver
+    pub nameservers: Vec<NameserverCheck>,
+    /// Problems found, empty if the delegation is healthy
+    pub issues: Vec<Issue>,
+}
+
+impl DelegationReport {
+    /// Whether no problems were found.
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Delegation checker.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = DelegationChecker::new().check("example.com").await?;
+/// for issue in &report.issues {
+///     println!("{issue}");
+/// }
+/// ```
+pub struct DelegationChecker {
+    timeout: Duration,
+    resolver: SocketAddr,
+    exchange: Arc<dyn Exchange>,
+}
+
+impl DelegationChecker {
+    /// Create a checker with the default timeout.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_TIMEOUT)
+    }
+
+    /// Create a checker with a custom per-query timeout.
+    #[must_use]
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            resolver: DEFAULT_RESOLVER,
+            exchange: Arc::new(NetworkExchange),
+        }
+    }
+
+    /// Look up nameservers without glue at this resolver instead of
+    /// 1.1.1.1.
+    #[must_use]
+    pub const fn with_resolver(mut self, resolver: SocketAddr) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Send the queries through `exchange` instead of the network.
+    #[must_use]
+    pub fn with_exchange(mut self, exchange: Arc<dyn Exchange>) -> Self {
+        self.exchange = exchange;
+        self
+    }
+
+    /// Check the delegation of `domain`.
+    ///
+    /// Nameservers are queried over IPv4.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the domain is not a valid name, does not exist,
+    /// is not delegated, or no server on the way from the root answers.
+    pub async fn check(&self, domain: &str) -> Result<DelegationReport> {
+        let domain = Name::from_ascii(domain)
+            .map_err(|e| Error::parse(format!("Invalid domain '{domain}': {e}")))?
+            .append_domain(&Name::root())
+            .map_err(|e| Error::parse(e.to_string()))?;
+        let (parent_zone, delegated) = self.parent_delegation(&domain).await?;
+
+        // Nameservers without glue are looked up, and then every address
+        // is asked, all at the same time
+        let addresses = join_all(delegated.iter().map(|(host, glue)| async move {
+            let ips: Vec<IpAddr> = glue.iter().copied().filter(IpAddr::is_ipv4).collect();
+            if ips.is_empty() {
+                self.addresses(host).await
+            } else {
+                ips
+            }
+        }))
+        .await;
+        let checks = join_all(
+            delegated
+                .iter()
+                .zip(&addresses)
+                .flat_map(|((host, _), ips)| {
+                    ips.iter()
+                        .map(|&ip| self.query_nameserver(&domain, display(host), ip))
+                }),
+        )
+        .await;
+
+        let mut nameservers = Vec::new();
+        let mut issues = Vec::new();
+        let mut checks = checks.into_iter();
+        for ((host, _), ips) in delegated.iter().zip(&addresses) {
+            let host_name = display(host);
+            if ips.is_empty() {
+                issues.push(Issue::Unresolvable { host: host_name });
+                continue;
+            }
+            for check in checks.by_ref().take(ips.len()) {
+                match check.state {
+                    NsState::Ok => {}
+                    NsState::Lame => issues.push(Issue::Lame {
+                        host: host_name.clone(),
+                        ip: check.ip,
+                    }),
+                    NsState::Unreachable => issues.push(Issue::Unreachable {
+                        host: host_name.clone(),
+                        ip: check.ip,
+                    }),
+                }
+                nameservers.push(check);
+            }
+        }
+
+        let parent_ns: Vec<String> = delegated.iter().map(|(host, _)| display(host)).collect();
+        let parent_set: BTreeSet<&str> = parent_ns.iter().map(String::as_str).collect();
+        let child_set: BTreeSet<&str> = nameservers
+            .iter()
+            .filter(|check| check.state == NsState::Ok)
+            .flat_map(|check| check.ns.iter().map(String::as_str))
+            .collect();
+        // Without an authoritative answer there is no child set to compare
+        if !child_set.is_empty() {
+            issues.extend(
+                parent_set
+                    .difference(&child_set)
+                    .map(|host| Issue::OnlyAtParent {
+                        host: (*host).to_string(),
+                    }),
+            );
+            issues.extend(
+                child_set
+                    .difference(&parent_set)
+                    .map(|host| Issue::OnlyAtChild {
+                        host: (*host).to_string(),
+                    }),
+            );
+        }
+
+        Ok(DelegationReport {
+            domain: display(&domain),
+            parent_zone: display(&parent_zone),
+            parent_ns,
+            nameservers,
+            issues,
+        })
+    }
+
+    /// Follow referrals from the root to the zone that delegates `domain`.
+    ///
+    /// Returns that zone and the nameservers it delegates to, with glue.
+    async fn parent_delegation(&self, domain: &Name) -> Result<(Name, Vec<(Name, Vec<IpAddr>)>)> {
+        let mut zone = Name::root();
+        let mut servers = presets::root_hints();
+        for _ in 0..MAX_REFERRALS {
+            let message = self.ask_any(&servers, domain).await?;
+            if message.response_code() == ResponseCode::NXDomain {
+                return Err(Error::config(format!("{} does not exist", display(domain))));
+            }
+            let cut = message
+                .name_servers()
+                .iter()
+                .find(|record| record.record_type() == RecordType::NS)
+                .map(|record| record.name().clone());
+
+            match cut {
+                // A referral to the domain itself: this is the delegation
+                Some(cut) if !message.authoritative() && &cut == domain => {
+                    return Ok((zone, query::nameservers(&message)));
+                }
+                // A referral further down: ask the nameservers of that zone
+                Some(cut)
+                    if !message.authoritative()
+                        && cut.num_labels() > zone.num_labels()
+                        && cut.zone_of(domain) =>
+                {
+                    let mut next = Vec::new();
+                    for (host, glue) in query::nameservers(&message) {
+                        let glue: Vec<IpAddr> = glue.into_iter().filter(IpAddr::is_ipv4).collect();
+                        if glue.is_empty() {
+                            next.extend(self.addresses(&host).await);
+                        } else {
+                            next.extend(glue);
+                        }
+                    }
+                    zone = cut;
+                    servers = next;
+                }
+                // Parent and child served by the same nameservers, which
+                // answer for the domain's apex directly
+                _ if message.authoritative()
+                    && message.answers().iter().any(|record| {
+                        record.record_type() == RecordType::NS && record.name() == domain
+                    }) =>
+                {
+                    return Ok((zone, query::nameservers(&message)));
+                }
+                _ => {
+                    return Err(Error::config(format!(
+                        "{} is not delegated, it is part of zone {}",
+                        display(domain),
+                        display(&zone)
+                    )));
+                }
+            }
+        }
+        Err(Error::config(format!(
+            "Too many referrals looking up {}",
+            display(domain)
+        )))
+    }
+
+    /// Ask the servers one after another, until one answers, for the NS
+    /// records of `domain`.
+    ///
+    /// Referrals with glue rarely fit into a plain UDP response, so they
+    /// are fetched over TCP.
+    async fn ask_any(&self, servers: &[IpAddr], domain: &Name) -> Result<Message> {
+        let name = domain.to_string();
+        let mut last_error = None;
+        for &ip in servers {
+            let server = SocketAddr::new(ip, DNS_PORT);
+            match self
+                .exchange
+                .tcp(server, &name, RecordType::NS, self.timeout)
+                .await
+            {
+                Ok(response) => return Ok(response.message),
+                Err(e) => {
+                    tracing::debug!("{server} did not answer for {name}: {e}");
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| Error::config(format!("No nameserver address found for {name}"))))
+    }
+
+    /// IPv4 addresses of a nameserver without glue, from the resolver.
+    async fn addresses(&self, host: &Name) -> Vec<IpAddr> {
+        let name = host.to_string();
+        match self
+            .exchange
+            .udp(self.resolver, &name, RecordType::A, self.timeout, None)
+            .await
+        {
+            Ok(responses) => responses
+                .into_iter()
+                .next()
+                .map(|response| response.answer_ips())
+                .unwrap_or_default(),
+            Err(e) => {
+                tracing::debug!("Looking up {name} at {} failed: {e}", self.resolver);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Ask one nameserver address for the NS set of `domain`.
+    async fn query_nameserver(&self, domain: &Name, host: String, ip: IpAddr) -> NameserverCheck {
+        let server = SocketAddr::new(ip, DNS_PORT);
+        let response = match self
+            .exchange
+            .udp(
+                server,
+                &domain.to_string(),
+                RecordType::NS,
+                self.timeout,
+                None,
+            )
+            .await
+        {
+            Ok(responses) => responses.into_iter().next(),
+            Err(e) => {
+                tracing::debug!("Query for {domain} to {server} failed: {e}");
+                None
+            }
+        };
+        let Some(response) = response else {
+            return NameserverCheck {
+                host,
+                ip,
+                state: NsState::Unreachable,
+                rcode: None,
+                latency_ms: None,
+                ns: Vec::new(),
+            };
+        };
+
+        let message = &response.message;
+        let mut ns: Vec<String> = query::nameservers(message)
+            .iter()
+            .map(|(host, _)| display(host))
+            .collect();
+        ns.sort();
+        let authoritative = message.authoritative()
+            && message.response_code() == ResponseCode::NoError
+            && !ns.is_empty();
+        NameserverCheck {
+            host,
+            ip,
+            state: if authoritative {
+                NsState::Ok
+            } else {
+                NsState::Lame
+            },
+            rcode: Some(format!("{:?}", message.response_code())),
+            latency_ms: Some(response.elapsed.as_secs_f64() * 1000.0),
+            ns: if authoritative { ns } else { Vec::new() },
+        }
+    }
+}
+
+impl Default for DelegationChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A name without the trailing dot, lowercased, the way it is shown and
+/// compared.
+fn display(name: &Name) -> String {
+    let name = name.to_lowercase().to_string();
+    match name.strip_suffix('.') {
+        Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::query::fake::{record, FakeExchange, Query, Reply};
+    use hickory_resolver::proto::rr::rdata::{A, NS};
+    use hickory_resolver::proto::rr::{RData, Record};
+    use std::net::Ipv4Addr;
+
+    fn ns(zone: &str, host: &str) -> Record {
+        record(
+            zone,
+            172_800,
+            RData::NS(NS(Name::from_ascii(host).unwrap())),
+        )
+    }
+
+    fn glue(host: &str, ip: [u8; 4]) -> Record {
+        record(host, 172_800, RData::A(Ipv4Addr::from(ip).into()))
+    }
+
+    /// The root refers `com` to 192.0.2.10, which delegates example.com to
+    /// ns1 (192.0.2.1), ns2 (192.0.2.2) and ns3.example.net (no glue,
+    /// 192.0.2.3 at the resolver 192.0.2.53). ns1 lists ns1, ns2 and ns4,
+    /// ns2 refuses and ns3 is silent.
+    fn fake_nameservers() -> FakeExchange {
+        let server = |last| Query::any().server([192, 0, 2, last]);
+        let parent_hosts = ["ns1.example.com.", "ns2.example.com.", "ns3.example.net."];
+        let child_hosts = ["ns1.example.com.", "ns2.example.com.", "ns4.example.com."];
+        FakeExchange::new()
+            .on(
+                Query::any().name("invalid.example.com.").tcp(),
+                Reply::rcode(ResponseCode::NXDomain),
+            )
+            .on(
+                server(10).tcp(),
+                Reply::empty()
+                    .with_authority(parent_hosts.map(|host| ns("example.com.", host)))
+                    .with_additional([
+                        glue("ns1.example.com.", [192, 0, 2, 1]),
+                        glue("ns2.example.com.", [192, 0, 2, 2]),
+                    ]),
+            )
+            .on(
+                Query::any().tcp(),
+                Reply::empty()
+                    .with_authority([ns("com.", "a.gtld-servers.net.")])
+                    .with_additional([glue("a.gtld-servers.net.", [192, 0, 2, 10])]),
+            )
+            .on(
+                server(53).name("ns3.example.net."),
+                Reply::answers([record(
+                    "ns3.example.net.",
+                    300,
+                    RData::A(A::new(192, 0, 2, 3)),
+                )]),
+            )
+            .on(
+                server(1),
+                Reply::answers(child_hosts.map(|host| ns("example.com.", host))).authoritative(),
+            )
+            .on(server(2), Reply::rcode(ResponseCode::Refused))
+    }
+
+    #[tokio::test]
+    async fn test_check_delegation() {
+        let checker = DelegationChecker::new()
+            .with_resolver(SocketAddr::from(([192, 0, 2, 53], 53)))
+            .with_exchange(Arc::new(fake_nameservers()));
+        let report = checker.check("Example.com").await.unwrap();
+
+        assert_eq!(report.domain, "example.com");
+        assert_eq!(report.parent_zone, "com");
+        assert_eq!(
+            report.parent_ns,
+            ["ns1.example.com", "ns2.example.com", "ns3.example.net"]
+        );
+        let states: Vec<NsState> = report.nameservers.iter().map(|ns| ns.state).collect();
+        assert_eq!(states, [NsState::Ok, NsState::Lame, NsState::Unreachable]);
+        assert_eq!(report.nameservers[2].ip, IpAddr::from([192, 0, 2, 3]));
+        assert_eq!(report.nameservers[1].rcode.as_deref(), Some("Refused"));
+
+        let ip = |last| IpAddr::from([192, 0, 2, last]);
+        assert_eq!(
+            report.issues,
+            [
+                Issue::Lame {
+                    host: "ns2.example.com".to_string(),
+                    ip: ip(2)
+                },
+                Issue::Unreachable {
+                    host: "ns3.example.net".to_string(),
+                    ip: ip(3)
+                },
+                Issue::OnlyAtParent {
+                    host: "ns3.example.net".to_string()
+                },
+                Issue::OnlyAtChild {
+                    host: "ns4.example.com".to_string()
+                },
+            ]
+        );
+        assert!(!report.is_healthy());
+
+        assert!(checker.check("invalid.example.com").await.is_err());
+    }
+}
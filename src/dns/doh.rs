@@ -0,0 +1,301 @@
+//! DNS-over-HTTPS benchmarking.
+//!
+//! Sends RFC 8484 queries (`POST` with an `application/dns-message` body)
+//! to endpoints over HTTP/1.1, HTTP/2 and HTTP/3 separately, so that
+//! the protocol versions of one provider can be compared from the local
+//! network. Requests go through the system `curl` binary like every other
+//! HTTP request of the crate; HTTP/3 needs a curl built with HTTP/3
+//! support.
+
+use crate::dns::query;
+use crate::dns::types::{ConnectionPhases, DnsServer, SpeedTestResult};
+use crate::error::{Error, Result};
+use crate::http::{self, HttpVersion, Transfer};
+use hickory_resolver::proto::op::{Message, MessageType};
+use hickory_resolver::proto::rr::RecordType;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Google Public DNS endpoint.
+pub const GOOGLE_URL: &str = "https://dns.google/dns-query";
+
+/// Cloudflare DNS endpoint.
+pub const CLOUDFLARE_URL: &str = "https://cloudflare-dns.com/dns-query";
+
+/// Endpoints tested when none are given.
+pub const DEFAULT_ENDPOINTS: &[&str] = &[GOOGLE_URL, CLOUDFLARE_URL];
+
+/// Domain queried by default.
+pub const DEFAULT_DOMAIN: &str = "example.com";
+
+/// Default number of queries per endpoint and version.
+pub const DEFAULT_COUNT: usize = 3;
+
+/// Default timeout for one query.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Media type of DNS wire-format messages.
+const CONTENT_TYPE: &str = "application/dns-message";
+
+/// DNS-over-HTTPS latency tester.
+///
+/// # Example
+///
+/// ```ignore
+/// let tester = DohTester::new();
+/// let result = tester.test(GOOGLE_URL, HttpVersion::Http3).await;
+/// ```
+#[derive(Debug, Clone)]
+pub struct DohTester {
+    /// Timeout for each query
+    timeout: Duration,
+    /// Number of queries per endpoint and version
+    count: usize,
+    /// Domain to query
+    domain: String,
+}
+
+impl DohTester {
+    /// Create a tester with default settings.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_settings(DEFAULT_TIMEOUT, DEFAULT_COUNT)
+    }
+
+    /// Create a tester with custom settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Timeout for each query
+    /// * `count` - Number of queries per endpoint and version
+    #[must_use]
+    pub fn with_settings(timeout: Duration, count: usize) -> Self {
+        Self {
+            timeout,
+            count: count.max(1),
+            domain: DEFAULT_DOMAIN.to_string(),
+        }
+    }
+
+    /// Query this domain instead of [`DEFAULT_DOMAIN`].
+    #[must_use]
+    pub fn with_domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = domain.into();
+        self
+    }
+
+    /// Test one endpoint over one HTTP version.
+    ///
+    /// Each query opens a fresh connection, so the latency includes the
+    /// TCP or QUIC and TLS handshakes but not the host name lookup; the
+    /// result's [`phases`](SpeedTestResult::phases) break it down. Each is
+    /// followed by a second query on the same kept-alive connection, whose
+    /// average is the result's
+    /// [`reused_latency_ms`](SpeedTestResult::reused_latency_ms). A query
+    /// answered over a different version than requested (a server without
+    /// HTTP/2 falls back to HTTP/1.1) counts as failed.
+    pub async fn test(&self, url: &str, version: HttpVersion) -> SpeedTestResult {
+        let server = endpoint_server(url);
+        let mut phases = Vec::with_capacity(self.count);
+        let mut reused = Vec::with_capacity(self.count);
+        let mut source_ip = None;
+        let mut last_error = None;
+
+        for _ in 0..self.count {
+            match self.query(url, version).await {
+                Ok(exchange) => {
+                    phases.push(exchange.cold);
+                    reused.extend(exchange.reused_ms);
+                    source_ip = exchange.local_ip.or(source_ip);
+                }
+                Err(e) => {
+                    tracing::debug!("DoH query to {url} over {version} failed: {e}");
+                    last_error = Some(e.to_string());
+                }
+            }
+        }
+
+        let result = match ConnectionPhases::mean(&phases) {
+            Some(mean) => {
+                let samples: Vec<f64> = phases.iter().map(ConnectionPhases::total_ms).collect();
+                let loss = 1.0 - samples.len() as f64 / self.count as f64;
+                let result = SpeedTestResult::success(server, mean.total_ms(), loss)
+                    .with_samples(samples)
+                    .with_phases(mean);
+                if reused.is_empty() {
+                    result
+                } else {
+                    result.with_reused_latency(reused.iter().sum::<f64>() / reused.len() as f64)
+                }
+            }
+            None => SpeedTestResult::failure(server, last_error.unwrap_or_default()),
+        };
+        result.with_http_version(version).with_source_ip(source_ip)
+    }
+
+    /// Test every endpoint over every version, one after another.
+    ///
+    /// Results are ordered by endpoint, then by version.
+    pub async fn test_all(
+        &self,
+        urls: &[String],
+        versions: &[HttpVersion],
+    ) -> Vec<SpeedTestResult> {
+        let mut results = Vec::with_capacity(urls.len() * versions.len());
+        for url in urls {
+            for &version in versions {
+                results.push(self.test(url, version).await);
+            }
+        }
+        results
+    }
+
+    /// Send a query on a fresh connection and again on the same
+    /// connection.
+    async fn query(&self, url: &str, version: HttpVersion) -> Result<Exchange> {
+        let (id, body) = query::build_query(&self.domain, RecordType::A)?;
+        let transfers =
+            http::post_timed(url, &body, CONTENT_TYPE, version, 2, self.timeout).await?;
+
+        let cold = &transfers[0];
+        check_answer(cold, id, version)?;
+        let warm = transfers
+            .get(1)
+            .filter(|warm| warm.connects == 0 && check_answer(warm, id, version).is_ok())
+            .map(|warm| phases(warm, version).total_ms());
+        Ok(Exchange {
+            cold: phases(cold, version),
+            reused_ms: warm,
+            local_ip: cold.local_ip,
+        })
+    }
+}
+
+/// Outcome of a query pair on one connection.
+struct Exchange {
+    /// Phases of the query on the fresh connection
+    cold: ConnectionPhases,
+    /// Latency in milliseconds of the query on the kept-alive connection,
+    /// if the server kept it open
+    reused_ms: Option<f64>,
+    /// Local address the queries were sent from
+    local_ip: Option<IpAddr>,
+}
+
+/// Check that a transfer is a DNS answer to query `id` over `version`.
+fn check_answer(transfer: &Transfer, id: u16, version: HttpVersion) -> Result<()> {
+    if transfer.status != 200 {
+        return Err(Error::network(format!("HTTP status {}", transfer.status)));
+    }
+    if transfer.version != Some(version) {
+        return Err(Error::network(format!(
+            "{version} not negotiated, the server answered over {}",
+            transfer
+                .version
+                .map_or_else(|| "an unknown version".to_string(), |v| v.to_string())
+        )));
+    }
+    let message = Message::from_vec(&transfer.body).map_err(|e| Error::parse(e.to_string()))?;
+    if message.message_type() != MessageType::Response || message.id() != id {
+        return Err(Error::parse("Response does not match the query"));
+    }
+    Ok(())
+}
+
+impl Default for DohTester {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Split a transfer's cumulative curl timings into connection phases.
+///
+/// curl marks QUIC connections as connected only once the handshake,
+/// TLS included, is done, so HTTP/3 has no separate TLS phase.
+fn phases(transfer: &Transfer, version: HttpVersion) -> ConnectionPhases {
+    let ms = |from: Duration, to: Duration| to.saturating_sub(from).as_secs_f64() * 1000.0;
+    let connected = transfer.connect.max(transfer.name_lookup);
+    if version == HttpVersion::Http3 || transfer.app_connect.is_zero() {
+        let connected = connected.max(transfer.app_connect);
+        return ConnectionPhases {
+            connect_ms: ms(transfer.name_lookup, connected),
+            tls_ms: None,
+            query_ms: ms(connected, transfer.total),
+        };
+    }
+    let secured = transfer.app_connect.max(connected);
+    ConnectionPhases {
+        connect_ms: ms(transfer.name_lookup, connected),
+        tls_ms: Some(ms(connected, secured)),
+        query_ms: ms(secured, transfer.total),
+    }
+}
+
+/// Describe an endpoint as a server named after its host.
+fn endpoint_server(url: &str) -> DnsServer {
+    let host = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .split(['/', '?'])
+        .next()
+        .unwrap_or(url);
+    DnsServer::new(host, url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_server() {
+        let server = endpoint_server(GOOGLE_URL);
+        assert_eq!(server.name, "dns.google");
+        assert_eq!(server.ip, GOOGLE_URL);
+        assert_eq!(
+            endpoint_server("https://doh.example:8443?dns").name,
+            "doh.example:8443"
+        );
+    }
+
+    fn transfer(name_lookup: u64, connect: u64, app_connect: u64, total: u64) -> Transfer {
+        Transfer {
+            version: None,
+            status: 200,
+            body: Vec::new(),
+            name_lookup: Duration::from_millis(name_lookup),
+            connect: Duration::from_millis(connect),
+            app_connect: Duration::from_millis(app_connect),
+            total: Duration::from_millis(total),
+            connects: 1,
+            local_ip: None,
+        }
+    }
+
+    #[test]
+    fn test_phases() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        let tls = phases(&transfer(5, 25, 65, 90), HttpVersion::Http2);
+        assert!(close(tls.connect_ms, 20.0));
+        assert!(close(tls.tls_ms.unwrap(), 40.0));
+        assert!(close(tls.query_ms, 25.0));
+        assert!(close(tls.total_ms(), 85.0));
+
+        // QUIC reports the TLS handshake as part of connecting
+        let quic = phases(&transfer(5, 45, 45, 70), HttpVersion::Http3);
+        assert!(close(quic.connect_ms, 40.0));
+        assert_eq!(quic.tls_ms, None);
+        assert!(close(quic.query_ms, 25.0));
+
+        // Plain HTTP has no TLS phase
+        let plain = phases(&transfer(0, 1, 0, 3), HttpVersion::Http1);
+        assert_eq!(plain.tls_ms, None);
+        assert!(close(plain.query_ms, 2.0));
+
+        let mean = ConnectionPhases::mean(&[tls, quic]).unwrap();
+        assert!(close(mean.connect_ms, 30.0));
+        assert!(close(mean.tls_ms.unwrap(), 40.0));
+        assert!(close(mean.query_ms, 25.0));
+        assert_eq!(ConnectionPhases::mean(&[]), None);
+    }
+}
@@ -0,0 +1,149 @@
+//! DNS-over-HTTPS JSON API queries.
+//!
+//! Google (`https://dns.google/resolve`) and Cloudflare
+//! (`https://cloudflare-dns.com/dns-query`) answer `GET ?name=&type=`
+//! requests with a JSON document instead of a DNS message. Networks that
+//! block RFC 8484 DNS-over-HTTPS by its `application/dns-message` content
+//! type, or intercept plain DNS to public resolvers, often still let these
+//! through as ordinary HTTPS API calls, so they make a usable reference
+//! for the pollution checker there.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::error::{Error, Result};
+use crate::http;
+use hickory_resolver::proto::rr::RecordType;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Google Public DNS JSON API.
+pub const GOOGLE_JSON_URL: &str = "https://dns.google/resolve";
+
+/// Cloudflare DNS JSON API.
+pub const CLOUDFLARE_JSON_URL: &str = "https://cloudflare-dns.com/dns-query";
+
+/// Endpoints tried, in order, when none are configured.
+pub const DEFAULT_ENDPOINTS: &[&str] = &[GOOGLE_JSON_URL, CLOUDFLARE_JSON_URL];
+
+/// Default timeout for one query.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// DNS response code for a name that does not exist.
+const NXDOMAIN: u16 = 3;
+
+/// The subset of a JSON API response we use.
+#[derive(Debug, Deserialize)]
+struct JsonResponse {
+    #[serde(rename = "Status")]
+    status: u16,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<JsonRecord>,
+}
+
+/// One answer record of a JSON API response.
+#[derive(Debug, Deserialize)]
+struct JsonRecord {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+/// Query one endpoint for the addresses of `domain`.
+///
+/// # Arguments
+///
+/// * `endpoint` - JSON API URL, e.g. [`GOOGLE_JSON_URL`]
+/// * `domain` - Domain name, with or without the trailing dot
+/// * `record_type` - `A` or `AAAA`
+/// * `timeout` - Maximum time for the request
+pub async fn resolve(
+    endpoint: &str,
+    domain: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> Result<Vec<IpAddr>> {
+    let url = format!(
+        "{endpoint}?name={}&type={record_type}",
+        domain.trim_end_matches('.')
+    );
+    // Cloudflare serves JSON only when asked for it
+    let body = http::get_with_headers(&url, &["Accept: application/dns-json"], timeout).await?;
+    parse_response(&body, record_type)
+}
+
+/// Resolve `domain` through the first endpoint that answers, to its `A`
+/// records or its `AAAA` records if there are none. Both are requested at
+/// the same time.
+///
+/// # Arguments
+///
+/// * `endpoints` - JSON API URLs, tried in order
+/// * `domain` - Domain name to resolve
+pub async fn resolve_any(endpoints: &[String], domain: &str) -> Result<Vec<IpAddr>> {
+    let mut last_error = Error::config("No DNS JSON API endpoint configured");
+    for endpoint in endpoints {
+        let (mut answer, aaaa) = tokio::join!(
+            resolve(endpoint, domain, RecordType::A, DEFAULT_TIMEOUT),
+            resolve(endpoint, domain, RecordType::AAAA, DEFAULT_TIMEOUT)
+        );
+        if answer.as_ref().is_ok_and(Vec::is_empty) {
+            answer = aaaa;
+        }
+        match answer {
+            Ok(ips) => return Ok(ips),
+            Err(e) => {
+                tracing::debug!("DNS JSON API {endpoint} failed for {domain}: {e}");
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Extract the addresses of `record_type` from a JSON API response.
+///
+/// A nonexistent name yields no addresses; other failure codes are
+/// errors. Records of other types, such as the CNAMEs leading to the
+/// addresses, are skipped.
+fn parse_response(body: &[u8], record_type: RecordType) -> Result<Vec<IpAddr>> {
+    let response: JsonResponse = serde_json::from_slice(body)?;
+    match response.status {
+        0 => {}
+        NXDOMAIN => return Ok(Vec::new()),
+        code => {
+            return Err(Error::network(format!(
+                "DNS JSON API returned response code {code}"
+            )))
+        }
+    }
+
+    let wanted = u16::from(record_type);
+    Ok(response
+        .answer
+        .iter()
+        .filter(|record| record.record_type == wanted)
+        .filter_map(|record| record.data.parse().ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let body = br#"{"Status":0,"TC":false,"Question":[{"name":"www.example.com.","type":1}],
+            "Answer":[{"name":"www.example.com.","type":5,"TTL":300,"data":"example.com."},
+                      {"name":"example.com.","type":1,"TTL":300,"data":"93.184.215.14"}]}"#;
+        let ips = parse_response(body, RecordType::A).unwrap();
+        assert_eq!(ips, ["93.184.215.14".parse::<IpAddr>().unwrap()]);
+        assert!(parse_response(body, RecordType::AAAA).unwrap().is_empty());
+
+        let nxdomain = br#"{"Status":3,"Question":[{"name":"nx.example.","type":1}]}"#;
+        assert!(parse_response(nxdomain, RecordType::A).unwrap().is_empty());
+
+        let servfail = br#"{"Status":2}"#;
+        assert!(parse_response(servfail, RecordType::A).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+//! Resolver egress lookup.
+//!
+//! A resolver's address says little about where it looks names up: anycast
+//! resolvers answer from the nearest site, and forwarders hand queries to
+//! an upstream elsewhere. Authoritative servers, and the CDNs that pick an
+//! address close to the asker, see the resolver's egress address instead.
+//! This module asks each resolver for a "whoami" name, whose answer is the
+//! address the query reached the authoritative server from, then looks up
+//! the network of that address in Team Cymru's IP-to-ASN zone.
+
+use crate::dns::query::{self, Exchange, NetworkExchange};
+use crate::dns::types::SpeedTestResult;
+use futures::StreamExt;
+use hickory_resolver::proto::rr::RecordType;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Name whose TXT answer is the egress address of the asking resolver,
+/// and the client subnet if the resolver sent one (ECS).
+pub(crate) const GOOGLE_WHOAMI: &str = "o-o.myaddr.l.google.com.";
+
+/// Name whose A answer is the egress address of the asking resolver, for
+/// resolvers that Google's name server does not answer.
+const AKAMAI_WHOAMI: &str = "whoami.akamai.net.";
+
+/// Default per-query timeout.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Number of resolvers asked at the same time.
+const CONCURRENCY: usize = 16;
+
+/// Where a resolver's queries leave for the internet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Egress {
+    /// Address authoritative servers see the resolver's queries from
+    pub ip: IpAddr,
+    /// Autonomous system announcing the address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    /// Name of that autonomous system, e.g. "CLOUDFLARENET"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub as_name: Option<String>,
+    /// Two-letter country code of the address's registration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+}
+
+impl std::fmt::Display for Egress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.ip)?;
+        let network = match (self.asn, &self.as_name) {
+            (Some(asn), Some(name)) => Some(format!("AS{asn} {name}")),
+            (Some(asn), None) => Some(format!("AS{asn}")),
+            _ => None,
+        };
+        match (network, &self.country) {
+            (Some(network), Some(country)) => write!(f, " ({network}, {country})"),
+            (Some(network), None) => write!(f, " ({network})"),
+            (None, Some(country)) => write!(f, " ({country})"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Egress lookup for resolvers.
+///
+/// # Example
+///
+/// ```ignore
+/// let egress = EgressProber::new().probe("1.1.1.1:53".parse()?).await;
+/// if let Some(egress) = egress {
+///     println!("queries leave from {egress}");
+/// }
+/// ```
+pub struct EgressProber {
+    timeout: Duration,
+    exchange: Arc<dyn Exchange>,
+}
+
+impl EgressProber {
+    /// Create a prober with the default timeout.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_TIMEOUT)
+    }
+
+    /// Create a prober with a custom per-query timeout.
+    #[must_use]
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            exchange: Arc::new(NetworkExchange),
+        }
+    }
+
+    /// Send the queries through `exchange` instead of the network.
+    #[must_use]
+    pub fn with_exchange(mut self, exchange: Arc<dyn Exchange>) -> Self {
+        self.exchange = exchange;
+        self
+    }
+
+    /// Look up the egress of the resolver at `server`.
+    ///
+    /// The network of the egress address is looked up through the same
+    /// resolver. Returns `None` if the resolver does not resolve the
+    /// whoami names.
+    pub async fn probe(&self, server: SocketAddr) -> Option<Egress> {
+        let ip = match self.whoami_google(server).await {
+            Some(ip) => ip,
+            None => self.whoami_akamai(server).await?,
+        };
+        let mut egress = Egress {
+            ip,
+            asn: None,
+            as_name: None,
+            country: None,
+        };
+        if let Some((asn, country)) = self.origin(server, ip).await {
+            egress.asn = Some(asn);
+            egress.country = country;
+            egress.as_name = self.as_name(server, asn).await;
+        }
+        Some(egress)
+    }
+
+    /// Fill in the egress of the plain DNS servers among `results`.
+    pub async fn fill(&self, results: &mut [SpeedTestResult]) {
+        let egresses: Vec<Option<Egress>> = futures::stream::iter(results.iter())
+            .map(|result| async move {
+                match result.server.socket_addr() {
+                    Some(addr) if result.server.protocol.is_plain() => self.probe(addr).await,
+                    _ => None,
+                }
+            })
+            .buffered(CONCURRENCY)
+            .collect()
+            .await;
+        for (result, egress) in results.iter_mut().zip(egresses) {
+            result.egress = egress;
+        }
+    }
+
+    /// Egress address from Google's whoami name. With ECS, the answer also
+    /// holds the client subnet, which is skipped.
+    async fn whoami_google(&self, server: SocketAddr) -> Option<IpAddr> {
+        self.txt(server, GOOGLE_WHOAMI)
+            .await
+            .iter()
+            .find_map(|txt| txt.parse().ok())
+    }
+
+    /// Egress address from Akamai's whoami name.
+    async fn whoami_akamai(&self, server: SocketAddr) -> Option<IpAddr> {
+        let responses = self
+            .exchange
+            .udp(server, AKAMAI_WHOAMI, RecordType::A, self.timeout, None)
+            .await
+            .map_err(|e| tracing::debug!("Query for {AKAMAI_WHOAMI} to {server} failed: {e}"))
+            .ok()?;
+        responses.first()?.answer_ips().into_iter().next()
+    }
+
+    /// ASN and country announcing `ip`, e.g. from
+    /// `13335 | 1.1.1.0/24 | AU | apnic | 2011-08-11`.
+    async fn origin(&self, server: SocketAddr, ip: IpAddr) -> Option<(u32, Option<String>)> {
+        let txt = self.txt(server, &origin_name(ip)).await;
+        let fields = cymru_fields(txt.first()?);
+        // Addresses announced by several ASes list them all
+        let asn = fields.first()?.split_whitespace().next()?.parse().ok()?;
+        let country = fields
+            .get(2)
+            .filter(|country| !country.is_empty())
+            .map(ToString::to_string);
+        Some((asn, country))
+    }
+
+    /// Name of an AS, e.g. `CLOUDFLARENET` from
+    /// `13335 | US | arin | 2010-07-14 | CLOUDFLARENET - Cloudflare, Inc., US`.
+    async fn as_name(&self, server: SocketAddr, asn: u32) -> Option<String> {
+        let txt = self.txt(server, &format!("AS{asn}.asn.cymru.com.")).await;
+        let description = *cymru_fields(txt.first()?).get(4)?;
+        let name = description
+            .split_once(" - ")
+            .map_or(description, |(name, _)| name);
+        Some(name.trim_end_matches(',').to_string())
+    }
+
+    /// TXT strings answered for `name`, empty if none.
+    async fn txt(&self, server: SocketAddr, name: &str) -> Vec<String> {
+        match self
+            .exchange
+            .udp(server, name, RecordType::TXT, self.timeout, None)
+            .await
+        {
+            Ok(responses) => responses
+                .first()
+                .map(|r| query::answer_txt(&r.message))
+                .unwrap_or_default(),
+            Err(e) => {
+                tracing::debug!("Query for {name} to {server} failed: {e}");
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl Default for EgressProber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Name in Team Cymru's zone whose TXT record holds the origin of `ip`.
+fn origin_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, d] = v4.octets();
+            format!("{d}.{c}.{b}.{a}.origin.asn.cymru.com.")
+        }
+        IpAddr::V6(v6) => {
+            let nibbles: Vec<String> = v6
+                .octets()
+                .iter()
+                .rev()
+                .flat_map(|byte| [byte & 0xf, byte >> 4])
+                .map(|nibble| format!("{nibble:x}"))
+                .collect();
+            format!("{}.origin6.asn.cymru.com.", nibbles.join("."))
+        }
+    }
+}
+
+/// Fields of a Team Cymru TXT record, separated by `|`.
+fn cymru_fields(txt: &str) -> Vec<&str> {
+    txt.split('|').map(str::trim).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::query::fake::{record, FakeExchange, Query, Reply};
+    use crate::dns::DnsServer;
+    use hickory_resolver::proto::rr::rdata::{A, TXT};
+    use hickory_resolver::proto::rr::RData;
+
+    /// Resolvers told apart by the last octet: .1 answers Google's whoami
+    /// name with ECS and the Cymru names, .2 only Akamai's whoami name and
+    /// .3 nothing.
+    fn fake_resolvers() -> FakeExchange {
+        let txt = |name: &str, strings: &[&str]| {
+            let strings = strings.iter().map(ToString::to_string).collect();
+            record(name, 60, RData::TXT(TXT::new(strings)))
+        };
+        let query = |last, name| Query::any().server([192, 0, 2, last]).name(name);
+        let origin = "2.1.70.172.origin.asn.cymru.com.";
+        let asn = "AS13335.asn.cymru.com.";
+        FakeExchange::new()
+            .on(
+                query(1, GOOGLE_WHOAMI),
+                Reply::answers([
+                    txt(GOOGLE_WHOAMI, &["edns0-client-subnet 198.51.100.0/24"]),
+                    txt(GOOGLE_WHOAMI, &["172.70.1.2"]),
+                ]),
+            )
+            .on(
+                query(1, origin),
+                Reply::answers([txt(
+                    origin,
+                    &["13335 | 172.70.0.0/15 | US | arin | 2014-03-28"],
+                )]),
+            )
+            .on(
+                query(1, asn),
+                Reply::answers([txt(
+                    asn,
+                    &["13335 | US | arin | 2010-07-14 | CLOUDFLARENET - Cloudflare, Inc., US"],
+                )]),
+            )
+            .on(
+                query(2, AKAMAI_WHOAMI),
+                Reply::answers([record(AKAMAI_WHOAMI, 60, RData::A(A::new(203, 0, 113, 9)))]),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_fill_egress() {
+        let mut results: Vec<SpeedTestResult> = ["192.0.2.1", "192.0.2.2", "192.0.2.3"]
+            .iter()
+            .map(|ip| SpeedTestResult::success(DnsServer::new("R", *ip), 5.0, 0.0))
+            .collect();
+        EgressProber::new()
+            .with_exchange(Arc::new(fake_resolvers()))
+            .fill(&mut results)
+            .await;
+
+        let cloudflare = results[0].egress.as_ref().unwrap();
+        assert_eq!(
+            cloudflare.to_string(),
+            "172.70.1.2 (AS13335 CLOUDFLARENET, US)"
+        );
+        let akamai = results[1].egress.as_ref().unwrap();
+        assert_eq!(akamai.to_string(), "203.0.113.9");
+        assert!(results[2].egress.is_none());
+    }
+
+    #[test]
+    fn test_origin_name() {
+        assert_eq!(
+            origin_name("8.8.4.4".parse().unwrap()),
+            "4.4.8.8.origin.asn.cymru.com."
+        );
+        assert!(origin_name("2001:db8::1".parse().unwrap())
+            .starts_with("1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2."));
+    }
+}
@@ -0,0 +1,481 @@
+//! Resolver feature report.
+//!
+//! Latency is only part of what sets resolvers apart. The probes here ask
+//! each resolver a few crafted questions and record how it behaves, so
+//! that resolvers can be compared feature by feature:
+//!
+//! - negative caching: whether an NXDOMAIN answer is cached, and for how
+//!   long compared to the zone's SOA minimum (RFC 2308)
+//! - client subnet: whether the resolver tells authoritative servers which
+//!   network its clients are in (EDNS Client Subnet, RFC 7871), a privacy
+//!   leak that helps CDNs pick a nearby address
+//! - filtering: whether ad, malware and adult domains are blocked, and
+//!   how, and whether safe search is enforced
+
+use crate::dns::egress::GOOGLE_WHOAMI;
+use crate::dns::filtering::{self, Category, DomainFiltering, Filtering, SafeSearch};
+use crate::dns::query::{self, DnsResponse, Exchange, NetworkExchange};
+use crate::dns::DnsServer;
+use futures::future::join_all;
+use futures::StreamExt;
+use hickory_resolver::proto::op::{Message, ResponseCode};
+use hickory_resolver::proto::rr::{RData, RecordType};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Zone the probe names are made up under; it has no wildcard records.
+const PROBE_ZONE: &str = "example.com.";
+
+/// Default per-query timeout.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Default number of resolvers probed at the same time.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Pause before repeating a negative query, long enough for the TTL of a
+/// cached answer to count down.
+const REPEAT_DELAY: Duration = Duration::from_millis(1100);
+
+/// How a resolver caches NXDOMAIN answers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NegativeCaching {
+    /// Latency of the first query for a name that does not exist, which
+    /// the resolver has to look up
+    pub first_ms: f64,
+    /// Latency of the same query repeated a second later
+    pub repeat_ms: f64,
+    /// Whether the repeated query was answered from the cache
+    pub cached: bool,
+    /// TTL of the SOA record in the first answer: how long the resolver
+    /// says the NXDOMAIN may be cached
+    pub negative_ttl: Option<u32>,
+    /// MINIMUM field of that SOA record, the zone's negative caching TTL
+    pub soa_minimum: Option<u32>,
+}
+
+impl NegativeCaching {
+    /// Whether the negative TTL stays within the SOA minimum, as RFC 2308
+    /// requires. `None` if the answer had no SOA record.
+    #[must_use]
+    pub fn honors_soa_minimum(&self) -> Option<bool> {
+        Some(self.negative_ttl? <= self.soa_minimum?)
+    }
+}
+
+/// Prefix of the TXT string in which Google's name server reflects the
+/// client subnet it received.
+const ECS_PREFIX: &str = "edns0-client-subnet ";
+
+/// Whether a resolver forwards its clients' subnet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientSubnet {
+    /// Whether the authoritative server received a client subnet
+    pub forwarded: bool,
+    /// The subnet it received, e.g. `198.51.100.0/24`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subnet: Option<String>,
+}
+
+/// Features of one resolver.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolverFeatures {
+    /// The resolver probed
+    pub server: DnsServer,
+    /// NXDOMAIN caching behavior, if the resolver answered
+    pub negative_caching: Option<NegativeCaching>,
+    /// Client subnet forwarding, if the resolver answered
+    pub client_subnet: Option<ClientSubnet>,
+    /// Content filtering and safe search, if the resolver answered
+    pub filtering: Option<Filtering>,
+    /// Why the resolver could not be probed at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Resolver feature prober.
+///
+/// # Example
+///
+/// ```ignore
+/// let reports = FeatureProber::new().probe_all(&servers, |_, _| {}).await;
+/// for report in reports {
+///     println!("{}: {:?}", report.server.name, report.negative_caching);
+/// }
+/// ```
+pub struct FeatureProber {
+    timeout: Duration,
+    concurrency: usize,
+    repeat_delay: Duration,
+    exchange: Arc<dyn Exchange>,
+}
+
+impl FeatureProber {
+    /// Create a prober with default settings.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_settings(DEFAULT_TIMEOUT, DEFAULT_CONCURRENCY)
+    }
+
+    /// Create a prober with custom settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Timeout for each query
+    /// * `concurrency` - Number of resolvers probed at the same time
+    #[must_use]
+    pub fn with_settings(timeout: Duration, concurrency: usize) -> Self {
+        Self {
+            timeout,
+            concurrency: concurrency.max(1),
+            repeat_delay: REPEAT_DELAY,
+            exchange: Arc::new(NetworkExchange),
+        }
+    }
+
+    /// Send the queries through `exchange` instead of the network.
+    #[must_use]
+    pub fn with_exchange(mut self, exchange: Arc<dyn Exchange>) -> Self {
+        self.exchange = exchange;
+        self
+    }
+
+    /// Probe one resolver.
+    ///
+    /// Plain DNS servers only; others are reported with an error.
+    pub async fn probe(&self, server: &DnsServer) -> ResolverFeatures {
+        let addr = match server.socket_addr() {
+            Some(addr) if server.protocol.is_plain() => addr,
+            _ => {
+                return ResolverFeatures {
+                    server: server.clone(),
+                    negative_caching: None,
+                    client_subnet: None,
+                    filtering: None,
+                    error: Some(format!("{} is not a plain DNS server", server.spec())),
+                }
+            }
+        };
+        let (negative_caching, client_subnet, filtering) = futures::join!(
+            self.negative_caching(addr),
+            self.client_subnet(addr),
+            self.filtering(addr)
+        );
+        ResolverFeatures {
+            server: server.clone(),
+            negative_caching,
+            client_subnet,
+            filtering,
+            error: None,
+        }
+    }
+
+    /// Probe several resolvers, `concurrency` at a time, in the given
+    /// order.
+    ///
+    /// `progress` is called with the number of finished and total
+    /// resolvers after each one.
+    pub async fn probe_all(
+        &self,
+        servers: &[DnsServer],
+        progress: impl Fn(usize, usize),
+    ) -> Vec<ResolverFeatures> {
+        let total = servers.len();
+        let mut pending = futures::stream::iter(servers)
+            .map(|server| self.probe(server))
+            .buffered(self.concurrency);
+
+        let mut reports = Vec::with_capacity(total);
+        while let Some(report) = pending.next().await {
+            reports.push(report);
+            progress(reports.len(), total);
+        }
+        reports
+    }
+
+    /// Ask twice for a name that does not exist.
+    ///
+    /// A resolver caching the NXDOMAIN answers the repeat faster, or with
+    /// a SOA TTL that has counted down since the first answer.
+    async fn negative_caching(&self, server: SocketAddr) -> Option<NegativeCaching> {
+        let name = format!("{}.{PROBE_ZONE}", query::random_label());
+        let first = self.nxdomain(server, &name).await?;
+        tokio::time::sleep(self.repeat_delay).await;
+        let repeat = self.nxdomain(server, &name).await?;
+
+        let first_ms = first.elapsed.as_secs_f64() * 1000.0;
+        let repeat_ms = repeat.elapsed.as_secs_f64() * 1000.0;
+        let (negative_ttl, soa_minimum) = soa(&first).unzip();
+        let repeat_ttl = soa(&repeat).map(|(ttl, _)| ttl);
+        let counted_down = matches!((negative_ttl, repeat_ttl), (Some(a), Some(b)) if b < a);
+        Some(NegativeCaching {
+            first_ms,
+            repeat_ms,
+            cached: counted_down || repeat_ms * 2.0 < first_ms,
+            negative_ttl,
+            soa_minimum,
+        })
+    }
+
+    /// Ask for Google's whoami name, whose authoritative server answers
+    /// with the client subnet it received, if any.
+    async fn client_subnet(&self, server: SocketAddr) -> Option<ClientSubnet> {
+        let responses = match self
+            .exchange
+            .udp(server, GOOGLE_WHOAMI, RecordType::TXT, self.timeout, None)
+            .await
+        {
+            Ok(responses) => responses,
+            Err(e) => {
+                tracing::debug!("Query for {GOOGLE_WHOAMI} to {server} failed: {e}");
+                return None;
+            }
+        };
+        let txt = query::answer_txt(&responses.first()?.message);
+        // Without any answer the resolver did not resolve the name at all
+        if txt.is_empty() {
+            return None;
+        }
+        let subnet = txt
+            .iter()
+            .find_map(|txt| txt.strip_prefix(ECS_PREFIX))
+            .map(|subnet| subnet.trim().to_string());
+        Some(ClientSubnet {
+            forwarded: subnet.is_some(),
+            subnet,
+        })
+    }
+
+    /// Ask for the test domains of every filtering category, and for the
+    /// host names of the services with a restricted mode.
+    ///
+    /// Names the resolver fails to answer, or answers with SERVFAIL, are
+    /// left out; `None` if it answered none of them.
+    async fn filtering(&self, server: SocketAddr) -> Option<Filtering> {
+        let domains = Category::ALL
+            .into_iter()
+            .flat_map(|category| category.domains().iter().map(move |&d| (category, d)))
+            .map(|(category, domain)| async move {
+                let message = self.filtering_answer(server, domain).await?;
+                Some(DomainFiltering {
+                    domain: domain.trim_end_matches('.').to_string(),
+                    category,
+                    block: filtering::classify(&message),
+                })
+            });
+        let services = SafeSearch::ALL.into_iter().map(|service| async move {
+            let message = self.filtering_answer(server, service.domain()).await?;
+            Some((service, service.is_enforced(&message)))
+        });
+        let (domains, services) = futures::join!(join_all(domains), join_all(services));
+
+        let domains: Vec<DomainFiltering> = domains.into_iter().flatten().collect();
+        let services: Vec<(SafeSearch, bool)> = services.into_iter().flatten().collect();
+        if domains.is_empty() && services.is_empty() {
+            return None;
+        }
+        Some(Filtering {
+            domains,
+            safe_search: services
+                .into_iter()
+                .filter_map(|(service, enforced)| enforced.then_some(service))
+                .collect(),
+        })
+    }
+
+    /// Send an A query for a filtering probe and return the response,
+    /// unless it is a SERVFAIL, which says nothing about filtering.
+    async fn filtering_answer(&self, server: SocketAddr, domain: &str) -> Option<Message> {
+        let responses = self
+            .exchange
+            .udp(server, domain, RecordType::A, self.timeout, None)
+            .await
+            .map_err(|e| tracing::debug!("Query for {domain} to {server} failed: {e}"))
+            .ok()?;
+        responses
+            .into_iter()
+            .next()
+            .map(|r| r.message)
+            .filter(|message| message.response_code() != ResponseCode::ServFail)
+    }
+
+    /// Send a query and return the response if it is an NXDOMAIN.
+    async fn nxdomain(&self, server: SocketAddr, name: &str) -> Option<DnsResponse> {
+        let responses = match self
+            .exchange
+            .udp(server, name, RecordType::A, self.timeout, None)
+            .await
+        {
+            Ok(responses) => responses,
+            Err(e) => {
+                tracing::debug!("Query for {name} to {server} failed: {e}");
+                return None;
+            }
+        };
+        responses
+            .into_iter()
+            .next()
+            .filter(|r| r.message.response_code() == ResponseCode::NXDomain)
+    }
+}
+
+impl Default for FeatureProber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// TTL and MINIMUM field of the SOA record in a negative answer.
+fn soa(response: &DnsResponse) -> Option<(u32, u32)> {
+    response
+        .message
+        .name_servers()
+        .iter()
+        .find_map(|record| match record.data() {
+            Some(RData::SOA(soa)) => Some((record.ttl(), soa.minimum())),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::filtering::Block;
+    use crate::dns::query::fake::{record, FakeExchange, Query, Reply};
+    use crate::dns::DnsProtocol;
+    use hickory_resolver::proto::rr::rdata::{A, SOA, TXT};
+    use hickory_resolver::proto::rr::Name;
+    use std::net::IpAddr;
+
+    fn nxdomain(ttl: u32, elapsed_ms: u64) -> Reply {
+        let soa = SOA::new(
+            Name::from_ascii("ns.icann.org.").unwrap(),
+            Name::from_ascii("noc.dns.icann.org.").unwrap(),
+            2_024_081_463,
+            7200,
+            3600,
+            1_209_600,
+            3600,
+        );
+        Reply::rcode(ResponseCode::NXDomain)
+            .with_authority([record(PROBE_ZONE, ttl, RData::SOA(soa))])
+            .with_elapsed(elapsed_ms)
+    }
+
+    fn whoami(strings: &[&str]) -> Reply {
+        Reply::answers(strings.iter().map(|string| {
+            record(
+                GOOGLE_WHOAMI,
+                60,
+                RData::TXT(TXT::new(vec![(*string).to_string()])),
+            )
+        }))
+    }
+
+    /// Resolvers told apart by the last octet: .1 caches NXDOMAIN for the
+    /// SOA minimum of 3600 seconds, so its second answer comes from cache,
+    /// forwards the client subnet and sinkholes ad domains, .2 never
+    /// caches, stretches the TTL to a day, keeps the subnet to itself and
+    /// blocks nothing, .3 does not answer.
+    fn fake_resolvers() -> FakeExchange {
+        let server = |last| Query::any().server([192, 0, 2, last]);
+        let probe = |last| server(last).name(&format!("*.{PROBE_ZONE}"));
+        let whoami_query = |last| {
+            server(last)
+                .name(GOOGLE_WHOAMI)
+                .record_type(RecordType::TXT)
+        };
+        let address = |name: &str, a| Reply::answers([record(name, 60, RData::A(a))]);
+        let mut exchange = FakeExchange::new()
+            .on(
+                whoami_query(1),
+                whoami(&["192.0.2.100", "edns0-client-subnet 198.51.100.0/24"]),
+            )
+            .on(whoami_query(2), whoami(&["192.0.2.200"]))
+            .once(probe(1), nxdomain(3600, 80))
+            .on(probe(1), nxdomain(3599, 2))
+            .on(probe(2), nxdomain(86_400, 80));
+        for domain in Category::Ads.domains() {
+            exchange = exchange.on(server(1).name(domain), address(domain, A::new(0, 0, 0, 0)));
+        }
+        let elsewhere = address("example.org.", A::new(93, 184, 215, 14));
+        exchange
+            .on(server(1), elsewhere.clone())
+            .on(server(2), elsewhere)
+    }
+
+    #[tokio::test]
+    async fn test_negative_caching() {
+        let prober = FeatureProber {
+            repeat_delay: Duration::ZERO,
+            ..FeatureProber::new()
+        }
+        .with_exchange(Arc::new(fake_resolvers()));
+        let servers = [
+            DnsServer::new("Caching", "192.0.2.1"),
+            DnsServer::new("Forgetful", "192.0.2.2"),
+            DnsServer::new("Silent", "192.0.2.3"),
+            DnsServer {
+                protocol: DnsProtocol::Tls,
+                ..DnsServer::new("Encrypted", "192.0.2.1")
+            },
+        ];
+        let reports = prober.probe_all(&servers, |_, _| {}).await;
+
+        let caching = reports[0].negative_caching.as_ref().unwrap();
+        assert!(caching.cached);
+        assert_eq!(caching.negative_ttl, Some(3600));
+        assert_eq!(caching.soa_minimum, Some(3600));
+        assert_eq!(caching.honors_soa_minimum(), Some(true));
+
+        let forgetful = reports[1].negative_caching.as_ref().unwrap();
+        assert!(!forgetful.cached);
+        assert_eq!(forgetful.honors_soa_minimum(), Some(false));
+
+        assert!(reports[2].negative_caching.is_none());
+        assert!(reports[2].error.is_none());
+        assert!(reports[3].error.is_some());
+    }
+
+    /// Reports of the fake resolvers: one that caches, one that does not
+    /// and one that never answers.
+    async fn probe_fakes() -> Vec<ResolverFeatures> {
+        let prober = FeatureProber {
+            repeat_delay: Duration::ZERO,
+            ..FeatureProber::new()
+        }
+        .with_exchange(Arc::new(fake_resolvers()));
+        let servers = [
+            DnsServer::new("Caching", "192.0.2.1"),
+            DnsServer::new("Forgetful", "192.0.2.2"),
+            DnsServer::new("Silent", "192.0.2.3"),
+        ];
+        prober.probe_all(&servers, |_, _| {}).await
+    }
+
+    #[tokio::test]
+    async fn test_ecs() {
+        let reports = probe_fakes().await;
+        assert_eq!(
+            reports[0].client_subnet.as_ref().unwrap().subnet.as_deref(),
+            Some("198.51.100.0/24")
+        );
+        assert!(!reports[1].client_subnet.as_ref().unwrap().forwarded);
+        assert!(reports[2].client_subnet.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_filtering() {
+        let reports = probe_fakes().await;
+        let filtering = reports[0].filtering.as_ref().unwrap();
+        assert_eq!(filtering.blocked_categories(), [Category::Ads]);
+        assert_eq!(
+            filtering.blocks(Category::Ads),
+            Some(Block::Sinkhole(IpAddr::from([0, 0, 0, 0])))
+        );
+        let unfiltered = reports[1].filtering.as_ref().unwrap();
+        assert!(unfiltered.blocked_categories().is_empty());
+        assert!(reports[2].filtering.is_none());
+    }
+}
@@ -0,0 +1,296 @@
+//! Content filtering detection.
+//!
+//! Filtering resolvers, like Quad9, `AdGuard` DNS or Cloudflare's `1.1.1.2`,
+//! refuse to resolve domains on their blocklists. That is the point of
+//! choosing one, and a surprise when it is not. Each category has test
+//! domains that every blocklist of the category lists and that resolve
+//! normally otherwise; a resolver blocks them in one of three ways:
+//!
+//! - answering NXDOMAIN (or refusing the query)
+//! - answering an unroutable address such as `0.0.0.0` (a sinkhole)
+//! - answering the address of a block page that explains the block
+//!
+//! Family resolvers also enforce safe search: they answer the search
+//! engines' host names with an alias of the engine's restricted mode, e.g.
+//! `forcesafesearch.google.com` for `www.google.com`, or directly with its
+//! address.
+
+use crate::dns::bogon;
+use hickory_resolver::proto::op::{Message, ResponseCode};
+use hickory_resolver::proto::rr::RData;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr};
+
+/// Networks of the block pages of public filtering resolvers.
+const BLOCK_PAGE_NETS: &[(Ipv4Addr, u8)] = &[
+    // Cisco Umbrella / OpenDNS
+    (Ipv4Addr::new(146, 112, 61, 0), 24),
+];
+
+/// Kind of content a test domain stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    /// Ad and tracking networks
+    Ads,
+    /// Malware and phishing
+    Malware,
+    /// Adult content, blocked by family resolvers
+    Adult,
+}
+
+impl Category {
+    /// All categories, in the order they are reported.
+    pub const ALL: [Self; 3] = [Self::Ads, Self::Malware, Self::Adult];
+
+    /// Test domains of the category.
+    #[must_use]
+    pub const fn domains(self) -> &'static [&'static str] {
+        match self {
+            Self::Ads => &["doubleclick.net.", "pagead2.googlesyndication.com."],
+            // Test names that filtering resolvers block on purpose
+            Self::Malware => &["malware.testcategory.com.", "isitblocked.org."],
+            Self::Adult => &["pornhub.com.", "xvideos.com."],
+        }
+    }
+}
+
+impl std::fmt::Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ads => write!(f, "ads"),
+            Self::Malware => write!(f, "malware"),
+            Self::Adult => write!(f, "adult"),
+        }
+    }
+}
+
+/// Service with a restricted mode that resolvers can enforce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SafeSearch {
+    /// Google search
+    Google,
+    /// Bing search
+    Bing,
+    /// Restricted mode of Youtube
+    YouTube,
+}
+
+impl SafeSearch {
+    /// All services, in the order they are reported.
+    pub const ALL: [Self; 3] = [Self::Google, Self::Bing, Self::YouTube];
+
+    /// Host name the service is used through.
+    #[must_use]
+    pub const fn domain(self) -> &'static str {
+        match self {
+            Self::Google => "www.google.com.",
+            Self::Bing => "www.bing.com.",
+            Self::YouTube => "www.youtube.com.",
+        }
+    }
+
+    /// Host names of the restricted mode, which resolvers alias the
+    /// service's host name to.
+    const fn restricted_names(self) -> &'static [&'static str] {
+        match self {
+            Self::Google => &["forcesafesearch.google.com"],
+            Self::Bing => &["strict.bing.com"],
+            Self::YouTube => &["restrict.youtube.com", "restrictmoderate.youtube.com"],
+        }
+    }
+
+    /// Addresses of the restricted mode, for resolvers that answer with
+    /// them instead of an alias.
+    const fn restricted_ips(self) -> &'static [Ipv4Addr] {
+        const GOOGLE: &[Ipv4Addr] = &[Ipv4Addr::new(216, 239, 38, 120)];
+        const BING: &[Ipv4Addr] = &[Ipv4Addr::new(204, 79, 197, 220)];
+        const YOUTUBE: &[Ipv4Addr] = &[
+            Ipv4Addr::new(216, 239, 38, 119),
+            Ipv4Addr::new(216, 239, 38, 120),
+        ];
+        match self {
+            Self::Google => GOOGLE,
+            Self::Bing => BING,
+            Self::YouTube => YOUTUBE,
+        }
+    }
+
+    /// Whether a response for the service's host name points to its
+    /// restricted mode.
+    #[must_use]
+    pub fn is_enforced(self, message: &Message) -> bool {
+        message.answers().iter().any(|record| match record.data() {
+            Some(RData::CNAME(target)) => {
+                let target = target.to_ascii().to_lowercase();
+                self.restricted_names()
+                    .contains(&target.trim_end_matches('.'))
+            }
+            Some(RData::A(ip)) => self.restricted_ips().contains(ip),
+            _ => false,
+        })
+    }
+}
+
+impl std::fmt::Display for SafeSearch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Google => write!(f, "Google"),
+            Self::Bing => write!(f, "Bing"),
+            Self::YouTube => write!(f, "YouTube"),
+        }
+    }
+}
+
+/// How a resolver blocked a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "ip", rename_all = "snake_case")]
+pub enum Block {
+    /// Answered that the domain does not exist, or refused the query
+    Nxdomain,
+    /// Answered an unroutable address, e.g. `0.0.0.0`
+    Sinkhole(IpAddr),
+    /// Answered the address of a block page
+    BlockPage(IpAddr),
+}
+
+impl std::fmt::Display for Block {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Nxdomain => write!(f, "NXDOMAIN"),
+            Self::Sinkhole(ip) => write!(f, "{ip}"),
+            Self::BlockPage(ip) => write!(f, "block page {ip}"),
+        }
+    }
+}
+
+/// Answer of a resolver for one test domain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DomainFiltering {
+    /// Test domain, without the trailing dot
+    pub domain: String,
+    /// Category the domain stands for
+    pub category: Category,
+    /// How the resolver blocked it, `None` if it resolved normally
+    pub block: Option<Block>,
+}
+
+/// Filtering of a resolver, from its answers for the test domains it
+/// answered.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Filtering {
+    /// Answer for each test domain
+    pub domains: Vec<DomainFiltering>,
+    /// Services whose restricted mode the resolver enforces
+    #[serde(default)]
+    pub safe_search: Vec<SafeSearch>,
+}
+
+impl Filtering {
+    /// How the resolver blocks `category`, `None` if it blocks none of
+    /// the category's test domains.
+    #[must_use]
+    pub fn blocks(&self, category: Category) -> Option<Block> {
+        self.domains
+            .iter()
+            .filter(|domain| domain.category == category)
+            .find_map(|domain| domain.block)
+    }
+
+    /// Categories the resolver blocks.
+    #[must_use]
+    pub fn blocked_categories(&self) -> Vec<Category> {
+        Category::ALL
+            .into_iter()
+            .filter(|&category| self.blocks(category).is_some())
+            .collect()
+    }
+}
+
+/// How a response blocks the domain asked for, if it does.
+#[must_use]
+pub fn classify(message: &Message) -> Option<Block> {
+    if matches!(
+        message.response_code(),
+        ResponseCode::NXDomain | ResponseCode::Refused
+    ) {
+        return Some(Block::Nxdomain);
+    }
+    let ips = crate::dns::query::answer_ips(message);
+    if let Some(&ip) = ips.iter().find(|ip| is_block_page(ip)) {
+        return Some(Block::BlockPage(ip));
+    }
+    ips.iter()
+        .find(|ip| bogon::is_bogon(ip))
+        .map(|&ip| Block::Sinkhole(ip))
+}
+
+/// Whether `ip` serves the block page of a public filtering resolver.
+fn is_block_page(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => BLOCK_PAGE_NETS
+            .iter()
+            .any(|&(network, prefix)| bogon::in_v4_range(*v4, network, prefix)),
+        IpAddr::V6(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_resolver::proto::op::MessageType;
+    use hickory_resolver::proto::rr::rdata::{A, CNAME};
+    use hickory_resolver::proto::rr::{Name, RData, Record};
+
+    fn answer(ip: [u8; 4]) -> Message {
+        let mut message = Message::new();
+        message
+            .set_message_type(MessageType::Response)
+            .add_answer(Record::from_rdata(
+                Name::from_ascii("doubleclick.net.").unwrap(),
+                60,
+                RData::A(Ipv4Addr::from(ip).into()),
+            ));
+        message
+    }
+
+    #[test]
+    fn test_safe_search() {
+        let google = Name::from_ascii("www.google.com.").unwrap();
+        let restricted = Name::from_ascii("ForceSafeSearch.google.com.").unwrap();
+        let mut aliased = Message::new();
+        aliased
+            .add_answer(Record::from_rdata(
+                google,
+                300,
+                RData::CNAME(CNAME(restricted.clone())),
+            ))
+            .add_answer(Record::from_rdata(
+                restricted,
+                300,
+                RData::A(A::new(216, 239, 38, 120)),
+            ));
+        assert!(SafeSearch::Google.is_enforced(&aliased));
+        assert!(!SafeSearch::Bing.is_enforced(&aliased));
+
+        assert!(SafeSearch::Bing.is_enforced(&answer([204, 79, 197, 220])));
+        assert!(!SafeSearch::Google.is_enforced(&answer([142, 250, 1, 1])));
+    }
+
+    #[test]
+    fn test_classify() {
+        let mut nxdomain = Message::new();
+        nxdomain.set_response_code(ResponseCode::NXDomain);
+        assert_eq!(classify(&nxdomain), Some(Block::Nxdomain));
+        assert_eq!(
+            classify(&answer([0, 0, 0, 0])),
+            Some(Block::Sinkhole(IpAddr::from([0, 0, 0, 0])))
+        );
+        assert_eq!(
+            classify(&answer([146, 112, 61, 106])),
+            Some(Block::BlockPage(IpAddr::from([146, 112, 61, 106])))
+        );
+        assert_eq!(classify(&answer([142, 250, 1, 1])), None);
+    }
+}
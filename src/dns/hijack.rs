@@ -0,0 +1,214 @@
+//! NXDOMAIN hijack detection.
+//!
+//! Some ISP resolvers, and some home routers, answer lookups for names that
+//! do not exist with the address of a search or advertising page instead
+//! of NXDOMAIN, to monetize mistyped addresses. This breaks more than
+//! browsing: programs can no longer tell a missing name from a working
+//! one. The detector asks the system resolver for a suite of names that
+//! cannot exist:
+//!
+//! - random names under `com` and `net`, and under a random TLD
+//! - common typos of popular domains, with mistyped TLDs like `.con`
+//!
+//! Every address in an answer is a redirect.
+
+use crate::dns::pollution::{self, Lookup};
+use crate::dns::query;
+use crate::error::Result;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Popular domains with a mistyped TLD that is not delegated.
+const TYPOS: &[&str] = &[
+    "google.con.",
+    "www.facebook.cmo.",
+    "youtube.ocm.",
+    "baidu.comm.",
+    "wikipedia.ogr.",
+    "amazon.nte.",
+];
+
+/// What kind of name a probe asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeKind {
+    /// A random name, which no one has registered
+    Nxdomain,
+    /// A popular domain with a typo
+    Typo,
+}
+
+/// Answer to one name that cannot exist.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HijackProbe {
+    /// Name asked for, without the trailing dot
+    pub name: String,
+    /// What kind of name it is
+    pub kind: ProbeKind,
+    /// Addresses answered instead of NXDOMAIN
+    pub addresses: Vec<IpAddr>,
+}
+
+impl HijackProbe {
+    /// Whether the name was redirected.
+    #[must_use]
+    pub fn is_hijacked(&self) -> bool {
+        !self.addresses.is_empty()
+    }
+}
+
+/// Result of NXDOMAIN hijack detection.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HijackReport {
+    /// Every probe, random names first
+    pub probes: Vec<HijackProbe>,
+    /// Addresses the redirected names pointed to, e.g. of a search page
+    pub redirect_ips: Vec<IpAddr>,
+}
+
+impl HijackReport {
+    /// Build a report from the probes.
+    #[must_use]
+    pub fn from_probes(probes: Vec<HijackProbe>) -> Self {
+        let mut redirect_ips: Vec<IpAddr> = probes
+            .iter()
+            .flat_map(|probe| probe.addresses.iter().copied())
+            .collect();
+        redirect_ips.sort_unstable();
+        redirect_ips.dedup();
+        Self {
+            probes,
+            redirect_ips,
+        }
+    }
+
+    /// Whether names that do not exist resolve to addresses.
+    #[must_use]
+    pub fn hijacked(&self, kind: ProbeKind) -> bool {
+        self.probes
+            .iter()
+            .any(|probe| probe.kind == kind && probe.is_hijacked())
+    }
+}
+
+/// NXDOMAIN hijack detector.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = HijackDetector::new()?.detect().await;
+/// if report.hijacked(ProbeKind::Nxdomain) {
+///     println!("Missing names are redirected to {:?}", report.redirect_ips);
+/// }
+/// ```
+pub struct HijackDetector {
+    resolver: Box<dyn Lookup>,
+}
+
+impl HijackDetector {
+    /// Create a detector asking the system resolver.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the system resolver cannot be initialized.
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_resolver(pollution::system_resolver()?))
+    }
+
+    /// Create a detector asking `resolver` instead of the system
+    /// resolver.
+    #[must_use]
+    pub fn with_resolver(resolver: impl Lookup + 'static) -> Self {
+        Self {
+            resolver: Box::new(resolver),
+        }
+    }
+
+    /// Ask for every name of the suite concurrently and build a report.
+    ///
+    /// Names that fail to resolve, whether with NXDOMAIN or a timeout,
+    /// count as not redirected, so this never fails.
+    pub async fn detect(&self) -> HijackReport {
+        let probes = join_all(suite().into_iter().map(|(kind, name)| async move {
+            let addresses = self
+                .resolver
+                .lookup_ips(&name)
+                .await
+                .map_err(|e| tracing::debug!("Lookup of {name} failed: {e}"))
+                .unwrap_or_default();
+            HijackProbe {
+                name: name.trim_end_matches('.').to_string(),
+                kind,
+                addresses,
+            }
+        }))
+        .await;
+        HijackReport::from_probes(probes)
+    }
+}
+
+/// Names of the suite, fully qualified so that no search domain is
+/// appended.
+fn suite() -> Vec<(ProbeKind, String)> {
+    let random = query::random_label();
+    let mut names = vec![
+        (ProbeKind::Nxdomain, format!("{random}.com.")),
+        (ProbeKind::Nxdomain, format!("www.{random}.net.")),
+        (ProbeKind::Nxdomain, format!("{random}.{random}.")),
+    ];
+    names.extend(
+        TYPOS
+            .iter()
+            .map(|name| (ProbeKind::Typo, (*name).to_string())),
+    );
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use futures::future::BoxFuture;
+
+    /// A resolver that redirects mistyped `.con` and `.cmo` names to a
+    /// search page, but answers NXDOMAIN for random names.
+    struct TypoCatcher;
+
+    impl Lookup for TypoCatcher {
+        fn lookup_ips<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>>> {
+            let redirected = domain.ends_with(".con.") || domain.ends_with(".cmo.");
+            Box::pin(async move {
+                if redirected {
+                    Ok(vec!["198.51.100.80".parse().unwrap()])
+                } else {
+                    Err(Error::network("NXDOMAIN"))
+                }
+            })
+        }
+
+        fn reverse_lookup(&self, _ip: IpAddr) -> BoxFuture<'_, Result<Vec<String>>> {
+            unreachable!("the detector only resolves names")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_detect_typo_hijack() {
+        let report = HijackDetector::with_resolver(TypoCatcher).detect().await;
+        assert_eq!(report.probes.len(), 3 + TYPOS.len());
+        assert!(!report.hijacked(ProbeKind::Nxdomain));
+        assert!(report.hijacked(ProbeKind::Typo));
+        assert_eq!(
+            report.redirect_ips,
+            ["198.51.100.80".parse::<IpAddr>().unwrap()]
+        );
+
+        let hijacked: Vec<&str> = report
+            .probes
+            .iter()
+            .filter(|probe| probe.is_hijacked())
+            .map(|probe| probe.name.as_str())
+            .collect();
+        assert_eq!(hijacked, ["google.con", "www.facebook.cmo"]);
+    }
+}
@@ -0,0 +1,745 @@
+//! ICMP backends and capability detection.
+//!
+//! Speed tests ping through surge-ping, which opens an unprivileged
+//! datagram ICMP socket where the OS allows it and falls back to a raw
+//! socket otherwise. macOS allows datagram ICMP sockets for every user;
+//! Linux only for groups in `net.ipv4.ping_group_range`. Windows has no
+//! datagram ICMP sockets and raw sockets need Administrator rights, so
+//! there the ICMP helper API (`IcmpSendEcho`) is used instead; it works
+//! for any user.
+//!
+//! When pinging needs privileges the process does not have, errors say so
+//! instead of surfacing as timeouts. On Linux, speed tests then measure
+//! the round trip of a small DNS query instead, so they still work in
+//! unprivileged containers.
+//!
+//! [`SpeedTester`](crate::dns::SpeedTester) sends echo requests through a
+//! [`Probe`], so that speed tests can also run against a stand-in, e.g.
+//! in tests without network access.
+
+use crate::dns::query;
+use crate::dns::source;
+use crate::error::{Error, Result};
+use futures::future::BoxFuture;
+use hickory_resolver::proto::rr::RecordType;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use surge_ping::{Client, Config, IcmpPacket, PingIdentifier, PingSequence, SurgeError};
+
+/// How ICMP echo requests are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IcmpMethod {
+    /// Unprivileged datagram socket (`SOCK_DGRAM`, `IPPROTO_ICMP`)
+    Datagram,
+    /// Raw socket, needs root / Administrator or `CAP_NET_RAW`
+    Raw,
+    /// Windows ICMP helper API (`IcmpSendEcho`)
+    IcmpApi,
+    /// No ICMP: round trip of a DNS query, by default for the name
+    /// servers of the root zone
+    DnsQuery,
+    /// No ICMP: round trip of a DNS query over TCP, including the
+    /// connection setup, for networks that drop ICMP
+    TcpQuery,
+}
+
+impl IcmpMethod {
+    /// Whether this measures DNS queries rather than echo requests.
+    #[must_use]
+    pub const fn is_dns_query(self) -> bool {
+        matches!(self, Self::DnsQuery | Self::TcpQuery)
+    }
+}
+
+impl std::fmt::Display for IcmpMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Datagram => write!(f, "datagram socket"),
+            Self::Raw => write!(f, "raw socket"),
+            Self::IcmpApi => write!(f, "ICMP API"),
+            Self::DnsQuery => write!(f, "DNS query"),
+            Self::TcpQuery => write!(f, "DNS query over TCP"),
+        }
+    }
+}
+
+/// Whether and how this process can ping.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IcmpCapability {
+    /// Method speed tests will use, if any works
+    pub method: Option<IcmpMethod>,
+    /// Whether ICMP only fails for lack of privileges
+    pub elevation_required: bool,
+    /// Why ICMP cannot be used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// What allows unprivileged ICMP on Linux
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linux: Option<LinuxPermissions>,
+}
+
+/// The Linux settings that decide whether this process may ping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LinuxPermissions {
+    /// `net.ipv4.ping_group_range`, groups allowed to open datagram
+    /// ICMP sockets (`1 0`, the default, allows none)
+    pub ping_group_range: Option<(u32, u32)>,
+    /// Whether one of the process's groups is in that range
+    pub in_ping_group: bool,
+    /// Whether the process has `CAP_NET_RAW` for raw sockets
+    pub cap_net_raw: bool,
+}
+
+impl LinuxPermissions {
+    /// Read the settings of the current process.
+    ///
+    /// Returns `None` where `/proc` is unavailable.
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        let range = std::fs::read_to_string("/proc/sys/net/ipv4/ping_group_range").ok();
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        Some(Self::parse(range.as_deref(), &status))
+    }
+
+    /// Build from the contents of `ping_group_range` and
+    /// `/proc/self/status`.
+    fn parse(range: Option<&str>, status: &str) -> Self {
+        /// Bit of `CAP_NET_RAW` in the capability sets.
+        const CAP_NET_RAW: u64 = 1 << 13;
+
+        let ping_group_range = range.and_then(|range| {
+            let mut bounds = range.split_whitespace().map(str::parse::<u32>);
+            Some((bounds.next()?.ok()?, bounds.next()?.ok()?))
+        });
+        let field = |name: &str| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                .unwrap_or_default()
+        };
+
+        // Effective GID plus supplementary groups
+        let groups: Vec<u32> = field("Gid")
+            .split_whitespace()
+            .nth(1)
+            .into_iter()
+            .chain(field("Groups").split_whitespace())
+            .filter_map(|gid| gid.parse().ok())
+            .collect();
+        let in_ping_group = ping_group_range
+            .is_some_and(|(low, high)| groups.iter().any(|gid| (low..=high).contains(gid)));
+        let cap_net_raw = u64::from_str_radix(field("CapEff").trim(), 16)
+            .is_ok_and(|caps| caps & CAP_NET_RAW != 0);
+
+        Self {
+            ping_group_range,
+            in_ping_group,
+            cap_net_raw,
+        }
+    }
+
+    /// Why neither kind of ICMP socket is allowed, if that is the case.
+    #[must_use]
+    pub fn denial(&self) -> Option<String> {
+        if self.in_ping_group || self.cap_net_raw {
+            return None;
+        }
+        let range = self.ping_group_range.map_or_else(
+            || "unknown".to_string(),
+            |(low, high)| format!("{low} {high}"),
+        );
+        Some(format!(
+            "not in net.ipv4.ping_group_range ({range}) and no CAP_NET_RAW"
+        ))
+    }
+}
+
+impl IcmpCapability {
+    /// Whether speed tests can ping at all.
+    #[must_use]
+    pub const fn available(&self) -> bool {
+        self.method.is_some()
+    }
+}
+
+/// Find out whether and how this process can ping.
+///
+/// Must be called from within a Tokio runtime.
+#[must_use]
+pub fn detect() -> IcmpCapability {
+    let linux = linux_permissions();
+    match Pinger::open_socket() {
+        Ok(pinger) => IcmpCapability {
+            method: Some(pinger.method()),
+            elevation_required: false,
+            error: None,
+            linux,
+        },
+        Err(e) => {
+            let elevation_required = e.kind() == std::io::ErrorKind::PermissionDenied;
+            IcmpCapability {
+                method: (elevation_required && cfg!(target_os = "linux"))
+                    .then_some(IcmpMethod::DnsQuery),
+                elevation_required,
+                error: Some(
+                    linux
+                        .and_then(|l| l.denial())
+                        .unwrap_or_else(|| e.to_string()),
+                ),
+                linux,
+            }
+        }
+    }
+}
+
+fn linux_permissions() -> Option<LinuxPermissions> {
+    if cfg!(target_os = "linux") {
+        LinuxPermissions::current()
+    } else {
+        None
+    }
+}
+
+/// How to get permission to ping on this platform.
+#[must_use]
+pub const fn elevation_hint() -> &'static str {
+    if cfg!(windows) {
+        "run as Administrator"
+    } else if cfg!(target_os = "macos") {
+        "run with sudo"
+    } else {
+        "run with sudo, grant CAP_NET_RAW or widen net.ipv4.ping_group_range"
+    }
+}
+
+/// Explain a failure to open an ICMP socket.
+fn describe_open_error(e: &std::io::Error) -> String {
+    if e.kind() == std::io::ErrorKind::PermissionDenied {
+        format!(
+            "{e}; ICMP needs elevated privileges here: {}",
+            elevation_hint()
+        )
+    } else {
+        e.to_string()
+    }
+}
+
+/// Why an echo request got no reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PingError {
+    /// No reply within the timeout
+    Timeout,
+    /// The OS refused to send the request
+    PermissionDenied(String),
+    /// Any other failure
+    Failed(String),
+}
+
+impl std::fmt::Display for PingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "timeout"),
+            Self::PermissionDenied(e) => write!(f, "{e}; {}", elevation_hint()),
+            Self::Failed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<SurgeError> for PingError {
+    fn from(e: SurgeError) -> Self {
+        match e {
+            SurgeError::Timeout { .. } => Self::Timeout,
+            SurgeError::IOError(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                Self::PermissionDenied(e.to_string())
+            }
+            e => Self::Failed(e.to_string()),
+        }
+    }
+}
+
+/// Initial TTLs used by common operating systems (Linux/macOS 64,
+/// Windows 128, network equipment 255, some older stacks 32).
+const INITIAL_TTLS: [u8; 4] = [32, 64, 128, 255];
+
+/// Estimate how many routers a reply crossed from its remaining TTL.
+///
+/// The sender's initial TTL is not in the packet, so this assumes the
+/// smallest common initial value that is not below `ttl`.
+#[must_use]
+pub fn estimate_hops(ttl: u8) -> u8 {
+    let initial = INITIAL_TTLS
+        .into_iter()
+        .find(|&initial| initial >= ttl)
+        .unwrap_or(u8::MAX);
+    initial - ttl
+}
+
+/// Next echo identifier handed out by [`next_ident`].
+static NEXT_IDENT: OnceLock<AtomicU16> = OnceLock::new();
+
+/// Allocate an echo identifier.
+///
+/// Identifiers come from one counter shared by every pinger and tracer in
+/// the process, so concurrent probes never share one and replies cannot be
+/// attributed to the wrong probe until 65536 are in flight. The counter
+/// starts at a value derived from the process id, so that separate
+/// processes are unlikely to overlap either.
+pub(crate) fn next_ident() -> u16 {
+    NEXT_IDENT
+        .get_or_init(|| AtomicU16::new(std::process::id() as u16))
+        .fetch_add(1, Ordering::Relaxed)
+}
+
+/// A received echo reply.
+#[derive(Debug, Clone, Copy)]
+pub struct Echo {
+    /// Round-trip time
+    pub rtt: Duration,
+    /// TTL of the reply's IP header, where the backend exposes it
+    pub ttl: Option<u8>,
+    /// Time from starting the request until the reply was handed over,
+    /// including any wait to be scheduled, where the backend timestamps
+    /// replies itself so that `rtt` leaves that wait out
+    pub elapsed: Option<Duration>,
+}
+
+/// Name asked for by the DNS-query probe unless told otherwise; the
+/// answer is small and every recursive resolver has it cached.
+pub const DEFAULT_PROBE_NAME: &str = ".";
+
+/// Record type the DNS-query probe asks for unless told otherwise.
+pub const DEFAULT_PROBE_TYPE: RecordType = RecordType::NS;
+
+/// Sends echo requests for a [`SpeedTester`](crate::dns::SpeedTester).
+pub trait Probe: Send + Sync {
+    /// Method this backend uses.
+    fn method(&self) -> IcmpMethod;
+
+    /// Send one echo request and wait for the reply.
+    ///
+    /// Returns the reply, or why none arrived. `target` is the server's
+    /// address and port; echo requests only use the address. `ident` and
+    /// `seq` are the echo identifier and sequence number; one series of
+    /// requests to a server shares an identifier.
+    fn ping<'a>(
+        &'a self,
+        target: SocketAddr,
+        ident: u16,
+        seq: u16,
+        payload: &'a [u8],
+        timeout: Duration,
+    ) -> BoxFuture<'a, std::result::Result<Echo, PingError>>;
+}
+
+/// Measures the round trip of a plain DNS query to the server's port
+/// instead of an echo request, over UDP unless
+/// [`over_tcp`](Self::over_tcp) says otherwise.
+///
+/// This needs no privileges and measures what a resolver is used for,
+/// including the time it takes to answer, but a resolver that has the
+/// name cached answers faster than one that has to recurse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsQueryProbe {
+    /// Name asked for
+    pub name: String,
+    /// Record type asked for
+    pub query_type: RecordType,
+    /// Whether to connect over TCP for each query instead of using UDP
+    pub tcp: bool,
+}
+
+impl DnsQueryProbe {
+    /// Create a probe asking for `query_type` records of `name`.
+    #[must_use]
+    pub fn new(name: impl Into<String>, query_type: RecordType) -> Self {
+        Self {
+            name: name.into(),
+            query_type,
+            tcp: false,
+        }
+    }
+
+    /// Send the queries over TCP, on a new connection each, so the round
+    /// trip includes the handshake. Some networks drop ICMP but let DNS
+    /// over TCP through.
+    #[must_use]
+    pub const fn over_tcp(mut self) -> Self {
+        self.tcp = true;
+        self
+    }
+}
+
+impl Default for DnsQueryProbe {
+    /// Ask for the name servers of the root zone.
+    fn default() -> Self {
+        Self::new(DEFAULT_PROBE_NAME, DEFAULT_PROBE_TYPE)
+    }
+}
+
+// The DNS-query probe does not see the IP header, so its replies carry
+// no TTL
+impl Probe for DnsQueryProbe {
+    fn method(&self) -> IcmpMethod {
+        if self.tcp {
+            IcmpMethod::TcpQuery
+        } else {
+            IcmpMethod::DnsQuery
+        }
+    }
+
+    fn ping<'a>(
+        &'a self,
+        server: SocketAddr,
+        _ident: u16,
+        _seq: u16,
+        _payload: &'a [u8],
+        timeout: Duration,
+    ) -> BoxFuture<'a, std::result::Result<Echo, PingError>> {
+        Box::pin(async move {
+            let response = if self.tcp {
+                query::query_tcp(server, &self.name, self.query_type, timeout).await
+            } else {
+                query::query_udp(server, &self.name, self.query_type, timeout).await
+            };
+            match response {
+                Ok(response) => Ok(Echo {
+                    rtt: response.elapsed,
+                    ttl: None,
+                    elapsed: None,
+                }),
+                Err(Error::Timeout) => Err(PingError::Timeout),
+                Err(e) => Err(PingError::Failed(e.to_string())),
+            }
+        })
+    }
+}
+
+/// An open ICMP backend.
+pub(crate) enum Pinger {
+    Socket(Client),
+    #[cfg(windows)]
+    IcmpApi,
+    #[cfg(target_os = "linux")]
+    DnsQuery(DnsQueryProbe),
+}
+
+impl Pinger {
+    /// Open the best available backend, falling back to DNS queries sent
+    /// by `fallback` where that is possible.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub(crate) fn open(fallback: DnsQueryProbe) -> Result<Self> {
+        match Self::open_socket() {
+            Ok(pinger) => Ok(pinger),
+            #[cfg(target_os = "linux")]
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                let reason = LinuxPermissions::current()
+                    .and_then(|l| l.denial())
+                    .unwrap_or_else(|| e.to_string());
+                tracing::warn!("ICMP unavailable ({reason}), measuring DNS query latency instead");
+                Ok(Self::DnsQuery(fallback))
+            }
+            Err(e) => Err(Error::Network(describe_open_error(&e))),
+        }
+    }
+
+    /// Open an ICMP socket, unprivileged if possible.
+    fn open_socket() -> std::io::Result<Self> {
+        // surge-ping retries with a raw socket if the datagram one is refused
+        let mut config = Config::builder().sock_type_hint(socket2::Type::DGRAM);
+        if let Some(binding) = source::current() {
+            if let Some(address @ IpAddr::V4(_)) = binding.address {
+                config = config.bind(SocketAddr::new(address, 0));
+            }
+            if let Some(interface) = &binding.interface {
+                config = config.interface(interface);
+            }
+        }
+        match Client::new(&config.build()) {
+            Ok(client) => {
+                #[cfg(target_os = "linux")]
+                if let Some(mark) = source::current().and_then(|binding| binding.mark) {
+                    // SAFETY: the descriptor stays open as long as `client`
+                    let fd = unsafe {
+                        std::os::fd::BorrowedFd::borrow_raw(client.get_socket().get_native_sock())
+                    };
+                    socket2::SockRef::from(&fd).set_mark(mark)?;
+                }
+                Ok(Self::Socket(client))
+            }
+            #[cfg(windows)]
+            Err(e) if windows::available() => {
+                tracing::debug!("ICMP socket unavailable ({e}), using IcmpSendEcho");
+                Ok(Self::IcmpApi)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Probe for Pinger {
+    fn method(&self) -> IcmpMethod {
+        match self {
+            Self::Socket(client) if client.get_socket().get_type() == socket2::Type::RAW => {
+                IcmpMethod::Raw
+            }
+            Self::Socket(_) => IcmpMethod::Datagram,
+            #[cfg(windows)]
+            Self::IcmpApi => IcmpMethod::IcmpApi,
+            #[cfg(target_os = "linux")]
+            Self::DnsQuery(probe) => probe.method(),
+        }
+    }
+
+    // Linux datagram sockets do not see the IP header, so their replies
+    // carry no TTL
+    fn ping<'a>(
+        &'a self,
+        target: SocketAddr,
+        ident: u16,
+        seq: u16,
+        payload: &'a [u8],
+        timeout: Duration,
+    ) -> BoxFuture<'a, std::result::Result<Echo, PingError>> {
+        Box::pin(async move {
+            let ip = target.ip();
+            match self {
+                Self::Socket(client) => {
+                    let mut pinger = client.pinger(ip, PingIdentifier(ident)).await;
+                    pinger.timeout(timeout);
+                    let start = Instant::now();
+                    match tokio::time::timeout(timeout, pinger.ping(PingSequence(seq), payload))
+                        .await
+                    {
+                        // surge-ping times the reply from sending to its
+                        // arrival on the socket, before this task gets to run
+                        Ok(Ok((packet, rtt))) => Ok(Echo {
+                            rtt,
+                            ttl: match packet {
+                                IcmpPacket::V4(packet) => packet.get_ttl(),
+                                IcmpPacket::V6(_) => None,
+                            },
+                            elapsed: Some(start.elapsed()),
+                        }),
+                        Ok(Err(e)) => Err(e.into()),
+                        Err(_) => Err(PingError::Timeout),
+                    }
+                }
+                #[cfg(windows)]
+                Self::IcmpApi => {
+                    let IpAddr::V4(ip) = ip else {
+                        return Err(PingError::Failed(
+                            "IcmpSendEcho only supports IPv4".to_string(),
+                        ));
+                    };
+                    let payload = payload.to_vec();
+                    tokio::task::spawn_blocking(move || windows::ping(ip, &payload, timeout))
+                        .await
+                        .map_err(|e| PingError::Failed(e.to_string()))?
+                }
+                #[cfg(target_os = "linux")]
+                Self::DnsQuery(probe) => probe.ping(target, ident, seq, payload, timeout).await,
+            }
+        })
+    }
+}
+
+#[cfg(windows)]
+pub(crate) mod windows {
+    //! `IcmpSendEcho` from `iphlpapi.dll`.
+
+    use super::{Echo, PingError};
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        IcmpCloseHandle, IcmpCreateFile, IcmpSendEcho, ICMP_ECHO_REPLY, IP_OPTION_INFORMATION,
+        IP_REQ_TIMED_OUT, IP_SUCCESS,
+    };
+
+    pub(crate) use windows_sys::Win32::NetworkManagement::IpHelper::{
+        IP_DEST_HOST_UNREACHABLE, IP_TTL_EXPIRED_TRANSIT,
+    };
+
+    /// Room for the ICMP error message that may follow the reply.
+    const ERROR_INFO_SIZE: usize = 8;
+
+    /// An ICMP handle, closed on drop.
+    struct Handle(windows_sys::Win32::Foundation::HANDLE);
+
+    impl Handle {
+        fn open() -> Option<Self> {
+            // SAFETY: IcmpCreateFile takes no arguments and returns a handle
+            // or INVALID_HANDLE_VALUE.
+            let handle = unsafe { IcmpCreateFile() };
+            (handle != INVALID_HANDLE_VALUE).then_some(Self(handle))
+        }
+    }
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            // SAFETY: the handle came from IcmpCreateFile and is closed once.
+            unsafe { IcmpCloseHandle(self.0) };
+        }
+    }
+
+    /// A reply as reported by `IcmpSendEcho`.
+    pub(crate) struct Reply {
+        /// Host that sent the reply, a router for expired TTLs
+        pub from: Ipv4Addr,
+        /// `IP_SUCCESS`, `IP_TTL_EXPIRED_TRANSIT`, ...
+        pub status: u32,
+        pub rtt: Duration,
+        pub ttl: u8,
+    }
+
+    /// Whether the ICMP helper API can be used.
+    pub(super) fn available() -> bool {
+        Handle::open().is_some()
+    }
+
+    /// Send one echo request, blocking until the reply or the timeout.
+    pub(super) fn ping(ip: Ipv4Addr, payload: &[u8], timeout: Duration) -> Result<Echo, PingError> {
+        let reply = send_echo(ip, payload, timeout, None)?;
+        match reply.status {
+            IP_SUCCESS => Ok(Echo {
+                rtt: reply.rtt,
+                ttl: Some(reply.ttl),
+                elapsed: None,
+            }),
+            IP_REQ_TIMED_OUT => Err(PingError::Timeout),
+            status => Err(PingError::Failed(format!("ICMP status {status}"))),
+        }
+    }
+
+    /// Send one echo request, optionally with a limited TTL, and return
+    /// whatever answered.
+    pub(crate) fn send_echo(
+        ip: Ipv4Addr,
+        payload: &[u8],
+        timeout: Duration,
+        ttl: Option<u8>,
+    ) -> Result<Reply, PingError> {
+        let handle =
+            Handle::open().ok_or_else(|| PingError::Failed("IcmpCreateFile failed".to_string()))?;
+        let mut reply =
+            vec![0u8; std::mem::size_of::<ICMP_ECHO_REPLY>() + payload.len() + ERROR_INFO_SIZE];
+        let options = ttl.map(|ttl| IP_OPTION_INFORMATION {
+            Ttl: ttl,
+            ..Default::default()
+        });
+
+        // SAFETY: all pointers are valid for the sizes passed alongside
+        // them, and the buffers outlive the call.
+        let replies = unsafe {
+            IcmpSendEcho(
+                handle.0,
+                u32::from_ne_bytes(ip.octets()),
+                payload.as_ptr().cast(),
+                payload.len() as u16,
+                options
+                    .as_ref()
+                    .map_or(std::ptr::null(), |options| options as *const _),
+                reply.as_mut_ptr().cast(),
+                reply.len() as u32,
+                timeout.as_millis().try_into().unwrap_or(u32::MAX),
+            )
+        };
+        if replies == 0 {
+            let e = std::io::Error::last_os_error();
+            return Err(match e.raw_os_error() {
+                Some(code) if code as u32 == IP_REQ_TIMED_OUT => PingError::Timeout,
+                _ => PingError::Failed(e.to_string()),
+            });
+        }
+
+        // SAFETY: IcmpSendEcho wrote at least one ICMP_ECHO_REPLY to the
+        // start of the buffer, which may not be aligned for it.
+        let reply = unsafe { std::ptr::read_unaligned(reply.as_ptr().cast::<ICMP_ECHO_REPLY>()) };
+        Ok(Reply {
+            from: Ipv4Addr::from(reply.Address.to_ne_bytes()),
+            status: reply.Status,
+            rtt: Duration::from_millis(u64::from(reply.RoundTripTime)),
+            ttl: reply.Options.Ttl,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_detect() {
+        let capability = detect();
+        assert_eq!(
+            capability.available(),
+            Pinger::open(DnsQueryProbe::default()).is_ok()
+        );
+        if !capability.elevation_required {
+            assert_eq!(capability.available(), capability.error.is_none());
+        }
+    }
+
+    #[test]
+    fn test_next_ident_unique() {
+        let idents = std::sync::Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let batch: Vec<u16> = (0..1000).map(|_| next_ident()).collect();
+                    idents.lock().unwrap().extend(batch);
+                });
+            }
+        });
+        let mut idents = idents.into_inner().unwrap();
+        idents.sort_unstable();
+        idents.dedup();
+        assert_eq!(idents.len(), 8000);
+    }
+
+    #[test]
+    fn test_linux_permissions() {
+        let status = "Name:\tdnstest\nGid:\t1000\t1000\t1000\t1000\nGroups:\t27 1000 \nCapEff:\t0000000000000000\n";
+
+        let perms = LinuxPermissions::parse(Some("1\t0\n"), status);
+        assert_eq!(perms.ping_group_range, Some((1, 0)));
+        assert!(!perms.in_ping_group);
+        assert!(!perms.cap_net_raw);
+        assert!(perms.denial().unwrap().contains("(1 0)"));
+
+        let perms = LinuxPermissions::parse(Some("0 2147483647"), status);
+        assert!(perms.in_ping_group);
+        assert_eq!(perms.denial(), None);
+
+        let root = status.replace("0000000000000000", "000001ffffffffff");
+        let perms = LinuxPermissions::parse(None, &root);
+        assert!(perms.cap_net_raw);
+        assert_eq!(perms.denial(), None);
+    }
+
+    #[test]
+    fn test_estimate_hops() {
+        assert_eq!(estimate_hops(64), 0);
+        assert_eq!(estimate_hops(55), 9);
+        assert_eq!(estimate_hops(117), 11);
+        assert_eq!(estimate_hops(244), 11);
+        assert_eq!(estimate_hops(30), 2);
+    }
+
+    #[test]
+    fn test_ping_error() {
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let e = PingError::from(SurgeError::IOError(denied));
+        assert!(matches!(e, PingError::PermissionDenied(_)));
+        assert!(e.to_string().ends_with(elevation_hint()));
+
+        let e = PingError::from(SurgeError::Timeout {
+            seq: PingSequence(0),
+        });
+        assert_eq!(e, PingError::Timeout);
+    }
+}
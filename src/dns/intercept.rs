@@ -0,0 +1,205 @@
+//! Transparent DNS proxy detection.
+//!
+//! Some ISPs silently redirect all port-53 traffic to their own resolvers.
+//! When that happens, "asking 8.8.8.8" actually asks the ISP, and comparing
+//! system and public answers proves nothing. Two probes reveal it:
+//!
+//! - a query sent to an address that runs no DNS service must go
+//!   unanswered; any reply comes from an interceptor
+//! - `o-o.myaddr.l.google.com` returns the egress address of the
+//!   recursive resolver that asked for it; different public resolvers
+//!   have different egress addresses, so identical answers mean they are
+//!   all the same (intercepting) resolver
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::dns::query::{self, Exchange, NetworkExchange, DNS_PORT};
+use futures::future::join_all;
+use hickory_resolver::proto::rr::RecordType;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Addresses that run no DNS service (RFC 5737 documentation ranges).
+const BLACKHOLE_ADDRS: &[Ipv4Addr] = &[Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(198, 51, 100, 1)];
+
+/// Public resolvers whose egress addresses are compared.
+const PUBLIC_RESOLVERS: &[Ipv4Addr] = &[
+    Ipv4Addr::new(1, 1, 1, 1),
+    Ipv4Addr::new(8, 8, 8, 8),
+    Ipv4Addr::new(9, 9, 9, 9),
+    Ipv4Addr::new(208, 67, 222, 222),
+];
+
+/// Name whose TXT answer is the egress address of the asking resolver.
+const EGRESS_NAME: &str = "o-o.myaddr.l.google.com.";
+
+/// Domain queried through the black-holed addresses.
+const BLACKHOLE_QUERY: &str = "example.com.";
+
+/// Default per-query timeout.
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Egress address reported for a single public resolver.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolverEgress {
+    /// The public resolver that was asked
+    pub resolver: IpAddr,
+    /// The egress address it reported (None if it did not answer)
+    pub egress: Option<String>,
+}
+
+/// Result of transparent proxy detection.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterceptionReport {
+    /// Black-holed addresses that nevertheless answered a query
+    pub blackhole_answers: Vec<IpAddr>,
+    /// Egress address reported by each public resolver
+    pub egress: Vec<ResolverEgress>,
+    /// Whether all answering public resolvers share one egress address
+    pub identical_egress: bool,
+    /// Whether port-53 traffic appears to be transparently intercepted
+    pub intercepted: bool,
+}
+
+impl InterceptionReport {
+    /// Build a report from raw probe outcomes.
+    #[must_use]
+    pub fn from_probes(blackhole_answers: Vec<IpAddr>, egress: Vec<ResolverEgress>) -> Self {
+        let answered: Vec<&String> = egress.iter().filter_map(|e| e.egress.as_ref()).collect();
+        let identical_egress = answered.len() > 1 && answered.windows(2).all(|w| w[0] == w[1]);
+        let intercepted = !blackhole_answers.is_empty() || identical_egress;
+
+        Self {
+            blackhole_answers,
+            egress,
+            identical_egress,
+            intercepted,
+        }
+    }
+}
+
+/// Transparent DNS proxy detector.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = InterceptionDetector::new().detect().await;
+/// if report.intercepted {
+///     println!("Port 53 traffic is being intercepted");
+/// }
+/// ```
+pub struct InterceptionDetector {
+    timeout: Duration,
+    exchange: Arc<dyn Exchange>,
+}
+
+impl InterceptionDetector {
+    /// Create a detector with the default timeout.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_TIMEOUT)
+    }
+
+    /// Create a detector with a custom per-query timeout.
+    #[must_use]
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            exchange: Arc::new(NetworkExchange),
+        }
+    }
+
+    /// Send the probes through `exchange` instead of the network.
+    #[must_use]
+    pub fn with_exchange(mut self, exchange: Arc<dyn Exchange>) -> Self {
+        self.exchange = exchange;
+        self
+    }
+
+    /// Run all probes concurrently and build a report.
+    ///
+    /// Unanswered probes are treated as "no evidence", so this never fails.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn detect(&self) -> InterceptionReport {
+        let blackhole = join_all(BLACKHOLE_ADDRS.iter().map(|&ip| async move {
+            let server = SocketAddr::new(IpAddr::V4(ip), DNS_PORT);
+            self.exchange
+                .udp(server, BLACKHOLE_QUERY, RecordType::A, self.timeout, None)
+                .await
+                .is_ok_and(|responses| !responses.is_empty())
+                .then_some(IpAddr::V4(ip))
+        }));
+
+        let egress = join_all(PUBLIC_RESOLVERS.iter().map(|&ip| async move {
+            let server = SocketAddr::new(IpAddr::V4(ip), DNS_PORT);
+            let egress = self
+                .exchange
+                .udp(server, EGRESS_NAME, RecordType::TXT, self.timeout, None)
+                .await
+                .ok()
+                .and_then(|responses| responses.into_iter().next())
+                .and_then(|r| query::answer_txt(&r.message).into_iter().next());
+            ResolverEgress {
+                resolver: IpAddr::V4(ip),
+                egress,
+            }
+        }));
+
+        let (blackhole, egress) = futures::join!(blackhole, egress);
+        InterceptionReport::from_probes(blackhole.into_iter().flatten().collect(), egress)
+    }
+}
+
+impl Default for InterceptionDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn egress(resolver: &str, egress: Option<&str>) -> ResolverEgress {
+        ResolverEgress {
+            resolver: resolver.parse().unwrap(),
+            egress: egress.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_distinct_egress_not_intercepted() {
+        let report = InterceptionReport::from_probes(
+            vec![],
+            vec![
+                egress("1.1.1.1", Some("172.70.1.1")),
+                egress("8.8.8.8", Some("74.125.1.1")),
+                egress("9.9.9.9", None),
+            ],
+        );
+        assert!(!report.identical_egress);
+        assert!(!report.intercepted);
+    }
+
+    #[test]
+    fn test_identical_egress_intercepted() {
+        let report = InterceptionReport::from_probes(
+            vec![],
+            vec![
+                egress("1.1.1.1", Some("61.139.2.69")),
+                egress("8.8.8.8", Some("61.139.2.69")),
+            ],
+        );
+        assert!(report.identical_egress);
+        assert!(report.intercepted);
+    }
+
+    #[test]
+    fn test_blackhole_answer_intercepted() {
+        let report =
+            InterceptionReport::from_probes(vec!["192.0.2.1".parse().unwrap()], Vec::new());
+        assert!(report.intercepted);
+    }
+}
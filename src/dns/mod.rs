@@ -2,13 +2,67 @@
 //!
 //! This module provides DNS-related functionality including:
 //! - Speed testing via ICMP ping
+//! - Adaptive concurrency for large scans
+//! - DNS-over-HTTPS benchmarking per HTTP version
 //! - Pollution detection
+//! - GFW-specific censorship heuristics
+//! - Bogon (reserved address) detection
+//! - Transparent DNS proxy detection
+//! - NXDOMAIN and typo hijack detection
+//! - Resolver egress address lookup
+//! - Open resolver detection
+//! - Delegation health checks
+//! - Propagation checks of changed records
+//! - Resolver feature probes (negative caching, content filtering)
+//! - Raw DNS message exchange
+//! - DNS-over-HTTPS JSON API and oblivious DNS (RFC 9230) queries
+//! - RDAP registration lookups for answer addresses
+//! - Traceroute to a server
+//! - Scanning address ranges for DNS servers
+//! - Binding probes to a source interface or address
 //! - Core data types
 
+pub mod adaptive;
+pub mod bogon;
+pub mod censorship;
+pub mod delegation;
+pub mod doh;
+pub mod doh_json;
+pub mod egress;
+pub mod features;
+pub mod filtering;
+pub mod hijack;
+pub mod icmp;
+pub mod intercept;
+pub mod odoh;
+pub mod open_resolver;
 pub mod pollution;
+pub mod progress;
+pub mod propagation;
+pub mod query;
+pub mod rdap;
+pub mod reference_cache;
+pub mod route;
+pub mod scan;
+pub mod source;
 pub mod speedtest;
 pub mod types;
 
+pub use adaptive::Aimd;
+pub use censorship::{CensorshipDetector, CensorshipSignal};
+pub use delegation::{DelegationChecker, DelegationReport};
+pub use doh::DohTester;
+pub use egress::{Egress, EgressProber};
+pub use features::{FeatureProber, ResolverFeatures};
+pub use filtering::Filtering;
+pub use hijack::{HijackDetector, HijackReport};
+pub use icmp::{IcmpCapability, IcmpMethod};
+pub use intercept::{InterceptionDetector, InterceptionReport};
+pub use open_resolver::{OpenResolverChecker, OpenResolverReport};
 pub use pollution::PollutionChecker;
-pub use speedtest::SpeedTester;
+pub use progress::{ProgressEvent, ProgressListener};
+pub use propagation::{PropagationChecker, PropagationResult};
+pub use route::{Hop, Tracer};
+pub use scan::{ScanHit, Scanner};
+pub use speedtest::{ResultFilter, SpeedTester, TestMethod};
 pub use types::*;
@@ -0,0 +1,585 @@
+//! Oblivious DNS over HTTPS (RFC 9230) client.
+//!
+//! An oblivious query is encrypted to the target resolver's public key and
+//! sent through a relay. The relay sees who is asking but not the question;
+//! the target sees the question but only the relay's address; the local
+//! network sees neither. That makes its answers a reference for pollution
+//! checks that no observer along the way can single out and tamper with.
+//!
+//! Encryption uses HPKE (RFC 9180) in base mode with the suite every
+//! deployment supports: DHKEM(X25519, HKDF-SHA256), HKDF-SHA256 and
+//! AES-128-GCM.
+//!
+//! ```ignore
+//! let client = OdohClient::new(odoh::DEFAULT_TARGET, "https://relay.example/proxy");
+//! let ips = client.resolve("example.com").await?;
+//! ```
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::dns::query;
+use crate::error::{Error, Result};
+use crate::http;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::Aes128Gcm;
+use hickory_resolver::proto::op::Message;
+use hickory_resolver::proto::rr::RecordType;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Cloudflare's oblivious DNS target.
+pub const DEFAULT_TARGET: &str = "odoh.cloudflare-dns.com";
+
+/// Path of the target's key configurations.
+const CONFIGS_PATH: &str = "/.well-known/odohconfigs";
+
+/// Path of the target's query endpoint.
+const QUERY_PATH: &str = "/dns-query";
+
+/// Media type of oblivious DNS messages.
+const CONTENT_TYPE: &str = "application/oblivious-dns-message";
+
+/// Default timeout for one HTTP exchange.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configuration version of RFC 9230.
+const ODOH_VERSION: u16 = 0x0001;
+
+/// HPKE algorithm identifiers of the supported suite.
+const KEM_X25519_SHA256: u16 = 0x0020;
+const KDF_HKDF_SHA256: u16 = 0x0001;
+const AEAD_AES_128_GCM: u16 = 0x0001;
+
+/// Key, nonce and hash lengths of the suite.
+const NK: usize = 16;
+const NN: usize = 12;
+const NH: usize = 32;
+
+/// Message types of RFC 9230.
+const MESSAGE_QUERY: u8 = 0x01;
+const MESSAGE_RESPONSE: u8 = 0x02;
+
+/// Queries are padded to a multiple of this many bytes, so their size
+/// says little about the name asked for.
+const PADDING_BLOCK: usize = 128;
+
+/// A target's public key configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OdohConfig {
+    /// Encoded `ObliviousDoHConfigContents`, from which the key id derives
+    contents: Vec<u8>,
+    /// Target's X25519 public key
+    public_key: [u8; 32],
+}
+
+impl OdohConfig {
+    /// Pick the first configuration with the supported version and suite
+    /// from an encoded `ObliviousDoHConfigs` list.
+    pub fn parse_list(data: &[u8]) -> Result<Self> {
+        let mut list = Reader::new(data);
+        let mut configs = Reader::new(list.vec16()?);
+        while !configs.is_empty() {
+            let version = configs.u16()?;
+            let contents = configs.vec16()?;
+            if version != ODOH_VERSION {
+                continue;
+            }
+
+            let mut fields = Reader::new(contents);
+            let suite = (fields.u16()?, fields.u16()?, fields.u16()?);
+            let public_key = fields.vec16()?;
+            if suite == (KEM_X25519_SHA256, KDF_HKDF_SHA256, AEAD_AES_128_GCM) {
+                if let Ok(public_key) = public_key.try_into() {
+                    return Ok(Self {
+                        contents: contents.to_vec(),
+                        public_key,
+                    });
+                }
+            }
+        }
+        Err(Error::parse("No supported ODoH configuration"))
+    }
+
+    /// Encode a configuration for `public_key`, as a target publishes it.
+    #[must_use]
+    pub fn new(public_key: [u8; 32]) -> Self {
+        let mut contents = Vec::with_capacity(8 + public_key.len());
+        for id in [KEM_X25519_SHA256, KDF_HKDF_SHA256, AEAD_AES_128_GCM] {
+            contents.extend_from_slice(&id.to_be_bytes());
+        }
+        put_vec16(&mut contents, &public_key);
+        Self {
+            contents,
+            public_key,
+        }
+    }
+
+    /// Encode as a one-entry `ObliviousDoHConfigs` list.
+    #[must_use]
+    pub fn to_list(&self) -> Vec<u8> {
+        let mut config = ODOH_VERSION.to_be_bytes().to_vec();
+        put_vec16(&mut config, &self.contents);
+        let mut list = Vec::new();
+        put_vec16(&mut list, &config);
+        list
+    }
+
+    /// Identifier of this key, sent along with each query.
+    fn key_id(&self) -> [u8; NH] {
+        let (_, hkdf) = Hkdf::<Sha256>::extract(Some(&[]), &self.contents);
+        let mut key_id = [0u8; NH];
+        hkdf.expand(b"odoh key id", &mut key_id)
+            .expect("valid HKDF length");
+        key_id
+    }
+}
+
+/// What is needed to decrypt the response to an encrypted query.
+pub struct QueryState {
+    /// Encoded `ObliviousDoHMessagePlaintext` of the query
+    plaintext: Vec<u8>,
+    context: HpkeContext,
+}
+
+/// Encrypt a DNS query message for `config`'s target.
+///
+/// Returns the encoded `ObliviousDoHMessage` and the state to decrypt the
+/// response with.
+pub fn encrypt_query(config: &OdohConfig, dns_message: &[u8]) -> Result<(Vec<u8>, QueryState)> {
+    let mut plaintext = Vec::new();
+    put_vec16(&mut plaintext, dns_message);
+    let padding = (PADDING_BLOCK - dns_message.len() % PADDING_BLOCK) % PADDING_BLOCK;
+    put_vec16(&mut plaintext, &vec![0u8; padding]);
+
+    let key_id = config.key_id();
+    let (enc, context) = setup_base_s(&config.public_key, b"odoh query", &StaticSecret::random());
+    let sealed = context.seal(&message_aad(MESSAGE_QUERY, &key_id), &plaintext)?;
+
+    let mut encrypted = enc.to_vec();
+    encrypted.extend_from_slice(&sealed);
+    Ok((
+        encode_message(MESSAGE_QUERY, &key_id, &encrypted),
+        QueryState { plaintext, context },
+    ))
+}
+
+/// Decrypt a target's `ObliviousDoHMessage` response to the DNS message
+/// it carries.
+pub fn decrypt_response(state: &QueryState, response: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(response);
+    if reader.u8()? != MESSAGE_RESPONSE {
+        return Err(Error::parse("Not an ODoH response"));
+    }
+    let nonce = reader.vec16()?;
+    let sealed = reader.vec16()?;
+
+    let (key, response_nonce) = response_keys(state, nonce);
+    let plaintext = Aes128Gcm::new(&key.into())
+        .decrypt(
+            &response_nonce.into(),
+            Payload {
+                msg: sealed,
+                aad: &message_aad(MESSAGE_RESPONSE, nonce),
+            },
+        )
+        .map_err(|_| Error::parse("ODoH response failed to decrypt"))?;
+
+    let mut reader = Reader::new(&plaintext);
+    Ok(reader.vec16()?.to_vec())
+}
+
+/// Key and nonce protecting the response, derived from the query's HPKE
+/// context and the target's response nonce.
+fn response_keys(state: &QueryState, response_nonce: &[u8]) -> ([u8; NK], [u8; NN]) {
+    let mut secret = [0u8; NK];
+    state.context.export(b"odoh response", &mut secret);
+
+    let mut salt = state.plaintext.clone();
+    put_vec16(&mut salt, response_nonce);
+    let (_, hkdf) = Hkdf::<Sha256>::extract(Some(&salt), &secret);
+    let mut key = [0u8; NK];
+    let mut nonce = [0u8; NN];
+    hkdf.expand(b"odoh key", &mut key)
+        .expect("valid HKDF length");
+    hkdf.expand(b"odoh nonce", &mut nonce)
+        .expect("valid HKDF length");
+    (key, nonce)
+}
+
+/// Additional authenticated data of a query or response.
+fn message_aad(message_type: u8, key_id: &[u8]) -> Vec<u8> {
+    let mut aad = vec![message_type];
+    put_vec16(&mut aad, key_id);
+    aad
+}
+
+/// Encode an `ObliviousDoHMessage`.
+fn encode_message(message_type: u8, key_id: &[u8], encrypted: &[u8]) -> Vec<u8> {
+    let mut message = vec![message_type];
+    put_vec16(&mut message, key_id);
+    put_vec16(&mut message, encrypted);
+    message
+}
+
+/// Append a vector with a 16-bit length prefix.
+fn put_vec16(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+/// Cursor over length-prefixed wire data.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    const fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    const fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(Error::parse("Truncated ODoH message"));
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn vec16(&mut self) -> Result<&'a [u8]> {
+        let len = self.u16()?;
+        self.take(usize::from(len))
+    }
+}
+
+/// HPKE sender context after the key schedule.
+struct HpkeContext {
+    key: [u8; NK],
+    base_nonce: [u8; NN],
+    exporter_secret: [u8; NH],
+}
+
+impl HpkeContext {
+    /// Encrypt the first (and only) message of the context.
+    fn seal(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        // Sequence number 0 leaves the base nonce unchanged
+        Aes128Gcm::new(&self.key.into())
+            .encrypt(
+                &self.base_nonce.into(),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| Error::parse("HPKE encryption failed"))
+    }
+
+    /// Derive a secret bound to this context.
+    fn export(&self, exporter_context: &[u8], out: &mut [u8]) {
+        labeled_expand(
+            &hpke_suite_id(),
+            &self.exporter_secret,
+            b"sec",
+            exporter_context,
+            out,
+        );
+    }
+}
+
+/// `SetupBaseS`: encapsulate a fresh shared secret to `public_key`.
+///
+/// Returns the encapsulated key and the sender context.
+fn setup_base_s(
+    public_key: &[u8; 32],
+    info: &[u8],
+    ephemeral: &StaticSecret,
+) -> ([u8; 32], HpkeContext) {
+    let enc = PublicKey::from(ephemeral).to_bytes();
+    let dh = ephemeral.diffie_hellman(&PublicKey::from(*public_key));
+    let shared_secret = extract_and_expand(dh.as_bytes(), &enc, public_key);
+    (enc, key_schedule(&shared_secret, info))
+}
+
+/// `ExtractAndExpand` of DHKEM(X25519, HKDF-SHA256).
+fn extract_and_expand(dh: &[u8], enc: &[u8; 32], public_key: &[u8; 32]) -> [u8; NH] {
+    let suite_id = [b"KEM".as_slice(), &KEM_X25519_SHA256.to_be_bytes()].concat();
+    let eae_prk = labeled_extract(&suite_id, &[], b"eae_prk", dh);
+    let mut shared_secret = [0u8; NH];
+    labeled_expand(
+        &suite_id,
+        &eae_prk,
+        b"shared_secret",
+        &[enc.as_slice(), public_key].concat(),
+        &mut shared_secret,
+    );
+    shared_secret
+}
+
+/// HPKE key schedule in base mode (no PSK).
+fn key_schedule(shared_secret: &[u8], info: &[u8]) -> HpkeContext {
+    const MODE_BASE: u8 = 0x00;
+
+    let suite_id = hpke_suite_id();
+    let psk_id_hash = labeled_extract(&suite_id, &[], b"psk_id_hash", &[]);
+    let info_hash = labeled_extract(&suite_id, &[], b"info_hash", info);
+    let context = [[MODE_BASE].as_slice(), &psk_id_hash, &info_hash].concat();
+    let secret = labeled_extract(&suite_id, shared_secret, b"secret", &[]);
+
+    let mut key_schedule = HpkeContext {
+        key: [0; NK],
+        base_nonce: [0; NN],
+        exporter_secret: [0; NH],
+    };
+    labeled_expand(&suite_id, &secret, b"key", &context, &mut key_schedule.key);
+    labeled_expand(
+        &suite_id,
+        &secret,
+        b"base_nonce",
+        &context,
+        &mut key_schedule.base_nonce,
+    );
+    labeled_expand(
+        &suite_id,
+        &secret,
+        b"exp",
+        &context,
+        &mut key_schedule.exporter_secret,
+    );
+    key_schedule
+}
+
+/// Suite identifier of the HPKE key schedule.
+fn hpke_suite_id() -> Vec<u8> {
+    let mut suite_id = b"HPKE".to_vec();
+    for id in [KEM_X25519_SHA256, KDF_HKDF_SHA256, AEAD_AES_128_GCM] {
+        suite_id.extend_from_slice(&id.to_be_bytes());
+    }
+    suite_id
+}
+
+/// HPKE `LabeledExtract`.
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> [u8; NH] {
+    let input = [b"HPKE-v1".as_slice(), suite_id, label, ikm].concat();
+    let (prk, _) = Hkdf::<Sha256>::extract(Some(salt), &input);
+    prk.into()
+}
+
+/// HPKE `LabeledExpand`, filling `out`.
+fn labeled_expand(suite_id: &[u8], prk: &[u8], label: &[u8], info: &[u8], out: &mut [u8]) {
+    let length = (out.len() as u16).to_be_bytes();
+    let info = [length.as_slice(), b"HPKE-v1", suite_id, label, info].concat();
+    Hkdf::<Sha256>::from_prk(prk)
+        .expect("PRK has the hash length")
+        .expand(&info, out)
+        .expect("valid HKDF length");
+}
+
+/// Resolves names through an oblivious DNS target and relay.
+pub struct OdohClient {
+    /// Host name of the target resolver
+    target: String,
+    /// URL of the relay's proxy endpoint
+    relay: String,
+    timeout: Duration,
+    /// Target key configuration, fetched on first use
+    config: OnceCell<OdohConfig>,
+}
+
+impl OdohClient {
+    /// Create a client for `target` (a host name such as
+    /// [`DEFAULT_TARGET`]) reached through the relay at `relay`.
+    pub fn new(target: impl Into<String>, relay: impl Into<String>) -> Self {
+        Self {
+            target: target.into(),
+            relay: relay.into(),
+            timeout: DEFAULT_TIMEOUT,
+            config: OnceCell::new(),
+        }
+    }
+
+    /// The target's key configuration.
+    ///
+    /// It is fetched from the target directly: that reveals only that an
+    /// oblivious DNS target is used, never a query.
+    async fn config(&self) -> Result<&OdohConfig> {
+        self.config
+            .get_or_try_init(|| async {
+                let url = format!("https://{}{CONFIGS_PATH}", self.target);
+                OdohConfig::parse_list(&http::get(&url, self.timeout).await?)
+            })
+            .await
+    }
+
+    /// Send one query through the relay and return the response message.
+    pub async fn query(&self, domain: &str, record_type: RecordType) -> Result<Message> {
+        let config = self.config().await?;
+        let (_, dns_message) = query::build_query(domain, record_type)?;
+        let (body, state) = encrypt_query(config, &dns_message)?;
+
+        let url = format!(
+            "{}?targethost={}&targetpath={QUERY_PATH}",
+            self.relay, self.target
+        );
+        let response = http::post_bytes(&url, &body, CONTENT_TYPE, self.timeout).await?;
+        let message = decrypt_response(&state, &response)?;
+        Message::from_vec(&message).map_err(|e| Error::parse(e.to_string()))
+    }
+
+    /// Resolve `domain` to its `A` records, or its `AAAA` records if there
+    /// are none. Both are queried at the same time.
+    pub async fn resolve(&self, domain: &str) -> Result<Vec<IpAddr>> {
+        let (a, aaaa) = tokio::join!(
+            self.query(domain, RecordType::A),
+            self.query(domain, RecordType::AAAA)
+        );
+        let ips = query::answer_ips(&a?);
+        if !ips.is_empty() {
+            return Ok(ips);
+        }
+        Ok(query::answer_ips(&aaaa?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn array<const N: usize>(s: &str) -> [u8; N] {
+        hex(s).try_into().unwrap()
+    }
+
+    /// RFC 9180, A.1.1: DHKEM(X25519, HKDF-SHA256), HKDF-SHA256,
+    /// AES-128-GCM in base mode.
+    #[test]
+    fn test_hpke_vector() {
+        let ephemeral = StaticSecret::from(array::<32>(
+            "52c4a758a802cd8b936eceea314432798d5baf2d7e9235dc084ab1b9cfa2f736",
+        ));
+        let recipient = PublicKey::from(&StaticSecret::from(array::<32>(
+            "4612c550263fc8ad58375df3f557aac531d26850903e55a9f23f21d8534e8ac8",
+        )));
+        let info = b"Ode on a Grecian Urn";
+
+        let (enc, context) = setup_base_s(recipient.as_bytes(), info, &ephemeral);
+        assert_eq!(
+            enc.to_vec(),
+            hex("37fda3567bdbd628e88668c3c8d7e97d1d1253b6d4ea6d44c150f741f1bf4431")
+        );
+        assert_eq!(
+            context.key.to_vec(),
+            hex("4531685d41d65f03dc48f6b8302c05b0")
+        );
+        assert_eq!(context.base_nonce.to_vec(), hex("56d890e5accaaf011cff4b7d"));
+        assert_eq!(
+            context.exporter_secret.to_vec(),
+            hex("45ff1c2e220db587171952c0592d5f5ebe103f1561a2614e38f2ffd47e99e3f8")
+        );
+
+        let sealed = context
+            .seal(b"Count-0", b"Beauty is truth, truth beauty")
+            .unwrap();
+        assert_eq!(
+            sealed,
+            hex("f938558b5d72f1a23810b4be2ab4f84331acc02fc97babc53a52ae8218a355a96d8770ac83d07bea87e13c512a")
+        );
+    }
+
+    #[test]
+    fn test_config_list() {
+        let config = OdohConfig::new([7; 32]);
+        assert_eq!(OdohConfig::parse_list(&config.to_list()).unwrap(), config);
+
+        // Unknown versions are skipped
+        let mut unknown = 0xff00u16.to_be_bytes().to_vec();
+        put_vec16(&mut unknown, b"future");
+        let mut configs = unknown;
+        configs.extend_from_slice(&config.to_list()[2..]);
+        let mut list = Vec::new();
+        put_vec16(&mut list, &configs);
+        assert_eq!(OdohConfig::parse_list(&list).unwrap(), config);
+
+        assert!(OdohConfig::parse_list(&[0, 4, 0, 1]).is_err());
+    }
+
+    /// Encrypt a query, decrypt it as the target would, and answer it.
+    #[test]
+    fn test_query_round_trip() {
+        let target_secret = StaticSecret::from([42; 32]);
+        let config = OdohConfig::new(PublicKey::from(&target_secret).to_bytes());
+        let query = b"dns query".to_vec();
+        let (body, state) = encrypt_query(&config, &query).unwrap();
+
+        // Target: decapsulate and open the query
+        let mut reader = Reader::new(&body);
+        assert_eq!(reader.u8().unwrap(), MESSAGE_QUERY);
+        let key_id = reader.vec16().unwrap();
+        assert_eq!(key_id, config.key_id());
+        let encrypted = reader.vec16().unwrap();
+        let (enc, sealed) = encrypted.split_at(32);
+        let enc: [u8; 32] = enc.try_into().unwrap();
+        let dh = target_secret.diffie_hellman(&PublicKey::from(enc));
+        let shared_secret = extract_and_expand(dh.as_bytes(), &enc, &config.public_key);
+        let context = key_schedule(&shared_secret, b"odoh query");
+        let plaintext = Aes128Gcm::new(&context.key.into())
+            .decrypt(
+                &context.base_nonce.into(),
+                Payload {
+                    msg: sealed,
+                    aad: &message_aad(MESSAGE_QUERY, key_id),
+                },
+            )
+            .unwrap();
+        assert_eq!(Reader::new(&plaintext).vec16().unwrap(), query);
+        assert_eq!(plaintext.len() % PADDING_BLOCK, 4);
+
+        // Target: seal the answer under keys derived from the query
+        let answer = b"dns answer".to_vec();
+        let response_nonce = [9u8; NK];
+        let target_state = QueryState { plaintext, context };
+        let (key, nonce) = response_keys(&target_state, &response_nonce);
+        let mut answer_plaintext = Vec::new();
+        put_vec16(&mut answer_plaintext, &answer);
+        put_vec16(&mut answer_plaintext, &[]);
+        let sealed = Aes128Gcm::new(&key.into())
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: &answer_plaintext,
+                    aad: &message_aad(MESSAGE_RESPONSE, &response_nonce),
+                },
+            )
+            .unwrap();
+        let response = encode_message(MESSAGE_RESPONSE, &response_nonce, &sealed);
+
+        assert_eq!(decrypt_response(&state, &response).unwrap(), answer);
+
+        // A tampered response is rejected
+        let mut tampered = response;
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(decrypt_response(&state, &tampered).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Open resolver detection.
+//!
+//! An open resolver resolves any name for anyone who asks. That is what a
+//! public resolver is for, and what a home router or company server
+//! reachable from the internet should not do, since open resolvers are
+//! abused for reflection attacks. Two queries tell them apart from the
+//! current vantage point: one for a well-known name, which a server may
+//! answer from its cache alone, and one for a random name under the same
+//! domain, which it can only answer by recursing on our behalf.
+
+use crate::dns::query::{self, Exchange, NetworkExchange};
+use futures::StreamExt;
+use hickory_resolver::proto::op::{Message, ResponseCode};
+use hickory_resolver::proto::rr::RecordType;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Domain of the probe names, which has no wildcard records.
+const PROBE_DOMAIN: &str = "example.com.";
+
+/// Default per-query timeout.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Default number of servers checked at the same time.
+pub const DEFAULT_CONCURRENCY: usize = 32;
+
+/// Whether a server resolves names for us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Recursion {
+    /// Resolves arbitrary names: an open resolver
+    Open,
+    /// Answers names it has cached, but does not recurse for us
+    CacheOnly,
+    /// Answers, but refuses or does not offer recursion
+    Closed,
+    /// Did not answer at all
+    NoAnswer,
+}
+
+impl std::fmt::Display for Recursion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Open => write!(f, "open"),
+            Self::CacheOnly => write!(f, "cache only"),
+            Self::Closed => write!(f, "closed"),
+            Self::NoAnswer => write!(f, "no answer"),
+        }
+    }
+}
+
+/// Outcome of checking one server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenResolverReport {
+    /// Address and port that were queried
+    pub server: SocketAddr,
+    /// What the server did with our queries
+    pub verdict: Recursion,
+    /// Response code to the random name, e.g. `NXDomain` or `Refused`,
+    /// or else to the well-known name
+    pub rcode: Option<String>,
+    /// Whether any response had the RA flag set
+    pub recursion_available: bool,
+}
+
+impl OpenResolverReport {
+    /// Whether the server is an open resolver.
+    #[must_use]
+    pub fn is_open(&self) -> bool {
+        self.verdict == Recursion::Open
+    }
+}
+
+/// Open resolver checker.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = OpenResolverChecker::new().check("192.0.2.53:53".parse()?).await;
+/// if report.is_open() {
+///     println!("{} resolves names for anyone", report.server);
+/// }
+/// ```
+pub struct OpenResolverChecker {
+    timeout: Duration,
+    concurrency: usize,
+    exchange: Arc<dyn Exchange>,
+}
+
+impl OpenResolverChecker {
+    /// Create a checker with the default timeout.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_TIMEOUT)
+    }
+
+    /// Create a checker with a custom per-query timeout.
+    #[must_use]
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            concurrency: DEFAULT_CONCURRENCY,
+            exchange: Arc::new(NetworkExchange),
+        }
+    }
+
+    /// Check `concurrency` servers at a time.
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Send the queries through `exchange` instead of the network.
+    #[must_use]
+    pub fn with_exchange(mut self, exchange: Arc<dyn Exchange>) -> Self {
+        self.exchange = exchange;
+        self
+    }
+
+    /// Check one server.
+    ///
+    /// Unanswered queries count as "no answer", so this never fails.
+    pub async fn check(&self, server: SocketAddr) -> OpenResolverReport {
+        let random = format!("{}.{PROBE_DOMAIN}", query::random_label());
+        let (known, uncached) = futures::join!(
+            self.query(server, PROBE_DOMAIN),
+            self.query(server, &random)
+        );
+
+        let verdict = match (&known, &uncached) {
+            (None, None) => Recursion::NoAnswer,
+            (_, Some(message)) if recursed(message) => Recursion::Open,
+            (Some(message), _) if recursed(message) && !message.answers().is_empty() => {
+                Recursion::CacheOnly
+            }
+            _ => Recursion::Closed,
+        };
+        OpenResolverReport {
+            server,
+            verdict,
+            rcode: uncached
+                .as_ref()
+                .or(known.as_ref())
+                .map(|message| format!("{:?}", message.response_code())),
+            recursion_available: known
+                .iter()
+                .chain(&uncached)
+                .any(Message::recursion_available),
+        }
+    }
+
+    /// Check several servers, `concurrency` at a time, in the given order.
+    pub async fn check_all(&self, servers: &[SocketAddr]) -> Vec<OpenResolverReport> {
+        futures::stream::iter(servers)
+            .map(|&server| self.check(server))
+            .buffered(self.concurrency)
+            .collect()
+            .await
+    }
+
+    /// Send a query for `name` and return the first response.
+    async fn query(&self, server: SocketAddr, name: &str) -> Option<Message> {
+        match self
+            .exchange
+            .udp(server, name, RecordType::A, self.timeout, None)
+            .await
+        {
+            Ok(responses) => responses.into_iter().next().map(|r| r.message),
+            Err(e) => {
+                tracing::debug!("Query for {name} to {server} failed: {e}");
+                None
+            }
+        }
+    }
+}
+
+impl Default for OpenResolverChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether a response is a recursive resolver's final answer, positive or
+/// negative, rather than a refusal, a referral or an authoritative answer.
+fn recursed(message: &Message) -> bool {
+    message.recursion_available()
+        && !message.authoritative()
+        && matches!(
+            message.response_code(),
+            ResponseCode::NoError | ResponseCode::NXDomain
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::query::fake::{record, FakeExchange, Query, Reply};
+    use hickory_resolver::proto::rr::rdata::A;
+    use hickory_resolver::proto::rr::RData;
+
+    /// Servers told apart by the last octet: .1 is open, .2 answers from
+    /// its cache only, .3 refuses, .4 is authoritative-only and .5 is
+    /// silent.
+    fn fake_servers() -> FakeExchange {
+        let server = |last| Query::any().server([192, 0, 2, last]);
+        let known = |last| server(last).name(PROBE_DOMAIN);
+        let answer =
+            || Reply::answers([record(PROBE_DOMAIN, 60, RData::A(A::new(93, 184, 216, 34)))]);
+        FakeExchange::new()
+            .on(known(1), answer().recursion_available())
+            .on(
+                server(1),
+                Reply::rcode(ResponseCode::NXDomain).recursion_available(),
+            )
+            .on(known(2), answer().recursion_available())
+            .on(server(2), Reply::rcode(ResponseCode::Refused))
+            .on(server(3), Reply::rcode(ResponseCode::Refused))
+            .on(known(4), answer().authoritative())
+            .on(
+                server(4),
+                Reply::rcode(ResponseCode::NXDomain).authoritative(),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_check_all() {
+        let servers: Vec<SocketAddr> = (1..=5)
+            .map(|last| SocketAddr::from(([192, 0, 2, last], 53)))
+            .collect();
+        let reports = OpenResolverChecker::new()
+            .with_exchange(Arc::new(fake_servers()))
+            .check_all(&servers)
+            .await;
+
+        let verdicts: Vec<Recursion> = reports.iter().map(|r| r.verdict).collect();
+        assert_eq!(
+            verdicts,
+            [
+                Recursion::Open,
+                Recursion::CacheOnly,
+                Recursion::Closed,
+                Recursion::Closed,
+                Recursion::NoAnswer
+            ]
+        );
+        assert!(reports[0].is_open());
+        assert_eq!(reports[0].rcode.as_deref(), Some("NXDomain"));
+        assert_eq!(reports[1].rcode.as_deref(), Some("Refused"));
+        assert!(reports[1].recursion_available);
+        assert!(!reports[3].recursion_available);
+        assert_eq!(reports[4].rcode, None);
+    }
+}
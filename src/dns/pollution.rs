@@ -3,16 +3,36 @@
 //! This module provides functionality to detect DNS pollution (also known as
 //! DNS hijacking or DNS censorship). It works by comparing DNS resolution
 //! results from the system DNS with results from known public DNS servers.
+//!
+//! Both sides are resolved through a [`Lookup`], normally a
+//! `hickory` resolver, and the active probes send their queries through
+//! an [`Exchange`]. Supplying other implementations (see
+//! [`PollutionChecker::with_resolvers`] and
+//! [`PollutionChecker::with_exchange`]) runs a check without a network.
 
 #![allow(clippy::missing_panics_doc)]
 #![allow(clippy::missing_errors_doc)]
 
-use crate::dns::types::PollutionResult;
-use crate::error::Result;
-use std::net::IpAddr;
-use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
-use trust_dns_resolver::name_server::TokioHandle;
-use trust_dns_resolver::TokioAsyncResolver;
+use crate::dns::bogon;
+use crate::dns::censorship::{self, CensorshipDetector};
+use crate::dns::doh_json;
+use crate::dns::intercept::{InterceptionDetector, InterceptionReport};
+use crate::dns::odoh::OdohClient;
+use crate::dns::query::{Exchange, ExtendedRecord, NetworkExchange, DNS_PORT};
+use crate::dns::rdap;
+use crate::dns::reference_cache::ReferenceCache;
+use crate::dns::source;
+use crate::dns::types::{self, PollutionReason, PollutionResult, PtrRecord, TransportComparison};
+use crate::error::{Error, Result};
+use crate::telemetry;
+use futures::future::BoxFuture;
+use hickory_resolver::config::{NameServerConfig, ResolverConfig, ResolverOpts};
+use hickory_resolver::proto::rr::RecordType;
+use hickory_resolver::TokioAsyncResolver;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
 
 /// Google Public DNS IPv4 addresses.
 const GOOGLE_DNS: &str = "8.8.8.8";
@@ -20,6 +40,23 @@ const GOOGLE_DNS: &str = "8.8.8.8";
 /// Cloudflare Public DNS IPv4 addresses.
 const CLOUDFLARE_DNS: &str = "1.1.1.1";
 
+/// Default overlap ratio at or above which answers count as agreeing.
+///
+/// Below it (but above zero) the answers still share addresses, which
+/// is common for CDNs rotating large address pools, so the result is
+/// reported as a partial overlap rather than pollution.
+pub const DEFAULT_OVERLAP_THRESHOLD: f64 = 0.5;
+
+/// Per-query timeout of lookups when only the number of attempts is
+/// set, the default of the resolver library.
+pub const DEFAULT_LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of attempts of each lookup query when only the timeout is set.
+pub const DEFAULT_LOOKUP_ATTEMPTS: usize = 2;
+
+/// Timeout for the raw UDP/TCP comparison queries.
+const TRANSPORT_TIMEOUT: Duration = Duration::from_secs(3);
+
 /// List of known public DNS server IP addresses.
 /// Used to identify legitimate DNS responses.
 const PUBLIC_DNS_IPS: &[&str] = &[
@@ -40,6 +77,150 @@ const PUBLIC_DNS_IPS: &[&str] = &[
     "2620:fe::9",
 ];
 
+/// Resolves names for a [`PollutionChecker`].
+pub trait Lookup: Send + Sync {
+    /// Addresses of `domain`: its A records, or its AAAA records if it
+    /// has no A records.
+    fn lookup_ips<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>>>;
+
+    /// Like [`lookup_ips`](Self::lookup_ips), with the number of seconds
+    /// the answer stays valid if the resolver reports it.
+    fn lookup_ips_with_ttl<'a>(
+        &'a self,
+        domain: &'a str,
+    ) -> BoxFuture<'a, Result<(Vec<IpAddr>, Option<u32>)>> {
+        Box::pin(async move { Ok((self.lookup_ips(domain).await?, None)) })
+    }
+
+    /// Host names of `ip` from its PTR records, without the trailing dot.
+    fn reverse_lookup(&self, ip: IpAddr) -> BoxFuture<'_, Result<Vec<String>>>;
+
+    /// Records of `record_type` for `domain` that [`ExtendedRecord`]
+    /// covers, e.g. HTTPS or DNSKEY records. Resolvers that only look up
+    /// addresses return an error.
+    fn lookup_records<'a>(
+        &'a self,
+        domain: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, Result<Vec<ExtendedRecord>>> {
+        Box::pin(async move {
+            Err(Error::network(format!(
+                "{record_type} lookups of {domain} are not supported"
+            )))
+        })
+    }
+}
+
+impl Lookup for TokioAsyncResolver {
+    fn lookup_ips<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>>> {
+        Box::pin(async move { Ok(self.lookup_ips_with_ttl(domain).await?.0) })
+    }
+
+    fn lookup_ips_with_ttl<'a>(
+        &'a self,
+        domain: &'a str,
+    ) -> BoxFuture<'a, Result<(Vec<IpAddr>, Option<u32>)>> {
+        Box::pin(async move {
+            // Ask for both record types at once; the AAAA records are only
+            // used if there are no A records
+            let (a, aaaa) = tokio::join!(
+                self.lookup(domain, RecordType::A),
+                self.lookup(domain, RecordType::AAAA)
+            );
+            let mut response = a?;
+            let mut ips: Vec<IpAddr> = response
+                .iter()
+                .filter_map(|r| {
+                    if let Some(ip) = r.as_a() {
+                        Some(IpAddr::V4(ip.0))
+                    } else if let Some(ip) = r.as_aaaa() {
+                        Some(IpAddr::V6(ip.0))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            if ips.is_empty() {
+                response = aaaa?;
+                ips = response
+                    .iter()
+                    .filter_map(|r| r.as_aaaa().map(|ip| IpAddr::V6(ip.0)))
+                    .collect();
+            }
+
+            let ttl = response
+                .valid_until()
+                .saturating_duration_since(std::time::Instant::now())
+                .as_secs();
+            Ok((ips, u32::try_from(ttl).ok()))
+        })
+    }
+
+    fn reverse_lookup(&self, ip: IpAddr) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async move {
+            let names = Self::reverse_lookup(self, ip).await?;
+            Ok(names
+                .iter()
+                .map(|n| n.to_utf8().trim_end_matches('.').to_string())
+                .collect())
+        })
+    }
+
+    fn lookup_records<'a>(
+        &'a self,
+        domain: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, Result<Vec<ExtendedRecord>>> {
+        Box::pin(async move {
+            let response = self.lookup(domain, record_type).await?;
+            Ok(response
+                .iter()
+                .filter_map(ExtendedRecord::from_rdata)
+                .collect())
+        })
+    }
+}
+
+/// A resolver whose lookups give up after `limit`.
+struct Deadline {
+    inner: Box<dyn Lookup>,
+    limit: Duration,
+}
+
+impl Deadline {
+    async fn run<T>(&self, lookup: BoxFuture<'_, Result<T>>) -> Result<T> {
+        tokio::time::timeout(self.limit, lookup)
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+}
+
+impl Lookup for Deadline {
+    fn lookup_ips<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>>> {
+        Box::pin(self.run(self.inner.lookup_ips(domain)))
+    }
+
+    fn lookup_ips_with_ttl<'a>(
+        &'a self,
+        domain: &'a str,
+    ) -> BoxFuture<'a, Result<(Vec<IpAddr>, Option<u32>)>> {
+        Box::pin(self.run(self.inner.lookup_ips_with_ttl(domain)))
+    }
+
+    fn reverse_lookup(&self, ip: IpAddr) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(self.run(self.inner.reverse_lookup(ip)))
+    }
+
+    fn lookup_records<'a>(
+        &'a self,
+        domain: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, Result<Vec<ExtendedRecord>>> {
+        Box::pin(self.run(self.inner.lookup_records(domain, record_type)))
+    }
+}
+
 /// DNS pollution checker.
 ///
 /// Compares system DNS resolution results with public DNS servers
@@ -55,8 +236,33 @@ const PUBLIC_DNS_IPS: &[&str] = &[
 /// }
 /// ```
 pub struct PollutionChecker {
-    system_resolver: TokioAsyncResolver,
-    public_resolver: TokioAsyncResolver,
+    system_resolver: Box<dyn Lookup>,
+    public_resolver: Box<dyn Lookup>,
+    /// Sends the queries of the active probes.
+    exchange: Arc<dyn Exchange>,
+    censorship_detector: CensorshipDetector,
+    interception_detector: InterceptionDetector,
+    /// Transparent proxy detection runs once per checker.
+    interception: OnceCell<InterceptionReport>,
+    /// Whether to query RDAP for mismatched addresses.
+    rdap: bool,
+    /// Reference resolver used for the UDP/TCP comparison.
+    transport_server: IpAddr,
+    /// Overlap ratio at or above which answers count as agreeing.
+    overlap_threshold: f64,
+    /// DNS JSON API endpoints giving the reference answer instead of the
+    /// public resolvers, if any.
+    doh_json: Vec<String>,
+    /// Oblivious DNS client giving the reference answer, if any.
+    odoh: Option<OdohClient>,
+    /// Addresses of the public resolvers, naming their answers in the
+    /// reference cache.
+    reference: String,
+    /// Reference answers kept from earlier checks, if caching is enabled.
+    reference_cache: Option<ReferenceCache>,
+    /// Per-query timeout and attempts of the resolvers the checker
+    /// builds, if not the defaults.
+    lookup_timeout: Option<(Duration, usize)>,
 }
 
 impl PollutionChecker {
@@ -69,27 +275,187 @@ impl PollutionChecker {
     ///
     /// Returns an error if either resolver cannot be initialized.
     pub fn new() -> Result<Self> {
-        // System default resolver
-        let system_resolver = TokioAsyncResolver::from_system_conf(TokioHandle)
-            .map_err(crate::error::Error::Resolver)?;
+        let system_resolver = system_resolver()?;
 
         // Public DNS resolver (Google DNS + Cloudflare)
-        let public_config = ResolverConfig::from_parts(
+        let reference = [GOOGLE_DNS.parse().unwrap(), CLOUDFLARE_DNS.parse().unwrap()];
+        let public_resolver = Self::public_resolver(&reference, None);
+
+        Ok(Self::with_resolvers(system_resolver, public_resolver).with_reference_name(&reference))
+    }
+
+    /// Give up on a lookup of the resolvers after `timeout` for each of
+    /// `attempts` tries.
+    ///
+    /// The system resolver otherwise uses the timeout of the system
+    /// configuration, which leaves a check against a dead system resolver
+    /// hanging for a long time. This applies to resolvers given to
+    /// [`with_resolvers`](Self::with_resolvers) as well; resolvers set
+    /// afterwards, e.g. by
+    /// [`with_reference_resolvers`](Self::with_reference_resolvers), wait
+    /// `timeout` for each query and try it `attempts` times.
+    #[must_use]
+    pub fn with_lookup_timeout(mut self, timeout: Duration, attempts: usize) -> Self {
+        let attempts = attempts.max(1);
+        let limit = timeout.saturating_mul(u32::try_from(attempts).unwrap_or(u32::MAX));
+        self.system_resolver = Box::new(Deadline {
+            inner: self.system_resolver,
+            limit,
+        });
+        self.public_resolver = Box::new(Deadline {
+            inner: self.public_resolver,
+            limit,
+        });
+        self.lookup_timeout = Some((timeout, attempts));
+        self
+    }
+
+    /// Create a `PollutionChecker` resolving through the given resolvers
+    /// instead of the system and public DNS, e.g. to check without a
+    /// network.
+    ///
+    /// The UDP/TCP comparison still queries Google Public DNS; see
+    /// [`with_reference_resolvers`](Self::with_reference_resolvers) and
+    /// [`with_exchange`](Self::with_exchange).
+    #[must_use]
+    pub fn with_resolvers(system: impl Lookup + 'static, public: impl Lookup + 'static) -> Self {
+        Self {
+            system_resolver: Box::new(system),
+            public_resolver: Box::new(public),
+            exchange: Arc::new(NetworkExchange),
+            censorship_detector: CensorshipDetector::new(),
+            interception_detector: InterceptionDetector::new(),
+            interception: OnceCell::new(),
+            rdap: false,
+            transport_server: GOOGLE_DNS.parse().unwrap(),
+            overlap_threshold: DEFAULT_OVERLAP_THRESHOLD,
+            doh_json: Vec::new(),
+            odoh: None,
+            reference: String::new(),
+            reference_cache: None,
+            lookup_timeout: None,
+        }
+    }
+
+    /// Name the public resolvers' answers in the reference cache after
+    /// their addresses.
+    fn with_reference_name(mut self, ips: &[IpAddr]) -> Self {
+        self.reference = ips
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        self
+    }
+
+    /// Build a resolver that queries the given servers in the clear.
+    fn public_resolver(
+        ips: &[IpAddr],
+        lookup_timeout: Option<(Duration, usize)>,
+    ) -> TokioAsyncResolver {
+        let config = ResolverConfig::from_parts(
             None,
             vec![],
-            trust_dns_resolver::config::NameServerConfigGroup::from_ips_clear(
-                &[GOOGLE_DNS.parse().unwrap(), CLOUDFLARE_DNS.parse().unwrap()],
-                53,
-                true,
-            ),
+            hickory_resolver::config::NameServerConfigGroup::from_ips_clear(ips, 53, true),
         );
-        let public_resolver = TokioAsyncResolver::tokio(public_config, ResolverOpts::default())
-            .map_err(crate::error::Error::Resolver)?;
+        let mut opts = ResolverOpts::default();
+        set_lookup_timeout(&mut opts, lookup_timeout);
+        TokioAsyncResolver::tokio(bind_source(&config), opts)
+    }
 
-        Ok(Self {
-            system_resolver,
-            public_resolver,
-        })
+    /// Compare against other public resolvers than Google and Cloudflare.
+    ///
+    /// The first resolver is also used for the UDP/TCP comparison. An
+    /// empty list keeps the defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the resolver cannot be initialized.
+    pub fn with_reference_resolvers(mut self, ips: &[IpAddr]) -> Result<Self> {
+        if let Some(&first) = ips.first() {
+            self.public_resolver = Box::new(Self::public_resolver(ips, self.lookup_timeout));
+            self.transport_server = first;
+            self = self.with_reference_name(ips);
+        }
+        Ok(self)
+    }
+
+    /// Check the answers of the given resolvers, queried in the clear,
+    /// instead of those of the system resolver.
+    ///
+    /// An empty list keeps the system resolver.
+    #[must_use]
+    pub fn with_system_resolver(mut self, ips: &[IpAddr]) -> Self {
+        if !ips.is_empty() {
+            self.system_resolver = Box::new(Self::public_resolver(ips, self.lookup_timeout));
+        }
+        self
+    }
+
+    /// Set the overlap ratio at or above which answers count as agreeing.
+    #[must_use]
+    pub fn with_overlap_threshold(mut self, threshold: f64) -> Self {
+        self.overlap_threshold = threshold;
+        self
+    }
+
+    /// Get the reference answer from DNS JSON API endpoints (see
+    /// [`doh_json`]), tried in order, instead of querying the public
+    /// resolvers in the clear.
+    ///
+    /// PTR lookups and the UDP/TCP comparison still use the public
+    /// resolvers. An empty list keeps plain DNS.
+    #[must_use]
+    pub fn with_doh_json(mut self, endpoints: Vec<String>) -> Self {
+        self.doh_json = endpoints;
+        self
+    }
+
+    /// Get the reference answer through an oblivious DNS relay (see
+    /// [`odoh`](crate::dns::odoh)), so that neither the local network nor
+    /// the relay can tell which domain is checked. This takes precedence
+    /// over [`with_doh_json`](Self::with_doh_json).
+    ///
+    /// PTR lookups and the UDP/TCP comparison still use the public
+    /// resolvers.
+    #[must_use]
+    pub fn with_odoh(mut self, client: OdohClient) -> Self {
+        self.odoh = Some(client);
+        self
+    }
+
+    /// Reuse answers of the public resolvers from `cache` until their TTL
+    /// runs out, and keep new ones in it (see
+    /// [`reference_cache`](crate::dns::reference_cache)).
+    ///
+    /// Only the answers of the public resolvers are cached; the DNS JSON
+    /// API and oblivious DNS references are always queried.
+    #[must_use]
+    pub fn with_reference_cache(mut self, cache: ReferenceCache) -> Self {
+        self.reference_cache = Some(cache);
+        self
+    }
+
+    /// Send the queries of the censorship probe, the UDP/TCP comparison
+    /// and the transparent proxy detection through `exchange` instead of
+    /// the network.
+    #[must_use]
+    pub fn with_exchange(mut self, exchange: impl Exchange + 'static) -> Self {
+        let exchange: Arc<dyn Exchange> = Arc::new(exchange);
+        self.censorship_detector = self.censorship_detector.with_exchange(exchange.clone());
+        self.interception_detector = self.interception_detector.with_exchange(exchange.clone());
+        self.exchange = exchange;
+        self
+    }
+
+    /// Enable or disable RDAP lookups of mismatched addresses.
+    ///
+    /// RDAP queries go to external registries and add noticeable latency,
+    /// so they are off by default.
+    #[must_use]
+    pub fn with_rdap(mut self, enabled: bool) -> Self {
+        self.rdap = enabled;
+        self
     }
 
     /// Check if DNS results are polluted for a domain.
@@ -112,6 +478,7 @@ impl PollutionChecker {
     /// let result = checker.check("google.com").await?;
     /// println!("Polluted: {}", result.is_polluted);
     /// ```
+    #[tracing::instrument(skip(self), fields(polluted = tracing::field::Empty))]
     pub async fn check(&self, domain: &str) -> Result<PollutionResult> {
         // Parse domain (ensure it ends with a dot for proper resolution)
         let domain = if domain.ends_with('.') {
@@ -120,88 +487,179 @@ impl PollutionChecker {
             format!("{domain}.")
         };
 
-        // Resolve using system DNS
-        let system_ips = self.resolve_with(&self.system_resolver, &domain).await?;
+        // Resolve using system DNS and public DNS at the same time
+        let public = async {
+            if let Some(odoh) = &self.odoh {
+                odoh.resolve(&domain).await
+            } else if self.doh_json.is_empty() {
+                self.public_lookup(&domain).await
+            } else {
+                doh_json::resolve_any(&self.doh_json, &domain).await
+            }
+        };
+        let (system_ips, public_ips) =
+            tokio::join!(self.system_resolver.lookup_ips(&domain), public);
+        let (system_ips, public_ips) = (system_ips?, public_ips?);
 
-        // Resolve using public DNS
-        let public_ips = self.resolve_with(&self.public_resolver, &domain).await?;
+        // Look for GFW fingerprints in the system answer and on the wire,
+        // compare UDP with TCP, and find out whether "public" answers
+        // really come from public DNS
+        let mut signals = censorship::ip_signals(&system_ips, &public_ips);
+        let (probe, transport, interception) = tokio::join!(
+            self.censorship_detector.probe(&domain, &public_ips),
+            self.compare_transports(&domain),
+            self.interception()
+        );
+        let transport = transport
+            .map_err(|e| tracing::debug!("UDP/TCP comparison failed for {domain}: {e}"))
+            .ok();
 
         // Determine if polluted
-        let is_polluted = self.detect_pollution(&system_ips, &public_ips);
-
-        let details = if is_polluted {
-            format!(
-                "System DNS returned: {:?}, Public DNS returned: {:?}",
-                system_ips, public_ips
-            )
-        } else {
-            format!("Both returned similar results: {:?}", public_ips)
-        };
+        let (mut is_polluted, reason) = self.detect_pollution(&system_ips, &public_ips);
+        let mut reasons = vec![reason];
+        if !bogon::unexpected_bogons(&system_ips, &public_ips).is_empty() {
+            is_polluted = true;
+            reasons.push(PollutionReason::BogonAnswer);
+        }
+        if transport.as_ref().is_some_and(|t| t.divergent) {
+            is_polluted = true;
+            reasons.push(PollutionReason::UdpTcpDivergence);
+        }
+        if interception.intercepted {
+            reasons.push(PollutionReason::TransparentProxy);
+        }
+        match probe {
+            Ok(probe_signals) => {
+                for signal in probe_signals {
+                    if !signals.contains(&signal) {
+                        signals.push(signal);
+                    }
+                }
+            }
+            Err(e) => tracing::debug!("Censorship probe failed for {domain}: {e}"),
+        }
 
-        Ok(PollutionResult {
-            domain: domain.trim_end_matches('.').to_string(),
+        let mut result = PollutionResult::new(
+            domain.trim_end_matches('.').to_string(),
             system_ips,
             public_ips,
             is_polluted,
-            details,
-        })
+            reasons,
+        )
+        .with_censorship_signals(signals);
+
+        // Name the addresses that only one side returned, so users can
+        // tell a CDN edge from a forged answer at a glance
+        let mismatched: Vec<IpAddr> = result
+            .system_only_ips
+            .iter()
+            .chain(&result.public_only_ips)
+            .copied()
+            .collect();
+        let (ptr_records, rdap_records) =
+            tokio::join!(self.reverse_lookup_all(&mismatched), async {
+                if self.rdap {
+                    rdap::lookup_all(&mismatched).await
+                } else {
+                    Vec::new()
+                }
+            });
+        result.ptr_records = ptr_records;
+        result.rdap_records = rdap_records;
+        result.transport = transport;
+        result.transparent_proxy = Some(interception.intercepted);
+
+        tracing::Span::current().record("polluted", result.is_polluted);
+        telemetry::record_pollution(&result);
+
+        Ok(result)
+    }
+
+    /// Resolve `domain` with the public resolvers, through the reference
+    /// cache if there is one.
+    async fn public_lookup(&self, domain: &str) -> Result<Vec<IpAddr>> {
+        let Some(cache) = &self.reference_cache else {
+            return self.public_resolver.lookup_ips(domain).await;
+        };
+        if let Some(ips) = cache.get(&self.reference, domain) {
+            tracing::debug!("Reference answer for {domain} from cache");
+            return Ok(ips);
+        }
+        let (ips, ttl) = self.public_resolver.lookup_ips_with_ttl(domain).await?;
+        if let Some(ttl) = ttl {
+            cache.insert(&self.reference, domain, ips.clone(), ttl);
+        }
+        Ok(ips)
     }
 
-    /// Resolve domain using specified resolver.
+    /// Query the public resolver over both UDP and TCP and compare.
+    ///
+    /// On-path injection typically only forges UDP replies, so a TCP
+    /// answer that shares no address with the UDP one indicates that the
+    /// UDP answer was tampered with.
     ///
     /// # Arguments
     ///
-    /// * `resolver` - The DNS resolver to use
-    /// * `domain` - The domain name to resolve
+    /// * `domain` - The domain name to query
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn compare_transports(&self, domain: &str) -> Result<TransportComparison> {
+        let server = SocketAddr::new(self.transport_server, DNS_PORT);
+        let (udp, tcp) = tokio::join!(
+            self.exchange
+                .udp(server, domain, RecordType::A, TRANSPORT_TIMEOUT, None),
+            self.exchange
+                .tcp(server, domain, RecordType::A, TRANSPORT_TIMEOUT)
+        );
+        let udp = udp?.into_iter().next().ok_or(Error::Timeout)?;
+
+        Ok(TransportComparison::new(
+            udp.answer_ips(),
+            tcp?.answer_ips(),
+        ))
+    }
+
+    /// Look up PTR names for several addresses concurrently.
     ///
-    /// # Returns
+    /// Lookups go through the public resolver, since the system one may
+    /// be the polluted party. Addresses without PTR records are omitted.
     ///
-    /// Returns a vector of IP addresses.
-    async fn resolve_with(
-        &self,
-        resolver: &TokioAsyncResolver,
-        domain: &str,
-    ) -> Result<Vec<IpAddr>> {
-        use trust_dns_resolver::proto::rr::RecordType;
-
-        // Try A records first (IPv4)
-        let response = resolver.lookup(domain, RecordType::A).await?;
-        let mut ips: Vec<IpAddr> = response
-            .iter()
-            .filter_map(|r| {
-                if let Some(ip) = r.as_a() {
-                    Some(IpAddr::V4(*ip))
-                } else if let Some(ip) = r.as_aaaa() {
-                    Some(IpAddr::V6(*ip))
-                } else {
-                    None
-                }
-            })
-            .collect();
+    /// # Arguments
+    ///
+    /// * `ips` - Addresses to look up
+    #[tracing::instrument(level = "debug", skip_all, fields(ips = ips.len()))]
+    pub async fn reverse_lookup_all(&self, ips: &[IpAddr]) -> Vec<PtrRecord> {
+        let lookups = ips.iter().map(|&ip| async move {
+            let hostnames = self
+                .public_resolver
+                .reverse_lookup(ip)
+                .await
+                .unwrap_or_default();
+            PtrRecord { ip, hostnames }
+        });
 
-        // Also try AAAA records if A returned nothing
-        if ips.is_empty() {
-            let response = resolver.lookup(domain, RecordType::AAAA).await?;
-            ips = response
-                .iter()
-                .filter_map(|r| {
-                    if let Some(ip) = r.as_aaaa() {
-                        Some(IpAddr::V6(*ip))
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-        }
+        futures::future::join_all(lookups)
+            .await
+            .into_iter()
+            .filter(|r| !r.hostnames.is_empty())
+            .collect()
+    }
 
-        Ok(ips)
+    /// Detect transparent DNS proxying on the current network.
+    ///
+    /// The probes run on first use; later calls return the cached report.
+    pub async fn interception(&self) -> &InterceptionReport {
+        self.interception
+            .get_or_init(|| self.interception_detector.detect())
+            .await
     }
 
     /// Detect pollution by comparing system DNS with public DNS.
     ///
-    /// Pollution is detected when:
-    /// 1. System returns IP addresses that differ from public DNS results
-    /// 2. System returns IP addresses that are not in public DNS results
+    /// The verdict is graded by the overlap ratio of the two answers:
+    /// 1. Mostly overlapping answers are clean
+    /// 2. Partially overlapping answers are clean but low-confidence
+    /// 3. Disjoint answers are polluted, unless they fall into the same
+    ///    network or the system answer is a known public DNS address
     ///
     /// # Arguments
     ///
@@ -210,33 +668,43 @@ impl PollutionChecker {
     ///
     /// # Returns
     ///
-    /// Returns `true` if pollution is detected.
-    fn detect_pollution(&self, system_ips: &[IpAddr], public_ips: &[IpAddr]) -> bool {
+    /// Returns whether pollution is detected and the reason code for it.
+    fn detect_pollution(
+        &self,
+        system_ips: &[IpAddr],
+        public_ips: &[IpAddr],
+    ) -> (bool, PollutionReason) {
         if system_ips.is_empty() || public_ips.is_empty() {
-            return false;
+            return (false, PollutionReason::EmptyAnswer);
         }
 
-        // If system returns IPs that are not in the public DNS results
-        // and are not known public IPs, it might be polluted
-        let public_ip_set: std::collections::HashSet<_> = public_ips.iter().collect();
+        let ratio = types::overlap_ratio(system_ips, public_ips);
+        if ratio >= self.overlap_threshold {
+            return (false, PollutionReason::SharedIps);
+        }
+        if ratio > 0.0 {
+            return (false, PollutionReason::PartialOverlap);
+        }
 
-        for sys_ip in system_ips {
-            // Check if this IP appears in public DNS results
-            if public_ip_set.contains(&sys_ip) {
-                return false; // Found matching IP, not polluted
-            }
+        // Check if system DNS returned a known public DNS IP
+        let known_public = system_ips.iter().any(|ip| {
+            let ip_str = ip.to_string();
+            PUBLIC_DNS_IPS.iter().any(|&p| p == ip_str)
+        });
+        if known_public {
+            return (false, PollutionReason::KnownPublicDnsIp);
+        }
 
-            // Check if it's a known public DNS IP
-            let ip_str = sys_ip.to_string();
-            if PUBLIC_DNS_IPS.iter().any(|&p| p == ip_str) {
-                return false;
-            }
+        // CDNs hand out different addresses from the same block
+        let same_network = system_ips
+            .iter()
+            .any(|s| public_ips.iter().any(|p| same_network(s, p)));
+        if same_network {
+            return (false, PollutionReason::SameNetwork);
         }
 
-        // If we get here, system returned IPs that aren't in public results
-        // This is likely pollution, but we need to be careful
-        // Only report as polluted if there's a clear mismatch
-        !system_ips.is_empty() && !public_ips.is_empty()
+        // System returned IPs that share nothing with public results
+        (true, PollutionReason::DisjointAnswers)
     }
 
     /// Check multiple domains in batch.
@@ -256,27 +724,295 @@ impl PollutionChecker {
                 results.push(result);
             }
         }
+        self.flush_reference_cache();
         results
     }
+
+    /// Save the reference answers cached since the last save, if there is
+    /// a reference cache. Call once after a batch of checks.
+    pub fn flush_reference_cache(&self) {
+        if let Some(cache) = &self.reference_cache {
+            if let Err(e) = cache.flush() {
+                tracing::debug!("Failed to save the reference cache: {e}");
+            }
+        }
+    }
+}
+
+/// Send a resolver's queries from the source address set with
+/// [`source::set`], for the name servers of the same family.
+///
+/// The resolver cannot bind to an interface, so an interface binding
+/// alone leaves it unchanged.
+fn bind_source(config: &ResolverConfig) -> ResolverConfig {
+    let address = source::current().and_then(|binding| binding.address);
+    let name_servers: Vec<NameServerConfig> = config
+        .name_servers()
+        .iter()
+        .cloned()
+        .map(|mut name_server| {
+            if let Some(address) = address {
+                if address.is_ipv4() == name_server.socket_addr.is_ipv4() {
+                    name_server.bind_addr = Some(SocketAddr::new(address, 0));
+                }
+            }
+            name_server
+        })
+        .collect();
+    ResolverConfig::from_parts(
+        config.domain().cloned(),
+        config.search().to_vec(),
+        name_servers,
+    )
+}
+
+/// The resolver configured on this system, bound to the source address
+/// probes are sent from.
+pub(crate) fn system_resolver() -> Result<TokioAsyncResolver> {
+    let (config, opts) =
+        hickory_resolver::system_conf::read_system_conf().map_err(crate::error::Error::Resolver)?;
+    Ok(TokioAsyncResolver::tokio(bind_source(&config), opts))
+}
+
+/// Override the per-query timeout and number of attempts of `opts`.
+fn set_lookup_timeout(opts: &mut ResolverOpts, lookup_timeout: Option<(Duration, usize)>) {
+    if let Some((timeout, attempts)) = lookup_timeout {
+        opts.timeout = timeout;
+        opts.attempts = attempts;
+    }
+}
+
+/// Check whether two addresses share a /24 (IPv4) or /48 (IPv6) network.
+fn same_network(a: &IpAddr, b: &IpAddr) -> bool {
+    match (a, b) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => a.octets()[..3] == b.octets()[..3],
+        (IpAddr::V6(a), IpAddr::V6(b)) => a.segments()[..3] == b.segments()[..3],
+        _ => false,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dns::censorship::CensorshipSignal;
+    use crate::dns::query::fake::{record, FakeExchange, Query, Reply};
+    use crate::dns::types::CheckVerdict;
+    use hickory_resolver::proto::rr::rdata::A;
+    use hickory_resolver::proto::rr::RData;
+    use std::collections::HashMap;
 
-    #[tokio::test]
-    async fn test_resolve_google() {
-        // This test requires network connection which may be unreliable in CI
-        // Skip if CI environment variable is set
-        if std::env::var("CI").is_ok() {
-            return;
+    /// Resolves from fixed tables.
+    struct FakeLookup {
+        answers: HashMap<&'static str, Vec<IpAddr>>,
+        ptr: HashMap<IpAddr, Vec<String>>,
+    }
+
+    impl Lookup for FakeLookup {
+        fn lookup_ips<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>>> {
+            let ips = self.answers.get(domain).cloned().unwrap_or_default();
+            Box::pin(async move { Ok(ips) })
+        }
+
+        fn reverse_lookup(&self, ip: IpAddr) -> BoxFuture<'_, Result<Vec<String>>> {
+            let names = self.ptr.get(&ip).cloned().unwrap_or_default();
+            Box::pin(async move { Ok(names) })
+        }
+    }
+
+    /// An on-path injector: every UDP query is answered with `forged`
+    /// first, then by the server with `genuine`, which only arrives in
+    /// time when the caller keeps listening. TCP gets `genuine`.
+    fn injected(forged: IpAddr, genuine: IpAddr) -> FakeExchange {
+        let answer = |ip: IpAddr| {
+            let IpAddr::V4(ip) = ip else { unreachable!() };
+            Reply::answers([record("example.com.", 60, RData::A(A(ip)))])
+        };
+        FakeExchange::new()
+            .on(
+                Query::any().udp(),
+                answer(forged).followed_by(answer(genuine)),
+            )
+            .on(Query::any().tcp(), answer(genuine))
+    }
+
+    /// A resolver that never answers.
+    struct DeadLookup;
+
+    impl Lookup for DeadLookup {
+        fn lookup_ips<'a>(&'a self, _domain: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>>> {
+            Box::pin(std::future::pending())
         }
 
+        fn reverse_lookup(&self, _ip: IpAddr) -> BoxFuture<'_, Result<Vec<String>>> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lookup_timeout() {
+        let checker = PollutionChecker::with_resolvers(DeadLookup, DeadLookup)
+            .with_lookup_timeout(Duration::from_millis(10), 2);
+        let started = std::time::Instant::now();
+        assert!(matches!(
+            checker.system_resolver.lookup_ips("example.com.").await,
+            Err(Error::Timeout)
+        ));
+        assert!(matches!(
+            checker
+                .public_resolver
+                .reverse_lookup(IpAddr::from([192, 0, 2, 1]))
+                .await,
+            Err(Error::Timeout)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_check_injected() {
+        let ip = |s: &str| -> IpAddr { s.parse().unwrap() };
+        let forged = ip("59.24.3.173");
+        let genuine = ip("93.184.216.34");
+
+        let system = FakeLookup {
+            answers: HashMap::from([("example.com.", vec![forged])]),
+            ptr: HashMap::new(),
+        };
+        let public = FakeLookup {
+            answers: HashMap::from([("example.com.", vec![genuine])]),
+            ptr: HashMap::from([(genuine, vec!["example.com".to_string()])]),
+        };
+        let checker = PollutionChecker::with_resolvers(system, public)
+            .with_exchange(injected(forged, genuine));
+
+        let result = checker.check("example.com").await.unwrap();
+        assert_eq!(result.domain, "example.com");
+        assert!(result.is_polluted);
+        assert_eq!(
+            result.reasons,
+            [
+                PollutionReason::DisjointAnswers,
+                PollutionReason::UdpTcpDivergence,
+                // The blackhole probes got answers
+                PollutionReason::TransparentProxy,
+            ]
+        );
+        assert!(result
+            .censorship_signals
+            .contains(&CensorshipSignal::KnownInjectedIp { ip: forged }));
+        assert!(result
+            .censorship_signals
+            .contains(&CensorshipSignal::DuplicateAnswers { count: 2 }));
+        assert_eq!(
+            result.ptr_records,
+            [PtrRecord {
+                ip: genuine,
+                hostnames: vec!["example.com".to_string()],
+            }]
+        );
+        assert_eq!(result.transparent_proxy, Some(true));
+        assert_eq!(result.verdict(), CheckVerdict::Polluted);
+    }
+
+    #[test]
+    fn test_transport_comparison() {
+        let ip = |s: &str| -> IpAddr { s.parse().unwrap() };
+
+        let same = TransportComparison::new(
+            vec![ip("142.250.1.1"), ip("142.250.1.2")],
+            vec![ip("142.250.1.2")],
+        );
+        assert!(!same.divergent);
+
+        let injected = TransportComparison::new(vec![ip("59.24.3.173")], vec![ip("142.250.1.1")]);
+        assert!(injected.divergent);
+
+        // A missing answer on one side proves nothing
+        let partial = TransportComparison::new(vec![ip("142.250.1.1")], vec![]);
+        assert!(!partial.divergent);
+    }
+
+    #[test]
+    fn test_pollution_result_partitions() {
+        let ip = |s: &str| -> IpAddr { s.parse().unwrap() };
+
+        let result = PollutionResult::new(
+            "example.com".to_string(),
+            vec![ip("1.1.1.1"), ip("2.2.2.2")],
+            vec![ip("2.2.2.2"), ip("3.3.3.3")],
+            false,
+            vec![PollutionReason::SharedIps],
+        );
+        assert_eq!(result.matching_ips, vec![ip("2.2.2.2")]);
+        assert_eq!(result.system_only_ips, vec![ip("1.1.1.1")]);
+        assert_eq!(result.public_only_ips, vec![ip("3.3.3.3")]);
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["reasons"][0], "shared_ips");
+    }
+
+    #[test]
+    fn test_pollution_verdict() {
+        let ip = |s: &str| -> IpAddr { s.parse().unwrap() };
+        let result = |polluted| {
+            PollutionResult::new(
+                "example.com".to_string(),
+                vec![ip("1.1.1.1")],
+                vec![ip("1.1.1.1")],
+                polluted,
+                vec![],
+            )
+        };
+
+        assert_eq!(result(false).verdict(), CheckVerdict::Clean);
+        assert_eq!(result(true).verdict(), CheckVerdict::Polluted);
+
+        let mut proxied = result(false);
+        proxied.transparent_proxy = Some(true);
+        assert_eq!(proxied.verdict(), CheckVerdict::Suspicious);
+
+        let censored =
+            result(false).with_censorship_signals(vec![CensorshipSignal::KnownInjectedIp {
+                ip: ip("59.24.3.173"),
+            }]);
+        assert_eq!(censored.verdict(), CheckVerdict::Suspicious);
+    }
+
+    #[test]
+    fn test_overlap_ratio_verdict() {
+        let ip = |s: &str| -> IpAddr { s.parse().unwrap() };
         let checker = PollutionChecker::new().unwrap();
-        let result = checker.check("google.com").await.unwrap();
 
-        println!("System IPs: {:?}", result.system_ips);
-        println!("Public IPs: {:?}", result.public_ips);
-        println!("Polluted: {}", result.is_polluted);
+        let system = [ip("93.184.216.1"), ip("93.184.217.1")];
+        assert!((types::overlap_ratio(&system, &system) - 1.0).abs() < f64::EPSILON);
+        assert_eq!(
+            checker.detect_pollution(&system, &system),
+            (false, PollutionReason::SharedIps)
+        );
+
+        // One shared address out of four distinct ones
+        let public = [ip("93.184.217.1"), ip("3.3.3.3"), ip("4.4.4.4")];
+        assert!((types::overlap_ratio(&system, &public) - 0.25).abs() < f64::EPSILON);
+        assert_eq!(
+            checker.detect_pollution(&system, &public),
+            (false, PollutionReason::PartialOverlap)
+        );
+        let lenient = PollutionChecker::new().unwrap().with_overlap_threshold(0.2);
+        assert_eq!(
+            lenient.detect_pollution(&system, &public),
+            (false, PollutionReason::SharedIps)
+        );
+
+        // Disjoint, but same /24 as a CDN pool would be
+        let cdn = [ip("93.184.216.9")];
+        assert_eq!(
+            checker.detect_pollution(&system, &cdn),
+            (false, PollutionReason::SameNetwork)
+        );
+
+        let forged = [ip("59.24.3.173")];
+        assert_eq!(
+            checker.detect_pollution(&forged, &public),
+            (true, PollutionReason::DisjointAnswers)
+        );
     }
 }
@@ -0,0 +1,72 @@
+//! Progress of speed test runs.
+//!
+//! A run reports what it is doing as [`ProgressEvent`]s to a
+//! [`ProgressListener`]. The CLI redraws its counter from them, the TUI
+//! forwards them to its event loop, and embedders can pass a closure or
+//! the sending half of a channel:
+//!
+//! ```ignore
+//! let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+//! let run = tokio::spawn(async move { tester.test_all(&servers, Some(&tx)).await });
+//! while let Some(event) = rx.recv().await {
+//!     if let ProgressEvent::ServerDone { done, total, .. } = event {
+//!         println!("{done}/{total}");
+//!     }
+//! }
+//! ```
+
+use crate::dns::types::SpeedTestResult;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// What a speed test run is doing.
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// The run started
+    Started {
+        /// Number of servers to test
+        total: usize,
+    },
+    /// A server was tested
+    ServerDone {
+        /// Number of servers tested so far, this one included
+        done: usize,
+        /// Number of servers to test
+        total: usize,
+        /// Result of the server
+        result: Box<SpeedTestResult>,
+    },
+    /// The run pauses between two batches of servers
+    BatchDelay {
+        /// Number of servers tested so far
+        done: usize,
+        /// Length of the pause
+        delay: Duration,
+    },
+    /// The run finished
+    Finished {
+        /// Number of servers tested
+        total: usize,
+        /// Number of servers that answered
+        succeeded: usize,
+    },
+}
+
+/// Receives the [`ProgressEvent`]s of a run.
+pub trait ProgressListener: Send + Sync {
+    /// Handle one event.
+    fn on_event(&self, event: ProgressEvent);
+}
+
+impl<F: Fn(ProgressEvent) + Send + Sync> ProgressListener for F {
+    fn on_event(&self, event: ProgressEvent) {
+        self(event);
+    }
+}
+
+/// Events are dropped once the receiver is gone.
+impl ProgressListener for mpsc::UnboundedSender<ProgressEvent> {
+    fn on_event(&self, event: ProgressEvent) {
+        let _ = self.send(event);
+    }
+}
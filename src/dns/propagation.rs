@@ -0,0 +1,307 @@
+//! Propagation check.
+//!
+//! After a DNS change, resolvers keep answering with the old records until
+//! their cached copy expires. This module asks every resolver of a list for
+//! a record and compares the answer with the values the record was changed
+//! to, telling which resolvers already see the change and, for the others,
+//! how long the old answer is still cached.
+
+use crate::dns::query::{Exchange, NetworkExchange};
+use crate::dns::DnsServer;
+use futures::StreamExt;
+use hickory_resolver::proto::op::{Message, ResponseCode};
+use hickory_resolver::proto::rr::{RData, RecordType};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default per-query timeout.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Default number of resolvers queried at the same time.
+pub const DEFAULT_CONCURRENCY: usize = 16;
+
+/// Whether a resolver sees the new record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Propagation {
+    /// Answers with every expected value
+    Updated,
+    /// Answers with other values
+    Stale,
+    /// Answers that the record does not exist
+    Missing,
+    /// Did not answer
+    NoAnswer,
+}
+
+impl std::fmt::Display for Propagation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Updated => write!(f, "updated"),
+            Self::Stale => write!(f, "stale"),
+            Self::Missing => write!(f, "missing"),
+            Self::NoAnswer => write!(f, "no answer"),
+        }
+    }
+}
+
+/// Answer of one resolver.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropagationResult {
+    /// The resolver queried
+    pub server: DnsServer,
+    /// Whether it sees the new record
+    pub state: Propagation,
+    /// Values it answered with, normalized like the expected ones
+    pub answers: Vec<String>,
+    /// Remaining TTL of the answer in seconds: for a stale answer, at most
+    /// how long until the resolver looks the record up again
+    pub ttl: Option<u32>,
+    /// Why the resolver could not be queried at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Propagation checker.
+///
+/// # Example
+///
+/// ```ignore
+/// let checker = PropagationChecker::new("example.com", RecordType::A, &["203.0.113.10"]);
+/// let results = checker.check_all(&servers, |_, _| {}).await;
+/// let updated = results.iter().filter(|r| r.state == Propagation::Updated).count();
+/// ```
+pub struct PropagationChecker {
+    domain: String,
+    record_type: RecordType,
+    expected: Vec<String>,
+    timeout: Duration,
+    concurrency: usize,
+    exchange: Arc<dyn Exchange>,
+}
+
+impl PropagationChecker {
+    /// Create a checker for `record_type` records of `domain` with the
+    /// expected values, e.g. addresses for A records or host names for
+    /// CNAME records.
+    #[must_use]
+    pub fn new(domain: &str, record_type: RecordType, expected: &[impl AsRef<str>]) -> Self {
+        Self {
+            domain: domain.to_string(),
+            record_type,
+            expected: expected
+                .iter()
+                .map(|value| normalize(value.as_ref(), record_type))
+                .collect(),
+            timeout: DEFAULT_TIMEOUT,
+            concurrency: DEFAULT_CONCURRENCY,
+            exchange: Arc::new(NetworkExchange),
+        }
+    }
+
+    /// Use custom settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Timeout for each query
+    /// * `concurrency` - Number of resolvers queried at the same time
+    #[must_use]
+    pub fn with_settings(mut self, timeout: Duration, concurrency: usize) -> Self {
+        self.timeout = timeout;
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Send the queries through `exchange` instead of the network.
+    #[must_use]
+    pub fn with_exchange(mut self, exchange: Arc<dyn Exchange>) -> Self {
+        self.exchange = exchange;
+        self
+    }
+
+    /// Query one resolver.
+    ///
+    /// Plain DNS servers only; others are reported with an error.
+    pub async fn check(&self, server: &DnsServer) -> PropagationResult {
+        let mut result = PropagationResult {
+            server: server.clone(),
+            state: Propagation::NoAnswer,
+            answers: Vec::new(),
+            ttl: None,
+            error: None,
+        };
+        let addr = match server.socket_addr() {
+            Some(addr) if server.protocol.is_plain() => addr,
+            _ => {
+                result.error = Some(format!("{} is not a plain DNS server", server.spec()));
+                return result;
+            }
+        };
+        let Some(message) = self.query(addr).await else {
+            return result;
+        };
+
+        let records: Vec<_> = message
+            .answers()
+            .iter()
+            .filter(|record| record.record_type() == self.record_type)
+            .collect();
+        result.ttl = records.iter().map(|record| record.ttl()).min();
+        result.answers = records
+            .iter()
+            .filter_map(|record| record.data())
+            .map(|data| value(data, self.record_type))
+            .collect();
+        result.answers.sort();
+        result.answers.dedup();
+        result.state = if result.answers.is_empty() {
+            match message.response_code() {
+                ResponseCode::NoError | ResponseCode::NXDomain => Propagation::Missing,
+                // SERVFAIL, REFUSED and the like say nothing about the record
+                _ => Propagation::NoAnswer,
+            }
+        } else if self.expected.iter().all(|v| result.answers.contains(v)) {
+            Propagation::Updated
+        } else {
+            Propagation::Stale
+        };
+        result
+    }
+
+    /// Query several resolvers, `concurrency` at a time, in the given
+    /// order.
+    ///
+    /// `progress` is called with the number of finished and total
+    /// resolvers after each one.
+    pub async fn check_all(
+        &self,
+        servers: &[DnsServer],
+        progress: impl Fn(usize, usize),
+    ) -> Vec<PropagationResult> {
+        let total = servers.len();
+        let mut pending = futures::stream::iter(servers)
+            .map(|server| self.check(server))
+            .buffered(self.concurrency);
+
+        let mut results = Vec::with_capacity(total);
+        while let Some(result) = pending.next().await {
+            results.push(result);
+            progress(results.len(), total);
+        }
+        results
+    }
+
+    /// Send the query and return the first response.
+    async fn query(&self, server: SocketAddr) -> Option<Message> {
+        match self
+            .exchange
+            .udp(server, &self.domain, self.record_type, self.timeout, None)
+            .await
+        {
+            Ok(responses) => responses.into_iter().next().map(|r| r.message),
+            Err(e) => {
+                tracing::debug!("Query for {} to {server} failed: {e}", self.domain);
+                None
+            }
+        }
+    }
+}
+
+/// A record's value in the form expected values are given in.
+fn value(data: &RData, record_type: RecordType) -> String {
+    match data {
+        RData::TXT(txt) => txt
+            .iter()
+            .map(|part| String::from_utf8_lossy(part).into_owned())
+            .collect(),
+        _ => normalize(&data.to_string(), record_type),
+    }
+}
+
+/// Normalize a value so that equal values compare equal: addresses in
+/// canonical form, names in lowercase without the trailing dot. TXT
+/// strings are kept as they are.
+fn normalize(value: &str, record_type: RecordType) -> String {
+    let value = value.trim();
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return ip.to_string();
+    }
+    if record_type == RecordType::TXT {
+        return value.to_string();
+    }
+    let value = value.to_lowercase();
+    match value.strip_suffix('.') {
+        Some(stripped) => stripped.to_string(),
+        None => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::query::fake::{record, FakeExchange, Query, Reply};
+    use hickory_resolver::proto::rr::rdata::{A, CNAME};
+    use hickory_resolver::proto::rr::Name;
+
+    /// Resolvers told apart by the last octet: .1 has the new address,
+    /// .2 the old one, .3 answers NXDOMAIN, .4 SERVFAIL and .5 is silent.
+    fn fake_resolvers() -> FakeExchange {
+        let server = |last| Query::any().server([192, 0, 2, last]);
+        let old = Name::from_ascii("old.example.net.").unwrap();
+        FakeExchange::new()
+            .on(
+                server(1),
+                Reply::answers([record(
+                    "example.com.",
+                    300,
+                    RData::A(A::new(203, 0, 113, 10)),
+                )]),
+            )
+            .on(
+                server(2),
+                Reply::answers([
+                    record("example.com.", 3600, RData::CNAME(CNAME(old))),
+                    record("old.example.net.", 1200, RData::A(A::new(198, 51, 100, 7))),
+                ]),
+            )
+            .on(server(3), Reply::rcode(ResponseCode::NXDomain))
+            .on(server(4), Reply::rcode(ResponseCode::ServFail))
+    }
+
+    #[tokio::test]
+    async fn test_check_all() {
+        let checker = PropagationChecker::new("example.com", RecordType::A, &["203.0.113.10"])
+            .with_exchange(Arc::new(fake_resolvers()));
+        let servers: Vec<DnsServer> = (1..=5)
+            .map(|last| DnsServer::new(format!("R{last}"), format!("192.0.2.{last}")))
+            .collect();
+        let results = checker.check_all(&servers, |_, _| {}).await;
+
+        let states: Vec<Propagation> = results.iter().map(|r| r.state).collect();
+        assert_eq!(
+            states,
+            [
+                Propagation::Updated,
+                Propagation::Stale,
+                Propagation::Missing,
+                Propagation::NoAnswer,
+                Propagation::NoAnswer
+            ]
+        );
+        assert_eq!(results[0].ttl, Some(300));
+        assert_eq!(results[1].answers, ["198.51.100.7"]);
+        assert_eq!(results[1].ttl, Some(1200));
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("WWW.Example.com.", RecordType::CNAME),
+            "www.example.com"
+        );
+        assert_eq!(normalize("2001:DB8::0:1", RecordType::AAAA), "2001:db8::1");
+        assert_eq!(normalize("v=spf1 -all", RecordType::TXT), "v=spf1 -all");
+    }
+}
@@ -0,0 +1,985 @@
+//! Raw DNS message exchange.
+//!
+//! This module sends hand-built DNS queries directly to a single server
+//! and returns the decoded response messages. Unlike the resolver used by
+//! [`PollutionChecker`](crate::dns::PollutionChecker), it exposes the whole
+//! message (response code, authority section, duplicate replies), which
+//! the censorship heuristics need to inspect.
+//!
+//! The censorship and interception detectors and the UDP/TCP comparison
+//! send their queries through an [`Exchange`], which is
+//! [`NetworkExchange`] unless another one is supplied, e.g. to run them
+//! without a network in tests.
+//!
+//! Records other than addresses, such as HTTPS service bindings and the
+//! DNSSEC records that [`Exchange::dnssec`] asks for, are decoded into
+//! [`ExtendedRecord`]s by [`answer_records`], so callers do not depend on
+//! hickory's record data types.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::dns::source;
+use crate::error::{Error, Result};
+use futures::future::BoxFuture;
+use hickory_resolver::proto::op::{Edns, Message, MessageType, OpCode, Query};
+use hickory_resolver::proto::rr::dnssec::rdata::DNSSECRData;
+use hickory_resolver::proto::rr::rdata::svcb::{SvcParamValue, SVCB};
+use hickory_resolver::proto::rr::{Name, RData, RecordType};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Standard DNS port.
+pub const DNS_PORT: u16 = 53;
+
+/// Maximum size of a UDP DNS message we accept.
+const MAX_UDP_SIZE: usize = 4096;
+
+/// A record that is not an address, decoded from its record data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtendedRecord {
+    /// HTTPS or SVCB service binding (RFC 9460)
+    ServiceBinding {
+        /// Whether it is an HTTPS record rather than an SVCB record
+        https: bool,
+        /// 0 for an alias to `target`, otherwise the preference
+        priority: u16,
+        /// Target host, without the trailing dot, `.` for the owner name
+        target: String,
+        /// Protocols offered, e.g. `h2` and `h3`
+        alpn: Vec<String>,
+        /// Port other than the default one of the protocol
+        port: Option<u16>,
+        /// Addresses the client may connect to before resolving `target`
+        ip_hints: Vec<IpAddr>,
+        /// Whether an Encrypted Client Hello configuration is published
+        ech: bool,
+    },
+    /// Public key of a signed zone
+    Dnskey {
+        /// Flags, 257 for a key-signing key and 256 for a zone-signing key
+        flags: u16,
+        /// Signing algorithm number, e.g. 13 for ECDSA P-256
+        algorithm: u8,
+        /// Key tag that signatures and DS records refer to the key by
+        key_tag: u16,
+    },
+    /// Digest of a child zone's key, published by the parent
+    Ds {
+        /// Key tag of the key
+        key_tag: u16,
+        /// Signing algorithm number of the key
+        algorithm: u8,
+        /// Digest algorithm number, e.g. 2 for SHA-256
+        digest_type: u8,
+    },
+    /// Signature over the records of a type
+    Rrsig {
+        /// Type of the records signed
+        type_covered: RecordType,
+        /// Signing algorithm number
+        algorithm: u8,
+        /// Key tag of the signing key
+        key_tag: u16,
+        /// Zone that signed the records, without the trailing dot
+        signer: String,
+        /// End of the validity period, in seconds since the Unix epoch
+        expiration: u32,
+    },
+}
+
+impl ExtendedRecord {
+    /// Decode record data, or `None` for the types not covered.
+    #[must_use]
+    pub fn from_rdata(data: &RData) -> Option<Self> {
+        match data {
+            RData::HTTPS(https) => Some(service_binding(true, &https.0)),
+            RData::SVCB(svcb) => Some(service_binding(false, svcb)),
+            RData::DNSSEC(DNSSECRData::DNSKEY(key)) => Some(Self::Dnskey {
+                flags: key.flags(),
+                algorithm: key.algorithm().into(),
+                key_tag: key.calculate_key_tag().ok()?,
+            }),
+            RData::DNSSEC(DNSSECRData::DS(ds)) => Some(Self::Ds {
+                key_tag: ds.key_tag(),
+                algorithm: ds.algorithm().into(),
+                digest_type: ds.digest_type().into(),
+            }),
+            RData::DNSSEC(DNSSECRData::RRSIG(sig)) => Some(Self::Rrsig {
+                type_covered: sig.type_covered(),
+                algorithm: sig.algorithm().into(),
+                key_tag: sig.key_tag(),
+                signer: host(sig.signer_name()),
+                expiration: sig.sig_expiration(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn service_binding(https: bool, svcb: &SVCB) -> ExtendedRecord {
+    let mut alpn = Vec::new();
+    let mut port = None;
+    let mut ip_hints = Vec::new();
+    let mut ech = false;
+    for (_, value) in svcb.svc_params() {
+        match value {
+            SvcParamValue::Alpn(protocols) => alpn.clone_from(&protocols.0),
+            SvcParamValue::Port(number) => port = Some(*number),
+            SvcParamValue::Ipv4Hint(hint) => {
+                ip_hints.extend(hint.0.iter().map(|ip| IpAddr::V4(ip.0)));
+            }
+            SvcParamValue::Ipv6Hint(hint) => {
+                ip_hints.extend(hint.0.iter().map(|ip| IpAddr::V6(ip.0)));
+            }
+            SvcParamValue::EchConfig(_) => ech = true,
+            _ => {}
+        }
+    }
+    ExtendedRecord::ServiceBinding {
+        https,
+        priority: svcb.svc_priority(),
+        target: host(svcb.target_name()),
+        alpn,
+        port,
+        ip_hints,
+        ech,
+    }
+}
+
+/// A name without the trailing dot, except for the root.
+fn host(name: &Name) -> String {
+    if name.is_root() {
+        return ".".to_string();
+    }
+    name.to_utf8().trim_end_matches('.').to_string()
+}
+
+/// A decoded DNS response together with its round-trip time.
+#[derive(Debug, Clone)]
+pub struct DnsResponse {
+    /// The decoded response message
+    pub message: Message,
+    /// Time between sending the query and receiving this response
+    pub elapsed: Duration,
+}
+
+impl DnsResponse {
+    /// Collect the A/AAAA addresses from the answer section.
+    #[must_use]
+    pub fn answer_ips(&self) -> Vec<IpAddr> {
+        answer_ips(&self.message)
+    }
+}
+
+/// Extract A/AAAA addresses from a message's answer section.
+#[must_use]
+pub fn answer_ips(message: &Message) -> Vec<IpAddr> {
+    message
+        .answers()
+        .iter()
+        .filter_map(|r| match r.data() {
+            Some(RData::A(ip)) => Some(IpAddr::V4(ip.0)),
+            Some(RData::AAAA(ip)) => Some(IpAddr::V6(ip.0)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Extract the records [`ExtendedRecord`] covers from a message's answer
+/// section, e.g. HTTPS records or DNSKEY records with their signatures.
+#[must_use]
+pub fn answer_records(message: &Message) -> Vec<ExtendedRecord> {
+    message
+        .answers()
+        .iter()
+        .filter_map(|r| r.data().and_then(ExtendedRecord::from_rdata))
+        .collect()
+}
+
+/// Extract TXT strings from a message's answer section.
+///
+/// The character-strings of each record are concatenated.
+#[must_use]
+pub fn answer_txt(message: &Message) -> Vec<String> {
+    message
+        .answers()
+        .iter()
+        .filter_map(|r| match r.data() {
+            Some(RData::TXT(txt)) => Some(
+                txt.iter()
+                    .map(|part| String::from_utf8_lossy(part).into_owned())
+                    .collect::<String>(),
+            ),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Nameservers named by the NS records of a message, in the authority
+/// section of a referral or the answer section of an NS answer, each with
+/// its glue addresses from the additional section.
+///
+/// Nameservers are listed once, in the order of their first NS record;
+/// those without glue have no addresses.
+#[must_use]
+pub fn nameservers(message: &Message) -> Vec<(Name, Vec<IpAddr>)> {
+    let mut hosts: Vec<Name> = Vec::new();
+    for record in message.name_servers().iter().chain(message.answers()) {
+        if let Some(RData::NS(host)) = record.data() {
+            if !hosts.contains(&host.0) {
+                hosts.push(host.0.clone());
+            }
+        }
+    }
+    hosts
+        .into_iter()
+        .map(|host| {
+            let glue = message
+                .additionals()
+                .iter()
+                .filter(|record| record.name() == &host)
+                .filter_map(|record| match record.data() {
+                    Some(RData::A(ip)) => Some(IpAddr::V4(ip.0)),
+                    Some(RData::AAAA(ip)) => Some(IpAddr::V6(ip.0)),
+                    _ => None,
+                })
+                .collect();
+            (host, glue)
+        })
+        .collect()
+}
+
+/// Parse a record type name such as `TXT` or `aaaa`.
+pub fn parse_record_type(value: &str) -> std::result::Result<RecordType, String> {
+    let upper = value.to_ascii_uppercase();
+    // RecordType::from_str expects an alphanumeric name
+    if upper.is_empty() || !upper.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        return Err(format!("invalid record type '{value}'"));
+    }
+    match upper.parse::<RecordType>() {
+        Ok(RecordType::Unknown(_)) | Err(_) => Err(format!(
+            "unknown record type '{value}', expected e.g. A, AAAA, TXT or NS"
+        )),
+        Ok(record_type) => Ok(record_type),
+    }
+}
+
+/// Sends raw DNS queries to a single server.
+pub trait Exchange: Send + Sync {
+    /// Send a query over UDP and collect the matching responses, as
+    /// [`query_udp_all`] does with a `window` and [`query_udp`] without
+    /// one (returning no responses instead of a timeout error).
+    fn udp<'a>(
+        &'a self,
+        server: SocketAddr,
+        domain: &'a str,
+        record_type: RecordType,
+        timeout: Duration,
+        window: Option<Duration>,
+    ) -> BoxFuture<'a, Result<Vec<DnsResponse>>>;
+
+    /// Send a query over TCP and return the response, as [`query_tcp`]
+    /// does.
+    fn tcp<'a>(
+        &'a self,
+        server: SocketAddr,
+        domain: &'a str,
+        record_type: RecordType,
+        timeout: Duration,
+    ) -> BoxFuture<'a, Result<DnsResponse>>;
+
+    /// Send a query with the DNSSEC OK bit set over TCP, so the answer
+    /// carries the RRSIG records however large it gets, as
+    /// [`query_dnssec`] does.
+    ///
+    /// Exchanges that do not tell the two apart answer as [`tcp`](Self::tcp).
+    fn dnssec<'a>(
+        &'a self,
+        server: SocketAddr,
+        domain: &'a str,
+        record_type: RecordType,
+        timeout: Duration,
+    ) -> BoxFuture<'a, Result<DnsResponse>> {
+        self.tcp(server, domain, record_type, timeout)
+    }
+}
+
+/// Exchanges queries over the network.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetworkExchange;
+
+impl Exchange for NetworkExchange {
+    fn udp<'a>(
+        &'a self,
+        server: SocketAddr,
+        domain: &'a str,
+        record_type: RecordType,
+        timeout: Duration,
+        window: Option<Duration>,
+    ) -> BoxFuture<'a, Result<Vec<DnsResponse>>> {
+        Box::pin(collect_udp(server, domain, record_type, timeout, window))
+    }
+
+    fn tcp<'a>(
+        &'a self,
+        server: SocketAddr,
+        domain: &'a str,
+        record_type: RecordType,
+        timeout: Duration,
+    ) -> BoxFuture<'a, Result<DnsResponse>> {
+        Box::pin(query_tcp(server, domain, record_type, timeout))
+    }
+
+    fn dnssec<'a>(
+        &'a self,
+        server: SocketAddr,
+        domain: &'a str,
+        record_type: RecordType,
+        timeout: Duration,
+    ) -> BoxFuture<'a, Result<DnsResponse>> {
+        Box::pin(query_dnssec(server, domain, record_type, timeout))
+    }
+}
+
+/// Generate a random DNS transaction ID.
+fn query_id() -> u16 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    (hasher.finish() & 0xFFFF) as u16
+}
+
+/// A label no resolver has cached, for probing how resolvers handle names
+/// they have to look up.
+#[must_use]
+pub fn random_label() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    format!("dnstest-{:016x}", hasher.finish())
+}
+
+/// Build an encoded DNS query message.
+///
+/// # Arguments
+///
+/// * `domain` - Domain name to query
+/// * `record_type` - Record type to ask for
+///
+/// # Returns
+///
+/// Returns the transaction ID and the wire-format bytes.
+pub fn build_query(domain: &str, record_type: RecordType) -> Result<(u16, Vec<u8>)> {
+    build_query_with(domain, record_type, false)
+}
+
+/// Build an encoded DNS query message, with an EDNS record setting the
+/// DNSSEC OK bit if `dnssec_ok` is set, so that the server includes the
+/// signatures of the answer.
+pub fn build_query_with(
+    domain: &str,
+    record_type: RecordType,
+    dnssec_ok: bool,
+) -> Result<(u16, Vec<u8>)> {
+    let name = Name::from_ascii(domain).map_err(|e| Error::parse(e.to_string()))?;
+    let id = query_id();
+
+    let mut message = Message::new();
+    message
+        .set_id(id)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .add_query(Query::query(name, record_type));
+    if dnssec_ok {
+        let mut edns = Edns::new();
+        edns.set_max_payload(MAX_UDP_SIZE as u16)
+            .set_dnssec_ok(true);
+        message.set_edns(edns);
+    }
+
+    let bytes = message.to_vec().map_err(|e| Error::parse(e.to_string()))?;
+    Ok((id, bytes))
+}
+
+/// Send a query over UDP and return the first matching response.
+///
+/// # Arguments
+///
+/// * `server` - Address of the DNS server
+/// * `domain` - Domain name to query
+/// * `record_type` - Record type to ask for
+/// * `timeout` - Maximum time to wait for a response
+pub async fn query_udp(
+    server: SocketAddr,
+    domain: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> Result<DnsResponse> {
+    let mut responses = collect_udp(server, domain, record_type, timeout, None).await?;
+    if responses.is_empty() {
+        Err(Error::Timeout)
+    } else {
+        Ok(responses.swap_remove(0))
+    }
+}
+
+/// Send a query over UDP and collect every matching response.
+///
+/// On-path injectors race the genuine server, so a censored query often
+/// yields several replies with the same transaction ID. After the first
+/// reply arrives this keeps listening for `window` to catch the others.
+///
+/// # Arguments
+///
+/// * `server` - Address of the DNS server
+/// * `domain` - Domain name to query
+/// * `record_type` - Record type to ask for
+/// * `timeout` - Maximum time to wait for the first response
+/// * `window` - How long to keep listening after the first response
+pub async fn query_udp_all(
+    server: SocketAddr,
+    domain: &str,
+    record_type: RecordType,
+    timeout: Duration,
+    window: Duration,
+) -> Result<Vec<DnsResponse>> {
+    collect_udp(server, domain, record_type, timeout, Some(window)).await
+}
+
+/// Send a query over TCP and return the response.
+///
+/// Uses the standard two-byte length framing (RFC 1035 section 4.2.2).
+/// The timeout covers connecting, sending and receiving.
+///
+/// # Arguments
+///
+/// * `server` - Address of the DNS server
+/// * `domain` - Domain name to query
+/// * `record_type` - Record type to ask for
+/// * `timeout` - Maximum time for the whole exchange
+pub async fn query_tcp(
+    server: SocketAddr,
+    domain: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> Result<DnsResponse> {
+    let (id, query) = build_query(domain, record_type)?;
+    exchange_tcp(server, id, &query, timeout).await
+}
+
+/// Send a query with the DNSSEC OK bit set over TCP and return the
+/// response, which includes the RRSIG records of the answer if the zone
+/// is signed. Nothing is validated.
+///
+/// # Arguments
+///
+/// * `server` - Address of the DNS server
+/// * `domain` - Domain name to query
+/// * `record_type` - Record type to ask for, e.g. `DNSKEY`
+/// * `timeout` - Maximum time for the whole exchange
+pub async fn query_dnssec(
+    server: SocketAddr,
+    domain: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> Result<DnsResponse> {
+    let (id, query) = build_query_with(domain, record_type, true)?;
+    exchange_tcp(server, id, &query, timeout).await
+}
+
+async fn exchange_tcp(
+    server: SocketAddr,
+    id: u16,
+    query: &[u8],
+    timeout: Duration,
+) -> Result<DnsResponse> {
+    let start = Instant::now();
+
+    let exchange = async {
+        let mut stream = source::tcp_connect(server).await?;
+
+        let mut framed = Vec::with_capacity(query.len() + 2);
+        framed.extend_from_slice(&(query.len() as u16).to_be_bytes());
+        framed.extend_from_slice(query);
+        stream.write_all(&framed).await?;
+
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).await?;
+        let mut buf = vec![0u8; usize::from(u16::from_be_bytes(len))];
+        stream.read_exact(&mut buf).await?;
+
+        let message = Message::from_vec(&buf).map_err(|e| Error::parse(e.to_string()))?;
+        if message.id() != id {
+            return Err(Error::network("Mismatched DNS response ID"));
+        }
+
+        Ok(DnsResponse {
+            message,
+            elapsed: start.elapsed(),
+        })
+    };
+
+    tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| Error::Timeout)?
+}
+
+async fn collect_udp(
+    server: SocketAddr,
+    domain: &str,
+    record_type: RecordType,
+    timeout: Duration,
+    window: Option<Duration>,
+) -> Result<Vec<DnsResponse>> {
+    let (id, query) = build_query(domain, record_type)?;
+    let socket = source::udp_socket(server).await?;
+
+    let start = Instant::now();
+    socket.send_to(&query, server).await?;
+
+    let mut responses = Vec::new();
+    let mut deadline = start + timeout;
+    let mut buf = [0u8; MAX_UDP_SIZE];
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        let Ok(received) = tokio::time::timeout(remaining, socket.recv_from(&mut buf)).await else {
+            break;
+        };
+        let (len, from) = received?;
+
+        // Ignore stray packets that cannot be a reply to our query
+        if from.ip() != server.ip() {
+            continue;
+        }
+        let Ok(message) = Message::from_vec(&buf[..len]) else {
+            continue;
+        };
+        if message.id() != id || message.message_type() != MessageType::Response {
+            continue;
+        }
+
+        let first = responses.is_empty();
+        responses.push(DnsResponse {
+            message,
+            elapsed: start.elapsed(),
+        });
+
+        match window {
+            Some(window) if first => deadline = Instant::now() + window,
+            Some(_) => {}
+            None => break,
+        }
+    }
+
+    Ok(responses)
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    //! A scripted network for tests.
+    //!
+    //! [`FakeExchange`] answers each query with the reply of the first
+    //! rule in its table that matches the server, name, record type and
+    //! transport. Queries no rule matches go unanswered: UDP returns no
+    //! responses and TCP times out.
+
+    use super::{DnsResponse, Exchange};
+    use crate::error::{Error, Result};
+    use futures::future::BoxFuture;
+    use hickory_resolver::proto::op::{Message, MessageType, ResponseCode};
+    use hickory_resolver::proto::rr::{Name, RData, Record, RecordType};
+    use std::net::{IpAddr, SocketAddr};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    /// A record for a reply, e.g. `record("example.com.", 60, RData::A(..))`.
+    pub fn record(name: &str, ttl: u32, data: RData) -> Record {
+        Record::from_rdata(Name::from_ascii(name).unwrap(), ttl, data)
+    }
+
+    /// The queries a rule applies to; unset fields match anything.
+    #[derive(Debug, Clone, Default)]
+    pub struct Query {
+        server: Option<IpAddr>,
+        name: Option<String>,
+        record_type: Option<RecordType>,
+        tcp: Option<bool>,
+    }
+
+    impl Query {
+        /// Match every query.
+        pub fn any() -> Self {
+            Self::default()
+        }
+
+        /// Match queries sent to `server`.
+        pub fn server(mut self, server: impl Into<IpAddr>) -> Self {
+            self.server = Some(server.into());
+            self
+        }
+
+        /// Match queries for `name`, or for any name below it when it
+        /// starts with `*.`.
+        pub fn name(mut self, name: &str) -> Self {
+            self.name = Some(name.to_string());
+            self
+        }
+
+        /// Match queries for `record_type`.
+        pub const fn record_type(mut self, record_type: RecordType) -> Self {
+            self.record_type = Some(record_type);
+            self
+        }
+
+        /// Match queries sent over UDP.
+        pub const fn udp(mut self) -> Self {
+            self.tcp = Some(false);
+            self
+        }
+
+        /// Match queries sent over TCP.
+        pub const fn tcp(mut self) -> Self {
+            self.tcp = Some(true);
+            self
+        }
+
+        fn matches(
+            &self,
+            server: SocketAddr,
+            domain: &str,
+            record_type: RecordType,
+            tcp: bool,
+        ) -> bool {
+            let name_matches = |name: &String| match name.strip_prefix('*') {
+                Some(zone) => {
+                    domain.len() > zone.len()
+                        && domain[domain.len() - zone.len()..].eq_ignore_ascii_case(zone)
+                }
+                None => domain.eq_ignore_ascii_case(name),
+            };
+            self.server.map_or(true, |ip| ip == server.ip())
+                && self.name.as_ref().map_or(true, name_matches)
+                && self.record_type.map_or(true, |rt| rt == record_type)
+                && self.tcp.map_or(true, |t| t == tcp)
+        }
+    }
+
+    /// The response messages a rule answers with.
+    #[derive(Debug, Clone)]
+    pub struct Reply {
+        messages: Vec<Message>,
+        elapsed: Duration,
+    }
+
+    impl Reply {
+        /// A NOERROR response without records.
+        pub fn empty() -> Self {
+            Self::rcode(ResponseCode::NoError)
+        }
+
+        /// A response without records carrying `rcode`.
+        pub fn rcode(rcode: ResponseCode) -> Self {
+            let mut message = Message::new();
+            message
+                .set_message_type(MessageType::Response)
+                .set_response_code(rcode);
+            Self {
+                messages: vec![message],
+                elapsed: Duration::from_millis(1),
+            }
+        }
+
+        /// A NOERROR response with `records` in the answer section.
+        pub fn answers(records: impl IntoIterator<Item = Record>) -> Self {
+            let mut reply = Self::empty();
+            reply.first().add_answers(records);
+            reply
+        }
+
+        /// Add `records` to the authority section.
+        #[must_use]
+        pub fn with_authority(mut self, records: impl IntoIterator<Item = Record>) -> Self {
+            self.first().add_name_servers(records);
+            self
+        }
+
+        /// Add `records` to the additional section.
+        #[must_use]
+        pub fn with_additional(mut self, records: impl IntoIterator<Item = Record>) -> Self {
+            self.first().add_additionals(records);
+            self
+        }
+
+        /// Set the authoritative answer flag.
+        #[must_use]
+        pub fn authoritative(mut self) -> Self {
+            self.first().set_authoritative(true);
+            self
+        }
+
+        /// Set the recursion available flag.
+        #[must_use]
+        pub fn recursion_available(mut self) -> Self {
+            self.first().set_recursion_available(true);
+            self
+        }
+
+        /// Report the response as taking `ms` milliseconds.
+        #[must_use]
+        pub const fn with_elapsed(mut self, ms: u64) -> Self {
+            self.elapsed = Duration::from_millis(ms);
+            self
+        }
+
+        /// Follow the response with the ones of `later` over UDP, which
+        /// only a caller that keeps listening receives.
+        #[must_use]
+        pub fn followed_by(mut self, later: Self) -> Self {
+            self.messages.extend(later.messages);
+            self
+        }
+
+        fn first(&mut self) -> &mut Message {
+            &mut self.messages[0]
+        }
+
+        fn responses(&self) -> impl Iterator<Item = DnsResponse> + '_ {
+            self.messages.iter().map(|message| DnsResponse {
+                message: message.clone(),
+                elapsed: self.elapsed,
+            })
+        }
+    }
+
+    struct Rule {
+        query: Query,
+        reply: Reply,
+        /// Whether the rule only answers the first query it matches
+        once: bool,
+        used: AtomicBool,
+    }
+
+    /// Answers queries from a table of rules, see the [module](self)
+    /// documentation.
+    #[derive(Default)]
+    pub struct FakeExchange {
+        rules: Vec<Rule>,
+    }
+
+    impl FakeExchange {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Answer queries matching `query` with `reply`.
+        #[must_use]
+        pub fn on(self, query: Query, reply: Reply) -> Self {
+            self.rule(query, reply, false)
+        }
+
+        /// Answer only the first query matching `query` with `reply`,
+        /// leaving later ones to the rules after it.
+        #[must_use]
+        pub fn once(self, query: Query, reply: Reply) -> Self {
+            self.rule(query, reply, true)
+        }
+
+        fn rule(mut self, query: Query, reply: Reply, once: bool) -> Self {
+            self.rules.push(Rule {
+                query,
+                reply,
+                once,
+                used: AtomicBool::new(false),
+            });
+            self
+        }
+
+        fn reply(
+            &self,
+            server: SocketAddr,
+            domain: &str,
+            record_type: RecordType,
+            tcp: bool,
+        ) -> Option<&Reply> {
+            self.rules
+                .iter()
+                .filter(|rule| rule.query.matches(server, domain, record_type, tcp))
+                .find(|rule| !(rule.once && rule.used.swap(true, Ordering::Relaxed)))
+                .map(|rule| &rule.reply)
+        }
+    }
+
+    impl Exchange for FakeExchange {
+        fn udp<'a>(
+            &'a self,
+            server: SocketAddr,
+            domain: &'a str,
+            record_type: RecordType,
+            _timeout: Duration,
+            window: Option<Duration>,
+        ) -> BoxFuture<'a, Result<Vec<DnsResponse>>> {
+            let received = if window.is_some() { usize::MAX } else { 1 };
+            let responses = self
+                .reply(server, domain, record_type, false)
+                .map(|reply| reply.responses().take(received).collect())
+                .unwrap_or_default();
+            Box::pin(async move { Ok(responses) })
+        }
+
+        fn tcp<'a>(
+            &'a self,
+            server: SocketAddr,
+            domain: &'a str,
+            record_type: RecordType,
+            _timeout: Duration,
+        ) -> BoxFuture<'a, Result<DnsResponse>> {
+            let response = self
+                .reply(server, domain, record_type, true)
+                .and_then(|reply| reply.responses().next())
+                .ok_or(Error::Timeout);
+            Box::pin(async move { response })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_label() {
+        let label = random_label();
+        assert!(Name::from_ascii(format!("{label}.example.com.")).is_ok());
+        assert_ne!(label, random_label());
+    }
+
+    #[test]
+    fn test_parse_record_type() {
+        assert_eq!(parse_record_type("TXT"), Ok(RecordType::TXT));
+        assert_eq!(parse_record_type("aaaa"), Ok(RecordType::AAAA));
+        assert_eq!(parse_record_type("https"), Ok(RecordType::HTTPS));
+        assert!(parse_record_type("").is_err());
+        assert!(parse_record_type("T-XT").is_err());
+        assert!(parse_record_type("BOGUS").is_err());
+    }
+
+    #[test]
+    fn test_build_query_roundtrip() {
+        let (id, bytes) = build_query("example.com.", RecordType::A).unwrap();
+        let message = Message::from_vec(&bytes).unwrap();
+
+        assert_eq!(message.id(), id);
+        assert_eq!(message.message_type(), MessageType::Query);
+        assert!(message.recursion_desired());
+        assert_eq!(message.queries().len(), 1);
+        assert_eq!(message.queries()[0].query_type(), RecordType::A);
+    }
+
+    #[test]
+    fn test_answer_records() {
+        use hickory_resolver::proto::rr::dnssec::rdata::{DNSKEY, RRSIG};
+        use hickory_resolver::proto::rr::dnssec::Algorithm;
+        use hickory_resolver::proto::rr::rdata::svcb::{Alpn, IpHint, SvcParamKey};
+        use hickory_resolver::proto::rr::rdata::{A, HTTPS};
+        use hickory_resolver::proto::rr::Record;
+
+        let (_, bytes) = build_query_with("example.com.", RecordType::DNSKEY, true).unwrap();
+        let query = Message::from_vec(&bytes).unwrap();
+        assert!(query.extensions().as_ref().is_some_and(Edns::dnssec_ok));
+        assert!(
+            Message::from_vec(&build_query("example.com.", RecordType::A).unwrap().1)
+                .unwrap()
+                .extensions()
+                .is_none()
+        );
+
+        let name = Name::from_ascii("example.com.").unwrap();
+        let https = HTTPS(SVCB::new(
+            1,
+            Name::root(),
+            vec![
+                (
+                    SvcParamKey::Alpn,
+                    SvcParamValue::Alpn(Alpn(vec!["h2".into(), "h3".into()])),
+                ),
+                (
+                    SvcParamKey::Ipv4Hint,
+                    SvcParamValue::Ipv4Hint(IpHint(vec![A::new(192, 0, 2, 1)])),
+                ),
+            ],
+        ));
+        let key = DNSKEY::new(true, true, false, Algorithm::ECDSAP256SHA256, vec![1; 64]);
+        let key_tag = key.calculate_key_tag().unwrap();
+        let signature = RRSIG::new(
+            RecordType::DNSKEY,
+            Algorithm::ECDSAP256SHA256,
+            2,
+            3600,
+            1_800_000_000,
+            1_700_000_000,
+            key_tag,
+            name.clone(),
+            vec![2; 64],
+        );
+        let mut message = Message::new();
+        message.set_message_type(MessageType::Response);
+        for data in [
+            RData::HTTPS(https),
+            RData::DNSSEC(DNSSECRData::DNSKEY(key)),
+            RData::DNSSEC(DNSSECRData::RRSIG(signature)),
+            RData::A(A::new(192, 0, 2, 1)),
+        ] {
+            message.add_answer(Record::from_rdata(name.clone(), 300, data));
+        }
+        let message = Message::from_vec(&message.to_vec().unwrap()).unwrap();
+
+        assert_eq!(
+            answer_records(&message),
+            [
+                ExtendedRecord::ServiceBinding {
+                    https: true,
+                    priority: 1,
+                    target: ".".into(),
+                    alpn: vec!["h2".into(), "h3".into()],
+                    port: None,
+                    ip_hints: vec![IpAddr::from([192, 0, 2, 1])],
+                    ech: false,
+                },
+                ExtendedRecord::Dnskey {
+                    flags: 257,
+                    algorithm: 13,
+                    key_tag,
+                },
+                ExtendedRecord::Rrsig {
+                    type_covered: RecordType::DNSKEY,
+                    algorithm: 13,
+                    key_tag,
+                    signer: "example.com".into(),
+                    expiration: 1_800_000_000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_query_invalid_name() {
+        assert!(build_query("bad..name", RecordType::A).is_err());
+    }
+}
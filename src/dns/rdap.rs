@@ -0,0 +1,262 @@
+//! RDAP (WHOIS successor) lookups for IP addresses.
+//!
+//! When system and public DNS disagree, knowing who owns an unexpected
+//! address is often enough to judge it: a CDN's network is fine, an
+//! unrelated foreign network is not. Queries go to the `rdap.org`
+//! bootstrap service, which redirects to the responsible registry.
+//!
+//! Registrations rarely change, so they are kept in `rdap.json` in the
+//! cache directory for a week and only addresses not seen before are
+//! looked up.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::cache::write_atomic;
+use crate::config::ConfigLoader;
+use crate::dns::types::DnsServer;
+use crate::error::Result;
+use crate::http;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// RDAP bootstrap service base URL.
+const RDAP_BASE_URL: &str = "https://rdap.org/ip";
+
+/// Default timeout for a single RDAP query.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of addresses looked up at the same time.
+const CONCURRENCY: usize = 8;
+
+/// Cache file name inside the cache directory.
+const CACHE_FILE: &str = "rdap.json";
+
+/// How long a registration is kept in the cache.
+const CACHE_TTL: chrono::Duration = chrono::Duration::days(7);
+
+/// Registration data of the network an address belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RdapRecord {
+    /// The address that was looked up
+    pub ip: IpAddr,
+    /// Registry handle of the network (e.g. "NET-8-8-8-0-2")
+    pub handle: Option<String>,
+    /// Network name (e.g. "GOGL")
+    pub name: Option<String>,
+    /// Two-letter country code of the registration
+    pub country: Option<String>,
+}
+
+impl std::fmt::Display for RdapRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = self.name.as_deref().unwrap_or("?");
+        match &self.country {
+            Some(country) => write!(f, "{name}, {country}"),
+            None => write!(f, "{name}"),
+        }
+    }
+}
+
+/// The subset of an RDAP IP network response we use.
+#[derive(Debug, Deserialize)]
+struct IpNetworkResponse {
+    handle: Option<String>,
+    name: Option<String>,
+    country: Option<String>,
+}
+
+/// Look up the registration data of a single address.
+///
+/// # Arguments
+///
+/// * `ip` - Address to look up
+pub async fn lookup(ip: IpAddr) -> Result<RdapRecord> {
+    let url = format!("{RDAP_BASE_URL}/{ip}");
+    let response: IpNetworkResponse = http::get_json(&url, DEFAULT_TIMEOUT).await?;
+
+    Ok(RdapRecord {
+        ip,
+        handle: response.handle,
+        name: response.name,
+        country: response.country,
+    })
+}
+
+/// Look up several addresses, a few at a time, answering from the cache
+/// where it has them.
+///
+/// Failed lookups are logged and omitted from the result.
+///
+/// # Arguments
+///
+/// * `ips` - Addresses to look up
+pub async fn lookup_all(ips: &[IpAddr]) -> Vec<RdapRecord> {
+    lookup_cached(ips, &ConfigLoader::cache_dir().join(CACHE_FILE)).await
+}
+
+async fn lookup_cached(ips: &[IpAddr], path: &Path) -> Vec<RdapRecord> {
+    let mut cache = Cache::load(path.to_path_buf());
+    let missing: Vec<IpAddr> = ips
+        .iter()
+        .copied()
+        .filter(|ip| cache.get(*ip).is_none())
+        .collect();
+
+    let fetched: Vec<RdapRecord> = futures::stream::iter(missing)
+        .map(lookup)
+        .buffer_unordered(CONCURRENCY)
+        .filter_map(|r| async move {
+            r.map_err(|e| tracing::debug!("RDAP lookup failed: {e}"))
+                .ok()
+        })
+        .collect()
+        .await;
+    if !fetched.is_empty() {
+        for record in fetched {
+            cache.insert(record);
+        }
+        if let Err(e) = cache.save() {
+            tracing::debug!("Failed to save RDAP cache: {e}");
+        }
+    }
+
+    ips.iter()
+        .filter_map(|&ip| cache.get(ip).cloned())
+        .collect()
+}
+
+/// A cached registration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    record: RdapRecord,
+    fetched_at: DateTime<Utc>,
+}
+
+/// Registrations by address, persisted to a file.
+struct Cache {
+    path: PathBuf,
+    entries: HashMap<IpAddr, CacheEntry>,
+}
+
+impl Cache {
+    /// Load the registrations saved at `path` that have not expired; a
+    /// missing or unreadable file starts an empty cache.
+    fn load(path: PathBuf) -> Self {
+        let mut entries: HashMap<IpAddr, CacheEntry> = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let now = Utc::now();
+        entries.retain(|_, entry| now - entry.fetched_at < CACHE_TTL);
+        Self { path, entries }
+    }
+
+    fn get(&self, ip: IpAddr) -> Option<&RdapRecord> {
+        self.entries.get(&ip).map(|entry| &entry.record)
+    }
+
+    fn insert(&mut self, record: RdapRecord) {
+        self.entries.insert(
+            record.ip,
+            CacheEntry {
+                record,
+                fetched_at: Utc::now(),
+            },
+        );
+    }
+
+    /// Replace the cache file.
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(&self.path, &serde_json::to_string(&self.entries)?)
+    }
+}
+
+/// Fill in the country of servers whose list entry has none, from the
+/// registration of their address.
+///
+/// Registration countries are a rough stand-in for a geolocation
+/// database: anycast addresses are registered in one country but answered
+/// from many.
+pub async fn fill_countries(servers: &mut [DnsServer]) {
+    let missing: Vec<IpAddr> = servers
+        .iter()
+        .filter(|s| s.country.is_none())
+        .filter_map(DnsServer::ip_addr)
+        .collect();
+    if missing.is_empty() {
+        return;
+    }
+
+    let records = lookup_all(&missing).await;
+    for server in servers.iter_mut().filter(|s| s.country.is_none()) {
+        let ip = server.ip_addr();
+        server.country = records
+            .iter()
+            .find(|record| Some(record.ip) == ip)
+            .and_then(|record| record.country.as_ref())
+            .map(|country| country.to_uppercase());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rdap_response() {
+        let body = r#"{
+            "objectClassName": "ip network",
+            "handle": "NET-8-8-8-0-2",
+            "startAddress": "8.8.8.0",
+            "endAddress": "8.8.8.255",
+            "name": "GOGL",
+            "country": "US"
+        }"#;
+        let response: IpNetworkResponse = serde_json::from_str(body).unwrap();
+        assert_eq!(response.name.as_deref(), Some("GOGL"));
+        assert_eq!(response.country.as_deref(), Some("US"));
+
+        let record = RdapRecord {
+            ip: "8.8.8.8".parse().unwrap(),
+            handle: response.handle,
+            name: response.name,
+            country: response.country,
+        };
+        assert_eq!(record.to_string(), "GOGL, US");
+    }
+
+    #[tokio::test]
+    async fn test_cached_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache").join(CACHE_FILE);
+        let record = RdapRecord {
+            ip: "8.8.8.8".parse().unwrap(),
+            handle: None,
+            name: Some("GOGL".into()),
+            country: Some("US".into()),
+        };
+        let mut cache = Cache::load(path.clone());
+        cache.insert(record.clone());
+        cache.save().unwrap();
+
+        // Answered from the cache, without a query
+        assert_eq!(
+            lookup_cached(&[record.ip], &path).await,
+            std::slice::from_ref(&record)
+        );
+
+        // Expired registrations are dropped on load
+        let mut cache = Cache::load(path.clone());
+        cache.entries.get_mut(&record.ip).unwrap().fetched_at -= CACHE_TTL;
+        cache.save().unwrap();
+        assert!(Cache::load(path).get(record.ip).is_none());
+    }
+}
@@ -0,0 +1,209 @@
+//! Cache of reference answers for pollution checks.
+//!
+//! Checking a watchlist asks the public reference resolvers for the same
+//! domains on every run. Their answers are kept in
+//! `reference-answers.json` in the cache directory until their TTL runs
+//! out, so a repeated batch check does not ask the public resolvers again
+//! for the domains whose reference answer is still fresh. The UDP and TCP
+//! queries that compare the transports still go to the first reference
+//! resolver for every domain, as they test the path to it rather than
+//! fetch a reference answer.
+//!
+//! New answers are kept in memory and written out by [`ReferenceCache::flush`],
+//! once per batch, which replaces the file atomically. Dropping the cache
+//! flushes it too.
+//!
+//! Answers are keyed by the reference resolvers they came from, so
+//! changing `reference_resolvers` does not reuse answers of the previous
+//! ones. Only answers that carry a TTL are cached.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::cache::write_atomic;
+use crate::config::ConfigLoader;
+use crate::error::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Cache file name inside the cache directory.
+const CACHE_FILE: &str = "reference-answers.json";
+
+/// Longest time an answer is kept, whatever its TTL.
+const MAX_TTL: Duration = Duration::days(1);
+
+/// A cached answer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    ips: Vec<IpAddr>,
+    expires_at: DateTime<Utc>,
+}
+
+/// Reference answers by resolver and domain, persisted to a file.
+///
+/// # Example
+///
+/// ```ignore
+/// let cache = ReferenceCache::load(ReferenceCache::default_path());
+/// let checker = PollutionChecker::new()?.with_reference_cache(cache);
+/// ```
+#[derive(Debug)]
+pub struct ReferenceCache {
+    /// File the answers are saved to, `None` to keep them in memory
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<String, Entry>>,
+    /// Whether answers were added since the last flush
+    dirty: AtomicBool,
+}
+
+impl ReferenceCache {
+    /// Path of the cache file in the cache directory.
+    #[must_use]
+    pub fn default_path() -> PathBuf {
+        ConfigLoader::cache_dir().join(CACHE_FILE)
+    }
+
+    /// Load the answers saved at `path` that have not expired.
+    ///
+    /// A missing or unreadable file starts an empty cache, which is
+    /// written to `path` on the first flush after an insert.
+    #[must_use]
+    pub fn load(path: PathBuf) -> Self {
+        let mut entries: HashMap<String, Entry> = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let now = Utc::now();
+        entries.retain(|_, entry| entry.expires_at > now);
+        Self {
+            path: Some(path),
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// An empty cache that is not saved.
+    #[must_use]
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            entries: Mutex::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// The answer of `reference` for `domain`, if it has not expired.
+    #[must_use]
+    pub fn get(&self, reference: &str, domain: &str) -> Option<Vec<IpAddr>> {
+        self.get_at(reference, domain, Utc::now())
+    }
+
+    fn get_at(&self, reference: &str, domain: &str, now: DateTime<Utc>) -> Option<Vec<IpAddr>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&key(reference, domain))
+            .filter(|entry| entry.expires_at > now)
+            .map(|entry| entry.ips.clone())
+    }
+
+    /// Keep the answer of `reference` for `domain` for `ttl` seconds, until
+    /// the next [`flush`](Self::flush) saves it.
+    pub fn insert(&self, reference: &str, domain: &str, ips: Vec<IpAddr>, ttl: u32) {
+        self.insert_at(reference, domain, ips, ttl, Utc::now());
+    }
+
+    fn insert_at(
+        &self,
+        reference: &str,
+        domain: &str,
+        ips: Vec<IpAddr>,
+        ttl: u32,
+        now: DateTime<Utc>,
+    ) {
+        if ttl == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.expires_at > now);
+        entries.insert(
+            key(reference, domain),
+            Entry {
+                ips,
+                expires_at: now + Duration::seconds(ttl.into()).min(MAX_TTL),
+            },
+        );
+        drop(entries);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Save the answers added since the last flush, replacing the cache
+    /// file atomically. Does nothing if there are none or the cache is
+    /// only kept in memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thread panicked while adding an answer.
+    pub fn flush(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let content = serde_json::to_string(&*self.entries.lock().unwrap())?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(path, &content)
+    }
+}
+
+impl Drop for ReferenceCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            tracing::debug!("Failed to save the reference cache: {e}");
+        }
+    }
+}
+
+/// Cache key of an answer: the domain, without the trailing dot, and the
+/// resolvers that gave it.
+fn key(reference: &str, domain: &str) -> String {
+    format!(
+        "{}@{reference}",
+        domain.trim_end_matches('.').to_ascii_lowercase()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_cache_expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache").join(CACHE_FILE);
+        let ips = vec![IpAddr::from([93, 184, 215, 14])];
+        let now = Utc::now();
+
+        let cache = ReferenceCache::load(path.clone());
+        cache.insert_at("8.8.8.8", "example.com.", ips.clone(), 300, now);
+        cache.insert_at("8.8.8.8", "example.org.", ips.clone(), 0, now);
+        assert_eq!(cache.get_at("8.8.8.8", "Example.com", now), Some(ips));
+        assert_eq!(cache.get_at("8.8.8.8", "example.org", now), None);
+        assert_eq!(cache.get_at("9.9.9.9", "example.com", now), None);
+        let later = now + Duration::seconds(301);
+        assert_eq!(cache.get_at("8.8.8.8", "example.com", later), None);
+
+        // Saved on flush, not on insert
+        assert!(!path.exists());
+        cache.flush().unwrap();
+        assert!(ReferenceCache::load(path)
+            .get("8.8.8.8", "example.com")
+            .is_some());
+    }
+}
@@ -0,0 +1,471 @@
+//! Traceroute to a DNS server.
+//!
+//! Sends ICMP echo requests with increasing TTLs and records which router
+//! answers each one, so a slow resolver can be told apart from a slow path
+//! to it. Unlike the speed test this needs to see ICMP errors sent by
+//! routers, which unprivileged datagram sockets do not deliver: on Unix a
+//! raw socket is used, on Windows `IcmpSendEcho` with a TTL option.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::error::{Error, Result};
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default number of hops before giving up.
+pub const DEFAULT_MAX_HOPS: u8 = 30;
+
+/// Default number of probes sent to each hop.
+pub const DEFAULT_PROBES: usize = 3;
+
+/// Default timeout for each probe in seconds.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 2;
+
+/// Payload size of each probe in bytes.
+const PAYLOAD_SIZE: usize = 32;
+
+/// One TTL step of a trace.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Hop {
+    /// TTL the probes were sent with
+    pub ttl: u8,
+    /// Router or host that answered, if any probe got a reply
+    pub address: Option<IpAddr>,
+    /// Round-trip time of each probe in milliseconds, `None` if lost
+    pub rtts_ms: Vec<Option<f64>>,
+    /// Whether the target itself answered
+    pub reached: bool,
+}
+
+impl Hop {
+    /// Average round-trip time of the answered probes.
+    #[must_use]
+    pub fn avg_ms(&self) -> Option<f64> {
+        let rtts: Vec<f64> = self.rtts_ms.iter().flatten().copied().collect();
+        if rtts.is_empty() {
+            None
+        } else {
+            Some(rtts.iter().sum::<f64>() / rtts.len() as f64)
+        }
+    }
+
+    /// Fraction of probes that got no answer.
+    #[must_use]
+    pub fn loss(&self) -> f64 {
+        if self.rtts_ms.is_empty() {
+            return 0.0;
+        }
+        let lost = self.rtts_ms.iter().filter(|rtt| rtt.is_none()).count();
+        lost as f64 / self.rtts_ms.len() as f64
+    }
+}
+
+/// What answered a single probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Answer {
+    /// A router on the way dropped the probe as its TTL ran out
+    TimeExceeded(Ipv4Addr),
+    /// The target replied
+    Reached(Ipv4Addr),
+    /// A router or the target reported the target unreachable
+    Unreachable(Ipv4Addr),
+}
+
+impl Answer {
+    const fn from(self) -> Ipv4Addr {
+        match self {
+            Self::TimeExceeded(ip) | Self::Reached(ip) | Self::Unreachable(ip) => ip,
+        }
+    }
+}
+
+/// Traceroute runner.
+///
+/// # Example
+///
+/// ```ignore
+/// let tracer = Tracer::new();
+/// let hops = tracer.trace("1.1.1.1".parse()?, |hop| println!("{hop:?}")).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Tracer {
+    max_hops: u8,
+    probes: usize,
+    timeout: Duration,
+}
+
+impl Tracer {
+    /// Create a tracer with default settings.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::with_settings(
+            DEFAULT_MAX_HOPS,
+            DEFAULT_PROBES,
+            Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+        )
+    }
+
+    /// Create a tracer with custom settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_hops` - Highest TTL to try
+    /// * `probes` - Probes sent to each hop
+    /// * `timeout` - Timeout for each probe
+    #[must_use]
+    pub const fn with_settings(max_hops: u8, probes: usize, timeout: Duration) -> Self {
+        Self {
+            max_hops,
+            probes,
+            timeout,
+        }
+    }
+
+    /// Trace the route to `target`, calling `on_hop` as each hop completes.
+    ///
+    /// Stops when the target answers, reports itself unreachable, or
+    /// `max_hops` is exceeded.
+    pub async fn trace(&self, target: IpAddr, mut on_hop: impl FnMut(&Hop)) -> Result<Vec<Hop>> {
+        let IpAddr::V4(target) = target else {
+            return Err(Error::network("IPv6 traceroute is not supported yet"));
+        };
+        let prober = Arc::new(Prober::open()?);
+        let mut hops = Vec::new();
+
+        for ttl in 1..=self.max_hops {
+            let mut hop = Hop {
+                ttl,
+                address: None,
+                rtts_ms: Vec::with_capacity(self.probes),
+                reached: false,
+            };
+            let mut unreachable = false;
+
+            for probe in 0..self.probes {
+                let seq = u16::from(ttl) << 8 | (probe as u16 & 0xff);
+                let prober = Arc::clone(&prober);
+                let timeout = self.timeout;
+                let answer =
+                    tokio::task::spawn_blocking(move || prober.probe(target, ttl, seq, timeout))
+                        .await
+                        .map_err(|e| Error::network(e.to_string()))??;
+
+                match answer {
+                    Some((answer, rtt)) => {
+                        hop.address.get_or_insert_with(|| IpAddr::V4(answer.from()));
+                        hop.rtts_ms.push(Some(rtt.as_secs_f64() * 1000.0));
+                        match answer {
+                            Answer::Reached(_) => hop.reached = true,
+                            Answer::Unreachable(_) => unreachable = true,
+                            Answer::TimeExceeded(_) => {}
+                        }
+                    }
+                    None => hop.rtts_ms.push(None),
+                }
+            }
+
+            on_hop(&hop);
+            let done = hop.reached || unreachable;
+            hops.push(hop);
+            if done {
+                break;
+            }
+        }
+
+        Ok(hops)
+    }
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(unix)]
+use unix::Prober;
+
+#[cfg(windows)]
+use windows::Prober;
+
+#[cfg(unix)]
+mod unix {
+    use super::{parse_reply, Answer, PAYLOAD_SIZE};
+    use crate::dns::icmp::next_ident;
+    use crate::dns::source;
+    use crate::error::{Error, Result};
+    use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+    use std::io::Read;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+    use std::time::{Duration, Instant};
+
+    /// A raw ICMP socket; routers' Time Exceeded messages only reach raw
+    /// sockets.
+    pub struct Prober {
+        socket: Socket,
+        ident: u16,
+    }
+
+    impl Prober {
+        pub fn open() -> Result<Self> {
+            let socket =
+                Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).map_err(|e| {
+                    if e.kind() == std::io::ErrorKind::PermissionDenied {
+                        Error::network(format!(
+                            "{e}; traceroute needs a raw ICMP socket: {}",
+                            if cfg!(target_os = "linux") {
+                                "run with sudo or grant CAP_NET_RAW"
+                            } else {
+                                "run with sudo"
+                            }
+                        ))
+                    } else {
+                        Error::network(e.to_string())
+                    }
+                })?;
+            if let Some(binding) = source::current() {
+                if let Some(address @ IpAddr::V4(_)) = binding.address {
+                    socket.bind(&SockAddr::from(SocketAddr::new(address, 0)))?;
+                }
+                #[cfg(target_os = "linux")]
+                binding.apply(&socket2::SockRef::from(&socket))?;
+            }
+            Ok(Self {
+                socket,
+                ident: next_ident(),
+            })
+        }
+
+        /// Send one probe and wait for the matching answer.
+        pub fn probe(
+            &self,
+            target: Ipv4Addr,
+            ttl: u8,
+            seq: u16,
+            timeout: Duration,
+        ) -> Result<Option<(Answer, Duration)>> {
+            let packet = super::echo_request(self.ident, seq, &[0u8; PAYLOAD_SIZE]);
+            self.socket.set_ttl_v4(u32::from(ttl))?;
+            let start = Instant::now();
+            self.socket
+                .send_to(&packet, &SockAddr::from(SocketAddrV4::new(target, 0)))?;
+
+            // Every raw ICMP socket sees every ICMP packet, so skip the ones
+            // meant for someone else until the deadline
+            let mut buf = [0u8; 1500];
+            loop {
+                let Some(left) = timeout
+                    .checked_sub(start.elapsed())
+                    .filter(|d| !d.is_zero())
+                else {
+                    return Ok(None);
+                };
+                self.socket.set_read_timeout(Some(left))?;
+                let len = match (&self.socket).read(&mut buf) {
+                    Ok(len) => len,
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        return Ok(None);
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                if let Some(answer) = parse_reply(&buf[..len], self.ident, seq) {
+                    return Ok(Some((answer, start.elapsed())));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::{Answer, PAYLOAD_SIZE};
+    use crate::dns::icmp::windows::{send_echo, IP_DEST_HOST_UNREACHABLE, IP_TTL_EXPIRED_TRANSIT};
+    use crate::dns::icmp::PingError;
+    use crate::error::{Error, Result};
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    /// `IcmpSendEcho`, which reports routers' Time Exceeded messages
+    /// without Administrator rights.
+    pub struct Prober;
+
+    impl Prober {
+        #[allow(clippy::unnecessary_wraps)]
+        pub const fn open() -> Result<Self> {
+            Ok(Self)
+        }
+
+        pub fn probe(
+            &self,
+            target: Ipv4Addr,
+            ttl: u8,
+            _seq: u16,
+            timeout: Duration,
+        ) -> Result<Option<(Answer, Duration)>> {
+            match send_echo(target, &[0u8; PAYLOAD_SIZE], timeout, Some(ttl)) {
+                Ok(reply) => {
+                    let answer = match reply.status {
+                        IP_TTL_EXPIRED_TRANSIT => Answer::TimeExceeded(reply.from),
+                        IP_DEST_HOST_UNREACHABLE => Answer::Unreachable(reply.from),
+                        _ if reply.from == target => Answer::Reached(reply.from),
+                        _ => Answer::Unreachable(reply.from),
+                    };
+                    Ok(Some((answer, reply.rtt)))
+                }
+                Err(PingError::Timeout) => Ok(None),
+                Err(e) => Err(Error::network(e.to_string())),
+            }
+        }
+    }
+}
+
+/// ICMP message types used by traceroute.
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_DEST_UNREACHABLE: u8 = 3;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_TIME_EXCEEDED: u8 = 11;
+
+/// Build an ICMP echo request.
+#[cfg_attr(windows, allow(dead_code))]
+fn echo_request(ident: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(8 + payload.len());
+    packet.extend_from_slice(&[ICMP_ECHO_REQUEST, 0, 0, 0]);
+    packet.extend_from_slice(&ident.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(payload);
+    let sum = checksum(&packet);
+    packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    packet
+}
+
+/// Internet checksum (RFC 1071).
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Interpret an IPv4 packet read from a raw ICMP socket, returning what
+/// it says about the probe `ident`/`seq`, or `None` if it is about
+/// something else.
+#[cfg_attr(windows, allow(dead_code))]
+fn parse_reply(packet: &[u8], ident: u16, seq: u16) -> Option<Answer> {
+    let (from, icmp) = split_ipv4(packet)?;
+    let kind = *icmp.first()?;
+    let matches = |echo: &[u8]| {
+        echo.len() >= 8
+            && u16::from_be_bytes([echo[4], echo[5]]) == ident
+            && u16::from_be_bytes([echo[6], echo[7]]) == seq
+    };
+
+    match kind {
+        ICMP_ECHO_REPLY => matches(icmp).then_some(Answer::Reached(from)),
+        ICMP_TIME_EXCEEDED | ICMP_DEST_UNREACHABLE => {
+            // The error quotes the IP header and first 8 bytes of the probe
+            let (_, quoted) = split_ipv4(icmp.get(8..)?)?;
+            if quoted.first() != Some(&ICMP_ECHO_REQUEST) || !matches(quoted) {
+                return None;
+            }
+            Some(if kind == ICMP_TIME_EXCEEDED {
+                Answer::TimeExceeded(from)
+            } else {
+                Answer::Unreachable(from)
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Split an IPv4 packet into its source address and payload.
+fn split_ipv4(packet: &[u8]) -> Option<(Ipv4Addr, &[u8])> {
+    let header_len = usize::from(packet.first()? & 0x0f) * 4;
+    if header_len < 20 || packet.len() < header_len {
+        return None;
+    }
+    let from = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    Some((from, &packet[header_len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal IPv4 header from `from` followed by `payload`.
+    fn ipv4(from: Ipv4Addr, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, 1, 0, 0];
+        packet.extend_from_slice(&from.octets());
+        packet.extend_from_slice(&[10, 0, 0, 1]);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn test_echo_request_checksum() {
+        let packet = echo_request(0x1234, 7, &[0u8; 4]);
+        assert_eq!(packet[0], ICMP_ECHO_REQUEST);
+        assert_eq!(&packet[4..8], &[0x12, 0x34, 0, 7]);
+        // A packet including its own checksum sums to zero
+        assert_eq!(checksum(&packet), 0);
+        // Odd lengths are padded
+        assert_eq!(checksum(&[0xff]), 0x00ff);
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let target = Ipv4Addr::new(1, 1, 1, 1);
+        let router = Ipv4Addr::new(192, 168, 1, 1);
+        let probe = echo_request(42, 3, &[0u8; 4]);
+
+        let mut reply = probe.clone();
+        reply[0] = ICMP_ECHO_REPLY;
+        assert_eq!(
+            parse_reply(&ipv4(target, &reply), 42, 3),
+            Some(Answer::Reached(target))
+        );
+        assert_eq!(parse_reply(&ipv4(target, &reply), 42, 4), None);
+
+        let mut exceeded = vec![ICMP_TIME_EXCEEDED, 0, 0, 0, 0, 0, 0, 0];
+        exceeded.extend(ipv4(Ipv4Addr::new(10, 0, 0, 1), &probe[..8]));
+        assert_eq!(
+            parse_reply(&ipv4(router, &exceeded), 42, 3),
+            Some(Answer::TimeExceeded(router))
+        );
+        assert_eq!(parse_reply(&ipv4(router, &exceeded), 43, 3), None);
+
+        let mut unreachable = exceeded.clone();
+        unreachable[0] = ICMP_DEST_UNREACHABLE;
+        assert_eq!(
+            parse_reply(&ipv4(router, &unreachable), 42, 3),
+            Some(Answer::Unreachable(router))
+        );
+
+        // Our own outgoing request, seen by the raw socket on loopback
+        assert_eq!(parse_reply(&ipv4(target, &probe), 42, 3), None);
+        assert_eq!(parse_reply(&[0x45, 0, 0], 42, 3), None);
+    }
+
+    #[test]
+    fn test_hop_stats() {
+        let hop = Hop {
+            ttl: 1,
+            address: None,
+            rtts_ms: vec![Some(1.0), None, Some(3.0), None],
+            reached: false,
+        };
+        assert_eq!(hop.avg_ms(), Some(2.0));
+        assert!((hop.loss() - 0.5).abs() < f64::EPSILON);
+    }
+}
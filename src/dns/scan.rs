@@ -0,0 +1,201 @@
+//! Discovery of DNS servers in an address range.
+//!
+//! Every address of the range gets one small UDP query, and whatever
+//! answers counts as a DNS server: even a `REFUSED` response shows that
+//! something listens on the port. Queries run concurrently with a short
+//! timeout, so a /24 takes a few timeouts rather than hundreds. Addresses
+//! that answer with recursion available and `NOERROR` are resolvers that
+//! can be added to the DNS list.
+
+use crate::dns::query::{Exchange, NetworkExchange, DNS_PORT};
+use futures::StreamExt;
+use hickory_resolver::proto::rr::RecordType;
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default timeout for each address.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Default number of addresses queried at the same time.
+pub const DEFAULT_CONCURRENCY: usize = 64;
+
+/// Name queried: the root NS set, which every resolver has cached.
+const SCAN_NAME: &str = ".";
+
+/// A DNS server found by a scan.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScanHit {
+    /// Address that answered
+    pub ip: IpAddr,
+    /// Time until the response arrived in milliseconds
+    pub latency_ms: f64,
+    /// Response code, e.g. `NoError` or `Refused`
+    pub rcode: String,
+    /// Whether the server offers recursion (the RA flag)
+    pub recursion_available: bool,
+}
+
+impl ScanHit {
+    /// Whether the server resolved the query for us, i.e. is usable as a
+    /// resolver.
+    #[must_use]
+    pub fn is_resolver(&self) -> bool {
+        self.recursion_available && self.rcode == "NoError"
+    }
+}
+
+/// Scanner for DNS servers in an address range.
+///
+/// # Example
+///
+/// ```ignore
+/// let ips = dnstest::config::expand_cidr("192.168.1.0/24")?;
+/// let hits = Scanner::new().scan(&ips, |_, _| {}).await;
+/// ```
+pub struct Scanner {
+    /// Timeout for each address
+    timeout: Duration,
+    /// Number of addresses queried at the same time
+    concurrency: usize,
+    /// Port queried
+    port: u16,
+    exchange: Arc<dyn Exchange>,
+}
+
+impl Scanner {
+    /// Create a scanner with default settings.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_settings(DEFAULT_TIMEOUT, DEFAULT_CONCURRENCY)
+    }
+
+    /// Create a scanner with custom settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Timeout for each address
+    /// * `concurrency` - Number of addresses queried at the same time
+    #[must_use]
+    pub fn with_settings(timeout: Duration, concurrency: usize) -> Self {
+        Self {
+            timeout,
+            concurrency: concurrency.max(1),
+            port: DNS_PORT,
+            exchange: Arc::new(NetworkExchange),
+        }
+    }
+
+    /// Query this port instead of 53.
+    #[must_use]
+    pub const fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Send the queries through `exchange` instead of the network.
+    #[must_use]
+    pub fn with_exchange(mut self, exchange: Arc<dyn Exchange>) -> Self {
+        self.exchange = exchange;
+        self
+    }
+
+    /// Query every address and return those that answered, ordered by
+    /// address.
+    ///
+    /// `progress` is called with the number of finished and total
+    /// addresses after each one.
+    pub async fn scan(&self, ips: &[IpAddr], progress: impl Fn(usize, usize)) -> Vec<ScanHit> {
+        let total = ips.len();
+        let mut pending = futures::stream::iter(ips)
+            .map(|&ip| self.probe(ip))
+            .buffer_unordered(self.concurrency);
+
+        let mut hits = Vec::new();
+        let mut done = 0;
+        while let Some(hit) = pending.next().await {
+            done += 1;
+            progress(done, total);
+            hits.extend(hit);
+        }
+        hits.sort_by_key(|hit| hit.ip);
+        hits
+    }
+
+    /// Query one address.
+    async fn probe(&self, ip: IpAddr) -> Option<ScanHit> {
+        let server = SocketAddr::new(ip, self.port);
+        let responses = match self
+            .exchange
+            .udp(server, SCAN_NAME, RecordType::NS, self.timeout, None)
+            .await
+        {
+            Ok(responses) => responses,
+            Err(e) => {
+                tracing::debug!("Scan query to {server} failed: {e}");
+                return None;
+            }
+        };
+        let response = responses.into_iter().next()?;
+        Some(ScanHit {
+            ip,
+            latency_ms: response.elapsed.as_secs_f64() * 1000.0,
+            // Mnemonic as in RFC 1035, e.g. `NoError` rather than "No Error"
+            rcode: format!("{:?}", response.message.response_code()),
+            recursion_available: response.message.recursion_available(),
+        })
+    }
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::query::fake::{FakeExchange, Query, Reply};
+    use hickory_resolver::proto::op::ResponseCode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A network where 192.168.1.1 is a resolver, .2 refuses queries
+    /// and nothing else answers.
+    fn fake_network() -> FakeExchange {
+        FakeExchange::new()
+            .on(
+                Query::any().server([192, 168, 1, 1]),
+                Reply::empty().recursion_available().with_elapsed(3),
+            )
+            .on(
+                Query::any().server([192, 168, 1, 2]),
+                Reply::rcode(ResponseCode::Refused),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_scan() {
+        let ips = crate::config::expand_cidr("192.168.1.0/29").unwrap();
+        let scanner =
+            Scanner::with_settings(DEFAULT_TIMEOUT, 2).with_exchange(Arc::new(fake_network()));
+        let calls = AtomicUsize::new(0);
+        let hits = scanner
+            .scan(&ips, |done, total| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                assert!(done <= total);
+                assert_eq!(total, 6);
+            })
+            .await;
+        assert_eq!(calls.into_inner(), 6);
+
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].ip, "192.168.1.1".parse::<IpAddr>().unwrap());
+        assert!(hits[0].is_resolver());
+        assert!((hits[0].latency_ms - 3.0).abs() < 1e-9);
+        assert_eq!(hits[1].ip, "192.168.1.2".parse::<IpAddr>().unwrap());
+        assert_eq!(hits[1].rcode, "Refused");
+        assert!(!hits[1].is_resolver());
+    }
+}
@@ -0,0 +1,203 @@
+//! Source interface and address of outgoing probes.
+//!
+//! On a multi-homed host (say a VPN next to the LAN) the routing table
+//! picks one path per destination. Binding the probe sockets to an
+//! interface or a source address measures a chosen path instead. The
+//! binding is set once per process with [`set`] and applied wherever ICMP
+//! and DNS sockets are opened. On Linux the sockets can also carry a
+//! firewall mark, which policy routing rules (`ip rule add fwmark ...`)
+//! match to send probes through another table or a VPN.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::dns::query::DNS_PORT;
+use crate::error::{Error, Result};
+#[cfg(target_os = "linux")]
+use socket2::SockRef;
+use socket2::{Domain, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::OnceLock;
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+
+/// Interface and address that probe sockets are bound to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceBinding {
+    /// Network interface name, e.g. `eth0` (Linux only)
+    pub interface: Option<String>,
+    /// Local address packets are sent from
+    pub address: Option<IpAddr>,
+    /// Firewall mark (`SO_MARK`) set on sent packets (Linux only)
+    pub mark: Option<u32>,
+}
+
+/// Binding of this process, if set.
+static SOURCE: OnceLock<SourceBinding> = OnceLock::new();
+
+/// Bind all later probe sockets as given.
+///
+/// Only the first call takes effect. Binding to an interface needs Linux
+/// (`SO_BINDTODEVICE`) and, for most interfaces, `CAP_NET_RAW`; a mark
+/// needs Linux and `CAP_NET_ADMIN`.
+pub fn set(binding: SourceBinding) -> Result<()> {
+    if binding.interface.is_some() && !cfg!(target_os = "linux") {
+        return Err(Error::config("--interface is only supported on Linux"));
+    }
+    if binding.mark.is_some() && !cfg!(target_os = "linux") {
+        return Err(Error::config("--fwmark is only supported on Linux"));
+    }
+    let _ = SOURCE.set(binding);
+    Ok(())
+}
+
+/// Binding of this process, if any.
+pub fn current() -> Option<&'static SourceBinding> {
+    SOURCE.get()
+}
+
+/// Parse a firewall mark, in hexadecimal with a `0x` prefix or decimal.
+pub fn parse_mark(value: &str) -> std::result::Result<u32, String> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| format!("invalid mark '{value}', expected e.g. 0x100 or 256"))
+}
+
+impl SourceBinding {
+    /// Local address to bind for talking to `peer`.
+    ///
+    /// Without a source address this is the unspecified address of
+    /// `peer`'s family, letting the routing table choose.
+    pub fn local_addr(&self, peer: IpAddr) -> io::Result<SocketAddr> {
+        match (self.address, peer) {
+            (None, IpAddr::V4(_)) => Ok((Ipv4Addr::UNSPECIFIED, 0).into()),
+            (None, IpAddr::V6(_)) => Ok((Ipv6Addr::UNSPECIFIED, 0).into()),
+            (Some(address), peer) if address.is_ipv4() == peer.is_ipv4() => Ok((address, 0).into()),
+            (Some(address), peer) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("source address {address} cannot reach {peer}"),
+            )),
+        }
+    }
+
+    /// Interface name as `SO_BINDTODEVICE` expects it.
+    #[cfg(target_os = "linux")]
+    fn device(&self) -> Option<&[u8]> {
+        self.interface.as_deref().map(str::as_bytes)
+    }
+
+    /// Bind `socket` to the interface and set the mark, if given.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn apply(&self, socket: &SockRef<'_>) -> io::Result<()> {
+        if let Some(device) = self.device() {
+            socket.bind_device(Some(device))?;
+        }
+        if let Some(mark) = self.mark {
+            socket.set_mark(mark)?;
+        }
+        Ok(())
+    }
+}
+
+/// Local address that packets to `peer` leave from, as chosen by the
+/// binding set with [`set`] and the routing table.
+///
+/// Found by connecting a UDP socket, which sends nothing. Returns `None`
+/// if there is no route to `peer`.
+#[must_use]
+pub fn route_source(peer: IpAddr) -> Option<IpAddr> {
+    let binding = current().cloned().unwrap_or_default();
+    let socket = Socket::new(
+        Domain::for_address(SocketAddr::new(peer, 0)),
+        Type::DGRAM,
+        None,
+    )
+    .ok()?;
+    #[cfg(target_os = "linux")]
+    binding.apply(&SockRef::from(&socket)).ok()?;
+    socket.bind(&binding.local_addr(peer).ok()?.into()).ok()?;
+    socket
+        .connect(&SocketAddr::new(peer, DNS_PORT).into())
+        .ok()?;
+    socket
+        .local_addr()
+        .ok()?
+        .as_socket()
+        .map(|local| local.ip())
+}
+
+/// Open a UDP socket for talking to `peer`, bound as set with [`set`].
+pub(crate) async fn udp_socket(peer: SocketAddr) -> io::Result<UdpSocket> {
+    let binding = current().cloned().unwrap_or_default();
+    let socket = UdpSocket::bind(binding.local_addr(peer.ip())?).await?;
+    #[cfg(target_os = "linux")]
+    binding.apply(&SockRef::from(&socket))?;
+    Ok(socket)
+}
+
+/// Connect a TCP stream to `peer`, bound as set with [`set`].
+pub(crate) async fn tcp_connect(peer: SocketAddr) -> io::Result<TcpStream> {
+    let Some(binding) = current() else {
+        return TcpStream::connect(peer).await;
+    };
+    let socket = if peer.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    #[cfg(target_os = "linux")]
+    binding.apply(&SockRef::from(&socket))?;
+    if binding.address.is_some() {
+        socket.bind(binding.local_addr(peer.ip())?)?;
+    }
+    socket.connect(peer).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_addr() {
+        let v4: IpAddr = "192.0.2.10".parse().unwrap();
+        let peer: IpAddr = "1.1.1.1".parse().unwrap();
+        let peer_v6: IpAddr = "2606:4700::1111".parse().unwrap();
+
+        let unbound = SourceBinding::default();
+        assert_eq!(
+            unbound.local_addr(peer).unwrap(),
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        );
+        assert_eq!(
+            unbound.local_addr(peer_v6).unwrap(),
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        );
+
+        let bound = SourceBinding {
+            address: Some(v4),
+            ..SourceBinding::default()
+        };
+        assert_eq!(bound.local_addr(peer).unwrap(), SocketAddr::new(v4, 0));
+        assert!(bound.local_addr(peer_v6).is_err());
+    }
+
+    #[test]
+    fn test_parse_mark() {
+        assert_eq!(parse_mark("0x100"), Ok(0x100));
+        assert_eq!(parse_mark("0XfF"), Ok(0xff));
+        assert_eq!(parse_mark("256"), Ok(256));
+        assert!(parse_mark("0x").is_err());
+        assert!(parse_mark("mark").is_err());
+        assert!(parse_mark("0x100000000").is_err());
+    }
+
+    #[test]
+    fn test_route_source() {
+        let loopback: IpAddr = "127.0.0.1".parse().unwrap();
+        assert_eq!(route_source(loopback), Some(loopback));
+    }
+}
@@ -1,33 +1,182 @@
 //! DNS speed test using ICMP ping.
 //!
 //! This module provides functionality to test DNS server response times
-//! using ICMP ping (Internet Control Message Protocol).
+//! using ICMP ping (Internet Control Message Protocol). See [`super::icmp`]
+//! for how echo requests are sent on each platform.
 
 #![allow(clippy::missing_panics_doc)]
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::manual_let_else)]
 #![allow(clippy::items_after_statements)]
 
-use crate::dns::types::{DnsServer, SpeedTestResult, TestSummary};
-use crate::error::{Error, Result};
-use std::time::{Duration, Instant};
-use surge_ping::{Client, Config, PingIdentifier, PingSequence};
-use tokio::time::timeout;
+use crate::dns::icmp::{self, DnsQueryProbe, IcmpMethod, PingError, Pinger, Probe};
+use crate::dns::progress::{ProgressEvent, ProgressListener};
+use crate::dns::source;
+use crate::dns::types::{DnsProtocol, DnsServer, ProviderSummary, SpeedTestResult, TestSummary};
+use crate::error::Result;
+use crate::telemetry;
+use hickory_resolver::proto::rr::RecordType;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
 
 /// Default packet size for ping in bytes.
 const DEFAULT_PACKET_SIZE: usize = 32;
 
 /// Default timeout for each ping attempt in seconds.
-const DEFAULT_TIMEOUT_SECS: u64 = 5;
+pub const DEFAULT_TIMEOUT_SECS: u64 = 5;
 
 /// Default number of ping attempts per server.
-const DEFAULT_PING_COUNT: usize = 3;
+pub const DEFAULT_PING_COUNT: usize = 3;
+
+/// What a [`SpeedTester`] measures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TestMethod {
+    /// ICMP ping, falling back to DNS queries where pinging is not
+    /// permitted (Linux only)
+    #[default]
+    Icmp,
+    /// Round trip of a DNS query over UDP to the server's port, see
+    /// [`DnsQueryProbe`]
+    Udp,
+    /// Round trip of a DNS query over TCP to the server's port, including
+    /// the connection setup
+    Tcp,
+}
+
+impl TestMethod {
+    /// Get all available method names.
+    #[must_use]
+    pub fn names() -> &'static [&'static str] {
+        &["icmp", "udp", "tcp"]
+    }
+}
+
+impl std::str::FromStr for TestMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "icmp" | "ping" => Ok(Self::Icmp),
+            "udp" | "dns" => Ok(Self::Udp),
+            "tcp" => Ok(Self::Tcp),
+            _ => Err(format!(
+                "Unknown test method: {}. Valid options are: {:?}",
+                s,
+                Self::names()
+            )),
+        }
+    }
+}
+
+/// Which speed test results to keep.
+///
+/// The default keeps every result.
+///
+/// # Example
+///
+/// ```ignore
+/// let usable = ResultFilter::default().only_success().with_max_latency(50.0);
+/// results.retain(|r| usable.matches(r));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResultFilter {
+    /// Keep only servers that answered
+    pub only_success: bool,
+    /// Keep only servers at most this slow, in milliseconds
+    pub max_latency_ms: Option<f64>,
+    /// Keep only servers losing at least this percentage of probes
+    pub min_loss_percent: Option<f64>,
+}
+
+impl ResultFilter {
+    /// Keep only servers that answered.
+    #[must_use]
+    pub const fn only_success(mut self) -> Self {
+        self.only_success = true;
+        self
+    }
+
+    /// Keep only servers whose latency is at most `ms`.
+    #[must_use]
+    pub const fn with_max_latency(mut self, ms: f64) -> Self {
+        self.max_latency_ms = Some(ms);
+        self
+    }
+
+    /// Keep only servers losing at least `percent` of their probes.
+    #[must_use]
+    pub const fn with_min_loss(mut self, percent: f64) -> Self {
+        self.min_loss_percent = Some(percent);
+        self
+    }
+
+    /// Whether `result` is kept.
+    ///
+    /// A failed server has no latency and is dropped by a latency bound.
+    #[must_use]
+    pub fn matches(&self, result: &SpeedTestResult) -> bool {
+        if self.only_success && !result.success {
+            return false;
+        }
+        if let Some(bound) = self.max_latency_ms {
+            if !result.latency_ms.is_some_and(|ms| ms <= bound) {
+                return false;
+            }
+        }
+        self.min_loss_percent
+            .map_or(true, |percent| result.packet_loss * 100.0 >= percent)
+    }
+
+    /// Keep the results that match.
+    pub fn apply(&self, results: &mut Vec<SpeedTestResult>) {
+        results.retain(|r| self.matches(r));
+    }
+}
+
+/// Shuffle `items` into an order determined by `seed`.
+///
+/// Testing servers in a random order keeps the ones late in a list from
+/// always meeting an uplink congested by those before them. The order
+/// depends on nothing but the seed, so runs with the same seed test the
+/// same list in the same order and can be compared.
+pub fn shuffle<T>(items: &mut [T], seed: u64) {
+    // SplitMix64, which is small and well distributed even for seeds
+    // like 1, 2, 3
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    // Fisher-Yates
+    for i in (1..items.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// A seed for [`shuffle`] that differs between runs.
+#[must_use]
+pub fn random_seed() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    hasher.finish()
+}
 
 /// DNS speed tester.
 ///
 /// This struct provides methods to test DNS server response times
-/// using ICMP ping. It requires appropriate permissions to send
-/// ICMP packets (typically needs root or raw socket access).
+/// using ICMP ping. Unprivileged ICMP is used where the platform
+/// supports it; otherwise raw socket access is needed.
 ///
 /// # Example
 ///
@@ -37,9 +186,11 @@ const DEFAULT_PING_COUNT: usize = 3;
 /// let result = tester.test_latency(&server).await;
 /// ```
 pub struct SpeedTester {
-    client: Client,
+    pinger: Box<dyn Probe>,
     timeout: Duration,
     ping_count: usize,
+    /// Measures servers the pinger gets no answer from over TCP, if set.
+    tcp_fallback: Option<DnsQueryProbe>,
 }
 
 impl SpeedTester {
@@ -50,14 +201,10 @@ impl SpeedTester {
     /// Returns an error if the ICMP client cannot be initialized
     /// (e.g., due to insufficient permissions or system limitations).
     pub fn new() -> Result<Self> {
-        let config = Config::default();
-        let client = Client::new(&config).map_err(|e| Error::Network(e.to_string()))?;
-
-        Ok(Self {
-            client,
-            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
-            ping_count: DEFAULT_PING_COUNT,
-        })
+        Self::with_settings(
+            Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            DEFAULT_PING_COUNT,
+        )
     }
 
     /// Create a new `SpeedTester` with custom settings.
@@ -71,16 +218,108 @@ impl SpeedTester {
     ///
     /// Returns an error if the ICMP client cannot be initialized.
     pub fn with_settings(timeout: Duration, ping_count: usize) -> Result<Self> {
-        let config = Config::default();
-        let client = Client::new(&config).map_err(|e| Error::Network(e.to_string()))?;
+        Self::with_query_type(timeout, ping_count, icmp::DEFAULT_PROBE_TYPE)
+    }
 
-        Ok(Self {
-            client,
+    /// Create a new `SpeedTester` with custom settings whose DNS-query
+    /// probe, used where ICMP is not permitted, asks for `query_type`
+    /// records. Middleboxes may treat record types differently, so this
+    /// measures the type that matters, e.g. `TXT` or `HTTPS`.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Timeout for each ping attempt
+    /// * `ping_count` - Number of ping attempts per server
+    /// * `query_type` - Record type of the DNS-query probe
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ICMP client cannot be initialized.
+    pub fn with_query_type(
+        timeout: Duration,
+        ping_count: usize,
+        query_type: RecordType,
+    ) -> Result<Self> {
+        Self::with_method(
             timeout,
             ping_count,
+            TestMethod::Icmp,
+            DnsQueryProbe::new(icmp::DEFAULT_PROBE_NAME, query_type),
+        )
+    }
+
+    /// Create a new `SpeedTester` measuring with `method`.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Timeout for each attempt
+    /// * `ping_count` - Number of attempts per server
+    /// * `method` - ICMP ping or DNS queries
+    /// * `query` - The DNS query sent with [`TestMethod::Udp`] and
+    ///   [`TestMethod::Tcp`], and where ICMP is not permitted
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `method` is ICMP and the ICMP client cannot be
+    /// initialized.
+    pub fn with_method(
+        timeout: Duration,
+        ping_count: usize,
+        method: TestMethod,
+        query: DnsQueryProbe,
+    ) -> Result<Self> {
+        Ok(match method {
+            TestMethod::Icmp => Self::with_probe(Pinger::open(query)?, timeout, ping_count),
+            TestMethod::Udp => Self::with_probe(query, timeout, ping_count),
+            TestMethod::Tcp => Self::with_probe(query.over_tcp(), timeout, ping_count),
         })
     }
 
+    /// Measure servers that answer none of the pings with DNS queries
+    /// over TCP instead, so that networks dropping ICMP do not show every
+    /// server as timed out. Results say which method measured them.
+    #[must_use]
+    pub fn with_tcp_fallback(mut self, query: DnsQueryProbe) -> Self {
+        self.tcp_fallback = Some(query.over_tcp());
+        self
+    }
+
+    /// Create a `SpeedTester` sending echo requests through `probe`
+    /// instead of the ICMP backend, e.g. to test without a network.
+    ///
+    /// # Arguments
+    ///
+    /// * `probe` - Sends the echo requests
+    /// * `timeout` - Timeout for each ping attempt
+    /// * `ping_count` - Number of ping attempts per server
+    #[must_use]
+    pub fn with_probe(probe: impl Probe + 'static, timeout: Duration, ping_count: usize) -> Self {
+        Self {
+            pinger: Box::new(probe),
+            timeout,
+            ping_count,
+            tcp_fallback: None,
+        }
+    }
+
+    /// Number of pings sent to each server.
+    #[must_use]
+    pub const fn ping_count(&self) -> usize {
+        self.ping_count
+    }
+
+    /// How long each ping waits for its reply.
+    #[must_use]
+    pub const fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// How this tester sends echo requests.
+    #[must_use]
+    pub fn icmp_method(&self) -> IcmpMethod {
+        self.pinger.method()
+    }
+
     /// Test latency to a single DNS server using ICMP ping.
     ///
     /// Performs multiple ping attempts and calculates the average latency.
@@ -92,7 +331,34 @@ impl SpeedTester {
     /// # Returns
     ///
     /// Returns a `SpeedTestResult` containing the test outcome.
+    #[tracing::instrument(
+        skip_all,
+        fields(
+            server.name = %server.name,
+            server.address = %server.ip,
+            latency_ms = tracing::field::Empty,
+            success = tracing::field::Empty,
+        )
+    )]
     pub async fn test_latency(&self, server: &DnsServer) -> SpeedTestResult {
+        let result = self
+            .ping_server(server)
+            .await
+            .with_settings(self.ping_count, self.timeout);
+
+        let span = tracing::Span::current();
+        if let Some(latency) = result.latency_ms {
+            span.record("latency_ms", latency);
+        }
+        span.record("success", result.success);
+        telemetry::record_probe(&result);
+
+        result
+    }
+
+    /// Ping a server `ping_count` times and aggregate the replies, falling
+    /// back to DNS queries over TCP if none arrived and that is enabled.
+    async fn ping_server(&self, server: &DnsServer) -> SpeedTestResult {
         let ip = match server.ip_addr() {
             Some(ip) => ip,
             None => {
@@ -100,50 +366,99 @@ impl SpeedTester {
             }
         };
 
-        // Skip IPv6 for now as it requires special handling
-        if ip.is_ipv6() {
+        let result = self.measure(&*self.pinger, server, ip).await;
+        let result = match &self.tcp_fallback {
+            Some(fallback) if !result.success => {
+                tracing::debug!("No reply from {ip}, measuring DNS queries over TCP");
+                let retried = self.measure(fallback, server, ip).await;
+                if retried.success {
+                    retried
+                } else {
+                    result
+                }
+            }
+            _ => result,
+        };
+        result.with_source_ip(source::route_source(ip))
+    }
+
+    /// Send `ping_count` requests through `probe` and aggregate the
+    /// replies.
+    async fn measure(&self, probe: &dyn Probe, server: &DnsServer, ip: IpAddr) -> SpeedTestResult {
+        // Skip IPv6 pings for now as they require special handling; DNS
+        // queries work over either family
+        if ip.is_ipv6() && !probe.method().is_dns_query() {
             return SpeedTestResult::failure(server.clone(), "IPv6 not supported yet");
         }
+        // DNS-query probes speak plain DNS, which would measure the wrong
+        // service on a TLS or HTTPS port
+        if probe.method().is_dns_query() && server.protocol != DnsProtocol::Plain {
+            return SpeedTestResult::failure(
+                server.clone(),
+                format!(
+                    "{} cannot be measured with plain DNS queries, use --method icmp",
+                    server.spec()
+                ),
+            );
+        }
+        let target = SocketAddr::new(ip, server.port());
 
         let payload = [0u8; DEFAULT_PACKET_SIZE];
         let mut latencies = Vec::new();
+        let mut attempts = Vec::with_capacity(self.ping_count);
+        // Wall-clock time beyond the reply's own round trip
+        let mut delays = Vec::new();
         let mut success_count = 0;
+        let mut last_error = PingError::Timeout;
+        let mut ttl = None;
+        // One identifier per series, so replies match by sequence number
+        let ident = icmp::next_ident();
 
         for seq in 0..self.ping_count {
-            let mut pinger = self.client.pinger(ip, PingIdentifier(rand_id())).await;
-
-            pinger.timeout(self.timeout);
-
-            let start = Instant::now();
-            let result = timeout(
-                self.timeout,
-                pinger.ping(PingSequence(seq as u16), &payload),
-            )
-            .await;
+            let result = probe
+                .ping(target, ident, seq as u16, &payload, self.timeout)
+                .await;
 
             match result {
-                Ok(Ok(_response)) => {
-                    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
-                    latencies.push(elapsed);
+                Ok(echo) => {
+                    latencies.push(echo.rtt.as_secs_f64() * 1000.0);
+                    attempts.push(Some(echo.rtt.as_secs_f64() * 1000.0));
+                    if let Some(elapsed) = echo.elapsed {
+                        let delay = elapsed.saturating_sub(echo.rtt);
+                        tracing::trace!(
+                            "Ping {ip} seq {seq}: reply {:?}, wall clock {elapsed:?}",
+                            echo.rtt
+                        );
+                        delays.push(delay.as_secs_f64() * 1000.0);
+                    }
+                    ttl = echo.ttl.or(ttl);
                     success_count += 1;
                 }
-                Ok(Err(e)) => {
+                Err(e) => {
                     tracing::debug!("Ping error for {ip}: {e}");
-                }
-                Err(_) => {
-                    // Timeout
+                    attempts.push(None);
+                    last_error = e;
                 }
             }
         }
 
         let packet_loss = 1.0 - (success_count as f64 / self.ping_count as f64);
 
-        if success_count > 0 {
+        let result = if success_count > 0 {
             let avg_latency = latencies.iter().sum::<f64>() / latencies.len() as f64;
-            SpeedTestResult::success(server.clone(), avg_latency, packet_loss)
+            let result = SpeedTestResult::success(server.clone(), avg_latency, packet_loss)
+                .with_samples(latencies)
+                .with_scheduling_delay(&delays);
+            match ttl {
+                Some(ttl) => result.with_ttl(ttl),
+                None => result,
+            }
         } else {
-            SpeedTestResult::failure(server.clone(), "timeout")
-        }
+            // "timeout" unless something more telling, like a permission
+            // error, went wrong
+            SpeedTestResult::failure(server.clone(), last_error.to_string())
+        };
+        result.with_attempts(attempts).with_method(probe.method())
     }
 
     /// Test multiple DNS servers sequentially.
@@ -151,36 +466,56 @@ impl SpeedTester {
     /// # Arguments
     ///
     /// * `servers` - Slice of DNS servers to test
-    /// * `progress_callback` - Optional callback for progress updates
+    /// * `progress` - Receives the [`ProgressEvent`]s of the run
     ///
     /// # Returns
     ///
     /// Returns a vector of test results.
+    #[tracing::instrument(skip_all, fields(servers = servers.len()))]
     pub async fn test_all(
         &self,
         servers: &[DnsServer],
-        progress_callback: Option<impl Fn(usize, usize, &DnsServer)>,
+        progress: Option<&dyn ProgressListener>,
     ) -> Vec<SpeedTestResult> {
         let total = servers.len();
         let mut results = Vec::with_capacity(total);
+        let emit = |event| {
+            if let Some(progress) = progress {
+                progress.on_event(event);
+            }
+        };
 
         // Process in batches to avoid overwhelming the network
         const BATCH_SIZE: usize = 20;
+        const BATCH_DELAY: Duration = Duration::from_millis(100);
 
+        emit(ProgressEvent::Started { total });
         for (idx, server) in servers.iter().enumerate() {
-            if let Some(ref cb) = progress_callback {
-                cb(idx, total, server);
-            }
-
             let result = self.test_latency(server).await;
+            if progress.is_some() {
+                emit(ProgressEvent::ServerDone {
+                    done: idx + 1,
+                    total,
+                    result: Box::new(result.clone()),
+                });
+            }
             results.push(result);
 
             // Small delay between batches
-            if (idx + 1) % BATCH_SIZE == 0 {
-                tokio::time::sleep(Duration::from_millis(100)).await;
+            if (idx + 1) % BATCH_SIZE == 0 && idx + 1 < total {
+                emit(ProgressEvent::BatchDelay {
+                    done: idx + 1,
+                    delay: BATCH_DELAY,
+                });
+                tokio::time::sleep(BATCH_DELAY).await;
             }
         }
 
+        emit(ProgressEvent::Finished {
+            total,
+            succeeded: results.iter().filter(|r| r.success).count(),
+        });
+
         results
     }
 
@@ -201,6 +536,76 @@ impl SpeedTester {
         }
         summary
     }
+
+    /// Summarize results per provider (see [`DnsServer::provider_name`]),
+    /// fastest provider first.
+    #[must_use]
+    pub fn summarize_by_provider(results: &[SpeedTestResult]) -> Vec<ProviderSummary> {
+        let mut groups: Vec<(&str, Vec<&SpeedTestResult>)> = Vec::new();
+        for result in results {
+            let provider = result.server.provider_name();
+            match groups.iter_mut().find(|(name, _)| *name == provider) {
+                Some((_, members)) => members.push(result),
+                None => groups.push((provider, vec![result])),
+            }
+        }
+
+        let mut summaries: Vec<ProviderSummary> = groups
+            .into_iter()
+            .map(|(provider, members)| {
+                let answered: Vec<&SpeedTestResult> = members
+                    .iter()
+                    .copied()
+                    .filter(|r| r.success && r.latency_ms.is_some())
+                    .collect();
+                let best = answered.iter().min_by(|a, b| {
+                    a.latency_ms
+                        .partial_cmp(&b.latency_ms)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                #[allow(clippy::cast_precision_loss)]
+                let avg_ms = (!answered.is_empty()).then(|| {
+                    answered.iter().filter_map(|r| r.latency_ms).sum::<f64>()
+                        / answered.len() as f64
+                });
+                ProviderSummary {
+                    provider: provider.to_string(),
+                    servers: members.len(),
+                    success: answered.len(),
+                    best_ms: best.and_then(|r| r.latency_ms),
+                    best_ip: best.map(|r| r.server.ip.clone()),
+                    avg_ms,
+                }
+            })
+            .collect();
+        summaries.sort_by(|a, b| {
+            a.best_ms
+                .unwrap_or(f64::MAX)
+                .partial_cmp(&b.best_ms.unwrap_or(f64::MAX))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        summaries
+    }
+
+    /// Replace results of `previous` with those of the same servers in
+    /// `retested`, as after retesting the failed servers of a run.
+    ///
+    /// The order of `previous` is kept; retested servers it does not
+    /// contain are appended.
+    #[must_use]
+    pub fn merge_retest(
+        mut previous: Vec<SpeedTestResult>,
+        retested: Vec<SpeedTestResult>,
+    ) -> Vec<SpeedTestResult> {
+        for result in retested {
+            let spec = result.server.spec();
+            match previous.iter_mut().find(|r| r.server.spec() == spec) {
+                Some(slot) => *slot = result,
+                None => previous.push(result),
+            }
+        }
+        previous
+    }
 }
 
 impl Default for SpeedTester {
@@ -209,39 +614,276 @@ impl Default for SpeedTester {
     }
 }
 
-/// Generate a random ping identifier.
-fn rand_id() -> u16 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let nanos = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .subsec_nanos();
-    (nanos % 65536) as u16
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dns::icmp::Echo;
+    use futures::future::BoxFuture;
+    use std::net::IpAddr;
 
-    #[tokio::test]
-    async fn test_ping_localhost() {
-        // This test requires ICMP socket permissions which are not available in CI
-        // Skip if CI environment variable is set
-        if std::env::var("CI").is_ok() {
-            return;
+    #[test]
+    fn test_summarize_by_provider() {
+        let results = vec![
+            SpeedTestResult::success(DnsServer::new("Quad9", "9.9.9.9"), 30.0, 0.0),
+            SpeedTestResult::success(
+                DnsServer::new("Google A", "8.8.8.8").with_provider("Google"),
+                20.0,
+                0.0,
+            ),
+            SpeedTestResult::failure(
+                DnsServer::new("Google B", "8.8.4.4").with_provider("Google"),
+                "timeout",
+            ),
+            SpeedTestResult::success(
+                DnsServer::new("Google A v6", "2001:4860:4860::8888").with_provider("Google"),
+                10.0,
+                0.0,
+            ),
+            SpeedTestResult::success(DnsServer::new("Cloudflare #1", "1.1.1.1"), 40.0, 0.0),
+            SpeedTestResult::success(DnsServer::new("Cloudflare #2", "1.0.0.1"), 50.0, 0.0),
+        ];
+
+        let summaries = SpeedTester::summarize_by_provider(&results);
+        let providers: Vec<_> = summaries.iter().map(|s| s.provider.as_str()).collect();
+        assert_eq!(providers, ["Google", "Quad9", "Cloudflare"]);
+        assert_eq!(summaries[0].servers, 3);
+        assert_eq!(summaries[0].success, 2);
+        assert_eq!(summaries[0].best_ms, Some(10.0));
+        assert_eq!(
+            summaries[0].best_ip.as_deref(),
+            Some("2001:4860:4860::8888")
+        );
+        assert_eq!(summaries[0].avg_ms, Some(15.0));
+        assert_eq!(summaries[2].servers, 2);
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let order = |seed| {
+            let mut items: Vec<u32> = (0..8).collect();
+            shuffle(&mut items, seed);
+            items
+        };
+        // Pinned, so that a seed keeps meaning the same order
+        assert_eq!(order(42), [3, 1, 6, 2, 4, 0, 7, 5]);
+        assert_ne!(order(1), order(2));
+
+        let mut sorted = order(7);
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_result_filter() {
+        let mut results = vec![
+            SpeedTestResult::success(DnsServer::new("Fast", "1.1.1.1"), 10.0, 0.0),
+            SpeedTestResult::success(DnsServer::new("Lossy", "8.8.8.8"), 20.0, 1.0 / 3.0),
+            SpeedTestResult::success(DnsServer::new("Slow", "9.9.9.9"), 90.0, 0.0),
+            SpeedTestResult::failure(DnsServer::new("Dead", "10.0.0.1"), "timeout"),
+        ];
+        let names = |results: &[SpeedTestResult]| {
+            results
+                .iter()
+                .map(|r| r.server.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut kept = results.clone();
+        ResultFilter::default().apply(&mut kept);
+        assert_eq!(kept.len(), 4);
+
+        let mut kept = results.clone();
+        ResultFilter::default().only_success().apply(&mut kept);
+        assert_eq!(names(&kept), ["Fast", "Lossy", "Slow"]);
+
+        let mut kept = results.clone();
+        ResultFilter::default()
+            .with_max_latency(50.0)
+            .apply(&mut kept);
+        assert_eq!(names(&kept), ["Fast", "Lossy"]);
+
+        ResultFilter::default()
+            .only_success()
+            .with_min_loss(30.0)
+            .apply(&mut results);
+        assert_eq!(names(&results), ["Lossy"]);
+    }
+
+    #[test]
+    fn test_merge_retest() {
+        let previous = vec![
+            SpeedTestResult::failure(DnsServer::new("Google", "8.8.8.8"), "timeout"),
+            SpeedTestResult::success(DnsServer::new("Quad9", "9.9.9.9"), 30.0, 0.0),
+        ];
+        let retested = vec![SpeedTestResult::success(
+            DnsServer::new("Google", "8.8.8.8"),
+            20.0,
+            0.0,
+        )];
+
+        let merged = SpeedTester::merge_retest(previous, retested);
+        assert_eq!(merged.len(), 2);
+        assert!(merged[0].success);
+        assert_eq!(merged[0].latency_ms, Some(20.0));
+        assert_eq!(merged[1].server.ip, "9.9.9.9");
+
+        // A retest over TLS leaves the plain server on the same address
+        let mut tls = DnsServer::new("Google DoT", "8.8.8.8");
+        tls.protocol = crate::dns::DnsProtocol::Tls;
+        let merged =
+            SpeedTester::merge_retest(merged, vec![SpeedTestResult::failure(tls, "timeout")]);
+        assert_eq!(merged.len(), 3);
+        assert!(merged[0].success);
+        assert_eq!(merged[2].server.spec(), "tls://8.8.8.8");
+    }
+
+    /// Answers every echo request after `rtt_ms` plus the sequence
+    /// number, except those to `lost`, which time out.
+    struct FakeProbe {
+        rtt_ms: u64,
+        lost: IpAddr,
+    }
+
+    impl Probe for FakeProbe {
+        fn method(&self) -> IcmpMethod {
+            IcmpMethod::Datagram
         }
 
-        let tester = SpeedTester::new().unwrap();
-        let server = DnsServer::new("localhost", "127.0.0.1");
-        let result = tester.test_latency(&server).await;
+        fn ping<'a>(
+            &'a self,
+            target: SocketAddr,
+            _ident: u16,
+            seq: u16,
+            _payload: &'a [u8],
+            _timeout: Duration,
+        ) -> BoxFuture<'a, std::result::Result<Echo, PingError>> {
+            let echo = if target.ip() == self.lost {
+                Err(PingError::Timeout)
+            } else {
+                Ok(Echo {
+                    rtt: Duration::from_millis(self.rtt_ms + u64::from(seq)),
+                    ttl: Some(55),
+                    // Two milliseconds of waiting to be scheduled
+                    elapsed: Some(Duration::from_millis(self.rtt_ms + u64::from(seq) + 2)),
+                })
+            };
+            Box::pin(async move { echo })
+        }
+    }
 
-        // Localhost should respond quickly
-        if result.success {
-            assert!(result.latency_ms.is_some());
-            assert!(result.latency_ms.unwrap() < 10.0);
+    #[tokio::test]
+    async fn test_fake_probe() {
+        let tester = SpeedTester::with_probe(
+            FakeProbe {
+                rtt_ms: 10,
+                lost: "192.0.2.1".parse().unwrap(),
+            },
+            Duration::from_secs(1),
+            3,
+        );
+        assert_eq!(tester.icmp_method(), IcmpMethod::Datagram);
+
+        let servers = [
+            DnsServer::new("Cloudflare", "1.1.1.1"),
+            DnsServer::new("Lost", "192.0.2.1"),
+            DnsServer::new("Cloudflare v6", "2606:4700:4700::1111"),
+        ];
+        let events = std::sync::Mutex::new(Vec::new());
+        let record = |event: ProgressEvent| events.lock().unwrap().push(event);
+        let results = tester.test_all(&servers, Some(&record)).await;
+        let events = events.into_inner().unwrap();
+        assert!(matches!(events[0], ProgressEvent::Started { total: 3 }));
+        assert!(matches!(
+            &events[2],
+            ProgressEvent::ServerDone { done: 2, result, .. } if result.is_timeout()
+        ));
+        assert!(matches!(
+            events[4],
+            ProgressEvent::Finished {
+                total: 3,
+                succeeded: 1
+            }
+        ));
+
+        assert!(results[0].success);
+        assert_eq!(results[0].latency_ms, Some(11.0));
+        assert_eq!(results[0].samples_ms, [10.0, 11.0, 12.0]);
+        assert_eq!(results[0].samples, [Some(10.0), Some(11.0), Some(12.0)]);
+        assert_eq!(results[1].samples, [None; 3]);
+        // Replies are timed by their own timestamps, without the wait
+        assert!((results[0].scheduling_ms.unwrap() - 2.0).abs() < 1e-9);
+        assert!(results[0].packet_loss.abs() < f64::EPSILON);
+        assert_eq!(results[0].hops, Some(9));
+        assert_eq!(
+            (results[0].count, results[0].timeout_ms),
+            (Some(3), Some(1000))
+        );
+
+        assert!(results[1].is_timeout());
+        assert!((results[1].packet_loss - 1.0).abs() < f64::EPSILON);
+
+        assert!(!results[2].success);
+    }
+
+    /// Answers every DNS query at once, keeping where it was sent.
+    #[derive(Default)]
+    struct QueryProbe {
+        targets: std::sync::Arc<std::sync::Mutex<Vec<SocketAddr>>>,
+    }
+
+    impl Probe for QueryProbe {
+        fn method(&self) -> IcmpMethod {
+            IcmpMethod::DnsQuery
+        }
+
+        fn ping<'a>(
+            &'a self,
+            target: SocketAddr,
+            _ident: u16,
+            _seq: u16,
+            _payload: &'a [u8],
+            _timeout: Duration,
+        ) -> BoxFuture<'a, std::result::Result<Echo, PingError>> {
+            self.targets.lock().unwrap().push(target);
+            Box::pin(async {
+                Ok(Echo {
+                    rtt: Duration::from_millis(1),
+                    ttl: None,
+                    elapsed: None,
+                })
+            })
         }
     }
 
+    #[tokio::test]
+    async fn test_query_probe_port() {
+        let probe = QueryProbe::default();
+        let targets = probe.targets.clone();
+        let tester = SpeedTester::with_probe(probe, Duration::from_secs(1), 1);
+
+        let servers = crate::config::ConfigLoader::from_args(vec![
+            "127.0.0.1:5353".into(),
+            "127.0.0.1".into(),
+            "tls://127.0.0.1".into(),
+        ])
+        .unwrap()
+        .servers;
+        let results = tester.test_all(&servers, None).await;
+        assert!(results[0].success && results[1].success);
+        assert!(results[2]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("tls://127.0.0.1"));
+        assert_eq!(
+            *targets.lock().unwrap(),
+            [
+                "127.0.0.1:5353".parse::<SocketAddr>().unwrap(),
+                "127.0.0.1:53".parse().unwrap()
+            ]
+        );
+    }
+
     #[test]
     fn test_speedtest_result() {
         let server = DnsServer::new("Test", "8.8.8.8");
@@ -3,8 +3,20 @@
 //! This module provides the core types used for DNS server representation,
 //! test results, and pollution detection results.
 
+use crate::dns::bogon;
+use crate::dns::censorship::CensorshipSignal;
+use crate::dns::egress::Egress;
+use crate::dns::icmp::IcmpMethod;
+use crate::dns::rdap::RdapRecord;
+use crate::error::{Error, Result};
+use crate::http::HttpVersion;
+use crate::plugin::{PluginVerdict, Verdict};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::net::IpAddr;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
 
 /// DNS server information.
 ///
@@ -23,6 +35,21 @@ pub struct DnsServer {
     /// Current status of the server
     #[serde(default)]
     pub status: DnsStatus,
+    /// Operator of the server (e.g. "Google"), grouping its addresses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Two-letter country code where the server is hosted (e.g. "CN")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// Port the server listens on, if not the protocol's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Protocol the server is queried over
+    #[serde(default, skip_serializing_if = "DnsProtocol::is_plain")]
+    pub protocol: DnsProtocol,
+    /// Kept in the list but left out of tests
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
 }
 
 impl DnsServer {
@@ -44,9 +71,45 @@ impl DnsServer {
             ip: ip.into(),
             delay: None,
             status: DnsStatus::Pending,
+            provider: None,
+            country: None,
+            port: None,
+            protocol: DnsProtocol::Plain,
+            disabled: false,
         }
     }
 
+    /// Set the provider.
+    #[must_use]
+    pub fn with_provider(mut self, provider: impl Into<String>) -> Self {
+        self.provider = Some(provider.into());
+        self
+    }
+
+    /// Take the latency and status measured by `result`.
+    pub fn update_from(&mut self, result: &SpeedTestResult) {
+        self.delay = result.latency_ms;
+        self.status = result.status();
+    }
+
+    /// Whether the server's country is one of `codes` (case-insensitive).
+    /// Servers without a country match none.
+    #[must_use]
+    pub fn in_country(&self, codes: &[String]) -> bool {
+        self.country
+            .as_deref()
+            .is_some_and(|country| codes.iter().any(|code| code.eq_ignore_ascii_case(country)))
+    }
+
+    /// Provider to group the server under: its `provider`, or else its name
+    /// without the `#N` numbering of duplicate names.
+    #[must_use]
+    pub fn provider_name(&self) -> &str {
+        self.provider
+            .as_deref()
+            .unwrap_or_else(|| unnumbered(&self.name))
+    }
+
     /// Parse the IP address string into an `IpAddr`.
     ///
     /// # Returns
@@ -57,6 +120,44 @@ impl DnsServer {
         self.ip.parse().ok()
     }
 
+    /// Port the server is queried on: its `port`, or else the default
+    /// port of its protocol.
+    #[must_use]
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or_else(|| self.protocol.default_port())
+    }
+
+    /// Address and port the server is queried on.
+    #[must_use]
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        self.ip_addr().map(|ip| SocketAddr::new(ip, self.port()))
+    }
+
+    /// The server as given on the command line, without the name, e.g.
+    /// `tls://[2001:db8::1]:8853`; the protocol and port are only included
+    /// where they are not the defaults.
+    #[must_use]
+    pub fn spec(&self) -> String {
+        let scheme = self
+            .protocol
+            .scheme()
+            .map_or_else(String::new, |scheme| format!("{scheme}://"));
+        match self.port {
+            Some(port) if self.is_ipv6() => format!("{scheme}[{}]:{port}", self.ip),
+            Some(port) => format!("{scheme}{}:{port}", self.ip),
+            None => format!("{scheme}{}", self.ip),
+        }
+    }
+
+    /// Rewrite the address in canonical form, e.g. `2001:4860:4860::8888`
+    /// for `2001:4860:4860:0:0:0:0:8888`, so equal addresses compare
+    /// equal as strings. Addresses that do not parse are left unchanged.
+    pub fn normalize_ip(&mut self) {
+        if let Ok(ip) = self.ip.trim().parse::<IpAddr>() {
+            self.ip = ip.to_string();
+        }
+    }
+
     /// Check if the server uses IPv4.
     #[must_use]
     pub fn is_ipv4(&self) -> bool {
@@ -68,6 +169,35 @@ impl DnsServer {
     pub fn is_ipv6(&self) -> bool {
         self.ip_addr().is_some_and(|ip| ip.is_ipv6())
     }
+
+    /// Check if the server uses the given address family.
+    #[must_use]
+    pub fn in_family(&self, family: IpFamily) -> bool {
+        match family {
+            IpFamily::V4 => self.is_ipv4(),
+            IpFamily::V6 => self.is_ipv6(),
+        }
+    }
+}
+
+/// IP address family, as selected with `--ipv4` / `--ipv6`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpFamily {
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    /// Family selected by a pair of `--ipv4` / `--ipv6` flags, `None` for
+    /// both families.
+    #[must_use]
+    pub const fn from_flags(ipv4: bool, ipv6: bool) -> Option<Self> {
+        match (ipv4, ipv6) {
+            (true, false) => Some(Self::V4),
+            (false, true) => Some(Self::V6),
+            _ => None,
+        }
+    }
 }
 
 /// DNS server testing status.
@@ -101,6 +231,71 @@ impl DnsStatus {
     }
 }
 
+impl std::fmt::Display for DnsStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pending => write!(f, "pending"),
+            Self::Testing => write!(f, "testing"),
+            Self::Success => write!(f, "success"),
+            Self::Failed => write!(f, "failed"),
+            Self::Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+/// Protocol a DNS server is queried over.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsProtocol {
+    /// Plain DNS over UDP or TCP
+    #[default]
+    Plain,
+    /// DNS over TLS (RFC 7858)
+    Tls,
+    /// DNS over HTTPS (RFC 8484)
+    Https,
+}
+
+impl DnsProtocol {
+    /// Port servers of this protocol listen on unless told otherwise.
+    #[must_use]
+    pub const fn default_port(self) -> u16 {
+        match self {
+            Self::Plain => 53,
+            Self::Tls => 853,
+            Self::Https => 443,
+        }
+    }
+
+    /// URL scheme selecting this protocol in a server spec, `None` for
+    /// plain DNS, which has none.
+    #[must_use]
+    pub const fn scheme(self) -> Option<&'static str> {
+        match self {
+            Self::Plain => None,
+            Self::Tls => Some("tls"),
+            Self::Https => Some("https"),
+        }
+    }
+
+    /// Whether this is plain DNS.
+    #[must_use]
+    #[allow(clippy::trivially_copy_pass_by_ref)] // serde's skip_serializing_if passes a reference
+    pub const fn is_plain(&self) -> bool {
+        matches!(self, Self::Plain)
+    }
+}
+
+impl std::fmt::Display for DnsProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Plain => write!(f, "plain"),
+            Self::Tls => write!(f, "tls"),
+            Self::Https => write!(f, "https"),
+        }
+    }
+}
+
 /// DNS server list container.
 ///
 /// Represents a collection of DNS servers, typically loaded from
@@ -136,6 +331,60 @@ impl DnsList {
     pub fn is_empty(&self) -> bool {
         self.servers.is_empty()
     }
+
+    /// Rewrite every address in canonical form and country codes in
+    /// upper case.
+    pub fn normalize(&mut self) {
+        for server in &mut self.servers {
+            server.normalize_ip();
+            if let Some(country) = &mut server.country {
+                country.make_ascii_uppercase();
+            }
+        }
+    }
+
+    /// Number servers that share a name but not an address, port and
+    /// protocol (see [`DnsServer::spec`]), e.g. `Google #1` and
+    /// `Google #2`, in list order.
+    ///
+    /// Names that are already numbered are numbered afresh, so numbering a
+    /// list again, such as after merging it with another, does not stack
+    /// numbers.
+    pub fn disambiguate_names(&mut self) {
+        let specs: Vec<String> = self.servers.iter().map(DnsServer::spec).collect();
+        let mut specs_by_name: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (server, spec) in self.servers.iter().zip(&specs) {
+            let names = specs_by_name.entry(unnumbered(&server.name)).or_default();
+            if !names.contains(&spec.as_str()) {
+                names.push(spec);
+            }
+        }
+        let names: Vec<Option<String>> = self
+            .servers
+            .iter()
+            .zip(&specs)
+            .map(|(server, spec)| {
+                let name = unnumbered(&server.name);
+                let specs = &specs_by_name[name];
+                (specs.len() > 1).then(|| {
+                    let number = specs.iter().position(|s| s == spec).unwrap_or(0) + 1;
+                    format!("{name} #{number}")
+                })
+            })
+            .collect();
+        for (server, name) in self.servers.iter_mut().zip(names) {
+            if let Some(name) = name {
+                server.name = name;
+            }
+        }
+    }
+}
+
+/// `name` without the `#N` numbering of duplicate names.
+fn unnumbered(name: &str) -> &str {
+    name.rsplit_once(" #")
+        .filter(|(_, number)| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+        .map_or(name, |(name, _)| name)
 }
 
 impl Default for DnsList {
@@ -159,6 +408,56 @@ pub struct SpeedTestResult {
     pub success: bool,
     /// Error message if the test failed
     pub error: Option<String>,
+    /// TTL of the last echo reply, where the ICMP backend exposes it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u8>,
+    /// Estimated number of routers between here and the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hops: Option<u8>,
+    /// How the latency was measured, for plain DNS servers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<IcmpMethod>,
+    /// Pings sent to the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+    /// Timeout of each ping in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Round-trip time of each answered ping in milliseconds
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub samples_ms: Vec<f64>,
+    /// Round-trip time in milliseconds of every ping in the order sent,
+    /// `None` for those that got no answer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub samples: Vec<Option<f64>>,
+    /// Spread of `samples_ms`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<LatencyStats>,
+    /// Average time in milliseconds each answered ping waited to be
+    /// scheduled on top of its round trip, where the ICMP backend
+    /// timestamps replies itself; `latency_ms` leaves it out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduling_ms: Option<f64>,
+    /// Verdicts of server plugins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginVerdict>,
+    /// HTTP version used, for DNS-over-HTTPS endpoints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_version: Option<HttpVersion>,
+    /// Average time per connection phase, for encrypted transports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phases: Option<ConnectionPhases>,
+    /// Average latency in milliseconds of queries on an already open
+    /// connection, for encrypted transports; `latency_ms` is that of
+    /// queries on a fresh connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reused_latency_ms: Option<f64>,
+    /// Local address the probes were sent from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_ip: Option<IpAddr>,
+    /// Where the resolver's own queries leave for the internet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub egress: Option<Egress>,
 }
 
 impl SpeedTestResult {
@@ -171,9 +470,101 @@ impl SpeedTestResult {
             packet_loss,
             success: true,
             error: None,
+            ttl: None,
+            hops: None,
+            method: None,
+            count: None,
+            timeout_ms: None,
+            samples_ms: Vec::new(),
+            samples: Vec::new(),
+            stats: None,
+            scheduling_ms: None,
+            plugins: Vec::new(),
+            http_version: None,
+            phases: None,
+            reused_latency_ms: None,
+            source_ip: None,
+            egress: None,
         }
     }
 
+    /// Attach the TTL of an echo reply and the hop count it implies.
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: u8) -> Self {
+        self.ttl = Some(ttl);
+        self.hops = Some(crate::dns::icmp::estimate_hops(ttl));
+        self
+    }
+
+    /// Record how the latency was measured.
+    #[must_use]
+    pub const fn with_method(mut self, method: IcmpMethod) -> Self {
+        self.method = Some(method);
+        self
+    }
+
+    /// Record how many pings were sent, each waiting up to `timeout`.
+    #[must_use]
+    pub fn with_settings(mut self, count: usize, timeout: Duration) -> Self {
+        self.count = Some(count);
+        self.timeout_ms = Some(u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX));
+        self
+    }
+
+    /// Attach the round-trip times of the individual pings and their
+    /// statistics.
+    #[must_use]
+    pub fn with_samples(mut self, samples_ms: Vec<f64>) -> Self {
+        self.stats = LatencyStats::from_samples(&samples_ms);
+        self.samples_ms = samples_ms;
+        self
+    }
+
+    /// Attach the outcome of every ping, answered or not.
+    #[must_use]
+    pub fn with_attempts(mut self, samples: Vec<Option<f64>>) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Record the average wait of the pings to be scheduled, from the
+    /// wall-clock time beyond each reply's round trip.
+    #[must_use]
+    pub fn with_scheduling_delay(mut self, delays_ms: &[f64]) -> Self {
+        if !delays_ms.is_empty() {
+            self.scheduling_ms = Some(delays_ms.iter().sum::<f64>() / delays_ms.len() as f64);
+        }
+        self
+    }
+
+    /// Record the HTTP version a DNS-over-HTTPS endpoint was tested over.
+    #[must_use]
+    pub fn with_http_version(mut self, version: HttpVersion) -> Self {
+        self.http_version = Some(version);
+        self
+    }
+
+    /// Attach the average connection phase breakdown.
+    #[must_use]
+    pub fn with_phases(mut self, phases: ConnectionPhases) -> Self {
+        self.phases = Some(phases);
+        self
+    }
+
+    /// Attach the average latency of queries on a reused connection.
+    #[must_use]
+    pub fn with_reused_latency(mut self, latency_ms: f64) -> Self {
+        self.reused_latency_ms = Some(latency_ms);
+        self
+    }
+
+    /// Record the local address the probes were sent from.
+    #[must_use]
+    pub fn with_source_ip(mut self, source_ip: Option<IpAddr>) -> Self {
+        self.source_ip = source_ip;
+        self
+    }
+
     /// Create a failed result.
     pub fn failure(server: DnsServer, error: impl Into<String>) -> Self {
         Self {
@@ -182,6 +573,21 @@ impl SpeedTestResult {
             packet_loss: 1.0,
             success: false,
             error: Some(error.into()),
+            ttl: None,
+            hops: None,
+            method: None,
+            count: None,
+            timeout_ms: None,
+            samples_ms: Vec::new(),
+            samples: Vec::new(),
+            stats: None,
+            scheduling_ms: None,
+            plugins: Vec::new(),
+            http_version: None,
+            phases: None,
+            reused_latency_ms: None,
+            source_ip: None,
+            egress: None,
         }
     }
 
@@ -190,6 +596,284 @@ impl SpeedTestResult {
     pub fn is_timeout(&self) -> bool {
         !self.success && matches!(self.error.as_deref(), Some("timeout"))
     }
+
+    /// Status of the server according to this result.
+    #[must_use]
+    pub fn status(&self) -> DnsStatus {
+        if self.success {
+            DnsStatus::Success
+        } else if self.is_timeout() {
+            DnsStatus::Timeout
+        } else {
+            DnsStatus::Failed
+        }
+    }
+
+    /// Load the results saved in a file written by
+    /// [`ResultsDocument::to_json`], or by older versions as a bare array.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a results
+    /// file this version understands.
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Vec<Self>> {
+        ResultsDocument::from_json_file(path).map(|document| document.results)
+    }
+}
+
+/// Version of the results file format written by this crate.
+pub const RESULTS_VERSION: u32 = 1;
+
+/// Speed test results as saved to disk.
+///
+/// `speed --format json` writes this document, and `compare`, `report`,
+/// `chart` and `speed --only-failed --from` read it, so other tools can rely
+/// on one representation:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "generated_at": "2024-05-01T12:00:00Z",
+///   "results": [{ "server": { "name": "Cloudflare", "ip": "1.1.1.1" }, ... }]
+/// }
+/// ```
+///
+/// The version is raised only for changes that older readers would
+/// misread; new optional fields keep it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultsDocument {
+    /// Format version, see [`RESULTS_VERSION`]
+    pub version: u32,
+    /// When the results were saved; unknown for bare arrays written by
+    /// older versions
+    pub generated_at: Option<DateTime<Utc>>,
+    /// The results, in output order
+    pub results: Vec<SpeedTestResult>,
+}
+
+impl ResultsDocument {
+    /// Wrap results saved now in the current format.
+    #[must_use]
+    pub fn new(results: Vec<SpeedTestResult>) -> Self {
+        Self {
+            version: RESULTS_VERSION,
+            generated_at: Some(Utc::now()),
+            results,
+        }
+    }
+
+    /// Parse a results document, or a bare array of results as written
+    /// before the format was versioned (read as version 0).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON is malformed or of a newer version.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        if value.is_array() {
+            return Ok(Self {
+                version: 0,
+                generated_at: None,
+                results: serde_json::from_value(value)?,
+            });
+        }
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| Error::parse("Not a results file: no version"))?;
+        if version > u64::from(RESULTS_VERSION) {
+            return Err(Error::parse(format!(
+                "Results file version {version} is newer than supported ({RESULTS_VERSION})"
+            )));
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Read a results document from a file, see [`from_json`](Self::from_json).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Pretty-printed JSON, ending with a newline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a result cannot be serialized.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+}
+
+/// Spread of the individual round-trip times of a server.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LatencyStats {
+    /// Fastest round trip in milliseconds
+    pub min_ms: f64,
+    /// Slowest round trip in milliseconds
+    pub max_ms: f64,
+    /// Median round trip in milliseconds
+    pub median_ms: f64,
+    /// Standard deviation in milliseconds, the jitter
+    pub stddev_ms: f64,
+    /// 95th percentile (nearest rank) in milliseconds
+    pub p95_ms: f64,
+}
+
+impl LatencyStats {
+    /// Compute the statistics of round-trip times, `None` if there are none.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    pub fn from_samples(samples_ms: &[f64]) -> Option<Self> {
+        if samples_ms.is_empty() {
+            return None;
+        }
+        let mut sorted = samples_ms.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len();
+        let median_ms = if n % 2 == 0 {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        } else {
+            sorted[n / 2]
+        };
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let variance = sorted.iter().map(|ms| (ms - mean).powi(2)).sum::<f64>() / n as f64;
+        let rank = (0.95 * n as f64).ceil() as usize;
+        Some(Self {
+            min_ms: sorted[0],
+            max_ms: sorted[n - 1],
+            median_ms,
+            stddev_ms: variance.sqrt(),
+            p95_ms: sorted[rank.clamp(1, n) - 1],
+        })
+    }
+}
+
+/// Time spent in each phase of a query over an encrypted transport.
+///
+/// Separating the phases shows whether a slow server is slow per
+/// connection (handshakes) or per query.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionPhases {
+    /// TCP connect in milliseconds, or for QUIC the whole handshake
+    /// including TLS
+    pub connect_ms: f64,
+    /// TLS handshake in milliseconds; absent where QUIC includes it in
+    /// the connect phase
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_ms: Option<f64>,
+    /// From the established connection to the complete answer, in
+    /// milliseconds
+    pub query_ms: f64,
+}
+
+impl ConnectionPhases {
+    /// Total time of all phases in milliseconds.
+    #[must_use]
+    pub fn total_ms(&self) -> f64 {
+        self.connect_ms + self.tls_ms.unwrap_or(0.0) + self.query_ms
+    }
+
+    /// Average the phases of several queries.
+    ///
+    /// The TLS phase is averaged over the queries that had one.
+    #[must_use]
+    pub fn mean(phases: &[Self]) -> Option<Self> {
+        if phases.is_empty() {
+            return None;
+        }
+        let n = phases.len() as f64;
+        let tls: Vec<f64> = phases.iter().filter_map(|p| p.tls_ms).collect();
+        Some(Self {
+            connect_ms: phases.iter().map(|p| p.connect_ms).sum::<f64>() / n,
+            tls_ms: (!tls.is_empty()).then(|| tls.iter().sum::<f64>() / tls.len() as f64),
+            query_ms: phases.iter().map(|p| p.query_ms).sum::<f64>() / n,
+        })
+    }
+}
+
+/// Reason code behind a pollution verdict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PollutionReason {
+    /// System and public DNS answers largely overlap
+    SharedIps,
+    /// System and public DNS share only a small fraction of addresses
+    PartialOverlap,
+    /// Answers share no address but lie in the same network (typical of CDNs)
+    SameNetwork,
+    /// System DNS returned a well-known public DNS address
+    KnownPublicDnsIp,
+    /// One side returned no addresses, so nothing can be compared
+    EmptyAnswer,
+    /// System and public DNS share no address
+    DisjointAnswers,
+    /// UDP and TCP answers from public DNS share no address
+    UdpTcpDivergence,
+    /// Port-53 traffic is intercepted, so the public reference is unreliable
+    TransparentProxy,
+    /// System DNS returned a private, loopback or otherwise reserved address
+    BogonAnswer,
+}
+
+impl std::fmt::Display for PollutionReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::SharedIps => "shared_ips",
+            Self::PartialOverlap => "partial_overlap",
+            Self::SameNetwork => "same_network",
+            Self::KnownPublicDnsIp => "known_public_dns_ip",
+            Self::EmptyAnswer => "empty_answer",
+            Self::DisjointAnswers => "disjoint_answers",
+            Self::UdpTcpDivergence => "udp_tcp_divergence",
+            Self::TransparentProxy => "transparent_proxy",
+            Self::BogonAnswer => "bogon_answer",
+        };
+        write!(f, "{text}")
+    }
+}
+
+/// Overall outcome of a pollution check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckVerdict {
+    /// Nothing points at tampering
+    Clean,
+    /// Not polluted, but censorship fingerprints, an intercepting proxy or
+    /// a plugin warning were found
+    Suspicious,
+    /// Pollution was detected
+    Polluted,
+}
+
+impl std::fmt::Display for CheckVerdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Clean => write!(f, "clean"),
+            Self::Suspicious => write!(f, "suspicious"),
+            Self::Polluted => write!(f, "polluted"),
+        }
+    }
+}
+
+/// Compute the Jaccard overlap of two address sets.
+///
+/// Returns `|a ∩ b| / |a ∪ b|`, or 0.0 when both are empty.
+#[must_use]
+pub fn overlap_ratio(a: &[IpAddr], b: &[IpAddr]) -> f64 {
+    let a: HashSet<&IpAddr> = a.iter().collect();
+    let b: HashSet<&IpAddr> = b.iter().collect();
+    let union = a.union(&b).count();
+    if union == 0 {
+        0.0
+    } else {
+        a.intersection(&b).count() as f64 / union as f64
+    }
 }
 
 /// DNS pollution check result.
@@ -204,33 +888,205 @@ pub struct PollutionResult {
     pub system_ips: Vec<IpAddr>,
     /// IP addresses returned by public DNS servers
     pub public_ips: Vec<IpAddr>,
+    /// Addresses returned by both system and public DNS
+    #[serde(default)]
+    pub matching_ips: Vec<IpAddr>,
+    /// Addresses returned only by system DNS
+    #[serde(default)]
+    pub system_only_ips: Vec<IpAddr>,
+    /// Addresses returned only by public DNS
+    #[serde(default)]
+    pub public_only_ips: Vec<IpAddr>,
+    /// Reserved addresses returned by system DNS but not by public DNS
+    #[serde(default)]
+    pub bogon_ips: Vec<IpAddr>,
+    /// Reverse DNS names of the mismatched addresses
+    #[serde(default)]
+    pub ptr_records: Vec<PtrRecord>,
+    /// RDAP registration data of the mismatched addresses
+    /// (only filled when requested)
+    #[serde(default)]
+    pub rdap_records: Vec<RdapRecord>,
+    /// Jaccard overlap of system and public answers
+    /// (|system ∩ public| / |system ∪ public|)
+    #[serde(default)]
+    pub overlap_ratio: f64,
     /// Whether pollution was detected
     pub is_polluted: bool,
-    /// Human-readable details about the result
-    pub details: String,
+    /// Reason codes the verdict was based on
+    #[serde(default)]
+    pub reasons: Vec<PollutionReason>,
+    /// Whether GFW-specific censorship fingerprints were found
+    #[serde(default)]
+    pub censorship_suspected: bool,
+    /// The censorship fingerprints that were found
+    #[serde(default)]
+    pub censorship_signals: Vec<CensorshipSignal>,
+    /// UDP vs TCP answers from the public resolver (None if not compared)
+    #[serde(default)]
+    pub transport: Option<TransportComparison>,
+    /// Whether port-53 traffic appeared transparently intercepted
+    /// (None if not determined)
+    #[serde(default)]
+    pub transparent_proxy: Option<bool>,
+    /// Verdicts of domain plugins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginVerdict>,
 }
 
 impl PollutionResult {
     /// Create a pollution check result.
-    #[allow(dead_code)]
+    ///
+    /// The matching and one-sided address lists are derived from
+    /// `system_ips` and `public_ips`.
     #[must_use]
     pub fn new(
         domain: String,
         system_ips: Vec<IpAddr>,
         public_ips: Vec<IpAddr>,
         is_polluted: bool,
-        details: String,
+        reasons: Vec<PollutionReason>,
     ) -> Self {
+        let matching_ips = system_ips
+            .iter()
+            .filter(|ip| public_ips.contains(ip))
+            .copied()
+            .collect();
+        let system_only_ips = system_ips
+            .iter()
+            .filter(|ip| !public_ips.contains(ip))
+            .copied()
+            .collect();
+        let public_only_ips = public_ips
+            .iter()
+            .filter(|ip| !system_ips.contains(ip))
+            .copied()
+            .collect();
+        let overlap_ratio = overlap_ratio(&system_ips, &public_ips);
+        let bogon_ips = bogon::unexpected_bogons(&system_ips, &public_ips);
+
         Self {
             domain,
             system_ips,
             public_ips,
+            matching_ips,
+            system_only_ips,
+            public_only_ips,
+            bogon_ips,
+            ptr_records: Vec::new(),
+            rdap_records: Vec::new(),
+            overlap_ratio,
             is_polluted,
-            details,
+            reasons,
+            censorship_suspected: false,
+            censorship_signals: Vec::new(),
+            transport: None,
+            transparent_proxy: None,
+            plugins: Vec::new(),
+        }
+    }
+
+    /// Classify the result as clean, suspicious or polluted.
+    #[must_use]
+    pub fn verdict(&self) -> CheckVerdict {
+        let plugin_flagged = self
+            .plugins
+            .iter()
+            .any(|p| matches!(p.verdict, Verdict::Warn | Verdict::Fail));
+        if self.is_polluted {
+            CheckVerdict::Polluted
+        } else if self.censorship_suspected
+            || self.transparent_proxy == Some(true)
+            || plugin_flagged
+        {
+            CheckVerdict::Suspicious
+        } else {
+            CheckVerdict::Clean
+        }
+    }
+
+    /// Get the reverse DNS names known for an address.
+    #[must_use]
+    pub fn hostnames(&self, ip: &IpAddr) -> &[String] {
+        self.ptr_records
+            .iter()
+            .find(|r| &r.ip == ip)
+            .map_or(&[], |r| r.hostnames.as_slice())
+    }
+
+    /// Get the RDAP registration data known for an address.
+    #[must_use]
+    pub fn rdap(&self, ip: &IpAddr) -> Option<&RdapRecord> {
+        self.rdap_records.iter().find(|r| &r.ip == ip)
+    }
+
+    /// Attach censorship signals and update the suspicion flag.
+    #[must_use]
+    pub fn with_censorship_signals(mut self, signals: Vec<CensorshipSignal>) -> Self {
+        self.censorship_suspected = !signals.is_empty();
+        self.censorship_signals = signals;
+        self
+    }
+}
+
+/// Reverse DNS (PTR) names of a single address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PtrRecord {
+    /// The address that was looked up
+    pub ip: IpAddr,
+    /// Host names from its PTR records (without trailing dot)
+    pub hostnames: Vec<String>,
+}
+
+/// Comparison of the same query sent over UDP and TCP.
+///
+/// On-path injectors usually only forge UDP replies, so a clean TCP
+/// answer next to a different UDP answer points to injection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransportComparison {
+    /// IP addresses returned over UDP
+    pub udp_ips: Vec<IpAddr>,
+    /// IP addresses returned over TCP
+    pub tcp_ips: Vec<IpAddr>,
+    /// Whether the two answers share no address
+    pub divergent: bool,
+}
+
+impl TransportComparison {
+    /// Compare UDP and TCP answers.
+    ///
+    /// Answers only diverge when both are non-empty and disjoint, since
+    /// CDNs legitimately rotate addresses between queries.
+    #[must_use]
+    pub fn new(udp_ips: Vec<IpAddr>, tcp_ips: Vec<IpAddr>) -> Self {
+        let divergent = !udp_ips.is_empty()
+            && !tcp_ips.is_empty()
+            && !udp_ips.iter().any(|ip| tcp_ips.contains(ip));
+        Self {
+            udp_ips,
+            tcp_ips,
+            divergent,
         }
     }
 }
 
+/// Results of the servers of one provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderSummary {
+    /// Provider name
+    pub provider: String,
+    /// Number of servers tested
+    pub servers: usize,
+    /// Number of servers that answered
+    pub success: usize,
+    /// Lowest latency in milliseconds
+    pub best_ms: Option<f64>,
+    /// Address of the server with the lowest latency
+    pub best_ip: Option<String>,
+    /// Average latency of the servers that answered
+    pub avg_ms: Option<f64>,
+}
+
 /// Overall test summary statistics.
 ///
 /// Aggregated results from multiple DNS speed tests.
@@ -294,3 +1150,53 @@ impl TestSummary {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_document() {
+        let results = vec![
+            SpeedTestResult::success(DnsServer::new("Cloudflare", "1.1.1.1"), 10.0, 0.0),
+            SpeedTestResult::failure(DnsServer::new("Google", "8.8.8.8"), "timeout"),
+        ];
+        let json = ResultsDocument::new(results.clone()).to_json().unwrap();
+        let document = ResultsDocument::from_json(&json).unwrap();
+        assert_eq!(document.version, RESULTS_VERSION);
+        assert!(document.generated_at.is_some());
+        assert_eq!(document.results.len(), 2);
+        assert!(document.results[1].is_timeout());
+
+        // Bare arrays of older versions
+        let legacy = ResultsDocument::from_json(&serde_json::to_string(&results).unwrap()).unwrap();
+        assert_eq!(legacy.version, 0);
+        assert_eq!(legacy.generated_at, None);
+        assert_eq!(legacy.results[0].server.ip, "1.1.1.1");
+
+        let newer = json.replacen(
+            &format!("\"version\": {RESULTS_VERSION}"),
+            "\"version\": 99",
+            1,
+        );
+        assert!(ResultsDocument::from_json(&newer).is_err());
+        assert!(ResultsDocument::from_json(r#"{"results": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        assert_eq!(LatencyStats::from_samples(&[]), None);
+
+        let samples: Vec<f64> = (1..=20).rev().map(f64::from).collect();
+        let stats = LatencyStats::from_samples(&samples).unwrap();
+        assert!((stats.min_ms - 1.0).abs() < f64::EPSILON);
+        assert!((stats.max_ms - 20.0).abs() < f64::EPSILON);
+        assert!((stats.median_ms - 10.5).abs() < f64::EPSILON);
+        assert!((stats.p95_ms - 19.0).abs() < f64::EPSILON);
+        assert!((stats.stddev_ms - 33.25_f64.sqrt()).abs() < 1e-9);
+
+        let single = LatencyStats::from_samples(&[7.0]).unwrap();
+        assert!((single.p95_ms - 7.0).abs() < f64::EPSILON);
+        assert!(single.stddev_ms.abs() < f64::EPSILON);
+    }
+}
@@ -27,7 +27,7 @@ pub enum Error {
 
     /// DNS resolver error (DNS query failures)
     #[error("DNS resolver error: {0}")]
-    Resolver(#[from] trust_dns_resolver::error::ResolveError),
+    Resolver(#[from] hickory_resolver::error::ResolveError),
 
     /// Network-related error (connection failures, timeouts)
     #[error("Network error: {0}")]
@@ -45,6 +45,10 @@ pub enum Error {
     #[error("Parse error: {0}")]
     Parse(String),
 
+    /// Chart rendering error (drawing backend, fonts)
+    #[error("Chart error: {0}")]
+    Chart(String),
+
     /// Operation timeout
     #[error("Operation timed out")]
     Timeout,
@@ -74,6 +78,12 @@ impl Error {
     pub fn tui(msg: impl Into<String>) -> Self {
         Self::Tui(msg.into())
     }
+
+    /// Create a new chart error with a message.
+    #[must_use]
+    pub fn chart(msg: impl Into<String>) -> Self {
+        Self::Chart(msg.into())
+    }
 }
 
 impl From<color_eyre::Report> for Error {
@@ -0,0 +1,348 @@
+//! Speed test history store.
+//!
+//! Every speed test run is appended as one JSON line to `history.ndjson`
+//! in the data directory. The append-only format keeps writes cheap and
+//! lets a partially written last line (e.g. after a crash) be skipped
+//! without losing earlier runs.
+//!
+//! [`trend`] pulls one server's measurements out of every run, for
+//! `dnstest history show`, [`fill_last_known`] the latest one of each
+//! server, for `dnstest list`, and [`failure_streaks`] how long each server
+//! has been failing, for `dnstest prune`.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::config::ConfigLoader;
+use crate::dns::{DnsServer, SpeedTestResult};
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// History file name inside the data directory.
+const HISTORY_FILE: &str = "history.ndjson";
+
+/// A single recorded speed test run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRun {
+    /// When the run finished
+    pub timestamp: DateTime<Utc>,
+    /// Results of the run
+    pub results: Vec<SpeedTestResult>,
+}
+
+/// Append-only store of speed test runs.
+///
+/// # Example
+///
+/// ```ignore
+/// let history = History::open_default();
+/// history.append(&results)?;
+/// for run in history.load()? {
+///     println!("{}: {} servers", run.timestamp, run.results.len());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    /// Use a history file at a custom path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Use the default history file in the data directory.
+    ///
+    /// A history left in the config directory by older versions is moved
+    /// there first.
+    #[must_use]
+    pub fn open_default() -> Self {
+        ConfigLoader::migrate_to_data_dir(HISTORY_FILE);
+        Self::new(ConfigLoader::data_dir().join(HISTORY_FILE))
+    }
+
+    /// Path of the history file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record a run finishing now.
+    ///
+    /// # Arguments
+    ///
+    /// * `results` - Results of the run
+    pub fn append(&self, results: &[SpeedTestResult]) -> Result<()> {
+        self.append_run(&HistoryRun {
+            timestamp: Utc::now(),
+            results: results.to_vec(),
+        })
+    }
+
+    /// Record a run with an explicit timestamp.
+    pub fn append_run(&self, run: &HistoryRun) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut line = serde_json::to_string(run)?;
+        line.push('\n');
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Load all recorded runs, oldest first.
+    ///
+    /// A missing file yields an empty history; malformed lines are skipped.
+    pub fn load(&self) -> Result<Vec<HistoryRun>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| tracing::debug!("Skipping malformed history line: {e}"))
+                    .ok()
+            })
+            .collect())
+    }
+}
+
+/// One run's measurement of a single server.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendPoint {
+    /// When the run finished
+    pub timestamp: DateTime<Utc>,
+    /// Average latency in milliseconds, `None` if the server did not answer
+    pub latency_ms: Option<f64>,
+    /// Packet loss ratio (0.0 - 1.0)
+    pub packet_loss: f64,
+}
+
+/// Measurements of one server across runs, oldest first.
+///
+/// `server` is matched against IP addresses first, then case-insensitively
+/// against names. Runs that did not test the server are skipped. Returns
+/// the matched server along with its points.
+#[must_use]
+pub fn trend(runs: &[HistoryRun], server: &str) -> Option<(DnsServer, Vec<TrendPoint>)> {
+    let results = |matches: &dyn Fn(&DnsServer) -> bool| -> Vec<(DateTime<Utc>, &SpeedTestResult)> {
+        runs.iter()
+            .filter_map(|run| {
+                run.results
+                    .iter()
+                    .find(|r| matches(&r.server))
+                    .map(|r| (run.timestamp, r))
+            })
+            .collect()
+    };
+
+    let mut found = results(&|s| s.ip == server);
+    if found.is_empty() {
+        found = results(&|s| s.name.eq_ignore_ascii_case(server));
+    }
+    let (_, last) = found.last()?;
+    let matched = last.server.clone();
+
+    let points = found
+        .into_iter()
+        .map(|(timestamp, r)| TrendPoint {
+            timestamp,
+            latency_ms: r.latency_ms,
+            packet_loss: r.packet_loss,
+        })
+        .collect();
+    Some((matched, points))
+}
+
+/// Set the `delay` and `status` of each server to its latest measurement.
+///
+/// Servers are matched by IP address. Servers that no run tested keep
+/// their values, such as a `delay` given in the list file.
+pub fn fill_last_known(runs: &[HistoryRun], servers: &mut [DnsServer]) {
+    let mut ordered: Vec<&HistoryRun> = runs.iter().collect();
+    ordered.sort_by_key(|run| run.timestamp);
+    let mut latest: HashMap<&str, &SpeedTestResult> = HashMap::new();
+    for result in ordered.into_iter().flat_map(|run| &run.results) {
+        latest.insert(&result.server.ip, result);
+    }
+    for server in servers {
+        if let Some(result) = latest.get(server.ip.as_str()) {
+            server.update_from(result);
+        }
+    }
+}
+
+/// Number of runs in a row, up to the latest, that each server failed in,
+/// by [`DnsServer::spec`], so that other ports and protocols on the same
+/// address keep their own streaks.
+///
+/// Runs that did not test a server neither extend nor break its streak.
+#[must_use]
+pub fn failure_streaks(runs: &[HistoryRun]) -> HashMap<String, u64> {
+    let mut ordered: Vec<&HistoryRun> = runs.iter().collect();
+    ordered.sort_by_key(|run| run.timestamp);
+    let mut streaks: HashMap<String, u64> = HashMap::new();
+    for result in ordered.into_iter().flat_map(|run| &run.results) {
+        let streak = streaks.entry(result.server.spec()).or_default();
+        *streak = if result.success { 0 } else { *streak + 1 };
+    }
+    streaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::DnsStatus;
+
+    #[test]
+    fn test_append_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::new(dir.path().join("nested").join(HISTORY_FILE));
+        assert!(history.load().unwrap().is_empty());
+
+        let results = vec![SpeedTestResult::success(
+            DnsServer::new("Cloudflare", "1.1.1.1"),
+            12.5,
+            0.0,
+        )];
+        history.append(&results).unwrap();
+        history.append(&results).unwrap();
+
+        // A truncated trailing line must not hide earlier runs
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(history.path())
+            .unwrap();
+        file.write_all(b"{\"timestamp\":").unwrap();
+
+        let runs = history.load().unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].results[0].server.ip, "1.1.1.1");
+    }
+
+    #[test]
+    fn test_trend() {
+        let run = |minute: u32, results: Vec<SpeedTestResult>| HistoryRun {
+            timestamp: DateTime::parse_from_rfc3339(&format!("2024-01-01T00:{minute:02}:00Z"))
+                .unwrap()
+                .with_timezone(&Utc),
+            results,
+        };
+        let cloudflare = DnsServer::new("Cloudflare", "1.1.1.1");
+        let google = DnsServer::new("Google", "8.8.8.8");
+        let runs = vec![
+            run(
+                0,
+                vec![SpeedTestResult::success(cloudflare.clone(), 10.0, 0.0)],
+            ),
+            run(1, vec![SpeedTestResult::success(google, 20.0, 0.0)]),
+            run(2, vec![SpeedTestResult::failure(cloudflare, "timeout")]),
+        ];
+
+        let (server, points) = trend(&runs, "1.1.1.1").unwrap();
+        assert_eq!(server.name, "Cloudflare");
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].latency_ms, Some(10.0));
+        assert_eq!(points[1].latency_ms, None);
+
+        let (server, points) = trend(&runs, "google").unwrap();
+        assert_eq!(server.ip, "8.8.8.8");
+        assert_eq!(points.len(), 1);
+
+        assert!(trend(&runs, "9.9.9.9").is_none());
+    }
+
+    #[test]
+    fn test_fill_last_known() {
+        let at = |minute: u32| {
+            DateTime::parse_from_rfc3339(&format!("2024-01-01T00:{minute:02}:00Z"))
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        let cloudflare = DnsServer::new("Cloudflare", "1.1.1.1");
+        let google = DnsServer::new("Google", "8.8.8.8");
+        // Recorded out of order, the later run must still win
+        let runs = vec![
+            HistoryRun {
+                timestamp: at(2),
+                results: vec![SpeedTestResult::failure(cloudflare.clone(), "timeout")],
+            },
+            HistoryRun {
+                timestamp: at(1),
+                results: vec![
+                    SpeedTestResult::success(cloudflare.clone(), 10.0, 0.0),
+                    SpeedTestResult::success(google.clone(), 20.0, 0.0),
+                ],
+            },
+        ];
+
+        let mut quad9 = DnsServer::new("Quad9", "9.9.9.9");
+        quad9.delay = Some(30.0);
+        let mut servers = vec![cloudflare, google, quad9];
+        fill_last_known(&runs, &mut servers);
+
+        assert_eq!(servers[0].delay, None);
+        assert_eq!(servers[0].status, DnsStatus::Timeout);
+        assert_eq!(servers[1].delay, Some(20.0));
+        assert_eq!(servers[1].status, DnsStatus::Success);
+        assert_eq!(servers[2].delay, Some(30.0));
+        assert_eq!(servers[2].status, DnsStatus::Pending);
+    }
+
+    #[test]
+    fn test_failure_streaks() {
+        let run = |minute: u32, results: Vec<SpeedTestResult>| HistoryRun {
+            timestamp: DateTime::parse_from_rfc3339(&format!("2024-01-01T00:{minute:02}:00Z"))
+                .unwrap()
+                .with_timezone(&Utc),
+            results,
+        };
+        let cloudflare = DnsServer::new("Cloudflare", "1.1.1.1");
+        let google = DnsServer::new("Google", "8.8.8.8");
+        let mut cloudflare_tls = cloudflare.clone();
+        cloudflare_tls.protocol = crate::dns::DnsProtocol::Tls;
+        let runs = vec![
+            // Another protocol on the same address has its own streak
+            run(0, vec![SpeedTestResult::success(cloudflare_tls, 15.0, 0.0)]),
+            run(
+                0,
+                vec![
+                    SpeedTestResult::failure(cloudflare.clone(), "timeout"),
+                    SpeedTestResult::failure(google.clone(), "timeout"),
+                ],
+            ),
+            run(1, vec![SpeedTestResult::success(google.clone(), 20.0, 0.0)]),
+            run(
+                2,
+                vec![
+                    SpeedTestResult::failure(cloudflare.clone(), "timeout"),
+                    SpeedTestResult::failure(google, "timeout"),
+                ],
+            ),
+            // Did not test Cloudflare
+            run(3, Vec::new()),
+            run(4, vec![SpeedTestResult::failure(cloudflare, "refused")]),
+        ];
+
+        let streaks = failure_streaks(&runs);
+        assert_eq!(streaks["1.1.1.1"], 3);
+        assert_eq!(streaks["8.8.8.8"], 1);
+        assert_eq!(streaks["tls://1.1.1.1"], 0);
+        assert!(!streaks.contains_key("9.9.9.9"));
+    }
+}
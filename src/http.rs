@@ -0,0 +1,633 @@
+//! Minimal HTTP client.
+//!
+//! HTTP requests are delegated to the system `curl` binary, as the
+//! `update` command already does, which keeps TLS and proxy handling
+//! out of the crate's dependency tree.
+//!
+//! Where the system resolver is broken or polluted, [`set_bootstrap`]
+//! makes host names of request URLs resolve through a given DNS server
+//! instead; curl then connects to that address via `--resolve`.
+//! [`set_proxy`] sends all requests through an HTTP or SOCKS proxy.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::dns::{query, source};
+use crate::error::{Error, Result};
+use hickory_resolver::proto::rr::RecordType;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// DNS server resolving request host names, if set.
+static BOOTSTRAP: OnceLock<SocketAddr> = OnceLock::new();
+
+/// Proxy URL for all requests, if set.
+static PROXY: OnceLock<String> = OnceLock::new();
+
+/// Proxy schemes curl understands.
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks4", "socks4a", "socks5", "socks5h"];
+
+/// Timeout for resolving a host name through the bootstrap server.
+const BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Resolve the host names of all later requests through this DNS server
+/// instead of the system resolver.
+///
+/// Only the first call takes effect.
+pub fn set_bootstrap(server: IpAddr) {
+    let _ = BOOTSTRAP.set(SocketAddr::new(server, query::DNS_PORT));
+}
+
+/// Send all later requests through this proxy, e.g.
+/// `socks5://127.0.0.1:1080` or `http://proxy.example:3128`.
+///
+/// With `socks5h://` the proxy resolves host names itself and the
+/// bootstrap server is bypassed. Only the first call takes effect.
+pub fn set_proxy(url: &str) -> Result<()> {
+    let scheme = url
+        .split_once("://")
+        .map(|(scheme, _)| scheme.to_ascii_lowercase());
+    if !scheme.is_some_and(|scheme| PROXY_SCHEMES.contains(&scheme.as_str())) {
+        return Err(Error::config(format!(
+            "Unsupported proxy {url}, expected one of {PROXY_SCHEMES:?} followed by ://"
+        )));
+    }
+    let _ = PROXY.set(url.to_string());
+    Ok(())
+}
+
+/// curl arguments applying the proxy, bootstrap server and source binding
+/// (see [`source`]) to a request for `url`.
+///
+/// curl binds to one interface or address, so an interface takes
+/// precedence over a source address. curl cannot mark packets, so a
+/// firewall mark is not applied.
+pub async fn curl_args(url: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    if let Some(binding) = source::current() {
+        let interface = binding
+            .interface
+            .clone()
+            .or_else(|| binding.address.map(|address| address.to_string()));
+        if let Some(interface) = interface {
+            args.extend(["--interface".to_string(), interface]);
+        }
+    }
+    if let Some(proxy) = PROXY.get() {
+        args.extend(["--proxy".to_string(), proxy.clone()]);
+    }
+    args.extend(resolve_args(url).await?);
+    Ok(args)
+}
+
+/// curl arguments pinning the host of `url` to the address returned by
+/// the bootstrap server.
+///
+/// Returns no arguments when no bootstrap server is set or the host is
+/// an IP address.
+async fn resolve_args(url: &str) -> Result<Vec<String>> {
+    let Some(server) = BOOTSTRAP.get() else {
+        return Ok(Vec::new());
+    };
+    let Some((host, port)) = host_port(url) else {
+        return Ok(Vec::new());
+    };
+    if host.parse::<IpAddr>().is_ok() {
+        return Ok(Vec::new());
+    }
+
+    let mut ips = Vec::new();
+    for record_type in [RecordType::A, RecordType::AAAA] {
+        ips = query::query_udp(*server, host, record_type, BOOTSTRAP_TIMEOUT)
+            .await?
+            .answer_ips();
+        if !ips.is_empty() {
+            break;
+        }
+    }
+    let ip = ips.first().ok_or_else(|| {
+        Error::network(format!(
+            "Bootstrap resolver {server} has no address for {host}"
+        ))
+    })?;
+    tracing::debug!("Bootstrap resolved {host} to {ip}");
+
+    let address = match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("[{ip}]"),
+    };
+    Ok(vec![
+        "--resolve".to_string(),
+        format!("{host}:{port}:{address}"),
+    ])
+}
+
+/// Host and port of an `http` or `https` URL.
+fn host_port(url: &str) -> Option<(&str, u16)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let default_port = match scheme.to_ascii_lowercase().as_str() {
+        "http" => 80,
+        "https" => 443,
+        _ => return None,
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+
+    let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+        let (host, rest) = bracketed.split_once(']')?;
+        (host, rest.strip_prefix(':'))
+    } else {
+        match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        }
+    };
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => default_port,
+    };
+    (!host.is_empty()).then_some((host, port))
+}
+
+/// A curl command for `url`, honoring the proxy and bootstrap server.
+async fn curl(url: &str) -> Result<Command> {
+    let mut command = Command::new("curl");
+    command.args(curl_args(url).await?);
+    Ok(command)
+}
+
+/// Fetch a URL and return the response body.
+///
+/// Redirects are followed and HTTP error statuses are reported as errors.
+///
+/// # Arguments
+///
+/// * `url` - URL to fetch
+/// * `timeout` - Maximum time for the whole transfer
+pub async fn get(url: &str, timeout: Duration) -> Result<Vec<u8>> {
+    get_with_headers(url, &[], timeout).await
+}
+
+/// Fetch a URL with extra request headers and return the response body.
+///
+/// # Arguments
+///
+/// * `url` - URL to fetch
+/// * `headers` - Headers in `Name: value` form
+/// * `timeout` - Maximum time for the whole transfer
+pub async fn get_with_headers(url: &str, headers: &[&str], timeout: Duration) -> Result<Vec<u8>> {
+    let mut command = curl(url).await?;
+    command
+        .args(["-sSfL", "--max-time"])
+        .arg(timeout.as_secs_f64().to_string());
+    for header in headers {
+        command.arg("-H").arg(header);
+    }
+    let output = command
+        .arg(url)
+        .output()
+        .await
+        .map_err(|e| Error::network(format!("Failed to run curl: {e}")))?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(Error::network(format!(
+            "GET {url} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// Fetch a URL and parse the response body as JSON.
+///
+/// # Arguments
+///
+/// * `url` - URL to fetch
+/// * `timeout` - Maximum time for the whole transfer
+pub async fn get_json<T: DeserializeOwned>(url: &str, timeout: Duration) -> Result<T> {
+    let body = get(url, timeout).await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// POST a value as JSON and return the response body.
+///
+/// The request is handed to curl as a config file on stdin, so neither
+/// the body nor the bearer token shows up in the process list.
+///
+/// # Arguments
+///
+/// * `url` - URL to post to
+/// * `body` - Value to send as the JSON body
+/// * `bearer` - Optional bearer token for the `Authorization` header
+/// * `timeout` - Maximum time for the whole transfer
+pub async fn post_json<T: Serialize + Sync>(
+    url: &str,
+    body: &T,
+    bearer: Option<&str>,
+    timeout: Duration,
+) -> Result<Vec<u8>> {
+    let mut config = format!(
+        "url = \"{}\"\nheader = \"Content-Type: application/json\"\ndata-binary = \"{}\"\n",
+        curl_escape(url),
+        curl_escape(&serde_json::to_string(body)?)
+    );
+    if let Some(token) = bearer {
+        config.push_str(&format!(
+            "header = \"Authorization: Bearer {}\"\n",
+            curl_escape(token)
+        ));
+    }
+
+    let mut child = curl(url)
+        .await?
+        .args(["-sSfL", "--config", "-", "--max-time"])
+        .arg(timeout.as_secs_f64().to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::network(format!("Failed to run curl: {e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(config.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(Error::network(format!(
+            "POST {url} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// POST a binary body and return the response body.
+///
+/// The body is passed to curl on stdin and `content_type` is sent as both
+/// the `Content-Type` and the accepted response type.
+///
+/// # Arguments
+///
+/// * `url` - URL to post to
+/// * `body` - Request body
+/// * `content_type` - Media type of the request and the response
+/// * `timeout` - Maximum time for the whole transfer
+pub async fn post_bytes(
+    url: &str,
+    body: &[u8],
+    content_type: &str,
+    timeout: Duration,
+) -> Result<Vec<u8>> {
+    let mut child = curl(url)
+        .await?
+        .args(["-sSfL", "--data-binary", "@-", "--max-time"])
+        .arg(timeout.as_secs_f64().to_string())
+        .arg("-H")
+        .arg(format!("Content-Type: {content_type}"))
+        .arg("-H")
+        .arg(format!("Accept: {content_type}"))
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::network(format!("Failed to run curl: {e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(body).await?;
+    }
+    let output = child.wait_with_output().await?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(Error::network(format!(
+            "POST {url} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// HTTP protocol version to request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HttpVersion {
+    /// HTTP/1.1
+    #[serde(rename = "1.1")]
+    Http1,
+    /// HTTP/2
+    #[serde(rename = "2")]
+    Http2,
+    /// HTTP/3 over QUIC
+    #[serde(rename = "3")]
+    Http3,
+}
+
+impl HttpVersion {
+    /// All versions, oldest first.
+    pub const ALL: [Self; 3] = [Self::Http1, Self::Http2, Self::Http3];
+
+    /// Get all available version names.
+    #[must_use]
+    pub fn names() -> &'static [&'static str] {
+        &["1.1", "2", "3"]
+    }
+
+    /// curl option selecting this version.
+    ///
+    /// HTTP/3 uses `--http3-only`, so that a failed QUIC handshake is
+    /// reported instead of silently falling back to TCP.
+    const fn curl_flag(self) -> &'static str {
+        match self {
+            Self::Http1 => "--http1.1",
+            Self::Http2 => "--http2",
+            Self::Http3 => "--http3-only",
+        }
+    }
+
+    /// Parse curl's `%{http_version}` output.
+    fn from_curl(value: &str) -> Option<Self> {
+        match value {
+            "1.1" => Some(Self::Http1),
+            "2" => Some(Self::Http2),
+            "3" => Some(Self::Http3),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for HttpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().trim_start_matches("http/") {
+            "1.1" | "1" | "h1" | "http1.1" => Ok(Self::Http1),
+            "2" | "h2" | "http2" => Ok(Self::Http2),
+            "3" | "h3" | "http3" => Ok(Self::Http3),
+            _ => Err(format!(
+                "Unknown HTTP version: {}. Valid options are: {:?}",
+                s,
+                Self::names()
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http1 => write!(f, "HTTP/1.1"),
+            Self::Http2 => write!(f, "HTTP/2"),
+            Self::Http3 => write!(f, "HTTP/3"),
+        }
+    }
+}
+
+/// One completed request with curl's timing breakdown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    /// Negotiated protocol version, if curl reported a known one
+    pub version: Option<HttpVersion>,
+    /// HTTP status code
+    pub status: u16,
+    /// Response body
+    pub body: Vec<u8>,
+    /// Time spent resolving the host name
+    pub name_lookup: Duration,
+    /// Time from the start until the TCP connection (or, for HTTP/3, the
+    /// QUIC handshake) was complete
+    pub connect: Duration,
+    /// Time from the start until the TLS handshake was complete, zero for
+    /// plain HTTP
+    pub app_connect: Duration,
+    /// Time from the start until the whole response was received
+    pub total: Duration,
+    /// Number of new connections the request opened, zero when it reused
+    /// the connection of an earlier request
+    pub connects: u32,
+    /// Local address the request was sent from
+    pub local_ip: Option<IpAddr>,
+}
+
+/// curl write-out appended after each response body.
+///
+/// The body size comes last, so that the output can be split into
+/// transfers from the end even when bodies contain newlines.
+const TIMING_WRITE_OUT: &str = concat!(
+    "\n%{http_version} %{http_code} ",
+    "%{time_namelookup} %{time_connect} %{time_appconnect} %{time_total} ",
+    "%{num_connects} [%{local_ip}] %{size_download}\n"
+);
+
+/// POST a binary body over a given HTTP version, `requests` times in a
+/// row, and time each request.
+///
+/// The requests share one connection where the server keeps it alive, so
+/// the first one shows the cost of a fresh connection and the others the
+/// cost of a reused one. Unlike [`post_bytes`], HTTP error statuses are
+/// returned rather than reported as errors, and redirects are not
+/// followed. If a later request fails, the earlier ones are still
+/// returned.
+///
+/// # Arguments
+///
+/// * `url` - URL to post to
+/// * `body` - Request body
+/// * `content_type` - Media type of the request and the response
+/// * `version` - Protocol version to use
+/// * `requests` - Number of requests to send
+/// * `timeout` - Maximum time for each request
+pub async fn post_timed(
+    url: &str,
+    body: &[u8],
+    content_type: &str,
+    version: HttpVersion,
+    requests: usize,
+    timeout: Duration,
+) -> Result<Vec<Transfer>> {
+    let mut child = curl(url)
+        .await?
+        .args(["-sS", "--data-binary", "@-", "--max-time"])
+        .arg(timeout.as_secs_f64().to_string())
+        .arg(version.curl_flag())
+        .arg("-H")
+        .arg(format!("Content-Type: {content_type}"))
+        .arg("-H")
+        .arg(format!("Accept: {content_type}"))
+        .args(["-w", TIMING_WRITE_OUT])
+        .args(std::iter::repeat(url).take(requests.max(1)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::network(format!("Failed to run curl: {e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(body).await?;
+    }
+    let output = child.wait_with_output().await?;
+
+    // curl reports failed requests with status 000
+    let transfers: Vec<Transfer> = parse_transfers(&output.stdout)
+        .unwrap_or_default()
+        .into_iter()
+        .take_while(|transfer| transfer.status != 0)
+        .collect();
+    if transfers.is_empty() {
+        return Err(Error::network(format!(
+            "POST {url} over {version} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .next()
+                .unwrap_or("no response")
+        )));
+    }
+    Ok(transfers)
+}
+
+/// Split curl output written with [`TIMING_WRITE_OUT`] into transfers,
+/// in request order.
+fn parse_transfers(output: &[u8]) -> Result<Vec<Transfer>> {
+    let invalid = || Error::parse("Malformed curl timing output");
+    let seconds = |value: &str| {
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|s| s.is_finite() && *s >= 0.0)
+            .map(Duration::from_secs_f64)
+            .ok_or_else(invalid)
+    };
+
+    let mut transfers = Vec::new();
+    let mut rest = output;
+    while !rest.is_empty() {
+        // `<body>\n<timing>\n`, taken from the end
+        let line_end = rest.len() - 1;
+        if rest[line_end] != b'\n' {
+            return Err(invalid());
+        }
+        let line_start = rest[..line_end]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .ok_or_else(invalid)?;
+        let line = std::str::from_utf8(&rest[line_start + 1..line_end]).map_err(|_| invalid())?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [version, status, name_lookup, connect, app_connect, total, connects, local_ip, size] =
+            fields[..]
+        else {
+            return Err(invalid());
+        };
+        let size: usize = size.parse().map_err(|_| invalid())?;
+        let body_start = line_start.checked_sub(size).ok_or_else(invalid)?;
+
+        transfers.push(Transfer {
+            version: HttpVersion::from_curl(version),
+            status: status.parse().map_err(|_| invalid())?,
+            body: rest[body_start..line_start].to_vec(),
+            name_lookup: seconds(name_lookup)?,
+            connect: seconds(connect)?,
+            app_connect: seconds(app_connect)?,
+            total: seconds(total)?,
+            connects: connects.parse().map_err(|_| invalid())?,
+            local_ip: local_ip
+                .strip_prefix('[')
+                .and_then(|ip| ip.strip_suffix(']'))
+                .and_then(|ip| ip.parse().ok()),
+        });
+        rest = &rest[..body_start];
+    }
+    transfers.reverse();
+    Ok(transfers)
+}
+
+/// Escape a value for a double-quoted curl config string.
+fn curl_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curl_escape() {
+        assert_eq!(curl_escape(r#"{"a":"b\"c"}"#), r#"{\"a\":\"b\\\"c\"}"#);
+        assert_eq!(curl_escape("a\nb"), "a\\nb");
+    }
+
+    #[test]
+    fn test_host_port() {
+        assert_eq!(
+            host_port("https://dns.google/resolve?name=a"),
+            Some(("dns.google", 443))
+        );
+        assert_eq!(
+            host_port("http://user@example.com:8080"),
+            Some(("example.com", 8080))
+        );
+        assert_eq!(
+            host_port("https://[2001:db8::1]/"),
+            Some(("2001:db8::1", 443))
+        );
+        assert_eq!(host_port("https://[::1]:8443"), Some(("::1", 8443)));
+        assert_eq!(host_port("ftp://example.com"), None);
+        assert_eq!(host_port("https://example.com:port"), None);
+    }
+
+    #[test]
+    fn test_set_proxy_rejects_unknown_schemes() {
+        assert!(set_proxy("127.0.0.1:1080").is_err());
+        assert!(set_proxy("ftp://127.0.0.1").is_err());
+    }
+
+    #[test]
+    fn test_parse_transfers() {
+        let mut output = b"first\nbody".to_vec();
+        output.extend_from_slice(b"\n2 200 0.004 0.010 0.030 0.050 1 [192.0.2.1] 10\n");
+        output.extend_from_slice(b"\n1.1 404 0 0.001 0 0.0125 0 [] 0\n");
+
+        let transfers = parse_transfers(&output).unwrap();
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[0].version, Some(HttpVersion::Http2));
+        assert_eq!(transfers[0].status, 200);
+        assert_eq!(transfers[0].body, b"first\nbody");
+        assert_eq!(transfers[0].name_lookup, Duration::from_millis(4));
+        assert_eq!(transfers[0].connect, Duration::from_millis(10));
+        assert_eq!(transfers[0].app_connect, Duration::from_millis(30));
+        assert_eq!(transfers[0].connects, 1);
+        assert_eq!(transfers[1].connects, 0);
+        assert_eq!(transfers[0].local_ip, Some("192.0.2.1".parse().unwrap()));
+        assert_eq!(transfers[1].local_ip, None);
+        assert_eq!(transfers[0].total, Duration::from_millis(50));
+        assert_eq!(transfers[1].version, Some(HttpVersion::Http1));
+        assert!(transfers[1].body.is_empty());
+
+        assert!(parse_transfers(b"").unwrap().is_empty());
+        assert!(parse_transfers(b"body\n2 200 0 0 0 0.1 1 [] 99\n").is_err());
+        assert!(parse_transfers(b"\n2 200 0 0.1 0\n").is_err());
+        assert!(parse_transfers(b"2 200 0 0 0 0.1 1 [] 0").is_err());
+    }
+
+    #[test]
+    fn test_http_version() {
+        assert_eq!("h2".parse::<HttpVersion>(), Ok(HttpVersion::Http2));
+        assert_eq!("HTTP/1.1".parse::<HttpVersion>(), Ok(HttpVersion::Http1));
+        assert_eq!("3".parse::<HttpVersion>(), Ok(HttpVersion::Http3));
+        assert!("4".parse::<HttpVersion>().is_err());
+        assert_eq!(HttpVersion::Http3.to_string(), "HTTP/3");
+        assert_eq!(
+            serde_json::to_string(&HttpVersion::Http1).unwrap(),
+            "\"1.1\""
+        );
+    }
+}
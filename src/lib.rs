@@ -4,7 +4,7 @@
 //! - Testing DNS server response times using ICMP ping
 //! - Detecting DNS pollution (censorship or hijacking)
 //! - Interactive TUI for easy navigation
-//! - Multiple output formats (table, JSON, CSV, TSV)
+//! - Multiple output formats (table, JSON, CSV, TSV, YAML)
 //!
 //! # Library Usage
 //!
@@ -20,6 +20,14 @@
 //! let result = checker.check("google.com").await?;
 //! ```
 //!
+//! Programs without an async runtime can use the wrappers in [`blocking`]
+//! instead:
+//!
+//! ```ignore
+//! let tester = dnstest::blocking::SpeedTester::new()?;
+//! let result = tester.test_latency(&server);
+//! ```
+//!
 //! # CLI Usage
 //!
 //! ```bash
@@ -47,18 +55,39 @@
 //! - **DNS Speed Testing**: Measure latency to DNS servers using ICMP ping
 //! - **Pollution Detection**: Compare system DNS with public DNS to detect tampering
 //! - **Interactive TUI**: User-friendly terminal interface
-//! - **Multiple Formats**: Output results in table, JSON, CSV, or TSV format
+//! - **Multiple Formats**: Output results in table, JSON, CSV, TSV or YAML format
 //! - **IPv4/IPv6 Support**: Works with both address families
 
+pub mod alert;
+pub mod blocking;
+pub mod cache;
+pub mod chart;
+pub mod check_history;
+pub mod checkpoint;
 pub mod cli;
+pub mod compare;
 pub mod config;
 pub mod dns;
 pub mod error;
+pub mod history;
+pub mod http;
+pub mod metrics;
+pub mod monitor;
+pub mod plugin;
+pub mod push;
+pub mod report;
+pub mod self_update;
+pub mod serve;
+pub mod telemetry;
+pub mod text;
 pub mod tui;
+pub mod update_check;
 
 // Re-export commonly used types
 pub use cli::{Cli, Commands, OutputFormat};
 pub use config::ConfigLoader;
-pub use dns::types::{DnsList, DnsServer, PollutionResult, SpeedTestResult, TestSummary};
+pub use dns::types::{
+    DnsList, DnsServer, PollutionResult, ResultsDocument, SpeedTestResult, TestSummary,
+};
 pub use dns::{PollutionChecker, SpeedTester};
 pub use error::{Error, Result};
@@ -6,13 +6,54 @@
 #![warn(clippy::pedantic, clippy::nursery)]
 #![allow(clippy::uninlined_format_args)]
 
-use dnstest::cli::{Commands, OutputFormat};
-use dnstest::config::ConfigLoader;
-use dnstest::dns::{self, DnsServer, PollutionChecker, SpeedTester};
-use dnstest::error::Result;
+use dnstest::alert::{AlertRules, Alerter};
+use dnstest::cache::write_atomic;
+use dnstest::chart::ChartKind;
+use dnstest::check_history::{self, CheckChange, CheckHistory, CheckRecord};
+use dnstest::checkpoint::{Checkpoint, CheckpointWriter};
+use dnstest::cli::{
+    CacheAction, Commands, DohArgs, FeaturesArgs, GroupBy, HistoryAction, Ipv6Export, MonitorArgs,
+    NsCheckArgs, OpenResolverArgs, OutputFormat, PropagateArgs, RouteArgs, ScanArgs, SummaryFormat,
+};
+use dnstest::compare::Verdict;
+use dnstest::config::{ConfigLoader, MergePolicy, Preset, Profile, Settings};
+use dnstest::dns::delegation::{self, DelegationChecker, Issue, NsState};
+use dnstest::dns::doh::{self, DohTester};
+use dnstest::dns::egress::EgressProber;
+use dnstest::dns::features::{self, FeatureProber};
+use dnstest::dns::filtering::{Block, Category, Filtering};
+use dnstest::dns::hijack::{HijackDetector, HijackReport, ProbeKind};
+use dnstest::dns::icmp::DnsQueryProbe;
+use dnstest::dns::odoh::OdohClient;
+use dnstest::dns::open_resolver::{self, OpenResolverChecker, Recursion};
+use dnstest::dns::propagation::{self, Propagation, PropagationChecker};
+use dnstest::dns::reference_cache::ReferenceCache;
+use dnstest::dns::source::SourceBinding;
+use dnstest::dns::{
+    self, route, scan, source, Aimd, CheckVerdict, DnsList, DnsProtocol, DnsServer, DnsStatus, Hop,
+    IcmpMethod, InterceptionDetector, IpFamily, PollutionChecker, ProgressEvent, ProgressListener,
+    ProviderSummary, ResultFilter, Scanner, SpeedTester, TestMethod, TestSummary, Tracer,
+};
+use dnstest::error::{Error, Result};
+use dnstest::history::History;
+use dnstest::http::HttpVersion;
+use dnstest::monitor::{self, Monitor};
+use dnstest::plugin::Plugins;
+use dnstest::push::{Pusher, RunKind};
+use dnstest::serve::watch::{self, Watcher, Watchlist};
+use dnstest::text::wide;
 use dnstest::tui::App;
-use std::path::PathBuf;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use dnstest::update_check::{self, UpdateStatus};
+use futures::StreamExt;
+use hickory_resolver::proto::rr::RecordType;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
 /// Set up logging based on verbosity level.
 ///
@@ -20,7 +61,12 @@ use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, Env
 ///
 /// * `verbose` - Enable debug-level logging
 /// * `quiet` - Enable error-level only logging
-fn setup_logging(verbose: bool, quiet: bool) {
+/// * `telemetry` - OTLP exporter receiving the crate's spans, if enabled
+fn setup_logging(
+    verbose: bool,
+    quiet: bool,
+    #[cfg(feature = "otel")] telemetry: Option<&dnstest::telemetry::Telemetry>,
+) {
     let filter = if quiet {
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("error"))
     } else if verbose {
@@ -29,19 +75,56 @@ fn setup_logging(verbose: bool, quiet: bool) {
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
     };
 
-    tracing_subscriber::registry()
-        .with(filter)
-        .with(fmt::layer().without_time())
-        .init();
+    let registry = tracing_subscriber::registry().with(
+        fmt::layer()
+            .without_time()
+            .with_writer(std::io::stderr)
+            .with_filter(filter),
+    );
+
+    // Spans are exported regardless of the console log level
+    #[cfg(feature = "otel")]
+    let registry = registry.with(telemetry.map(|t| {
+        t.layer().with_filter(
+            tracing_subscriber::filter::Targets::new()
+                .with_target("dnstest", tracing::Level::DEBUG),
+        )
+    }));
+
+    registry.init();
+}
+
+/// Set up OTLP export (when an endpoint is configured) and logging.
+///
+/// The returned exporter flushes on drop and must outlive the command.
+#[cfg(feature = "otel")]
+fn setup_telemetry(
+    cli: &dnstest::Cli,
+    verbose: bool,
+) -> Result<Option<dnstest::telemetry::Telemetry>> {
+    let telemetry = cli
+        .otlp_endpoint
+        .as_deref()
+        .map(dnstest::telemetry::Telemetry::init)
+        .transpose()?;
+    setup_logging(verbose, cli.quiet, telemetry.as_ref());
+    Ok(telemetry)
 }
 
 /// Load DNS server list from file or command-line arguments.
 ///
+/// Command-line servers and files take precedence over the profile's.
+///
 /// # Arguments
 ///
 /// * `file` - Optional path to DNS list JSON file
 /// * `dns_args` - Optional command-line DNS server specifications (IP#Name)
-fn load_dns_list(file: Option<PathBuf>, dns_args: Vec<String>) -> Result<Vec<DnsServer>> {
+/// * `profile` - Active profile
+fn load_dns_list(
+    file: Option<PathBuf>,
+    dns_args: Vec<String>,
+    profile: &Profile,
+) -> Result<Vec<DnsServer>> {
     if !dns_args.is_empty() {
         let list = ConfigLoader::from_args(dns_args)?;
         return Ok(list.servers);
@@ -49,55 +132,445 @@ fn load_dns_list(file: Option<PathBuf>, dns_args: Vec<String>) -> Result<Vec<Dns
 
     if let Some(path) = file {
         let list = ConfigLoader::load_from_file(path)?;
+        return Ok(enabled(list.servers));
+    }
+
+    if !profile.servers.is_empty() {
+        let list = ConfigLoader::from_args(profile.servers.clone())?;
         return Ok(list.servers);
     }
 
+    if let Some(path) = &profile.dns_list {
+        let list = ConfigLoader::load_from_file(path)?;
+        return Ok(enabled(list.servers));
+    }
+
     // Try to load default
     let lists = ConfigLoader::load_all()?;
-    Ok(ConfigLoader::merge(lists).servers)
+    Ok(enabled(ConfigLoader::merge(lists).servers))
+}
+
+/// Leave out the servers disabled in a list file.
+fn enabled(mut servers: Vec<DnsServer>) -> Vec<DnsServer> {
+    servers.retain(|s| !s.disabled);
+    servers
+}
+
+/// DNS list files that [`load_dns_list`] reads for the same arguments,
+/// none for servers given as `IP#Name`.
+fn list_files(file: Option<&Path>, dns_args: &[String], profile: &Profile) -> Vec<PathBuf> {
+    if !dns_args.is_empty() {
+        return Vec::new();
+    }
+    if let Some(path) = file {
+        return vec![path.to_path_buf()];
+    }
+    if !profile.servers.is_empty() {
+        return Vec::new();
+    }
+    if let Some(path) = &profile.dns_list {
+        return vec![path.clone()];
+    }
+    [dnstest::config::LIST_FILE, dnstest::config::LIST_V6_FILE]
+        .into_iter()
+        .map(ConfigLoader::list_path)
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Timeout for downloading one DNS list with `update --source`.
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Exit status when a health threshold was crossed: `speed --fail-over-ms`
+/// or `--min-success`, or an alert during `monitor --rounds`.
+const EXIT_THRESHOLD: u8 = 3;
+
+/// Exit status of `check` when pollution was detected.
+const EXIT_POLLUTED: u8 = 4;
+
+/// Exit status of `check` when the result is suspicious.
+const EXIT_SUSPICIOUS: u8 = 5;
+
+/// Number of latest alert events shown below the `monitor` table.
+const MONITOR_ALERT_LINES: usize = 5;
+
+/// Settings shared by the commands that run tests.
+struct Context {
+    profile: Profile,
+    /// Record type of the DNS-query probe, if not the default
+    query_type: Option<RecordType>,
+    /// What speed tests measure
+    method: TestMethod,
+    /// Name asked for by DNS-query probes, if not the default
+    query_name: Option<String>,
+    /// Measure servers that answer no ping over TCP
+    tcp_fallback: bool,
+    /// Seed of the order servers are tested in, list order if `None`
+    shuffle: Option<u64>,
+    plugins: Plugins,
+    push: Option<Pusher>,
+    alerts: AlertRules,
+    watch: Watchlist,
+}
+
+impl Context {
+    /// Combine the settings file with command-line and environment
+    /// overrides.
+    fn new(
+        settings: Settings,
+        profile: Option<&str>,
+        push_url: Option<String>,
+        push_token: Option<String>,
+    ) -> Result<Self> {
+        let profile = settings.resolve(profile)?;
+        let push = push_url
+            .or(settings.push.url)
+            .map(|url| Pusher::new(url, push_token.or(settings.push.token)));
+        Ok(Self {
+            profile,
+            query_type: None,
+            method: TestMethod::default(),
+            query_name: None,
+            tcp_fallback: false,
+            shuffle: None,
+            plugins: Plugins::new(settings.plugins),
+            push,
+            alerts: settings.alerts,
+            watch: settings.watch,
+        })
+    }
+
+    /// Apply speed test options given on the command line.
+    fn override_speed(
+        &mut self,
+        count: Option<usize>,
+        timeout: Option<u64>,
+        timeout_ms: Option<u64>,
+        concurrency: Option<usize>,
+        query_type: Option<RecordType>,
+    ) {
+        self.profile.count = count.or(self.profile.count);
+        if timeout.is_some() || timeout_ms.is_some() {
+            self.profile.timeout = timeout;
+            self.profile.timeout_ms = timeout_ms;
+        }
+        self.profile.concurrency = concurrency.or(self.profile.concurrency);
+        self.query_type = query_type;
+    }
+
+    /// Create a speed tester with the profile's ping settings.
+    fn speed_tester(&self) -> Result<SpeedTester> {
+        let query = DnsQueryProbe::new(
+            self.query_name
+                .as_deref()
+                .unwrap_or(dns::icmp::DEFAULT_PROBE_NAME),
+            self.query_type.unwrap_or(dns::icmp::DEFAULT_PROBE_TYPE),
+        );
+        let tester = SpeedTester::with_method(
+            self.profile
+                .ping_timeout()
+                .unwrap_or(Duration::from_secs(dns::speedtest::DEFAULT_TIMEOUT_SECS)),
+            self.profile
+                .count
+                .unwrap_or(dns::speedtest::DEFAULT_PING_COUNT),
+            self.method,
+            query.clone(),
+        )?;
+        let tester = if self.tcp_fallback && !tester.icmp_method().is_dns_query() {
+            tester.with_tcp_fallback(query)
+        } else {
+            tester
+        };
+        if (self.query_type.is_some() || self.query_name.is_some())
+            && !self.tcp_fallback
+            && !tester.icmp_method().is_dns_query()
+        {
+            tracing::warn!(
+                "--qtype and --query-name have no effect: latency is measured with ICMP ping"
+            );
+        }
+        Ok(tester)
+    }
+
+    /// Number of servers to test at the same time.
+    fn concurrency(&self) -> usize {
+        self.profile.concurrency.unwrap_or(1).max(1)
+    }
+
+    /// How many servers a speed test run tests at the same time: adapted
+    /// to the timeout rate up to `concurrency` if `adaptive` is set, or
+    /// else always `concurrency`.
+    fn test_concurrency(&self) -> Concurrency {
+        if self.profile.adaptive.unwrap_or(false) {
+            Concurrency::Adaptive(
+                self.profile
+                    .concurrency
+                    .unwrap_or(dns::adaptive::DEFAULT_MAX)
+                    .max(1),
+            )
+        } else {
+            Concurrency::Fixed(self.concurrency())
+        }
+    }
+
+    /// Create a pollution checker with the profile's reference resolvers
+    /// and threshold.
+    fn pollution_checker(&self) -> Result<PollutionChecker> {
+        let checker = match (self.profile.lookup_timeout, self.profile.lookup_attempts) {
+            (None, None) => PollutionChecker::new()?,
+            (timeout, attempts) => PollutionChecker::new()?.with_lookup_timeout(
+                timeout.map_or(dns::pollution::DEFAULT_LOOKUP_TIMEOUT, Duration::from_secs),
+                attempts.unwrap_or(dns::pollution::DEFAULT_LOOKUP_ATTEMPTS),
+            ),
+        };
+        let checker = checker
+            .with_reference_resolvers(&self.profile.reference_resolvers)?
+            .with_doh_json(self.profile.doh_json.clone());
+        let checker = match &self.profile.odoh_relay {
+            Some(relay) => checker.with_odoh(OdohClient::new(
+                self.profile
+                    .odoh_target
+                    .as_deref()
+                    .unwrap_or(dns::odoh::DEFAULT_TARGET),
+                relay,
+            )),
+            None => checker,
+        };
+        let checker = if self.profile.reference_cache.unwrap_or(true) {
+            checker.with_reference_cache(ReferenceCache::load(ReferenceCache::default_path()))
+        } else {
+            checker
+        };
+        Ok(match self.profile.overlap_threshold {
+            Some(threshold) => checker.with_overlap_threshold(threshold),
+            None => checker,
+        })
+    }
+
+    /// Checker of the `[watch]` domains on each watched resolver, if any
+    /// domains are configured.
+    fn watcher(&self) -> Result<Option<Watcher>> {
+        if self.watch.domains.is_empty() {
+            return Ok(None);
+        }
+        let watcher = Watcher::new(self.watch.domains.clone()).with_concurrency(
+            self.profile
+                .concurrency
+                .unwrap_or(watch::DEFAULT_CONCURRENCY),
+        );
+        if self.watch.resolvers.is_empty() {
+            return Ok(Some(
+                watcher.with_resolver(watch::SYSTEM_RESOLVER, self.pollution_checker()?),
+            ));
+        }
+        let mut watcher = watcher;
+        for server in ConfigLoader::from_args(self.watch.resolvers.clone())?.servers {
+            let ip = server
+                .ip
+                .parse()
+                .ok()
+                .filter(|_| {
+                    server.protocol == DnsProtocol::Plain && server.port.unwrap_or(53) == 53
+                })
+                .ok_or_else(|| {
+                    Error::config(format!(
+                        "Watch resolver {} must be a plain DNS server on port 53",
+                        server.name
+                    ))
+                })?;
+            let checker = self.pollution_checker()?.with_system_resolver(&[ip]);
+            watcher = watcher.with_resolver(server.name, checker);
+        }
+        Ok(Some(watcher))
+    }
+
+    /// Push run results if an endpoint is configured.
+    ///
+    /// The results are already printed by then, so a failed push is
+    /// logged rather than failing the command, and the notice goes to
+    /// stderr to keep JSON or CSV output on stdout intact.
+    async fn push<T: serde::Serialize + Sync>(&self, kind: RunKind, results: &T) {
+        if let Some(pusher) = &self.push {
+            match pusher.push(kind, results).await {
+                Ok(()) => eprintln!("结果已推送到: {}", pusher.url()),
+                Err(e) => tracing::warn!("Failed to push results to {}: {e}", pusher.url()),
+            }
+        }
+    }
+}
+
+/// Options for the `speed` command.
+#[allow(clippy::struct_excessive_bools)] // one per command-line switch
+struct SpeedOptions {
+    file: Option<PathBuf>,
+    dns_servers: Vec<String>,
+    /// Built-in list tested instead of `file` or `dns_servers`
+    preset: Option<Preset>,
+    /// Countries to keep, all if empty
+    countries: Vec<String>,
+    /// Address family to keep, both if `None`
+    family: Option<IpFamily>,
+    sort_by_latency: bool,
+    /// Results printed; the history, pushes and statistics keep them all
+    filter: ResultFilter,
+    /// Keep the round-trip time of every ping in the printed results
+    samples: bool,
+    group_by: Option<GroupBy>,
+    /// Append the run to the history
+    record: bool,
+    /// Write the results back into the DNS list files
+    save_results: bool,
+    /// File to write the formatted results to
+    output: Option<PathBuf>,
+    /// Previous results whose failed servers are retested
+    retest_from: Option<PathBuf>,
+    /// Reuse the results saved by an interrupted run
+    resume: bool,
+    /// Look up the egress of each resolver
+    egress: bool,
+    /// Whether to print the statistics, and the results with them
+    summary: SummaryOutput,
+    /// Format of the statistics, derived from the output format if `None`
+    summary_format: Option<SummaryFormat>,
+}
+
+/// Where `speed` prints its statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SummaryOutput {
+    /// After the results: on stdout for tables, on stderr for machine
+    /// formats so that they stay parseable
+    After,
+    /// Instead of the results, on stdout
+    Only,
+    /// Not at all
+    None,
+}
+
+/// Print a status line of `speed`, on stderr unless stdout carries the
+/// table, so that machine formats stay parseable.
+fn print_status(format: OutputFormat, line: std::fmt::Arguments) {
+    if format == OutputFormat::Table {
+        println!("{line}");
+    } else {
+        eprintln!("{line}");
+    }
+}
+
+/// Append tested results to the history if `record` is set, and write
+/// them back into the DNS list files at `list_paths`.
+fn save_run(
+    results: &[dns::SpeedTestResult],
+    record: bool,
+    list_paths: &[PathBuf],
+    format: OutputFormat,
+) -> Result<()> {
+    if record && !results.is_empty() {
+        if let Err(e) = History::open_default().append(results) {
+            tracing::warn!("Failed to record history: {e}");
+        }
+    }
+    for path in list_paths {
+        let updated = ConfigLoader::save_results(path, results)?;
+        print_status(
+            format,
+            format_args!(
+                "已更新 {} 中 {updated} 个服务器的延迟和状态",
+                path.display()
+            ),
+        );
+    }
+    Ok(())
 }
 
 /// Run DNS speed test and output results.
 ///
 /// # Arguments
 ///
-/// * `file` - Optional DNS list file
-/// * `dns_servers` - Optional custom DNS servers
-/// * `sort_by_latency` - Whether to sort results by latency
+/// * `opts` - Servers to test and how to output the results
 /// * `format` - Output format
+/// * `ctx` - Plugins and push endpoint
+///
+/// With `opts.retest_from`, only the failed servers of that run are tested
+/// and only their new results are recorded and pushed; the output and
+/// summary cover the merged run.
+///
+/// Returns the summary of the run for `--fail-over-ms` / `--min-success`.
 async fn run_speed_test(
-    file: Option<PathBuf>,
-    dns_servers: Vec<String>,
-    sort_by_latency: bool,
+    opts: SpeedOptions,
     format: OutputFormat,
-) -> Result<()> {
-    println!("加载DNS列表...");
-    let servers = load_dns_list(file, dns_servers)?;
-
-    println!("开始DNS测速 (共 {} 个服务器)...\n", servers.len());
+    ctx: &Context,
+) -> Result<TestSummary> {
+    let previous = opts
+        .retest_from
+        .as_deref()
+        .map(dns::SpeedTestResult::from_json_file)
+        .transpose()?;
+    let mut list_paths = Vec::new();
+    let servers = if let Some(previous) = &previous {
+        previous
+            .iter()
+            .filter(|r| !r.success)
+            .map(|r| r.server.clone())
+            .collect()
+    } else {
+        print_status(format, format_args!("加载DNS列表..."));
+        if opts.save_results {
+            list_paths = list_files(opts.file.as_deref(), &opts.dns_servers, &ctx.profile);
+            if list_paths.is_empty() {
+                tracing::warn!(
+                    "Servers come from the profile, not a DNS list file; nothing to save"
+                );
+            }
+        }
+        let mut servers = match opts.preset {
+            Some(preset) => preset.servers().await?,
+            None => load_dns_list(opts.file, opts.dns_servers, &ctx.profile)?,
+        };
+        if let Some(family) = opts.family {
+            servers.retain(|s| s.in_family(family));
+        }
+        let servers = filter_country(servers, &opts.countries).await;
+        if servers.is_empty() {
+            return Err(Error::config("没有符合条件的DNS服务器"));
+        }
+        servers
+    };
 
-    let tester = SpeedTester::new()?;
-    let mut results = Vec::new();
-    let total = servers.len();
+    let mut retested = if servers.is_empty() {
+        print_status(format, format_args!("没有失败的服务器，无需重测\n"));
+        Vec::new()
+    } else if previous.is_some() {
+        test_servers(&servers, ctx, None).await?
+    } else {
+        test_with_checkpoint(&servers, opts.resume, format, ctx).await?
+    };
+    ctx.plugins.check_servers(&mut retested).await;
+    if opts.egress && !retested.is_empty() {
+        print_status(format, format_args!("查询解析器出口地址..."));
+        EgressProber::new().fill(&mut retested).await;
+    }
 
-    for (idx, server) in servers.iter().enumerate() {
-        print!(
-            "\r测速中 [{:>3}/{}] {} ({})",
-            idx + 1,
-            total,
-            server.name,
-            server.ip
-        );
-        std::io::Write::flush(&mut std::io::stdout())?;
+    save_run(&retested, opts.record, &list_paths, format)?;
 
-        let result = tester.test_latency(server).await;
-        results.push(result);
+    let mut results = retested.clone();
+    if let Some(previous) = previous {
+        results = SpeedTester::merge_retest(previous, results);
+    }
+    if let (Some(path), None) = (&opts.retest_from, &opts.output) {
+        write_atomic(path, &format_results_json(&results))?;
+        print_status(format, format_args!("结果已合并到: {}", path.display()));
     }
 
-    println!("\n");
+    let summary = SpeedTester::summarize(&results);
+    opts.filter.apply(&mut results);
+    if !opts.samples {
+        for result in &mut results {
+            result.samples.clear();
+        }
+    }
 
     // Sort if requested
-    if sort_by_latency {
+    if opts.sort_by_latency {
         results.sort_by(|a, b| {
             let a_lat = a.latency_ms.unwrap_or(f64::MAX);
             let b_lat = b.latency_ms.unwrap_or(f64::MAX);
@@ -108,90 +581,496 @@ async fn run_speed_test(
     }
 
     // Output results
-    match format {
-        OutputFormat::Table => print_results_table(&results),
-        OutputFormat::Json => print_results_json(&results),
-        OutputFormat::Csv => print_results_csv(&results),
-        OutputFormat::Tsv => print_results_tsv(&results),
+    let formatted = match (opts.group_by, format) {
+        // OpenMetrics keeps per-server series, which can be aggregated
+        // by the `provider` label instead
+        (_, OutputFormat::OpenMetrics) => {
+            dnstest::metrics::render_openmetrics(&results, chrono::Utc::now())
+        }
+        (Some(GroupBy::Provider), format) => {
+            format_providers(&SpeedTester::summarize_by_provider(&results), format)
+        }
+        (None, OutputFormat::Table) => format_results_table(&results),
+        (None, OutputFormat::Json) => format_results_json(&results),
+        (None, OutputFormat::Csv) => format_results_csv(&results),
+        (None, OutputFormat::Tsv) => format_results_tsv(&results),
+        (None, OutputFormat::Yaml) => serde_norway::to_string(&results).unwrap(),
+    };
+    if let Some(path) = &opts.output {
+        write_atomic(path, &formatted)?;
+        print_status(format, format_args!("结果已保存到: {}", path.display()));
+    } else if opts.summary != SummaryOutput::Only {
+        print!("{formatted}");
     }
 
-    // Summary
-    let summary = SpeedTester::summarize(&results);
-    println!("\n=== 统计 ===");
-    println!("总服务器数: {}", summary.total);
-    println!("成功: {}", summary.success);
-    println!("失败/超时: {}", summary.failed + summary.timeout);
+    print_summary(&summary, opts.summary, opts.summary_format, format)?;
+
+    ctx.push(RunKind::Speed, &retested).await;
+    Ok(summary)
+}
+
+/// Print the statistics of a speed test run where `output` asks for them.
+///
+/// Without `summary_format`, they are formatted as JSON for JSON results
+/// and as a table otherwise.
+fn print_summary(
+    summary: &TestSummary,
+    output: SummaryOutput,
+    summary_format: Option<SummaryFormat>,
+    format: OutputFormat,
+) -> Result<()> {
+    let summary_format = summary_format.unwrap_or(if format == OutputFormat::Json {
+        SummaryFormat::Json
+    } else {
+        SummaryFormat::Table
+    });
+    let text = format_summary(summary, summary_format)?;
+    match output {
+        // Keep stdout parseable for machine-readable formats
+        SummaryOutput::After if format != OutputFormat::Table => eprint!("{text}"),
+        SummaryOutput::After => print!("\n{text}"),
+        SummaryOutput::Only => print!("{text}"),
+        SummaryOutput::None => {}
+    }
+    Ok(())
+}
+
+/// Format the statistics of a speed test run.
+fn format_summary(summary: &TestSummary, format: SummaryFormat) -> Result<String> {
+    if format == SummaryFormat::Json {
+        return Ok(serde_json::to_string_pretty(summary)? + "\n");
+    }
+    let mut out = String::from("=== 统计 ===\n");
+    let _ = writeln!(out, "总服务器数: {}", summary.total);
+    let _ = writeln!(out, "成功: {}", summary.success);
+    let _ = writeln!(out, "失败/超时: {}", summary.failed + summary.timeout);
     if let Some(avg) = summary.avg_latency {
-        println!("平均延迟: {avg:.2} ms");
+        let _ = writeln!(out, "平均延迟: {avg:.2} ms");
     }
     if let Some(min) = summary.min_latency {
-        println!("最低延迟: {min:.2} ms");
+        let _ = writeln!(out, "最低延迟: {min:.2} ms");
     }
     if let Some(max) = summary.max_latency {
-        println!("最高延迟: {max:.2} ms");
+        let _ = writeln!(out, "最高延迟: {max:.2} ms");
     }
+    Ok(out)
+}
 
-    Ok(())
+/// Check a speed test against the `--fail-over-ms` / `--min-success`
+/// thresholds, printing each one that was crossed.
+///
+/// Returns whether the run passed.
+fn speed_gate(summary: &TestSummary, fail_over_ms: Option<f64>, min_success: Option<f64>) -> bool {
+    let mut passed = true;
+    if let Some(bound) = fail_over_ms {
+        match summary.min_latency {
+            Some(best) if best <= bound => {}
+            Some(best) => {
+                eprintln!("未通过: 最低延迟 {best:.2} ms 超过 {bound} ms");
+                passed = false;
+            }
+            None => {
+                eprintln!("未通过: 没有服务器响应");
+                passed = false;
+            }
+        }
+    }
+    if let Some(percent) = min_success {
+        let rate = summary.success_rate();
+        if rate < percent {
+            eprintln!("未通过: 成功率 {rate:.1}% 低于 {percent}%");
+            passed = false;
+        }
+    }
+    passed
+}
+
+/// Test servers, saving each result to the checkpoint so that an
+/// interrupted run can be continued with `resume`.
+///
+/// The checkpoint is removed once every server is tested. Results stay in
+/// list order, including those reused from the checkpoint.
+async fn test_with_checkpoint(
+    servers: &[DnsServer],
+    resume: bool,
+    format: OutputFormat,
+    ctx: &Context,
+) -> Result<Vec<dns::SpeedTestResult>> {
+    let checkpoint = Checkpoint::open_default();
+    let done = if resume {
+        checkpoint.resume(servers)?
+    } else {
+        Vec::new()
+    };
+    if resume {
+        print_status(format, format_args!("从检查点恢复 {} 个结果", done.len()));
+    }
+
+    let done_specs: HashSet<String> = done.iter().map(|r| r.server.spec()).collect();
+    let remaining: Vec<DnsServer> = servers
+        .iter()
+        .filter(|server| !done_specs.contains(&server.spec()))
+        .cloned()
+        .collect();
+    let mut writer = checkpoint
+        .writer(resume)
+        .map_err(|e| tracing::warn!("Failed to open checkpoint: {e}"))
+        .ok();
+    let tested = test_servers(&remaining, ctx, writer.as_mut()).await?;
+    drop(writer);
+    if let Err(e) = checkpoint.remove() {
+        tracing::warn!("Failed to remove checkpoint: {e}");
+    }
+
+    let order: HashMap<String, usize> = servers
+        .iter()
+        .enumerate()
+        .map(|(i, server)| (server.spec(), i))
+        .collect();
+    let mut results: Vec<_> = done.into_iter().chain(tested).collect();
+    results.sort_by_cached_key(|r| order.get(&r.server.spec()).copied());
+    Ok(results)
+}
+
+/// Number of servers a speed test run tests at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Concurrency {
+    /// Always this many
+    Fixed(usize),
+    /// Adapted to the timeout rate, up to this many
+    Adaptive(usize),
+}
+
+/// Test servers, printing progress as each one finishes.
+///
+/// # Arguments
+///
+/// * `servers` - DNS servers to test
+/// * `ctx` - Speed test settings, concurrency and test order
+/// * `checkpoint` - Where to save each result as it finishes
+async fn test_servers(
+    servers: &[DnsServer],
+    ctx: &Context,
+    mut checkpoint: Option<&mut CheckpointWriter>,
+) -> Result<Vec<dns::SpeedTestResult>> {
+    let tester = &ctx.speed_tester()?;
+    let concurrency = ctx.test_concurrency();
+    let mut adaptive = match concurrency {
+        Concurrency::Fixed(_) => None,
+        Concurrency::Adaptive(max) => Some(Aimd::new(max)),
+    };
+    let mut parallel = match concurrency {
+        Concurrency::Fixed(n) => format!("并发 {n}"),
+        Concurrency::Adaptive(max) => format!("自适应并发, 最多 {max}"),
+    };
+    // The seed is shown so that a random order can be repeated
+    if let Some(seed) = ctx.shuffle {
+        let _ = write!(parallel, ", 随机顺序 --shuffle-seed {seed}");
+    }
+    eprintln!(
+        "开始DNS测速 (共 {} 个服务器, 每个 {} 次, 超时 {} ms, {parallel})...\n",
+        servers.len(),
+        tester.ping_count(),
+        tester.timeout().as_millis()
+    );
+
+    let total = servers.len();
+    let mut results = Vec::with_capacity(total);
+
+    let mut queue: Vec<(usize, &DnsServer)> = servers.iter().enumerate().collect();
+    if let Some(seed) = ctx.shuffle {
+        dns::speedtest::shuffle(&mut queue, seed);
+    }
+    let mut queue = queue.into_iter();
+    let mut pending = futures::stream::FuturesUnordered::new();
+    let progress = Progress::new("测速中");
+    progress.on_event(ProgressEvent::Started { total });
+    loop {
+        let limit = match (&adaptive, concurrency) {
+            (Some(aimd), _) => aimd.limit(),
+            (None, Concurrency::Fixed(n) | Concurrency::Adaptive(n)) => n,
+        };
+        while pending.len() < limit {
+            let Some((index, server)) = queue.next() else {
+                break;
+            };
+            pending.push(async move { (index, tester.test_latency(server).await) });
+        }
+        let Some((index, result)) = pending.next().await else {
+            break;
+        };
+        if let Some(aimd) = &mut adaptive {
+            aimd.record(result.is_timeout());
+        }
+        if let Some(writer) = checkpoint.as_deref_mut() {
+            if let Err(e) = writer.record(&result) {
+                tracing::warn!("Failed to save checkpoint: {e}");
+            }
+        }
+        results.push((index, result.clone()));
+        progress.on_event(ProgressEvent::ServerDone {
+            done: results.len(),
+            total,
+            result: Box::new(result),
+        });
+    }
+
+    progress.on_event(ProgressEvent::Finished {
+        total,
+        succeeded: results.iter().filter(|(_, r)| r.success).count(),
+    });
+    if let Some(aimd) = adaptive {
+        eprintln!("自适应并发: 最高 {}, 结束时 {}", aimd.peak(), aimd.limit());
+    }
+    // Results stay in list order regardless of which ping finishes first
+    results.sort_by_key(|(index, _)| *index);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+/// Progress counter redrawn in place on stderr.
+///
+/// It is shown only when stderr is a terminal, so that redirected output,
+/// logs of scheduled runs and pipes are not filled with partial lines.
+struct Progress {
+    label: &'static str,
+    enabled: bool,
+}
+
+impl Progress {
+    fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            enabled: std::io::IsTerminal::is_terminal(&std::io::stderr()),
+        }
+    }
+
+    /// Redraw the counter, followed by `detail` if not empty.
+    fn update(&self, done: usize, total: usize, detail: &str) {
+        if !self.enabled {
+            return;
+        }
+        let digits = total.to_string().len();
+        // Erase the rest of a longer previous line
+        eprint!("\r\x1b[K{} [{done:>digits$}/{total}] {detail}", self.label);
+        let _ = std::io::Write::flush(&mut std::io::stderr());
+    }
+
+    /// Clear the counter.
+    fn finish(&self) {
+        if self.enabled {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+impl ProgressListener for Progress {
+    fn on_event(&self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::ServerDone {
+                done,
+                total,
+                result,
+            } => self.update(
+                done,
+                total,
+                &format!("{} ({})", result.server.name, result.server.ip),
+            ),
+            ProgressEvent::Finished { .. } => self.finish(),
+            ProgressEvent::Started { .. } | ProgressEvent::BatchDelay { .. } => {}
+        }
+    }
 }
 
-/// Print results in table format.
-fn print_results_table(results: &[dns::SpeedTestResult]) {
-    println!("{:<4} {:<20} {:<18} {:<12}", "#", "名称", "IP", "延迟");
-    println!("{}", "-".repeat(60));
+/// Format results in table format.
+fn format_results_table(results: &[dns::SpeedTestResult]) -> String {
+    // Spread columns only where individual round trips were recorded
+    let spread = results.iter().any(|r| r.stats.is_some());
+    let mut out = format!(
+        "{:<4} {:<20} {:<18} {:<12} {:<4}",
+        "#",
+        wide("名称"),
+        "IP",
+        wide("延迟"),
+        wide("跳数")
+    );
+    if spread {
+        for label in ["最低", "最高", "中位", "抖动", "P95"] {
+            let _ = write!(out, " {:>7}", wide(label));
+        }
+    }
+    out.push('\n');
+    let _ = writeln!(out, "{}", "-".repeat(if spread { 106 } else { 66 }));
 
     for (idx, r) in results.iter().enumerate() {
         let latency = r
             .latency_ms
             .map_or_else(|| "Timeout".to_string(), |l| format!("{l:.1} ms"));
+        // Mark latencies that are DNS round trips rather than pings
+        let latency = match r.method {
+            Some(IcmpMethod::DnsQuery) if r.success => format!("{latency} UDP"),
+            Some(IcmpMethod::TcpQuery) if r.success => format!("{latency} TCP"),
+            _ => latency,
+        };
 
         let status = if r.success { "" } else { "[失败] " };
 
-        println!(
-            "{:<4} {:<20} {:<18} {:<12}",
+        let hops = r.hops.map_or_else(|| "-".to_string(), |h| h.to_string());
+
+        let _ = write!(
+            out,
+            "{:<4} {:<20} {:<18} {:<12} {:<4}",
             idx + 1,
-            format!("{}{}", status, r.server.name),
+            wide(&format!("{}{}", status, r.server.name)),
             r.server.ip,
-            latency
+            latency,
+            hops
         );
+        if spread {
+            for value in stats_columns(r) {
+                let _ = write!(out, " {:>7}", if value.is_empty() { "-" } else { &value });
+            }
+        }
+        out.push('\n');
+        if let Some(egress) = &r.egress {
+            let _ = writeln!(out, "     └ 出口 {egress}");
+        }
+        for verdict in &r.plugins {
+            let _ = writeln!(out, "     └ 插件 {verdict}");
+        }
+    }
+    out
+}
+
+/// Format per-provider summaries.
+fn format_providers(summaries: &[ProviderSummary], format: OutputFormat) -> String {
+    let ms = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |ms| format!("{ms:.1}"));
+    let mut out = String::new();
+    match format {
+        OutputFormat::Json => {
+            out = serde_json::to_string_pretty(summaries).unwrap();
+            out.push('\n');
+        }
+        OutputFormat::Yaml => out = serde_norway::to_string(summaries).unwrap(),
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let sep = if format == OutputFormat::Csv {
+                ","
+            } else {
+                "\t"
+            };
+            let header = [
+                "Provider", "Servers", "Success", "Best(ms)", "BestIP", "Avg(ms)",
+            ];
+            let _ = writeln!(out, "{}", header.join(sep));
+            for s in summaries {
+                let row = [
+                    s.provider.clone(),
+                    s.servers.to_string(),
+                    s.success.to_string(),
+                    s.best_ms.map_or_else(String::new, |ms| format!("{ms:.1}")),
+                    s.best_ip.clone().unwrap_or_default(),
+                    s.avg_ms.map_or_else(String::new, |ms| format!("{ms:.1}")),
+                ];
+                let _ = writeln!(out, "{}", row.join(sep));
+            }
+        }
+        OutputFormat::Table | OutputFormat::OpenMetrics => {
+            let _ = writeln!(
+                out,
+                "{:<20} {:>6} {:>6} {:>10} {:<24} {:>10}",
+                wide("提供商"),
+                wide("服务器"),
+                wide("成功"),
+                wide("最低延迟"),
+                wide("最快地址"),
+                wide("平均延迟")
+            );
+            let _ = writeln!(out, "{}", "-".repeat(82));
+            for s in summaries {
+                let _ = writeln!(
+                    out,
+                    "{:<20} {:>6} {:>6} {:>10} {:<24} {:>10}",
+                    wide(&s.provider),
+                    s.servers,
+                    s.success,
+                    ms(s.best_ms),
+                    s.best_ip.as_deref().unwrap_or("-"),
+                    ms(s.avg_ms)
+                );
+            }
+        }
     }
+    out
 }
 
-/// Print results in JSON format.
-fn print_results_json(results: &[dns::SpeedTestResult]) {
-    let json = serde_json::to_string_pretty(results).unwrap();
-    println!("{json}");
+/// Format results in JSON format, as a versioned results document.
+fn format_results_json(results: &[dns::SpeedTestResult]) -> String {
+    dns::ResultsDocument::new(results.to_vec())
+        .to_json()
+        .unwrap()
 }
 
-/// Print results in CSV format.
-fn print_results_csv(results: &[dns::SpeedTestResult]) {
-    println!("#Idx,Name,IP,Latency(ms),Success");
+/// Format results in CSV format.
+fn format_results_csv(results: &[dns::SpeedTestResult]) -> String {
+    let mut out = format!(
+        "#Idx,Name,IP,Latency(ms),Success,TTL,Hops,{}\n",
+        STATS_HEADER.join(",")
+    );
     for (idx, r) in results.iter().enumerate() {
         let latency = r.latency_ms.unwrap_or(-1.0);
-        println!(
-            "{},{},{},{:.1},{}",
+        let _ = writeln!(
+            out,
+            "{},{},{},{:.1},{},{},{},{}",
             idx + 1,
             r.server.name,
             r.server.ip,
             latency,
-            r.success
+            r.success,
+            optional(r.ttl),
+            optional(r.hops),
+            stats_columns(r).join(",")
         );
     }
+    out
 }
 
-/// Print results in TSV format.
-fn print_results_tsv(results: &[dns::SpeedTestResult]) {
-    println!("#\tName\tIP\tLatency(ms)\tSuccess");
+/// Format results in TSV format.
+fn format_results_tsv(results: &[dns::SpeedTestResult]) -> String {
+    let mut out = format!(
+        "#\tName\tIP\tLatency(ms)\tSuccess\tTTL\tHops\t{}\n",
+        STATS_HEADER.join("\t")
+    );
     for (idx, r) in results.iter().enumerate() {
         let latency = r.latency_ms.unwrap_or(-1.0);
-        println!(
-            "{}\t{}\t{}\t{:.1}\t{}",
+        let _ = writeln!(
+            out,
+            "{}\t{}\t{}\t{:.1}\t{}\t{}\t{}\t{}",
             idx + 1,
             r.server.name,
             r.server.ip,
             latency,
-            r.success
+            r.success,
+            optional(r.ttl),
+            optional(r.hops),
+            stats_columns(r).join("\t")
         );
     }
+    out
+}
+
+/// CSV/TSV headers of the latency spread columns.
+const STATS_HEADER: [&str; 5] = ["Min(ms)", "Max(ms)", "Median(ms)", "StdDev(ms)", "P95(ms)"];
+
+/// Latency spread of a result in the order of [`STATS_HEADER`], empty
+/// when no round trips were recorded.
+fn stats_columns(result: &dns::SpeedTestResult) -> [String; 5] {
+    result.stats.map_or_else(Default::default, |s| {
+        [s.min_ms, s.max_ms, s.median_ms, s.stddev_ms, s.p95_ms].map(|ms| format!("{ms:.1}"))
+    })
+}
+
+/// Format an optional value for CSV/TSV, empty when absent.
+fn optional(value: Option<u8>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
 }
 
 /// Run DNS pollution check for a domain.
@@ -199,13 +1078,24 @@ fn print_results_tsv(results: &[dns::SpeedTestResult]) {
 /// # Arguments
 ///
 /// * `domain` - Domain name to check
+/// * `whois` - Look up RDAP data of mismatched IPs
 /// * `format` - Output format
-async fn run_pollution_check(domain: String, format: OutputFormat) -> Result<()> {
+/// * `ctx` - Plugins and push endpoint
+///
+/// Returns the verdict for the exit status.
+async fn run_pollution_check(
+    domain: String,
+    whois: bool,
+    history: CheckHistoryOptions,
+    format: OutputFormat,
+    ctx: &Context,
+) -> Result<CheckVerdict> {
     println!("检测域名: {domain}");
     println!("正在解析...\n");
 
-    let checker = PollutionChecker::new()?;
-    let result = checker.check(&domain).await?;
+    let checker = ctx.pollution_checker()?.with_rdap(whois);
+    let mut result = checker.check(&domain).await?;
+    ctx.plugins.check_domain(&mut result).await;
 
     if format == OutputFormat::Json {
         let json = serde_json::to_string_pretty(&result).unwrap();
@@ -213,30 +1103,1206 @@ async fn run_pollution_check(domain: String, format: OutputFormat) -> Result<()>
     } else {
         println!("域名: {}", result.domain);
         println!("系统DNS解析: {:?}", result.system_ips);
-        println!("公共DNS解析: {:?}", result.public_ips);
-        println!(
-            "污染检测: {}",
-            if result.is_polluted {
-                "可能污染"
+        let reference = if ctx.profile.odoh_relay.is_some() {
+            " (ODoH)"
+        } else if ctx.profile.doh_json.is_empty() {
+            ""
+        } else {
+            " (DoH JSON)"
+        };
+        println!("公共DNS解析{reference}: {:?}", result.public_ips);
+        println!("污染检测: {}", verdict_label(result.verdict()));
+        if result.censorship_suspected {
+            let signals: Vec<String> = result
+                .censorship_signals
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            println!("GFW特征: 疑似审查 ({})", signals.join(", "));
+        }
+        if let Some(transport) = &result.transport {
+            println!(
+                "UDP/TCP对比: {} (UDP: {:?}, TCP: {:?})",
+                if transport.divergent {
+                    "不一致, 疑似UDP注入"
+                } else {
+                    "一致"
+                },
+                transport.udp_ips,
+                transport.tcp_ips
+            );
+        }
+        if let Some(intercepted) = result.transparent_proxy {
+            println!(
+                "透明代理: {}",
+                if intercepted {
+                    "检测到 (53端口流量被劫持)"
+                } else {
+                    "未检测到"
+                }
+            );
+        }
+        println!("重合度: {:.0}%", result.overlap_ratio * 100.0);
+        if !result.matching_ips.is_empty() {
+            println!("一致IP: {:?}", result.matching_ips);
+        }
+        if !result.bogon_ips.is_empty() {
+            println!(
+                "保留地址: {:?} (系统DNS返回了不可能合法的地址)",
+                result.bogon_ips
+            );
+        }
+        print_ips_with_hostnames("仅系统DNS", &result.system_only_ips, &result);
+        print_ips_with_hostnames("仅公共DNS", &result.public_only_ips, &result);
+        let reasons: Vec<String> = result.reasons.iter().map(ToString::to_string).collect();
+        println!("判定依据: {}", reasons.join(", "));
+        for verdict in &result.plugins {
+            println!("插件 {verdict}");
+        }
+    }
+
+    let check_history = CheckHistory::open_default();
+    let current = CheckRecord::from_result(&result);
+    if history.diff {
+        print_check_changes(&check_history, &current, format)?;
+    }
+    if history.record {
+        if let Err(e) = check_history.append(&current) {
+            tracing::warn!("Failed to record check history: {e}");
+        }
+    }
+
+    ctx.push(RunKind::Check, &[&result]).await;
+    Ok(result.verdict())
+}
+
+/// What `check` does with the check history.
+#[derive(Debug, Clone, Copy)]
+struct CheckHistoryOptions {
+    /// Report the changes since the last check of the domain
+    diff: bool,
+    /// Append the check
+    record: bool,
+}
+
+/// Print how `current` differs from the last recorded check of its domain.
+///
+/// The changes go to stderr for JSON output, which stays parseable.
+fn print_check_changes(
+    history: &CheckHistory,
+    current: &CheckRecord,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut out = String::new();
+    match history.last(&current.domain)? {
+        None => out.push_str("\n变化: 没有该域名的检测记录, 无法比较\n"),
+        Some(previous) => {
+            let since = previous
+                .timestamp
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S");
+            let changes = check_history::changes(&previous, current);
+            if changes.is_empty() {
+                let _ = writeln!(out, "\n变化: 与上次检测 ({since}) 相比没有变化");
             } else {
-                "正常"
+                let _ = writeln!(out, "\n变化 (与上次检测 {since} 相比):");
             }
-        );
-        println!("详情: {}", result.details);
+            for change in changes {
+                match change {
+                    CheckChange::Verdict { from, to } => {
+                        let _ = writeln!(
+                            out,
+                            "  污染检测: {} -> {}",
+                            verdict_label(from),
+                            verdict_label(to)
+                        );
+                    }
+                    CheckChange::SystemIps { added, removed } => {
+                        write_ip_changes(&mut out, "系统DNS", &added, &removed);
+                    }
+                    CheckChange::PublicIps { added, removed } => {
+                        write_ip_changes(&mut out, "公共DNS", &added, &removed);
+                    }
+                }
+            }
+        }
+    }
+    if format == OutputFormat::Json {
+        eprint!("{out}");
+    } else {
+        print!("{out}");
+    }
+    Ok(())
+}
+
+/// Append the addresses a resolver started and stopped returning.
+fn write_ip_changes(
+    out: &mut String,
+    label: &str,
+    added: &[std::net::IpAddr],
+    removed: &[std::net::IpAddr],
+) {
+    if !added.is_empty() {
+        let _ = writeln!(out, "  {label}新增: {added:?}");
+    }
+    if !removed.is_empty() {
+        let _ = writeln!(out, "  {label}不再返回: {removed:?}");
+    }
+}
+
+/// Chinese name of a check verdict.
+const fn verdict_label(verdict: CheckVerdict) -> &'static str {
+    match verdict {
+        CheckVerdict::Polluted => "可能污染",
+        CheckVerdict::Suspicious => "可疑",
+        CheckVerdict::Clean => "正常",
+    }
+}
+
+/// Print one-sided addresses of a pollution result with their PTR names.
+fn print_ips_with_hostnames(label: &str, ips: &[std::net::IpAddr], result: &dns::PollutionResult) {
+    if ips.is_empty() {
+        return;
+    }
+    println!("{label}:");
+    for ip in ips {
+        let mut parts = vec![format!("  {ip}")];
+        let hostnames = result.hostnames(ip);
+        if !hostnames.is_empty() {
+            parts.push(format!("({})", hostnames.join(", ")));
+        }
+        if let Some(rdap) = result.rdap(ip) {
+            parts.push(format!("[{rdap}]"));
+        }
+        println!("{}", parts.join(" "));
+    }
+}
+
+/// Options for the `report` command.
+struct ReportOptions {
+    html: PathBuf,
+    from: Option<PathBuf>,
+    file: Option<PathBuf>,
+    dns_servers: Vec<String>,
+    domains: Vec<String>,
+    no_check: bool,
+}
+
+/// Generate a self-contained HTML report.
+///
+/// Speed results are loaded from `from` when given, otherwise a fresh
+/// speed test is run.
+async fn run_report(opts: ReportOptions, ctx: &Context) -> Result<()> {
+    let results = if let Some(path) = &opts.from {
+        dns::SpeedTestResult::from_json_file(path)?
+    } else {
+        println!("加载DNS列表...");
+        let servers = load_dns_list(opts.file, opts.dns_servers, &ctx.profile)?;
+        test_servers(&servers, ctx, None).await?
+    };
+
+    let mut pollution = Vec::new();
+    if !opts.no_check {
+        let checker = ctx.pollution_checker()?;
+        for domain in &opts.domains {
+            println!("检测域名: {domain}");
+            match checker.check(domain).await {
+                Ok(result) => pollution.push(result),
+                Err(e) => eprintln!("检测 {domain} 失败: {e}"),
+            }
+        }
+        checker.flush_reference_cache();
+    }
+
+    let html = dnstest::report::render_html(&results, &pollution);
+    std::fs::write(&opts.html, html)?;
+    println!("报告已保存到: {}", opts.html.display());
+
+    Ok(())
+}
+
+/// Render speed test results from a JSON file as an image.
+///
+/// # Arguments
+///
+/// * `from` - Speed test results JSON file
+/// * `output` - Output image path (.svg or .png)
+/// * `kind` - Chart kind
+fn run_chart(from: &Path, output: &Path, kind: ChartKind) -> Result<()> {
+    let results = dns::SpeedTestResult::from_json_file(from)?;
+
+    dnstest::chart::render_file(&results, kind, output)?;
+    println!("图表已保存到: {}", output.display());
+
+    Ok(())
+}
+
+/// Serve the history over HTTP.
+///
+/// # Arguments
+///
+/// * `listen` - Address to listen on
+/// * `interval` - Seconds between periodic speed tests, if any
+/// * `file` - Optional DNS list file for periodic tests
+/// * `dns_servers` - Optional custom DNS servers for periodic tests
+/// * `ctx` - Plugins and push endpoint for periodic tests
+async fn run_serve(
+    listen: SocketAddr,
+    interval: Option<u64>,
+    file: Option<PathBuf>,
+    dns_servers: Vec<String>,
+    ctx: Context,
+) -> Result<()> {
+    let history = History::open_default();
+
+    let watcher = ctx.watcher()?.map(Arc::new);
+    if let Some(watcher) = &watcher {
+        let watcher = Arc::clone(watcher);
+        let secs = ctx.watch.interval.max(1);
+        println!(
+            "每 {secs} 秒检测一次域名污染 (共 {} 个域名, {} 个解析器)",
+            watcher.domains(),
+            watcher.resolvers()
+        );
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(secs));
+            loop {
+                ticker.tick().await;
+                let status = watcher.check().await;
+                tracing::info!(
+                    "Checked the watchlist: {} of {} polluted",
+                    status.polluted().count(),
+                    status.entries.len()
+                );
+            }
+        });
+    }
+
+    if let Some(secs) = interval.filter(|&secs| secs > 0) {
+        let servers = load_dns_list(file, dns_servers, &ctx.profile)?;
+        let tester = ctx.speed_tester()?;
+        let history = history.clone();
+        let mut alerter = Alerter::new(ctx.alerts.clone());
+        println!("每 {secs} 秒测速一次 (共 {} 个服务器)", servers.len());
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(secs));
+            loop {
+                ticker.tick().await;
+                let mut results = tester.test_all(&servers, None).await;
+                ctx.plugins.check_servers(&mut results).await;
+                if let Some(alerter) = &mut alerter {
+                    alerter.observe(&results).await;
+                }
+                if let Err(e) = history.append(&results) {
+                    tracing::warn!("Failed to record history: {e}");
+                }
+                if let Some(pusher) = &ctx.push {
+                    if let Err(e) = pusher.push(RunKind::Monitor, &results).await {
+                        tracing::warn!("Failed to push results: {e}");
+                    }
+                }
+            }
+        });
+    }
+
+    println!("HTTP 服务已启动: http://{listen}");
+    println!("历史记录: {}", history.path().display());
+    if watcher.is_some() {
+        println!("污染监测: http://{listen}/status");
+    }
+    dnstest::serve::run(listen, history, watcher).await
+}
+
+/// Format the rolling statistics of a monitor session like mtr.
+fn format_monitor_table(monitor: &Monitor) -> String {
+    let ms = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |ms| format!("{ms:.1}"));
+    let mut out = format!(
+        "第 {} 轮\n\n{:<20} {:<18} {:>6} {:>4} {:>7} {:>7} {:>7} {:>7} {:>7}\n",
+        monitor.rounds(),
+        wide("名称"),
+        "IP",
+        wide("丢包%"),
+        wide("次数"),
+        wide("最近"),
+        wide("平均"),
+        wide("最好"),
+        wide("最差"),
+        wide("标准差")
+    );
+    let _ = writeln!(out, "{}", "-".repeat(92));
+    for (server, stats) in monitor.iter() {
+        let _ = writeln!(
+            out,
+            "{:<20} {:<18} {:>6.1} {:>4} {:>7} {:>7} {:>7} {:>7} {:>7}",
+            wide(&server.name),
+            server.ip,
+            stats.loss() * 100.0,
+            stats.sent,
+            ms(stats.last_ms),
+            ms(stats.avg_ms()),
+            ms(stats.best_ms),
+            ms(stats.worst_ms),
+            ms(stats.stddev_ms())
+        );
+    }
+    out
+}
+
+/// Test servers round after round, showing rolling statistics.
+///
+/// # Arguments
+///
+/// * `args` - Servers, speed test options, interval and round limit
+/// * `format` - Output format; JSON prints one line per round
+/// * `ctx` - Speed test settings, plugins and push endpoint
+///
+/// Returns `false` if an alert was raised.
+async fn run_monitor(args: MonitorArgs, format: OutputFormat, mut ctx: Context) -> Result<bool> {
+    ctx.override_speed(
+        args.count,
+        args.timeout,
+        args.timeout_ms,
+        args.concurrency,
+        args.qtype,
+    );
+    let list_paths = if args.prune_after.is_some() {
+        list_files(args.file.as_deref(), &args.dns_servers, &ctx.profile)
+    } else {
+        Vec::new()
+    };
+    let mut servers = load_dns_list(args.file, args.dns_servers, &ctx.profile)?;
+    let tester = ctx.speed_tester()?;
+    let history = (!args.no_history).then(History::open_default);
+    let interval = args
+        .interval
+        .unwrap_or(monitor::DEFAULT_INTERVAL_SECS)
+        .max(1);
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut monitor = Monitor::new();
+    let mut rules = ctx.alerts.clone();
+    rules.latency_ms = args.alert_latency.or(rules.latency_ms);
+    rules.loss = args.alert_loss.or(rules.loss);
+    let mut alerter = Alerter::new(rules);
+    // Latest alert events, shown below the table as the redraw clears the
+    // warnings logged for them
+    let mut recent_alerts = std::collections::VecDeque::new();
+
+    while args.rounds.map_or(true, |rounds| monitor.rounds() < rounds) {
+        ticker.tick().await;
+        let mut results: Vec<_> = futures::stream::iter(&servers)
+            .map(|server| tester.test_latency(server))
+            .buffered(ctx.concurrency())
+            .collect()
+            .await;
+        ctx.plugins.check_servers(&mut results).await;
+        monitor.record(&results);
+        if let Some(after) = args.prune_after {
+            prune_monitored(&mut servers, &monitor, after, &list_paths);
+        }
+        if let Some(alerter) = &mut alerter {
+            for event in alerter.observe(&results).await {
+                recent_alerts.push_back(event);
+                if recent_alerts.len() > MONITOR_ALERT_LINES {
+                    recent_alerts.pop_front();
+                }
+            }
+        }
+
+        if format == OutputFormat::Json {
+            println!("{}", serde_json::to_string(&monitor.snapshot())?);
+        } else {
+            // Redraw in place like mtr when writing to a terminal
+            let redraw = std::io::IsTerminal::is_terminal(&std::io::stdout());
+            if redraw {
+                print!("\x1b[2J\x1b[H");
+            } else if monitor.rounds() > 1 {
+                println!();
+            }
+            print!("{}", format_monitor_table(&monitor));
+            if redraw && !recent_alerts.is_empty() {
+                println!("\n最近告警:");
+                for event in &recent_alerts {
+                    println!(
+                        "  {} {event}",
+                        event
+                            .timestamp
+                            .with_timezone(&chrono::Local)
+                            .format("%H:%M:%S")
+                    );
+                }
+            }
+        }
+        std::io::Write::flush(&mut std::io::stdout())?;
+
+        if let Some(history) = &history {
+            if let Err(e) = history.append(&results) {
+                tracing::warn!("Failed to record history: {e}");
+            }
+        }
+        if let Some(pusher) = &ctx.push {
+            if let Err(e) = pusher.push(RunKind::Monitor, &results).await {
+                tracing::warn!("Failed to push results: {e}");
+            }
+        }
+    }
+
+    Ok(!alerter.as_ref().is_some_and(Alerter::raised))
+}
+
+/// Stop monitoring servers that failed `after` rounds in a row and remove
+/// them from the DNS list files at `list_paths`.
+fn prune_monitored(
+    servers: &mut Vec<DnsServer>,
+    monitor: &Monitor,
+    after: u64,
+    list_paths: &[PathBuf],
+) {
+    let failing: HashSet<String> = servers
+        .iter()
+        .map(DnsServer::spec)
+        .filter(|spec| {
+            monitor
+                .get(spec)
+                .is_some_and(|stats| stats.failed_streak >= after)
+        })
+        .collect();
+    if failing.is_empty() {
+        return;
+    }
+    for s in servers.iter().filter(|s| failing.contains(&s.spec())) {
+        tracing::warn!(
+            "Pruning {} ({}) after {after} failed rounds",
+            s.name,
+            s.spec()
+        );
+    }
+    servers.retain(|s| !failing.contains(&s.spec()));
+    for path in list_paths {
+        if let Err(e) = ConfigLoader::remove_specs(path, &failing) {
+            tracing::warn!("Failed to prune {}: {e}", path.display());
+        }
+    }
+}
+
+/// Describe an ICMP method for display.
+const fn icmp_method_label(method: IcmpMethod) -> &'static str {
+    match method {
+        IcmpMethod::Datagram => "无特权数据报套接字",
+        IcmpMethod::Raw => "原始套接字",
+        IcmpMethod::IcmpApi => "Windows ICMP API, 无需管理员权限",
+        IcmpMethod::DnsQuery => "不可用, 改为测量DNS查询延迟",
+        IcmpMethod::TcpQuery => "不可用, 改为测量TCP DNS查询延迟",
+    }
+}
+
+/// Diagnose the local network environment.
+///
+/// # Arguments
+///
+/// * `format` - Output format
+async fn run_doctor(format: OutputFormat) -> Result<()> {
+    let icmp = dns::icmp::detect();
+    let interception = InterceptionDetector::new().detect().await;
+    let hijack = match HijackDetector::new() {
+        Ok(detector) => Some(detector.detect().await),
+        Err(e) => {
+            tracing::warn!("Skipping the NXDOMAIN hijack check: {e}");
+            None
+        }
+    };
+
+    if format == OutputFormat::Json {
+        let report = serde_json::json!({
+            "icmp": {
+                "available": icmp.available(),
+                "method": icmp.method,
+                "elevation_required": icmp.elevation_required,
+                "error": icmp.error,
+                "linux": icmp.linux,
+            },
+            "interception": interception,
+            "hijack": hijack,
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("=== ICMP ===");
+    if let Some(linux) = &icmp.linux {
+        println!(
+            "ping_group_range: {}{}",
+            linux
+                .ping_group_range
+                .map_or_else(|| "未知".to_string(), |(low, high)| format!("{low} {high}")),
+            if linux.in_ping_group {
+                " (已包含本进程)"
+            } else {
+                ""
+            }
+        );
+        println!(
+            "CAP_NET_RAW: {}",
+            if linux.cap_net_raw { "有" } else { "无" }
+        );
+    }
+    match (icmp.method, &icmp.error) {
+        (Some(IcmpMethod::DnsQuery), error) => println!(
+            "测速方式: {} ({})",
+            icmp_method_label(IcmpMethod::DnsQuery),
+            error.as_deref().unwrap_or_default()
+        ),
+        (Some(method), _) => println!("ICMP: 可用 ({})", icmp_method_label(method)),
+        (None, error) => println!("ICMP: 不可用 ({})", error.as_deref().unwrap_or_default()),
+    }
+    if icmp.elevation_required {
+        println!("需要提升权限: {}", dns::icmp::elevation_hint());
+    }
+
+    println!("\n=== 透明代理检测 ===");
+    if interception.blackhole_answers.is_empty() {
+        println!("黑洞地址应答: 无");
+    } else {
+        println!("黑洞地址应答: {:?}", interception.blackhole_answers);
+    }
+    for e in &interception.egress {
+        println!(
+            "{:<18} 出口: {}",
+            e.resolver,
+            e.egress.as_deref().unwrap_or("无应答")
+        );
+    }
+    println!(
+        "结论: {}",
+        if interception.intercepted {
+            "53端口流量被透明代理, 公共DNS的应答可能来自运营商"
+        } else {
+            "未发现透明代理"
+        }
+    );
+
+    if let Some(hijack) = &hijack {
+        print_hijack(hijack);
+    }
+
+    Ok(())
+}
+
+/// Print the NXDOMAIN hijack section of `doctor`.
+fn print_hijack(hijack: &HijackReport) {
+    println!("\n=== NXDOMAIN劫持检测 (系统DNS) ===");
+    for probe in &hijack.probes {
+        let kind = match probe.kind {
+            ProbeKind::Nxdomain => "随机域名",
+            ProbeKind::Typo => "拼写错误",
+        };
+        let answer = if probe.is_hijacked() {
+            let ips: Vec<String> = probe.addresses.iter().map(ToString::to_string).collect();
+            format!("被重定向到 {}", ips.join(", "))
+        } else {
+            "不存在 (正常)".to_string()
+        };
+        println!("{kind}  {:<42} {answer}", wide(&probe.name));
+    }
+    let conclusion = match (
+        hijack.hijacked(ProbeKind::Nxdomain),
+        hijack.hijacked(ProbeKind::Typo),
+    ) {
+        (true, _) => "不存在的域名被重定向, 系统DNS劫持了NXDOMAIN",
+        (false, true) => "拼写错误的域名被重定向到搜索或广告页面",
+        (false, false) => "未发现NXDOMAIN劫持",
+    };
+    println!("结论: {conclusion}");
+}
+
+/// Format a hop's round-trip times like traceroute does.
+fn format_rtts(hop: &Hop) -> String {
+    hop.rtts_ms
+        .iter()
+        .map(|rtt| rtt.map_or_else(|| "*".to_string(), |ms| format!("{ms:.1}ms")))
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// Trace the route to a server.
+///
+/// # Arguments
+///
+/// * `args` - Target and tracer options
+/// * `format` - Output format
+async fn run_route(args: RouteArgs, format: OutputFormat) -> Result<()> {
+    let target = args.target;
+    let tracer = Tracer::with_settings(
+        args.max_hops.unwrap_or(route::DEFAULT_MAX_HOPS),
+        args.probes.unwrap_or(route::DEFAULT_PROBES),
+        Duration::from_secs(args.timeout.unwrap_or(route::DEFAULT_TIMEOUT_SECS)),
+    );
+    if format == OutputFormat::Json {
+        let hops = tracer.trace(target, |_| {}).await?;
+        println!("{}", serde_json::to_string_pretty(&hops)?);
+        return Ok(());
+    }
+
+    println!("路由追踪 {target}:\n");
+    let hops = tracer
+        .trace(target, |hop| {
+            let address = hop
+                .address
+                .map_or_else(|| "*".to_string(), |ip| ip.to_string());
+            println!("{:>3}  {address:<18} {}", hop.ttl, format_rtts(hop));
+        })
+        .await?;
+
+    if !hops.last().is_some_and(|hop| hop.reached) {
+        println!("\n未到达 {target}");
+    }
+    Ok(())
+}
+
+/// Find DNS servers in an address range.
+///
+/// # Arguments
+///
+/// * `args` - Range and scanner options
+/// * `format` - Output format
+async fn run_scan(args: ScanArgs, format: OutputFormat) -> Result<()> {
+    let ips = dnstest::config::expand_cidr(&args.range)?;
+    let port = args.port.unwrap_or(dns::query::DNS_PORT);
+    let scanner = Scanner::with_settings(
+        args.timeout_ms
+            .map_or(scan::DEFAULT_TIMEOUT, Duration::from_millis),
+        args.concurrency.unwrap_or(scan::DEFAULT_CONCURRENCY),
+    )
+    .with_port(port);
+
+    let hits = if format == OutputFormat::Json {
+        scanner.scan(&ips, |_, _| {}).await
+    } else {
+        println!("扫描 {} ({} 个地址)...\n", args.range, ips.len());
+        let progress = Progress::new("扫描中");
+        let hits = scanner
+            .scan(&ips, |done, total| {
+                progress.update(done, total, "");
+            })
+            .await;
+        progress.finish();
+        hits
+    };
+
+    // Non-default ports are part of the spec that `add` takes
+    let specs: Vec<String> = hits
+        .iter()
+        .filter(|hit| hit.is_resolver())
+        .map(|hit| {
+            if port == dns::query::DNS_PORT {
+                hit.ip.to_string()
+            } else {
+                SocketAddr::new(hit.ip, port).to_string()
+            }
+        })
+        .collect();
+
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&hits)?);
+    } else if hits.is_empty() {
+        println!("未发现DNS服务器");
+    } else {
+        println!(
+            "{:<40} {:>10} {:<10} 递归",
+            wide("地址"),
+            wide("延迟"),
+            wide("响应码")
+        );
+        println!("{}", "-".repeat(72));
+        for hit in &hits {
+            println!(
+                "{:<40} {:>10} {:<10} {}",
+                hit.ip,
+                format!("{:.1} ms", hit.latency_ms),
+                hit.rcode,
+                if hit.recursion_available {
+                    "是"
+                } else {
+                    "否"
+                }
+            );
+        }
+        println!(
+            "\n发现 {} 个DNS服务器, 其中 {} 个可用作解析器",
+            hits.len(),
+            specs.len()
+        );
+        if !specs.is_empty() && !args.add {
+            println!("添加到列表: dnstest add {}", specs.join(" "));
+        }
+    }
+
+    if args.add && !specs.is_empty() {
+        run_add(specs, None, None)?;
+    }
+    Ok(())
+}
+
+/// Check whether servers are open recursive resolvers.
+///
+/// # Arguments
+///
+/// * `args` - Servers and checker options
+/// * `format` - Output format
+async fn run_open_resolver(args: OpenResolverArgs, format: OutputFormat) -> Result<()> {
+    let servers = ConfigLoader::from_args(args.servers)?
+        .servers
+        .iter()
+        .map(|server| match server.socket_addr() {
+            Some(addr) if server.protocol.is_plain() => Ok(addr),
+            _ => Err(Error::config(format!(
+                "{} 不是普通DNS服务器地址",
+                server.spec()
+            ))),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let checker = OpenResolverChecker::with_timeout(
+        args.timeout
+            .map_or(open_resolver::DEFAULT_TIMEOUT, Duration::from_secs),
+    )
+    .with_concurrency(
+        args.concurrency
+            .unwrap_or(open_resolver::DEFAULT_CONCURRENCY),
+    );
+
+    if format != OutputFormat::Json {
+        println!("检测 {} 个服务器...\n", servers.len());
+    }
+    let reports = checker.check_all(&servers).await;
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<40} {:<12} {:<10} 递归标志",
+        wide("服务器"),
+        wide("结果"),
+        wide("响应码")
+    );
+    println!("{}", "-".repeat(72));
+    for report in &reports {
+        let verdict = match report.verdict {
+            Recursion::Open => "开放递归",
+            Recursion::CacheOnly => "仅缓存",
+            Recursion::Closed => "不递归",
+            Recursion::NoAnswer => "无响应",
+        };
+        println!(
+            "{:<40} {:<12} {:<10} {}",
+            report.server,
+            wide(verdict),
+            report.rcode.as_deref().unwrap_or("-"),
+            if report.recursion_available {
+                "是"
+            } else {
+                "否"
+            }
+        );
+    }
+
+    let open = reports.iter().filter(|r| r.is_open()).count();
+    println!("\n{open} 个服务器为开放递归解析器");
+    Ok(())
+}
+
+/// Probe resolver features such as negative caching.
+///
+/// # Arguments
+///
+/// * `args` - Servers and prober options
+/// * `format` - Output format
+/// * `ctx` - Shared run context
+async fn run_features(args: FeaturesArgs, format: OutputFormat, ctx: &Context) -> Result<()> {
+    let servers = load_dns_list(args.file, args.dns_servers, &ctx.profile)?;
+    let prober = FeatureProber::with_settings(
+        args.timeout
+            .map_or(features::DEFAULT_TIMEOUT, Duration::from_secs),
+        args.concurrency.unwrap_or(features::DEFAULT_CONCURRENCY),
+    );
+
+    if format == OutputFormat::Json {
+        let reports = prober.probe_all(&servers, |_, _| {}).await;
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+
+    println!("检测 {} 个解析器...\n", servers.len());
+    let progress = Progress::new("检测中");
+    let reports = prober
+        .probe_all(&servers, |done, total| {
+            progress.update(done, total, "");
+        })
+        .await;
+    progress.finish();
+
+    println!(
+        "{:<20} {:<40} {:<10} {:>10} {:>10} {:>8} {:>9}  {:<24} 过滤",
+        wide("名称"),
+        "IP",
+        wide("否定缓存"),
+        wide("首次"),
+        wide("重复"),
+        wide("否定TTL"),
+        wide("SOA最小值"),
+        "ECS"
+    );
+    println!("{}", "-".repeat(160));
+    let ms = |value: f64| format!("{value:.1} ms");
+    let ttl = |value: Option<u32>| value.map_or_else(|| "-".to_string(), |v| format!("{v} s"));
+    for report in &reports {
+        if let Some(error) = &report.error {
+            println!(
+                "{:<20} {:<40} [跳过] {error}",
+                wide(&report.server.name),
+                report.server.ip
+            );
+            continue;
+        }
+        let negative = report.negative_caching.as_ref();
+        let cached = negative.map_or("无响应", |negative| {
+            match (negative.cached, negative.honors_soa_minimum()) {
+                (false, _) => "否",
+                (true, Some(false)) => "是, 超出",
+                (true, _) => "是",
+            }
+        });
+        let ecs = report.client_subnet.as_ref().map_or_else(
+            || "无响应".to_string(),
+            |ecs| {
+                ecs.subnet
+                    .as_ref()
+                    .map_or_else(|| "不转发".to_string(), |subnet| format!("转发 {subnet}"))
+            },
+        );
+        let filtering = report
+            .filtering
+            .as_ref()
+            .map_or_else(|| "无响应".to_string(), filtering_label);
+        let (cached, ecs) = (wide(cached), wide(&ecs));
+        println!(
+            "{:<20} {:<40} {cached:<10} {:>10} {:>10} {:>8} {:>9}  {ecs:<24} {filtering}",
+            wide(&report.server.name),
+            report.server.ip,
+            negative.map_or_else(|| "-".to_string(), |n| ms(n.first_ms)),
+            negative.map_or_else(|| "-".to_string(), |n| ms(n.repeat_ms)),
+            ttl(negative.and_then(|n| n.negative_ttl)),
+            ttl(negative.and_then(|n| n.soa_minimum)),
+        );
+    }
+    println!("\n\"超出\": 否定缓存时间超过SOA最小值 (RFC 2308)");
+    println!("ECS \"转发\": 解析器把客户端所在网段告诉权威服务器 (RFC 7871)");
+    println!("过滤: 解析器拦截的测试域名类别, 括号内为拦截方式; \"安全搜索\": 强制启用搜索引擎的安全模式");
+    Ok(())
+}
+
+/// Blocked categories of a resolver and how it blocks them, then the
+/// services it enforces safe search on, e.g. "广告, 成人 (0.0.0.0) 安全搜索
+/// Google".
+fn filtering_label(filtering: &Filtering) -> String {
+    let categories = filtering.blocked_categories();
+    let mut parts = Vec::new();
+    if let Some(block) = categories
+        .first()
+        .and_then(|&category| filtering.blocks(category))
+    {
+        let names: Vec<&str> = categories
+            .iter()
+            .map(|category| match category {
+                Category::Ads => "广告",
+                Category::Malware => "恶意",
+                Category::Adult => "成人",
+            })
+            .collect();
+        let how = match block {
+            Block::Nxdomain => "NXDOMAIN".to_string(),
+            Block::Sinkhole(ip) => ip.to_string(),
+            Block::BlockPage(ip) => format!("拦截页 {ip}"),
+        };
+        parts.push(format!("{} ({how})", names.join(", ")));
+    }
+    if !filtering.safe_search.is_empty() {
+        let services: Vec<String> = filtering
+            .safe_search
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        parts.push(format!("安全搜索 {}", services.join(", ")));
+    }
+    if parts.is_empty() {
+        return "否".to_string();
+    }
+    parts.join(" ")
+}
+
+/// Check the delegation of a domain.
+///
+/// # Arguments
+///
+/// * `args` - Domain and checker options
+/// * `format` - Output format
+async fn run_ns_check(args: NsCheckArgs, format: OutputFormat) -> Result<()> {
+    let mut checker = DelegationChecker::with_timeout(
+        args.timeout
+            .map_or(delegation::DEFAULT_TIMEOUT, Duration::from_secs),
+    );
+    if let Some(ip) = args.resolver {
+        checker = checker.with_resolver(SocketAddr::new(ip, dns::query::DNS_PORT));
+    }
+
+    if format != OutputFormat::Json {
+        println!("检查 {} 的委派...\n", args.domain);
+    }
+    let report = checker.check(&args.domain).await?;
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("上级区域 {} 委派给:", report.parent_zone);
+    for host in &report.parent_ns {
+        println!("  {host}");
+    }
+    println!();
+
+    println!(
+        "{:<30} {:<16} {:<8} {:<10} {:>10} NS记录",
+        wide("名称服务器"),
+        "IP",
+        wide("状态"),
+        wide("响应码"),
+        wide("延迟")
+    );
+    println!("{}", "-".repeat(100));
+    for ns in &report.nameservers {
+        let state = wide(match ns.state {
+            NsState::Ok => "正常",
+            NsState::Lame => "跛脚",
+            NsState::Unreachable => "无响应",
+        });
+        println!(
+            "{:<30} {:<16} {state:<8} {:<10} {:>10} {}",
+            ns.host,
+            ns.ip,
+            ns.rcode.as_deref().unwrap_or("-"),
+            ns.latency_ms
+                .map_or_else(|| "-".to_string(), |ms| format!("{ms:.1} ms")),
+            ns.ns.join(", ")
+        );
+    }
+
+    if report.is_healthy() {
+        println!("\n委派正常");
+        return Ok(());
+    }
+    println!("\n发现 {} 个问题:", report.issues.len());
+    for issue in &report.issues {
+        let text = match issue {
+            Issue::Lame { host, ip } => format!("{host} ({ip}) 不是该域名的权威服务器"),
+            Issue::Unreachable { host, ip } => format!("{host} ({ip}) 无响应"),
+            Issue::Unresolvable { host } => format!("{host} 无法解析出地址"),
+            Issue::OnlyAtParent { host } => format!("{host} 仅在上级区域列出"),
+            Issue::OnlyAtChild { host } => format!("{host} 仅在域名自身的NS记录中列出"),
+        };
+        println!("  - {text}");
+    }
+    Ok(())
+}
+
+/// Check which resolvers already return a changed record.
+///
+/// # Arguments
+///
+/// * `args` - Record, expected values and servers
+/// * `format` - Output format
+/// * `ctx` - Shared run context
+async fn run_propagate(args: PropagateArgs, format: OutputFormat, ctx: &Context) -> Result<()> {
+    let servers = load_dns_list(args.file, args.dns_servers, &ctx.profile)?;
+    let record_type = args.record_type.unwrap_or(RecordType::A);
+    let checker = PropagationChecker::new(&args.domain, record_type, &args.expect).with_settings(
+        args.timeout
+            .map_or(propagation::DEFAULT_TIMEOUT, Duration::from_secs),
+        args.concurrency.unwrap_or(propagation::DEFAULT_CONCURRENCY),
+    );
+
+    if format == OutputFormat::Json {
+        let results = checker.check_all(&servers, |_, _| {}).await;
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+
+    println!(
+        "查询 {} 个解析器的 {} {record_type} 记录...\n",
+        servers.len(),
+        args.domain
+    );
+    let progress = Progress::new("查询中");
+    let results = checker
+        .check_all(&servers, |done, total| {
+            progress.update(done, total, "");
+        })
+        .await;
+    progress.finish();
+
+    println!(
+        "{:<20} {:<40} {:<8} {:>8} 应答",
+        wide("名称"),
+        "IP",
+        wide("状态"),
+        "TTL"
+    );
+    println!("{}", "-".repeat(100));
+    for result in &results {
+        if let Some(error) = &result.error {
+            println!(
+                "{:<20} {:<40} [跳过] {error}",
+                wide(&result.server.name),
+                result.server.ip
+            );
+            continue;
+        }
+        let state = wide(match result.state {
+            Propagation::Updated => "已更新",
+            Propagation::Stale => "旧记录",
+            Propagation::Missing => "无记录",
+            Propagation::NoAnswer => "无响应",
+        });
+        println!(
+            "{:<20} {:<40} {state:<8} {:>8} {}",
+            wide(&result.server.name),
+            result.server.ip,
+            result
+                .ttl
+                .map_or_else(|| "-".to_string(), |ttl| format!("{ttl} s")),
+            result.answers.join(", ")
+        );
+    }
+
+    let answered: Vec<_> = results
+        .iter()
+        .filter(|r| r.error.is_none() && r.state != Propagation::NoAnswer)
+        .collect();
+    let updated = answered
+        .iter()
+        .filter(|r| r.state == Propagation::Updated)
+        .count();
+    println!("\n{updated}/{} 个解析器已返回预期记录", answered.len());
+    // Stale answers expire at the latest when their TTL runs out
+    if let Some(ttl) = answered
+        .iter()
+        .filter(|r| r.state == Propagation::Stale)
+        .filter_map(|r| r.ttl)
+        .max()
+    {
+        println!("旧记录最迟在 {ttl} 秒后过期");
+    }
+    Ok(())
+}
+
+/// Benchmark DNS-over-HTTPS endpoints per HTTP version.
+///
+/// # Arguments
+///
+/// * `args` - Endpoints and tester options
+/// * `format` - Output format
+async fn run_doh(args: DohArgs, format: OutputFormat) -> Result<()> {
+    let urls = if args.urls.is_empty() {
+        doh::DEFAULT_ENDPOINTS
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    } else {
+        args.urls
+    };
+    let versions = if args.http.is_empty() {
+        HttpVersion::ALL.to_vec()
+    } else {
+        args.http
+    };
+    let mut tester = DohTester::with_settings(
+        args.timeout
+            .map_or(doh::DEFAULT_TIMEOUT, Duration::from_secs),
+        args.count.unwrap_or(doh::DEFAULT_COUNT),
+    );
+    if let Some(domain) = args.domain {
+        tester = tester.with_domain(domain);
+    }
+
+    if format != OutputFormat::Json {
+        println!("测试 {} 个DoH端点...\n", urls.len());
+    }
+    let results = tester.test_all(&urls, &versions).await;
+    if format == OutputFormat::Json {
+        print!("{}", format_results_json(&results));
+        return Ok(());
+    }
+
+    let ms = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |ms| format!("{ms:.1}"));
+    println!(
+        "{:<24} {:<10} {:>10} {:>8} {:>8} {:>8} {:>10} {:>6}",
+        wide("端点"),
+        wide("协议"),
+        wide("新连接"),
+        wide("连接"),
+        "TLS",
+        wide("查询"),
+        wide("复用连接"),
+        wide("丢包")
+    );
+    println!("{}", "-".repeat(93));
+    for r in &results {
+        let version = r.http_version.map(|v| v.to_string()).unwrap_or_default();
+        match r.latency_ms {
+            Some(latency) => println!(
+                "{:<24} {:<10} {:>10} {:>8} {:>8} {:>8} {:>10} {:>5.0}%",
+                wide(&r.server.name),
+                version,
+                format!("{latency:.1} ms"),
+                ms(r.phases.map(|p| p.connect_ms)),
+                ms(r.phases.and_then(|p| p.tls_ms)),
+                ms(r.phases.map(|p| p.query_ms)),
+                r.reused_latency_ms
+                    .map_or_else(|| "-".to_string(), |ms| format!("{ms:.1} ms")),
+                r.packet_loss * 100.0
+            ),
+            None => println!(
+                "{:<24} {:<10} [失败] {}",
+                wide(&r.server.name),
+                version,
+                r.error.as_deref().unwrap_or_default()
+            ),
+        }
     }
-
     Ok(())
 }
 
 /// List DNS servers with optional filtering.
 ///
+/// Servers show their latest latency and status from the history, if they
+/// were ever tested.
+///
 /// # Arguments
 ///
 /// * `file` - Optional DNS list file
-/// * `ipv4_only` - Show only IPv4 servers
-/// * `ipv6_only` - Show only IPv6 servers
-fn run_list_dns(file: Option<PathBuf>, ipv4_only: bool, ipv6_only: bool) -> Result<()> {
-    let servers = if let Some(path) = file {
+/// * `family` - Show only servers of this address family
+/// * `countries` - Show only servers hosted in these countries
+/// * `format` - Output format
+async fn run_list_dns(
+    file: Option<PathBuf>,
+    preset: Option<Preset>,
+    family: Option<IpFamily>,
+    countries: &[String],
+    format: OutputFormat,
+) -> Result<()> {
+    let servers = if let Some(preset) = preset {
+        preset.servers().await?
+    } else if let Some(path) = file {
         ConfigLoader::load_from_file(path)?.servers
     } else {
         let lists = ConfigLoader::load_all()?;
@@ -245,41 +2311,334 @@ fn run_list_dns(file: Option<PathBuf>, ipv4_only: bool, ipv6_only: bool) -> Resu
 
     let filtered: Vec<_> = servers
         .into_iter()
-        .filter(|s| {
-            let ip: std::net::IpAddr = s.ip.parse().unwrap_or_else(|_| "0.0.0.0".parse().unwrap());
-            let is_v4 = ip.is_ipv4();
-            let is_v6 = ip.is_ipv6();
+        .filter(|s| family.map_or(true, |family| s.in_family(family)))
+        .collect();
+    let mut filtered = filter_country(filtered, countries).await;
+
+    match History::open_default().load() {
+        Ok(runs) => dnstest::history::fill_last_known(&runs, &mut filtered),
+        Err(e) => tracing::warn!("Failed to load history: {e}"),
+    }
+
+    print!("{}", format_servers(&filtered, format));
+    Ok(())
+}
 
-            if ipv4_only && !is_v4 {
-                return false;
+/// Format a server list.
+fn format_servers(servers: &[DnsServer], format: OutputFormat) -> String {
+    let mut out = String::new();
+    match format {
+        OutputFormat::Json => {
+            out = serde_json::to_string_pretty(servers).unwrap();
+            out.push('\n');
+        }
+        OutputFormat::Yaml => out = serde_norway::to_string(servers).unwrap(),
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let sep = if format == OutputFormat::Csv {
+                ","
+            } else {
+                "\t"
+            };
+            let header = [
+                "#Idx",
+                "Name",
+                "IP",
+                "Provider",
+                "Country",
+                "Delay(ms)",
+                "Status",
+            ];
+            let _ = writeln!(out, "{}", header.join(sep));
+            for (idx, s) in servers.iter().enumerate() {
+                let row = [
+                    (idx + 1).to_string(),
+                    s.name.clone(),
+                    s.ip.clone(),
+                    s.provider.clone().unwrap_or_default(),
+                    s.country.clone().unwrap_or_default(),
+                    s.delay.map_or_else(String::new, |ms| format!("{ms:.1}")),
+                    s.status.to_string(),
+                ];
+                let _ = writeln!(out, "{}", row.join(sep));
+            }
+        }
+        OutputFormat::Table | OutputFormat::OpenMetrics => {
+            // Latency columns only once something was measured
+            let measured = servers
+                .iter()
+                .any(|s| s.delay.is_some() || s.status != DnsStatus::Pending);
+            let _ = writeln!(out, "DNS服务器列表 (共 {} 个):\n", servers.len());
+            if measured {
+                let _ = writeln!(
+                    out,
+                    "{:<4} {:<20} {:<20} {:<4} {:>10} {:<6}",
+                    "#",
+                    wide("名称"),
+                    "IP",
+                    wide("国家"),
+                    wide("上次延迟"),
+                    wide("状态")
+                );
+                let _ = writeln!(out, "{}", "-".repeat(72));
+            } else {
+                let _ = writeln!(
+                    out,
+                    "{:<4} {:<20} {:<20} {:<4}",
+                    "#",
+                    wide("名称"),
+                    "IP",
+                    wide("国家")
+                );
+                let _ = writeln!(out, "{}", "-".repeat(54));
             }
-            if ipv6_only && !is_v6 {
-                return false;
+            for (idx, s) in servers.iter().enumerate() {
+                let _ = write!(
+                    out,
+                    "{:<4} {:<20} {:<20} {:<4}",
+                    idx + 1,
+                    wide(&s.name),
+                    s.spec(),
+                    s.country.as_deref().unwrap_or("-")
+                );
+                if measured {
+                    let delay = s
+                        .delay
+                        .map_or_else(|| "-".to_string(), |ms| format!("{ms:.1}ms"));
+                    let _ = write!(out, " {:>10} {:<6}", delay, wide(status_label(s.status)));
+                }
+                if s.disabled {
+                    out.push_str(" (已停用)");
+                }
+                out.push('\n');
             }
-            true
-        })
-        .collect();
+        }
+    }
+    out
+}
+
+/// Add servers to the saved DNS list of their address family.
+///
+/// # Arguments
+///
+/// * `servers` - Servers to add (IP#Name)
+/// * `provider` - Operator of the servers
+/// * `country` - Country code of the servers
+fn run_add(servers: Vec<String>, provider: Option<&str>, country: Option<&str>) -> Result<()> {
+    // One at a time, so that servers sharing a name are not numbered
+    let mut servers = servers
+        .into_iter()
+        .map(|spec| ConfigLoader::from_args(vec![spec]).map(|list| list.servers))
+        .collect::<Result<Vec<_>>>()?
+        .concat();
+    for server in &mut servers {
+        server.provider = provider.map(str::to_string);
+        server.country = country.map(str::to_ascii_uppercase);
+    }
+    let (v6, v4): (Vec<_>, Vec<_>) = servers.into_iter().partition(DnsServer::is_ipv6);
+
+    for (name, servers) in [
+        (dnstest::config::LIST_FILE, v4),
+        (dnstest::config::LIST_V6_FILE, v6),
+    ] {
+        if servers.is_empty() {
+            continue;
+        }
+        let path = ConfigLoader::list_path(name);
+        let added = ConfigLoader::add_servers(&path, &servers)?;
+        print!("已添加 {added} 个服务器到 {}", path.display());
+        if added < servers.len() {
+            print!(" ({} 个已存在)", servers.len() - added);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Remove servers, by address or name, from the saved DNS lists.
+///
+/// Names match as written in the lists or as numbered by `list`, e.g.
+/// `Google #2`.
+fn run_remove(servers: &[String]) -> Result<()> {
+    let lists: Vec<(PathBuf, DnsList)> =
+        [dnstest::config::LIST_FILE, dnstest::config::LIST_V6_FILE]
+            .into_iter()
+            .map(ConfigLoader::list_path)
+            .filter(|path| path.exists())
+            .map(|path| ConfigLoader::load_from_file(&path).map(|list| (path, list)))
+            .collect::<Result<_>>()?;
+
+    let merged = ConfigLoader::merge(lists.iter().map(|(_, list)| list.clone()).collect());
+
+    let mut specs = HashSet::new();
+    for wanted in servers {
+        // An address matches only with the same port and protocol, so
+        // `127.0.0.1:5353` leaves `127.0.0.1` in the list
+        let wanted_specs: Option<HashSet<String>> = ConfigLoader::from_args(vec![wanted.clone()])
+            .ok()
+            .map(|list| list.servers.iter().map(DnsServer::spec).collect());
+        let matched: Vec<&DnsServer> = lists
+            .iter()
+            .flat_map(|(_, list)| &list.servers)
+            .chain(&merged.servers)
+            .filter(|s| {
+                wanted_specs.as_ref().map_or_else(
+                    || s.name.eq_ignore_ascii_case(wanted),
+                    |wanted| wanted.contains(&s.spec()),
+                )
+            })
+            .collect();
+        if matched.is_empty() {
+            return Err(Error::config(format!("未找到服务器: {wanted}")));
+        }
+        specs.extend(matched.into_iter().map(DnsServer::spec));
+    }
 
-    println!("DNS服务器列表 (共 {} 个):\n", filtered.len());
-    println!("{:<4} {:<20} {:<20}", "#", "名称", "IP");
-    println!("{}", "-".repeat(50));
+    for (path, _) in &lists {
+        let removed = ConfigLoader::remove_specs(path, &specs)?;
+        if removed > 0 {
+            println!("已从 {} 删除 {removed} 个服务器", path.display());
+        }
+    }
+    Ok(())
+}
 
-    for (idx, s) in filtered.iter().enumerate() {
-        println!("{:<4} {:<20} {:<20}", idx + 1, s.name, s.ip);
+/// Remove servers that failed the latest `after` runs in a row, according
+/// to the history, from the DNS list files.
+///
+/// # Arguments
+///
+/// * `file` - DNS list file, the saved lists if `None`
+/// * `after` - Number of failed runs in a row
+/// * `dry_run` - Only show the servers that would be removed
+/// * `profile` - Active profile
+fn run_prune(file: Option<&Path>, after: u64, dry_run: bool, profile: &Profile) -> Result<()> {
+    let paths = list_files(file, &[], profile);
+    if paths.is_empty() {
+        return Err(Error::config("没有可修剪的DNS列表文件"));
     }
+    let streaks = dnstest::history::failure_streaks(&History::open_default().load()?);
+    let failing: HashSet<String> = streaks
+        .iter()
+        .filter(|(_, &streak)| streak >= after)
+        .map(|(spec, _)| spec.clone())
+        .collect();
 
+    for path in &paths {
+        let servers: Vec<DnsServer> = ConfigLoader::load_from_file(path)?
+            .servers
+            .into_iter()
+            .filter(|s| failing.contains(&s.spec()))
+            .collect();
+        if servers.is_empty() {
+            println!("{}: 没有连续失败 {after} 次的服务器", path.display());
+            continue;
+        }
+        let verb = if dry_run { "将移除" } else { "移除" };
+        println!(
+            "{}: {verb} {} 个连续失败 {after} 次以上的服务器",
+            path.display(),
+            servers.len()
+        );
+        for s in &servers {
+            let spec = s.spec();
+            println!("  {} ({spec}) 连续失败 {} 次", s.name, streaks[&spec]);
+        }
+        if !dry_run {
+            ConfigLoader::remove_specs(path, &failing)?;
+        }
+    }
     Ok(())
 }
 
+/// Chinese label of a server status.
+const fn status_label(status: DnsStatus) -> &'static str {
+    match status {
+        DnsStatus::Pending => "-",
+        DnsStatus::Testing => "测试中",
+        DnsStatus::Success => "成功",
+        DnsStatus::Failed => "失败",
+        DnsStatus::Timeout => "超时",
+    }
+}
+
+/// Keep only servers hosted in one of `countries`, all of them if empty.
+///
+/// Servers without a country in the list are looked up via RDAP first.
+async fn filter_country(mut servers: Vec<DnsServer>, countries: &[String]) -> Vec<DnsServer> {
+    if countries.is_empty() {
+        return servers;
+    }
+    if servers.iter().any(|s| s.country.is_none()) {
+        eprintln!("正在查询服务器所在国家...");
+        dns::rdap::fill_countries(&mut servers).await;
+    }
+    let unknown = servers.iter().filter(|s| s.country.is_none()).count();
+    if unknown > 0 {
+        tracing::warn!("Country of {unknown} servers is unknown, skipping them");
+    }
+    servers.retain(|s| s.in_country(countries));
+    servers
+}
+
 /// Run interactive TUI mode.
-async fn run_interactive(file: Option<PathBuf>) -> Result<()> {
+///
+/// # Arguments
+///
+/// * `file` - DNS list to test instead of the configured lists
+/// * `from` - Results file to browse, `-` for stdin
+/// * `family` - Address family to keep, both if `None`
+/// * `check_updates` - Whether to show update notices
+async fn run_interactive(
+    file: Option<PathBuf>,
+    from: Option<PathBuf>,
+    family: Option<IpFamily>,
+    check_updates: bool,
+    ctx: &Context,
+) -> Result<()> {
+    // Without a terminal the TUI would wait for keys no one can press
+    if !std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+        return Err(Error::config(
+            "标准输出不是终端, 无法启动交互界面; 请指定子命令, 例如 dnstest speed --format json",
+        ));
+    }
     let mut app = App::new();
+    app.set_check_updates(check_updates);
+    app.set_family(family);
+    app.set_limits(
+        ctx.profile
+            .concurrency
+            .unwrap_or(dnstest::tui::DEFAULT_CONCURRENCY),
+        ctx.profile
+            .run_timeout
+            .map_or(dnstest::tui::DEFAULT_RUN_TIMEOUT, Duration::from_secs),
+    );
 
     // Load custom file if provided
     if let Some(path) = file {
         if let Ok(list) = ConfigLoader::load_from_file(&path) {
             app.set_dns_servers(list.servers);
         }
+        app.set_list_file(path);
+    }
+    // Results are read before the terminal is taken over, so that errors
+    // are printed normally
+    if let Some(path) = from {
+        let (results, source) = if path.as_os_str() == "-" {
+            let json = std::io::read_to_string(std::io::stdin())?;
+            (
+                dns::ResultsDocument::from_json(&json)?.results,
+                "stdin".to_string(),
+            )
+        } else {
+            (
+                dns::SpeedTestResult::from_json_file(&path)?,
+                path.display().to_string(),
+            )
+        };
+        app.set_results(results, source);
+    }
+    if let Some(watcher) = ctx.watcher()? {
+        app.set_watcher(watcher);
     }
 
     app.run().await?;
@@ -288,81 +2647,686 @@ async fn run_interactive(file: Option<PathBuf>) -> Result<()> {
 
 /// Main entry point for the dnstest CLI application.
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     // Set up panic hook for better error reporting
     std::panic::set_hook(Box::new(|panic_info| {
         eprintln!("程序崩溃: {panic_info}");
     }));
 
     let (cli, verbose) = dnstest::cli::parse_verbose();
+    #[cfg(feature = "otel")]
+    let _telemetry = setup_telemetry(&cli, verbose)?;
+    #[cfg(not(feature = "otel"))]
     setup_logging(verbose, cli.quiet);
 
     tracing::info!("dnstest starting...");
+    if let Some(server) = cli.bootstrap {
+        dnstest::http::set_bootstrap(server);
+    }
+    if let Some(proxy) = &cli.proxy {
+        dnstest::http::set_proxy(proxy)?;
+    }
+    if cli.interface.is_some() || cli.source.is_some() || cli.fwmark.is_some() {
+        source::set(SourceBinding {
+            interface: cli.interface.clone(),
+            address: cli.source,
+            mark: cli.fwmark,
+        })?;
+    }
+
+    // The TUI shows update notices itself
+    let interactive = matches!(cli.command, None | Some(Commands::Interactive { .. }));
+    let update_check = (cli.check_updates && !interactive).then(UpdateCheck::start);
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        // Commands that never read the settings still work with a broken file
+        Err(e) if !uses_settings(cli.command.as_ref()) => {
+            tracing::warn!("Ignoring settings: {e}");
+            Settings::default()
+        }
+        Err(e) => return Err(e),
+    };
+    let ctx = Context::new(settings, cli.profile.as_deref(), cli.push, cli.push_token)?;
+
+    let format = cli.format.or(ctx.profile.format).unwrap_or_default();
+    let status = run_command(cli.command, format, cli.check_updates, ctx).await?;
 
-    match cli.command {
-        Some(Commands::Interactive { file }) => {
-            run_interactive(file).await?;
+    if let Some(update_check) = update_check {
+        update_check.finish(cli.quiet).await;
+    }
+
+    Ok(status)
+}
+
+/// Whether a subcommand reads `config.toml`: profiles, plugins, push,
+/// alerts or the watchlist. The TUI runs without a subcommand.
+const fn uses_settings(command: Option<&Commands>) -> bool {
+    matches!(
+        command,
+        None | Some(
+            Commands::Interactive { .. }
+                | Commands::Speed { .. }
+                | Commands::Check { .. }
+                | Commands::Report { .. }
+                | Commands::Serve { .. }
+                | Commands::Monitor(_)
+                | Commands::Features(_)
+                | Commands::Propagate(_)
+                | Commands::Prune { .. }
+        )
+    )
+}
+
+/// Dispatch a subcommand.
+#[allow(clippy::too_many_lines)] // one arm per subcommand
+async fn run_command(
+    command: Option<Commands>,
+    format: OutputFormat,
+    check_updates: bool,
+    mut ctx: Context,
+) -> Result<ExitCode> {
+    if format == OutputFormat::Yaml
+        && !matches!(
+            command,
+            Some(Commands::Speed { .. } | Commands::List { .. })
+        )
+    {
+        return Err(Error::config(
+            "YAML output is only supported by speed and list",
+        ));
+    }
+
+    let mut status = 0;
+    match command {
+        Some(Commands::Interactive {
+            file,
+            from,
+            ipv4_only,
+            ipv6_only,
+        }) => {
+            let family = IpFamily::from_flags(ipv4_only, ipv6_only);
+            run_interactive(file, from, family, check_updates, &ctx).await?;
         }
 
         Some(Commands::Speed {
             file,
-            count: _,
-            timeout: _,
+            count,
+            timeout,
+            timeout_ms,
+            concurrency,
+            qtype,
+            method,
+            query_name,
+            tcp_fallback,
             dns_servers,
+            preset,
+            country,
+            ipv4_only,
+            ipv6_only,
             sort_by_latency,
+            only_success,
+            max_latency,
+            min_loss,
+            no_history,
+            no_samples,
+            adaptive,
+            shuffle,
+            shuffle_seed,
+            save_results,
+            output,
+            group_by,
+            no_summary,
+            summary_only,
+            summary_format,
+            fail_over_ms,
+            min_success,
+            only_failed: _,
+            from,
+            resume,
+            egress,
         }) => {
-            run_speed_test(file, dns_servers, sort_by_latency, cli.format).await?;
+            ctx.override_speed(count, timeout, timeout_ms, concurrency, qtype);
+            if adaptive {
+                ctx.profile.adaptive = Some(true);
+            }
+            ctx.shuffle = shuffle_seed.or_else(|| shuffle.then(dns::speedtest::random_seed));
+            ctx.method = method.unwrap_or_default();
+            ctx.query_name = query_name;
+            ctx.tcp_fallback = tcp_fallback;
+            let opts = SpeedOptions {
+                file,
+                dns_servers,
+                preset,
+                countries: country,
+                family: IpFamily::from_flags(ipv4_only, ipv6_only),
+                sort_by_latency,
+                filter: ResultFilter {
+                    only_success,
+                    max_latency_ms: max_latency,
+                    min_loss_percent: min_loss,
+                },
+                samples: !no_samples,
+                group_by,
+                record: !no_history,
+                save_results,
+                output,
+                retest_from: from,
+                resume,
+                egress,
+                summary: if no_summary {
+                    SummaryOutput::None
+                } else if summary_only {
+                    SummaryOutput::Only
+                } else {
+                    SummaryOutput::After
+                },
+                summary_format,
+            };
+            let summary = run_speed_test(opts, format, &ctx).await?;
+            if !speed_gate(&summary, fail_over_ms, min_success) {
+                status = EXIT_THRESHOLD;
+            }
         }
 
-        Some(Commands::Check { domain, file: _ }) => {
-            run_pollution_check(domain, cli.format).await?;
+        Some(Commands::Check {
+            domain,
+            whois,
+            doh_json,
+            odoh_relay,
+            odoh_target,
+            no_cache,
+            lookup_timeout,
+            lookup_attempts,
+            strict,
+            lenient,
+            diff,
+            no_history,
+            ..
+        }) => {
+            if no_cache {
+                ctx.profile.reference_cache = Some(false);
+            }
+            if lookup_timeout.is_some() {
+                ctx.profile.lookup_timeout = lookup_timeout;
+            }
+            if lookup_attempts.is_some() {
+                ctx.profile.lookup_attempts = lookup_attempts;
+            }
+            if odoh_relay.is_some() {
+                ctx.profile.odoh_relay = odoh_relay;
+            }
+            if odoh_target.is_some() {
+                ctx.profile.odoh_target = odoh_target;
+            }
+            if doh_json && ctx.profile.doh_json.is_empty() {
+                ctx.profile.doh_json = dns::doh_json::DEFAULT_ENDPOINTS
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+            }
+            let history = CheckHistoryOptions {
+                diff,
+                record: !no_history,
+            };
+            status = match run_pollution_check(domain, whois, history, format, &ctx).await? {
+                CheckVerdict::Clean => 0,
+                CheckVerdict::Suspicious if lenient => 0,
+                CheckVerdict::Suspicious if !strict => EXIT_SUSPICIOUS,
+                CheckVerdict::Suspicious | CheckVerdict::Polluted => EXIT_POLLUTED,
+            };
         }
 
-        Some(Commands::List {
+        Some(Commands::Report {
+            html,
+            from,
+            file,
+            dns_servers,
+            domain,
+            no_check,
+        }) => {
+            run_report(
+                ReportOptions {
+                    html,
+                    from,
+                    file,
+                    dns_servers,
+                    domains: domain,
+                    no_check,
+                },
+                &ctx,
+            )
+            .await?;
+        }
+
+        Some(Commands::Chart { from, output, kind }) => {
+            run_chart(&from, &output, kind)?;
+        }
+
+        Some(Commands::Serve {
+            listen,
+            interval,
             file,
+            dns_servers,
+        }) => {
+            run_serve(listen, interval, file, dns_servers, ctx).await?;
+        }
+
+        Some(Commands::Doctor) => {
+            run_doctor(format).await?;
+        }
+
+        Some(Commands::Monitor(args)) => {
+            if !run_monitor(args, format, ctx).await? {
+                status = EXIT_THRESHOLD;
+            }
+        }
+
+        Some(Commands::Route(args)) => {
+            run_route(args, format).await?;
+        }
+
+        Some(Commands::Scan(args)) => {
+            run_scan(args, format).await?;
+        }
+
+        Some(Commands::OpenResolver(args)) => {
+            run_open_resolver(args, format).await?;
+        }
+
+        Some(Commands::Features(args)) => {
+            run_features(args, format, &ctx).await?;
+        }
+
+        Some(Commands::NsCheck(args)) => {
+            run_ns_check(args, format).await?;
+        }
+
+        Some(Commands::Propagate(args)) => {
+            run_propagate(args, format, &ctx).await?;
+        }
+
+        Some(Commands::Doh(args)) => {
+            run_doh(args, format).await?;
+        }
+
+        Some(Commands::List {
+            mut file,
+            saved,
+            preset,
             ipv4_only,
             ipv6_only,
+            country,
+        }) => {
+            if let Some(saved) = saved {
+                let path = ConfigLoader::list_path(saved.file_name());
+                if !path.exists() {
+                    return Err(Error::config(format!("{} 不存在", path.display())));
+                }
+                file = Some(path);
+            }
+            run_list_dns(
+                file,
+                preset,
+                IpFamily::from_flags(ipv4_only, ipv6_only),
+                &country,
+                format,
+            )
+            .await?;
+        }
+
+        Some(Commands::Add {
+            servers,
+            provider,
+            country,
+        }) => {
+            run_add(servers, provider.as_deref(), country.as_deref())?;
+        }
+
+        Some(Commands::Remove { servers }) => {
+            run_remove(&servers)?;
+        }
+
+        Some(Commands::Prune {
+            file,
+            after,
+            dry_run,
         }) => {
-            run_list_dns(file, ipv4_only, ipv6_only)?;
+            run_prune(file.as_deref(), after, dry_run, &ctx.profile)?;
+        }
+
+        Some(Commands::Export { output, ipv6 }) => {
+            run_export(&output, ipv6)?;
         }
 
-        Some(Commands::Export {
+        Some(Commands::Update {
+            url,
+            sources,
+            merge,
             output,
-            include_ipv6: _,
         }) => {
-            let lists = ConfigLoader::load_all()?;
-            let merged = ConfigLoader::merge(lists);
-            let json = serde_json::to_string_pretty(&merged)?;
-            std::fs::write(&output, json)?;
-            println!("已导出到: {}", output.display());
+            if sources.is_empty() {
+                run_update(url, output).await?;
+            } else {
+                run_update_sources(&sources, merge, output).await?;
+            }
+        }
+
+        Some(Commands::SelfUpdate { check, force }) => {
+            run_self_update(check, force).await?;
+        }
+
+        Some(Commands::Cache { action }) => {
+            run_cache(&action)?;
+        }
+
+        Some(Commands::Compare { old, new }) => {
+            run_compare(old.as_deref(), new.as_deref(), format)?;
         }
 
-        Some(Commands::Update { url, output }) => {
-            run_update(url, output)?;
+        Some(Commands::History { action }) => {
+            run_history(&action, format)?;
         }
 
         None => {
             // Default to interactive mode
-            run_interactive(None).await?;
+            run_interactive(None, None, None, check_updates, &ctx).await?;
+        }
+    }
+
+    Ok(ExitCode::from(status))
+}
+
+/// Update check running alongside a CLI command.
+struct UpdateCheck {
+    cached: Option<UpdateStatus>,
+    refresh: Option<tokio::task::JoinHandle<Result<UpdateStatus>>>,
+}
+
+impl UpdateCheck {
+    /// Load the cached status and refresh it in the background if stale.
+    fn start() -> Self {
+        let cached = UpdateStatus::load();
+        let refresh = cached
+            .as_ref()
+            .map_or(true, UpdateStatus::is_stale)
+            .then(|| tokio::spawn(update_check::refresh()));
+        Self { cached, refresh }
+    }
+
+    /// Print a notice for the freshest status available.
+    ///
+    /// A refresh still running is given a short grace period; if it does
+    /// not finish, the cached status is used and the next run retries.
+    async fn finish(self, quiet: bool) {
+        let mut status = self.cached;
+        if let Some(refresh) = self.refresh {
+            match tokio::time::timeout(Duration::from_secs(2), refresh).await {
+                Ok(Ok(Ok(fresh))) => status = Some(fresh),
+                Ok(Ok(Err(e))) => tracing::debug!("Update check failed: {e}"),
+                _ => tracing::debug!("Update check did not finish"),
+            }
+        }
+
+        let Some(status) = status else { return };
+        if quiet {
+            return;
+        }
+        if let Some(tag) = status.newer_release() {
+            eprintln!("提示: dnstest {tag} 已发布，运行 `dnstest self-update` 更新");
+        }
+        if status.list_outdated() {
+            eprintln!("提示: DNS 列表有更新，运行 `dnstest update` 获取");
+        }
+    }
+}
+
+/// Export the merged DNS list to a JSON file.
+///
+/// With [`Ipv6Export::Split`], IPv6 servers go to a `-v6` file next to
+/// `output` instead, the layout that the saved lists use.
+fn run_export(output: &Path, ipv6: Ipv6Export) -> Result<()> {
+    let lists = ConfigLoader::load_all()?;
+    let servers = ConfigLoader::merge(lists).servers;
+
+    let files = if ipv6 == Ipv6Export::Include {
+        vec![(output.to_path_buf(), servers)]
+    } else {
+        let (v4, v6): (Vec<_>, Vec<_>) = servers.into_iter().partition(|s| !s.is_ipv6());
+        let mut files = vec![(output.to_path_buf(), v4)];
+        if ipv6 == Ipv6Export::Split {
+            files.push((ConfigLoader::v6_path(output), v6));
+        }
+        files
+    };
+    for (path, servers) in files {
+        let count = servers.len();
+        let json = serde_json::to_string_pretty(&DnsList::from_servers(servers))?;
+        std::fs::write(&path, json)?;
+        println!("已导出 {count} 个服务器到: {}", path.display());
+    }
+    Ok(())
+}
+
+/// Check for and install a newer dnstest release.
+async fn run_self_update(check: bool, force: bool) -> Result<()> {
+    let current = dnstest::self_update::current_version();
+    println!("当前版本: v{current}");
+
+    let release = dnstest::self_update::latest_release().await?;
+    let newer = release.is_newer()?;
+    println!("最新版本: {} ({})", release.tag_name, release.html_url);
+
+    if !newer && !force {
+        println!("已是最新版本");
+        return Ok(());
+    }
+    if check {
+        println!("有新版本可用，运行 `dnstest self-update` 进行更新");
+        return Ok(());
+    }
+
+    let exe = dnstest::self_update::install(&release).await?;
+    println!("已更新到 {}: {}", release.tag_name, exe.display());
+    Ok(())
+}
+
+/// Show or clean the data directory.
+fn run_cache(action: &CacheAction) -> Result<()> {
+    match action {
+        CacheAction::Info => {
+            for (label, dir) in [
+                ("配置目录", ConfigLoader::config_dir()),
+                ("数据目录", ConfigLoader::data_dir()),
+                ("缓存目录", ConfigLoader::cache_dir()),
+            ] {
+                let usage = dnstest::cache::usage(&dir)?;
+                println!(
+                    "{label}: {} ({} 个文件, {})",
+                    dir.display(),
+                    usage.files,
+                    format_bytes(usage.bytes)
+                );
+            }
         }
+        CacheAction::Clean { all } => {
+            let report = if *all {
+                dnstest::cache::clean_all()?
+            } else {
+                dnstest::cache::clean()?
+            };
+            println!(
+                "已清理 {} 个文件, 释放 {}",
+                report.files,
+                format_bytes(report.bytes)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Load the two runs to compare: two files, a file against the latest
+/// recorded run, or the last two recorded runs.
+fn load_compared_runs(
+    old: Option<&Path>,
+    new: Option<&Path>,
+) -> Result<(Vec<dns::SpeedTestResult>, Vec<dns::SpeedTestResult>)> {
+    if let (Some(old), Some(new)) = (old, new) {
+        return Ok((
+            dns::SpeedTestResult::from_json_file(old)?,
+            dns::SpeedTestResult::from_json_file(new)?,
+        ));
+    }
+
+    let mut runs = History::open_default().load()?;
+    let latest = runs.pop().map(|run| run.results);
+    match (old, latest) {
+        (Some(old), Some(latest)) => Ok((dns::SpeedTestResult::from_json_file(old)?, latest)),
+        (None, Some(latest)) => match runs.pop() {
+            Some(previous) => Ok((previous.results, latest)),
+            None => Err(Error::config("历史记录中不足两次测速")),
+        },
+        (_, None) => Err(Error::config("历史记录为空")),
+    }
+}
+
+/// Compare two speed test runs.
+///
+/// # Arguments
+///
+/// * `old` - Older results file, or the second to last recorded run
+/// * `new` - Newer results file, or the latest recorded run
+/// * `format` - Output format
+fn run_compare(old: Option<&Path>, new: Option<&Path>, format: OutputFormat) -> Result<()> {
+    let (old, new) = load_compared_runs(old, new)?;
+    let comparisons = dnstest::compare::compare(&old, &new);
+
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&comparisons)?);
+        return Ok(());
+    }
+
+    let ms = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |ms| format!("{ms:.1}ms"));
+    println!(
+        "{:<20} {:<18} {:<10} {:<10} {:<10} {:<8}",
+        wide("名称"),
+        "IP",
+        wide("之前"),
+        wide("现在"),
+        wide("变化"),
+        wide("结论")
+    );
+    println!("{}", "-".repeat(80));
+    for c in &comparisons {
+        println!(
+            "{:<20} {:<18} {:<10} {:<10} {:<10} {:<8}",
+            wide(&c.server.name),
+            c.server.ip,
+            ms(c.old_ms),
+            ms(c.new_ms),
+            c.delta_ms
+                .map_or_else(|| "-".to_string(), |ms| format!("{ms:+.1}ms")),
+            match c.verdict {
+                Verdict::Faster => "显著变快",
+                Verdict::Slower => "显著变慢",
+                Verdict::Noise => "波动",
+                Verdict::Insufficient => "样本不足",
+            }
+        );
     }
+    Ok(())
+}
+
+/// Inspect the speed test history.
+///
+/// # Arguments
+///
+/// * `action` - What to show
+/// * `format` - Output format
+#[allow(clippy::cast_precision_loss)]
+fn run_history(action: &HistoryAction, format: OutputFormat) -> Result<()> {
+    match action {
+        HistoryAction::Show { server, last } => {
+            let runs = History::open_default().load()?;
+            let Some((matched, mut points)) = dnstest::history::trend(&runs, server) else {
+                return Err(Error::config(format!("历史记录中没有服务器: {server}")));
+            };
+            if let Some(last) = last {
+                points.drain(..points.len().saturating_sub(*last));
+            }
 
+            if format == OutputFormat::Json {
+                let report = serde_json::json!({ "server": matched, "points": points });
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+
+            let latencies: Vec<Option<f64>> = points.iter().map(|p| p.latency_ms).collect();
+            println!(
+                "{} ({}) 共 {} 次记录\n",
+                matched.name,
+                matched.ip,
+                points.len()
+            );
+            let answered: Vec<f64> = latencies.iter().flatten().copied().collect();
+            let mut spark = dnstest::chart::sparkline(&latencies);
+            if !answered.is_empty() {
+                let _ = write!(
+                    spark,
+                    "  最低 {:.1}ms 平均 {:.1}ms 最高 {:.1}ms",
+                    answered.iter().copied().fold(f64::INFINITY, f64::min),
+                    answered.iter().sum::<f64>() / answered.len() as f64,
+                    answered.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+                );
+            }
+            println!("{spark}\n");
+            println!(
+                "{:<20} {:<12} {:<8}",
+                wide("时间"),
+                wide("延迟"),
+                wide("丢包")
+            );
+            println!("{}", "-".repeat(42));
+            for point in &points {
+                println!(
+                    "{:<20} {:<12} {:<8}",
+                    point
+                        .timestamp
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M:%S"),
+                    wide(
+                        &point
+                            .latency_ms
+                            .map_or_else(|| "超时".to_string(), |ms| format!("{ms:.1}ms"))
+                    ),
+                    format!("{:.0}%", point.packet_loss * 100.0)
+                );
+            }
+        }
+    }
     Ok(())
 }
 
+/// Format a byte count for display.
+#[allow(clippy::cast_precision_loss)]
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes} B"),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
 /// Run DNS list update from remote URL.
-fn run_update(url: Option<String>, output: Option<std::path::PathBuf>) -> Result<()> {
+async fn run_update(url: Option<String>, output: Option<std::path::PathBuf>) -> Result<()> {
     // Default URLs
     let ipv4_url = url
         .clone()
-        .unwrap_or_else(|| "https://wjsoj.github.io/dnstest/dnslist.json".to_string());
-    let ipv6_url =
-        url.unwrap_or_else(|| "https://wjsoj.github.io/dnstest/dnslist-v6.json".to_string());
+        .unwrap_or_else(|| dnstest::config::DEFAULT_LIST_URL.to_string());
+    let ipv6_url = url.unwrap_or_else(|| dnstest::config::DEFAULT_LIST_V6_URL.to_string());
 
-    // Get user config directory
-    let config_dir = ConfigLoader::config_dir();
+    let data_dir = ConfigLoader::data_dir();
 
-    // Determine output paths (default to config directory)
+    // Determine output paths (default to data directory)
     let (ipv4_output, ipv6_output) = if output.is_some() {
         (
             output
@@ -372,12 +3336,12 @@ fn run_update(url: Option<String>, output: Option<std::path::PathBuf>) -> Result
         )
     } else {
         (
-            config_dir.join("dnslist.json"),
-            config_dir.join("dnslist-v6.json"),
+            data_dir.join(dnstest::config::LIST_FILE),
+            data_dir.join(dnstest::config::LIST_V6_FILE),
         )
     };
 
-    // Create config directory if it doesn't exist
+    // Create data directory if it doesn't exist
     if let Some(parent) = ipv4_output.parent() {
         if !parent.exists() {
             std::fs::create_dir_all(parent)?;
@@ -392,6 +3356,7 @@ fn run_update(url: Option<String>, output: Option<std::path::PathBuf>) -> Result
 
     // Download IPv4 list
     let ipv4_result = std::process::Command::new("curl")
+        .args(dnstest::http::curl_args(&ipv4_url).await?)
         .args(["-sL", &ipv4_url, "-o"])
         .arg(&ipv4_output)
         .output();
@@ -410,6 +3375,7 @@ fn run_update(url: Option<String>, output: Option<std::path::PathBuf>) -> Result
 
     // Download IPv6 list
     let ipv6_result = std::process::Command::new("curl")
+        .args(dnstest::http::curl_args(&ipv6_url).await?)
         .args(["-sL", &ipv6_url, "-o"])
         .arg(&ipv6_output)
         .output();
@@ -429,3 +3395,61 @@ fn run_update(url: Option<String>, output: Option<std::path::PathBuf>) -> Result
     println!("更新完成!");
     Ok(())
 }
+
+/// Download several DNS lists in parallel and merge them into one file.
+///
+/// Each source is validated on its own; a source that fails to download
+/// or parse is reported and skipped.
+///
+/// # Arguments
+///
+/// * `sources` - URLs of the lists to download
+/// * `merge` - Whether to replace the local list or add to it
+/// * `output` - Output file (default: dnslist.json in the data directory)
+async fn run_update_sources(
+    sources: &[String],
+    merge: MergePolicy,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let output =
+        output.unwrap_or_else(|| ConfigLoader::data_dir().join(dnstest::config::LIST_FILE));
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    println!("正在从 {} 个来源更新 DNS 列表...", sources.len());
+    let downloads = futures::future::join_all(sources.iter().map(|url| async move {
+        let mut list: DnsList = dnstest::http::get_json(url, UPDATE_TIMEOUT).await?;
+        list.normalize();
+        ConfigLoader::validate(&list)?;
+        Ok::<_, Error>(list)
+    }))
+    .await;
+
+    let mut lists = Vec::new();
+    for (url, download) in sources.iter().zip(downloads) {
+        match download {
+            Ok(list) => {
+                println!("{url}: {} 个服务器", list.len());
+                lists.push(list);
+            }
+            Err(e) => eprintln!("{url}: 失败 ({e})"),
+        }
+    }
+    if lists.is_empty() {
+        return Err(Error::network("所有来源均下载失败"));
+    }
+    if merge == MergePolicy::Union && output.exists() {
+        // Listed first, so local entries win duplicates
+        lists.insert(0, ConfigLoader::load_from_file(&output)?);
+    }
+
+    let merged = ConfigLoader::merge(lists);
+    write_atomic(&output, &serde_json::to_string_pretty(&merged)?)?;
+    println!(
+        "已合并 {} 个服务器 ({merge}), 保存到: {}",
+        merged.len(),
+        output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,170 @@
+//! `OpenMetrics` exposition of speed test results.
+//!
+//! The output is a complete `OpenMetrics` text snapshot (terminated by
+//! `# EOF`), which is also valid Prometheus text format, so it can be
+//! dropped into `node_exporter`'s textfile collector directory by a cron job.
+
+use crate::dns::{SpeedTestResult, SpeedTester};
+use chrono::{DateTime, Utc};
+use std::fmt::Write;
+
+/// Render results as an `OpenMetrics` text snapshot.
+///
+/// Latency is exported in seconds, the `OpenMetrics` base unit. Failed
+/// servers have no latency sample but are still listed in `dnstest_up`.
+///
+/// # Arguments
+///
+/// * `results` - Speed test results
+/// * `timestamp` - When the run finished
+///
+/// # Example
+///
+/// ```ignore
+/// let text = metrics::render_openmetrics(&results, Utc::now());
+/// std::fs::write("dnstest.prom", text)?;
+/// ```
+#[must_use]
+pub fn render_openmetrics(results: &[SpeedTestResult], timestamp: DateTime<Utc>) -> String {
+    let mut out = String::new();
+
+    family(
+        &mut out,
+        "dnstest_up",
+        "gauge",
+        None,
+        "Whether the DNS server answered the probe (1) or not (0).",
+    );
+    for r in results {
+        sample(&mut out, "dnstest_up", r, if r.success { 1.0 } else { 0.0 });
+    }
+
+    family(
+        &mut out,
+        "dnstest_latency_seconds",
+        "gauge",
+        Some("seconds"),
+        "Average ICMP round-trip time to the DNS server.",
+    );
+    for r in results {
+        if let Some(latency) = r.latency_ms {
+            sample(&mut out, "dnstest_latency_seconds", r, latency / 1000.0);
+        }
+    }
+
+    family(
+        &mut out,
+        "dnstest_packet_loss_ratio",
+        "gauge",
+        Some("ratio"),
+        "Fraction of probes to the DNS server that were lost.",
+    );
+    for r in results {
+        sample(&mut out, "dnstest_packet_loss_ratio", r, r.packet_loss);
+    }
+
+    family(
+        &mut out,
+        "dnstest_hops",
+        "gauge",
+        None,
+        "Estimated number of routers between the host and the DNS server.",
+    );
+    for r in results {
+        if let Some(hops) = r.hops {
+            sample(&mut out, "dnstest_hops", r, f64::from(hops));
+        }
+    }
+
+    let summary = SpeedTester::summarize(results);
+    family(
+        &mut out,
+        "dnstest_servers",
+        "gauge",
+        None,
+        "Number of DNS servers tested, by outcome.",
+    );
+    for (outcome, count) in [
+        ("success", summary.success),
+        ("failed", summary.failed),
+        ("timeout", summary.timeout),
+    ] {
+        let _ = writeln!(out, "dnstest_servers{{result=\"{outcome}\"}} {count}");
+    }
+
+    family(
+        &mut out,
+        "dnstest_last_run_timestamp_seconds",
+        "gauge",
+        Some("seconds"),
+        "Unix time at which the speed test finished.",
+    );
+    let _ = writeln!(
+        out,
+        "dnstest_last_run_timestamp_seconds {}",
+        timestamp.timestamp()
+    );
+
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Write the `TYPE`, `UNIT` and `HELP` lines of a metric family.
+fn family(out: &mut String, name: &str, kind: &str, unit: Option<&str>, help: &str) {
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    if let Some(unit) = unit {
+        let _ = writeln!(out, "# UNIT {name} {unit}");
+    }
+    let _ = writeln!(out, "# HELP {name} {help}");
+}
+
+/// Write one per-server sample.
+fn sample(out: &mut String, name: &str, result: &SpeedTestResult, value: f64) {
+    let _ = writeln!(
+        out,
+        "{name}{{server=\"{}\",ip=\"{}\",provider=\"{}\"}} {value}",
+        escape_label(&result.server.name),
+        escape_label(&result.server.ip),
+        escape_label(result.server.provider_name())
+    );
+}
+
+/// Escape a label value (backslash, double quote and newline).
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::DnsServer;
+
+    #[test]
+    fn test_render_openmetrics() {
+        let results = vec![
+            SpeedTestResult::success(
+                DnsServer::new("Cloudflare", "1.1.1.1").with_provider("CF"),
+                12.5,
+                0.0,
+            ),
+            SpeedTestResult::failure(DnsServer::new("Say \"hi\"", "192.0.2.1"), "timeout"),
+        ];
+        let timestamp = "2024-01-01T00:00:00Z".parse().unwrap();
+        let text = render_openmetrics(&results, timestamp);
+
+        assert!(
+            text.contains("dnstest_up{server=\"Cloudflare\",ip=\"1.1.1.1\",provider=\"CF\"} 1\n")
+        );
+        assert!(
+            text.contains("dnstest_latency_seconds{server=\"Cloudflare\",ip=\"1.1.1.1\",provider=\"CF\"} 0.0125\n")
+        );
+        assert!(!text.contains("dnstest_latency_seconds{server=\"Say"));
+        assert!(text.contains("dnstest_up{server=\"Say \\\"hi\\\"\",ip=\"192.0.2.1\",provider=\"Say \\\"hi\\\"\"} 0\n"));
+        assert!(text.contains("dnstest_servers{result=\"timeout\"} 1\n"));
+        assert!(text.contains("dnstest_last_run_timestamp_seconds 1704067200\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
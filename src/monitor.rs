@@ -0,0 +1,389 @@
+//! Rolling per-server statistics for monitor mode.
+//!
+//! `dnstest monitor` tests the same servers round after round. Instead of
+//! showing only the latest round, [`Monitor`] folds every result into
+//! per-server statistics over the whole session, like mtr: loss, last,
+//! average, best, worst and standard deviation. Each round counts as one
+//! sample per server, its latency being the average of that round's pings.
+
+use crate::dns::{DnsServer, SpeedTestResult};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+/// Default seconds between the starts of two rounds.
+pub const DEFAULT_INTERVAL_SECS: u64 = 10;
+
+/// Rounds kept per provider in a [`Heatmap`].
+pub const HEATMAP_ROUNDS: usize = 120;
+
+/// Upper bounds in milliseconds of the heatmap latency buckets; slower
+/// answers fall in the last bucket.
+pub const HEATMAP_BUCKETS_MS: [f64; 4] = [20.0, 50.0, 100.0, 200.0];
+
+/// Statistics of one server over a session.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RollingStats {
+    /// Rounds the server was tested in
+    pub sent: u64,
+    /// Rounds it answered in
+    pub received: u64,
+    /// Latency of the latest answered round in milliseconds
+    pub last_ms: Option<f64>,
+    /// Lowest latency in milliseconds
+    pub best_ms: Option<f64>,
+    /// Highest latency in milliseconds
+    pub worst_ms: Option<f64>,
+    /// Rounds in a row, up to the latest, without an answer
+    pub failed_streak: u64,
+    /// Running mean (Welford)
+    mean: f64,
+    /// Running sum of squared deviations from the mean (Welford)
+    m2: f64,
+}
+
+impl RollingStats {
+    /// Add one round's result.
+    pub fn record(&mut self, latency_ms: Option<f64>) {
+        self.sent += 1;
+        let Some(latency) = latency_ms else {
+            self.failed_streak += 1;
+            return;
+        };
+        self.failed_streak = 0;
+        self.received += 1;
+        self.last_ms = Some(latency);
+        self.best_ms = Some(self.best_ms.map_or(latency, |best| best.min(latency)));
+        self.worst_ms = Some(self.worst_ms.map_or(latency, |worst| worst.max(latency)));
+
+        let delta = latency - self.mean;
+        self.mean += delta / self.received as f64;
+        self.m2 += delta * (latency - self.mean);
+    }
+
+    /// Fraction of rounds without an answer.
+    #[must_use]
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        1.0 - self.received as f64 / self.sent as f64
+    }
+
+    /// Average latency of the answered rounds.
+    #[must_use]
+    pub fn avg_ms(&self) -> Option<f64> {
+        (self.received > 0).then_some(self.mean)
+    }
+
+    /// Standard deviation of the answered rounds' latency.
+    #[must_use]
+    pub fn stddev_ms(&self) -> Option<f64> {
+        (self.received > 0).then(|| (self.m2 / self.received as f64).sqrt())
+    }
+}
+
+/// Statistics of one server, as written to ndjson output.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerStats {
+    pub name: String,
+    pub ip: String,
+    pub sent: u64,
+    pub received: u64,
+    /// Fraction of rounds without an answer
+    pub loss: f64,
+    pub last_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub best_ms: Option<f64>,
+    pub worst_ms: Option<f64>,
+    pub stddev_ms: Option<f64>,
+}
+
+/// State of a session after a round, one ndjson line.
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub timestamp: DateTime<Utc>,
+    /// Number of completed rounds
+    pub round: u64,
+    pub servers: Vec<ServerStats>,
+}
+
+/// Rolling statistics of every server seen in a session.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut monitor = Monitor::new();
+/// loop {
+///     let results = test_round(&servers).await;
+///     monitor.record(&results);
+///     println!("{}", serde_json::to_string(&monitor.snapshot())?);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Monitor {
+    rounds: u64,
+    /// Servers in the order they were first seen
+    servers: Vec<(DnsServer, RollingStats)>,
+    /// Position in `servers` by spec
+    index: HashMap<String, usize>,
+}
+
+impl Monitor {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a complete round of results.
+    pub fn record(&mut self, results: &[SpeedTestResult]) {
+        for result in results {
+            self.record_result(result);
+        }
+        self.finish_round();
+    }
+
+    /// Add one result of the round in progress.
+    pub fn record_result(&mut self, result: &SpeedTestResult) {
+        let position = *self.index.entry(result.server.spec()).or_insert_with(|| {
+            self.servers
+                .push((result.server.clone(), RollingStats::default()));
+            self.servers.len() - 1
+        });
+        self.servers[position].1.record(result.latency_ms);
+    }
+
+    /// Mark the round in progress as complete.
+    pub fn finish_round(&mut self) {
+        self.rounds += 1;
+    }
+
+    /// Number of completed rounds.
+    #[must_use]
+    pub const fn rounds(&self) -> u64 {
+        self.rounds
+    }
+
+    /// Statistics of a server by [`DnsServer::spec`].
+    #[must_use]
+    pub fn get(&self, spec: &str) -> Option<&RollingStats> {
+        self.index
+            .get(spec)
+            .map(|&position| &self.servers[position].1)
+    }
+
+    /// Every server with its statistics, in the order first seen.
+    pub fn iter(&self) -> impl Iterator<Item = (&DnsServer, &RollingStats)> {
+        self.servers.iter().map(|(server, stats)| (server, stats))
+    }
+
+    /// Current state for ndjson output.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            timestamp: Utc::now(),
+            round: self.rounds,
+            servers: self
+                .iter()
+                .map(|(server, stats)| ServerStats {
+                    name: server.name.clone(),
+                    ip: server.ip.clone(),
+                    sent: stats.sent,
+                    received: stats.received,
+                    loss: stats.loss(),
+                    last_ms: stats.last_ms,
+                    avg_ms: stats.avg_ms(),
+                    best_ms: stats.best_ms,
+                    worst_ms: stats.worst_ms,
+                    stddev_ms: stats.stddev_ms(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Latency of each provider round after round, for a heatmap of
+/// providers by rounds.
+///
+/// A provider's latency in a round is that of its fastest address (see
+/// [`DnsServer::provider_name`]); only the latest [`HEATMAP_ROUNDS`] are
+/// kept.
+#[derive(Debug, Clone, Default)]
+pub struct Heatmap {
+    /// Providers in the order first seen, with one entry per round
+    rows: Vec<(String, VecDeque<Option<f64>>)>,
+    rounds: usize,
+}
+
+impl Heatmap {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a complete round of results.
+    pub fn record(&mut self, results: &[SpeedTestResult]) {
+        let mut best: Vec<(&str, Option<f64>)> = Vec::new();
+        for result in results {
+            let provider = result.server.provider_name();
+            let latency = result.latency_ms.filter(|_| result.success);
+            match best.iter_mut().find(|(name, _)| *name == provider) {
+                Some((_, ms)) => {
+                    *ms = match (*ms, latency) {
+                        (Some(a), Some(b)) => Some(a.min(b)),
+                        (a, b) => a.or(b),
+                    };
+                }
+                None => best.push((provider, latency)),
+            }
+        }
+
+        for (provider, latency) in best {
+            let position = match self.rows.iter().position(|(name, _)| name == provider) {
+                Some(position) => position,
+                None => {
+                    // Pad providers that appear late, so columns line up
+                    let padding = self.rounds.min(HEATMAP_ROUNDS);
+                    self.rows.push((
+                        provider.to_string(),
+                        std::iter::repeat(None).take(padding).collect(),
+                    ));
+                    self.rows.len() - 1
+                }
+            };
+            self.rows[position].1.push_back(latency);
+        }
+        self.rounds += 1;
+
+        // Providers missing from this round get an empty cell
+        let width = self.rounds.min(HEATMAP_ROUNDS);
+        for (_, cells) in &mut self.rows {
+            while cells.len() < width {
+                cells.push_back(None);
+            }
+            while cells.len() > width {
+                cells.pop_front();
+            }
+        }
+    }
+
+    /// Number of recorded rounds.
+    #[must_use]
+    pub const fn rounds(&self) -> usize {
+        self.rounds
+    }
+
+    /// Every provider with its latest rounds, oldest first.
+    pub fn rows(&self) -> impl Iterator<Item = (&str, &VecDeque<Option<f64>>)> {
+        self.rows.iter().map(|(name, cells)| (name.as_str(), cells))
+    }
+}
+
+/// Heatmap bucket of a latency: the index of the first bound in
+/// [`HEATMAP_BUCKETS_MS`] it is below, or the number of bounds.
+#[must_use]
+pub fn heatmap_bucket(latency_ms: f64) -> usize {
+    HEATMAP_BUCKETS_MS
+        .iter()
+        .position(|&bound| latency_ms < bound)
+        .unwrap_or(HEATMAP_BUCKETS_MS.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_stats() {
+        let mut stats = RollingStats::default();
+        assert!(stats.loss().abs() < 1e-9);
+        assert_eq!(stats.avg_ms(), None);
+
+        for latency in [Some(10.0), None, Some(20.0), Some(30.0)] {
+            stats.record(latency);
+        }
+        assert_eq!(stats.sent, 4);
+        assert_eq!(stats.received, 3);
+        assert!((stats.loss() - 0.25).abs() < 1e-9);
+        assert_eq!(stats.last_ms, Some(30.0));
+        assert_eq!(stats.best_ms, Some(10.0));
+        assert_eq!(stats.worst_ms, Some(30.0));
+        assert!((stats.avg_ms().unwrap() - 20.0).abs() < 1e-9);
+        // Population standard deviation of 10, 20, 30
+        assert!((stats.stddev_ms().unwrap() - (200.0f64 / 3.0).sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_failed_streak() {
+        let mut stats = RollingStats::default();
+        assert_eq!(stats.failed_streak, 0);
+
+        for latency in [None, Some(10.0), None, Some(20.0), None, None] {
+            stats.record(latency);
+        }
+        assert_eq!(stats.failed_streak, 2);
+        stats.record(Some(30.0));
+        assert_eq!(stats.failed_streak, 0);
+    }
+
+    #[test]
+    fn test_monitor_rounds() {
+        let google = DnsServer::new("Google", "8.8.8.8");
+        let cloudflare = DnsServer::new("Cloudflare", "1.1.1.1");
+        let mut monitor = Monitor::new();
+
+        monitor.record(&[
+            SpeedTestResult::success(google.clone(), 10.0, 0.0),
+            SpeedTestResult::failure(cloudflare.clone(), "timeout"),
+        ]);
+        monitor.record(&[
+            SpeedTestResult::success(cloudflare, 5.0, 0.0),
+            SpeedTestResult::success(google, 30.0, 0.0),
+        ]);
+
+        assert_eq!(monitor.rounds(), 2);
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.round, 2);
+        // First-seen order is kept across rounds
+        let ips: Vec<_> = snapshot.servers.iter().map(|s| s.ip.as_str()).collect();
+        assert_eq!(ips, ["8.8.8.8", "1.1.1.1"]);
+        assert_eq!(snapshot.servers[0].avg_ms, Some(20.0));
+        assert!((snapshot.servers[1].loss - 0.5).abs() < 1e-9);
+        assert_eq!(monitor.get("1.1.1.1").unwrap().best_ms, Some(5.0));
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let google = |ip: &str| DnsServer::new(ip, ip).with_provider("Google");
+        let mut heatmap = Heatmap::new();
+        heatmap.record(&[
+            SpeedTestResult::success(google("8.8.8.8"), 30.0, 0.0),
+            SpeedTestResult::success(google("8.8.4.4"), 10.0, 0.0),
+        ]);
+        heatmap.record(&[
+            SpeedTestResult::failure(google("8.8.8.8"), "timeout"),
+            SpeedTestResult::success(DnsServer::new("Quad9", "9.9.9.9"), 50.0, 0.0),
+        ]);
+
+        assert_eq!(heatmap.rounds(), 2);
+        let rows: Vec<_> = heatmap
+            .rows()
+            .map(|(name, cells)| (name, cells.iter().copied().collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("Google", vec![Some(10.0), None]),
+                ("Quad9", vec![None, Some(50.0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heatmap_bucket() {
+        assert_eq!(heatmap_bucket(5.0), 0);
+        assert_eq!(heatmap_bucket(20.0), 1);
+        assert_eq!(heatmap_bucket(150.0), 3);
+        assert_eq!(heatmap_bucket(900.0), 4);
+    }
+}
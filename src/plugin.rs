@@ -0,0 +1,268 @@
+//! External check plugins.
+//!
+//! A plugin is any executable declared in `config.toml`. It is run once
+//! per tested server or checked domain, receives the result as JSON on
+//! stdin and prints a JSON verdict on stdout:
+//!
+//! ```json
+//! {"verdict": "fail", "message": "listed in corporate blocklist", "data": {}}
+//! ```
+//!
+//! `verdict` is one of `pass`, `warn` or `fail`; `message` and `data` are
+//! optional. Verdicts are attached to the result's `plugins` field. A
+//! plugin that crashes, times out or prints something else yields an
+//! `error` verdict instead of failing the whole run.
+
+use crate::dns::{PollutionResult, SpeedTestResult};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Number of servers checked by plugins at the same time.
+const CONCURRENCY: usize = 8;
+
+/// What a plugin is invoked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginScope {
+    /// Once per speed-tested server
+    Server,
+    /// Once per pollution-checked domain
+    Domain,
+}
+
+/// A plugin declared in `config.toml`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginConfig {
+    /// Name reported in verdicts
+    pub name: String,
+    /// Executable to run
+    pub command: String,
+    /// Extra arguments
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// What the plugin is invoked for
+    pub scope: PluginScope,
+    /// Seconds to wait for a verdict
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+const fn default_timeout() -> u64 {
+    10
+}
+
+/// Outcome reported by a plugin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    /// Nothing found
+    Pass,
+    /// Something worth a look
+    Warn,
+    /// The check failed
+    Fail,
+    /// The plugin itself did not work
+    Error,
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pass => write!(f, "pass"),
+            Self::Warn => write!(f, "warn"),
+            Self::Fail => write!(f, "fail"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A plugin's verdict on one server or domain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginVerdict {
+    /// Plugin name
+    pub plugin: String,
+    /// Outcome
+    pub verdict: Verdict,
+    /// Human-readable explanation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Free-form plugin data
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub data: serde_json::Value,
+}
+
+impl std::fmt::Display for PluginVerdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.plugin, self.verdict)?;
+        if let Some(message) = &self.message {
+            write!(f, " ({message})")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a plugin prints on stdout.
+#[derive(Debug, Deserialize)]
+struct PluginOutput {
+    verdict: Verdict,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+/// The configured plugins.
+///
+/// # Example
+///
+/// ```ignore
+/// let plugins = Plugins::new(Settings::load()?.plugins);
+/// plugins.check_servers(&mut results).await;
+/// for verdict in &results[0].plugins {
+///     println!("{verdict}");
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Plugins {
+    plugins: Vec<PluginConfig>,
+}
+
+impl Plugins {
+    /// Wrap a set of plugin declarations.
+    #[must_use]
+    pub fn new(plugins: Vec<PluginConfig>) -> Self {
+        Self { plugins }
+    }
+
+    /// Run the server plugins on every speed test result.
+    pub async fn check_servers(&self, results: &mut [SpeedTestResult]) {
+        if !self.plugins.iter().any(|p| p.scope == PluginScope::Server) {
+            return;
+        }
+        futures::stream::iter(results.iter_mut())
+            .for_each_concurrent(CONCURRENCY, |result| async move {
+                let input = json!({ "server": result.server, "result": &*result });
+                result.plugins = self.run_all(PluginScope::Server, &input).await;
+            })
+            .await;
+    }
+
+    /// Run the domain plugins on a pollution check result.
+    pub async fn check_domain(&self, result: &mut PollutionResult) {
+        let input = json!({ "domain": result.domain, "result": &*result });
+        result.plugins = self.run_all(PluginScope::Domain, &input).await;
+    }
+
+    async fn run_all(&self, scope: PluginScope, input: &serde_json::Value) -> Vec<PluginVerdict> {
+        let input = input.to_string();
+        futures::future::join_all(
+            self.plugins
+                .iter()
+                .filter(|p| p.scope == scope)
+                .map(|p| run(p, &input)),
+        )
+        .await
+    }
+}
+
+/// Run one plugin, turning any failure into an `error` verdict.
+#[tracing::instrument(level = "debug", skip_all, fields(plugin = %plugin.name))]
+async fn run(plugin: &PluginConfig, input: &str) -> PluginVerdict {
+    let timeout = Duration::from_secs(plugin.timeout);
+    let (verdict, message, data) = match tokio::time::timeout(timeout, invoke(plugin, input)).await
+    {
+        Ok(Ok(output)) => (output.verdict, output.message, output.data),
+        Ok(Err(e)) => (Verdict::Error, Some(e), serde_json::Value::Null),
+        Err(_) => (
+            Verdict::Error,
+            Some(format!("timed out after {}s", plugin.timeout)),
+            serde_json::Value::Null,
+        ),
+    };
+    if verdict == Verdict::Error {
+        tracing::warn!(
+            "Plugin {} failed: {}",
+            plugin.name,
+            message.as_deref().unwrap_or_default()
+        );
+    }
+    PluginVerdict {
+        plugin: plugin.name.clone(),
+        verdict,
+        message,
+        data,
+    }
+}
+
+async fn invoke(plugin: &PluginConfig, input: &str) -> Result<PluginOutput, String> {
+    let mut child = Command::new(&plugin.command)
+        .args(&plugin.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to start {}: {e}", plugin.command))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A plugin may exit without reading its input
+        let _ = stdin.write_all(input.as_bytes()).await;
+    }
+
+    let output = child.wait_with_output().await.map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!(
+            "exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("invalid verdict: {e}"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::dns::DnsServer;
+
+    fn shell(name: &str, scope: PluginScope, script: &str) -> PluginConfig {
+        PluginConfig {
+            name: name.to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            scope,
+            timeout: 5,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_servers() {
+        let plugins = Plugins::new(vec![
+            // Echo the address back to prove the input arrived on stdin
+            shell(
+                "echo",
+                PluginScope::Server,
+                r#"ip=$(sed 's/.*"IP":"\([^"]*\)".*/\1/'); echo "{\"verdict\":\"warn\",\"message\":\"$ip\"}""#,
+            ),
+            shell("broken", PluginScope::Server, "echo nope"),
+            shell("domain-only", PluginScope::Domain, "exit 1"),
+        ]);
+        let mut results = vec![SpeedTestResult::success(
+            DnsServer::new("Cloudflare", "1.1.1.1"),
+            12.5,
+            0.0,
+        )];
+        plugins.check_servers(&mut results).await;
+
+        let verdicts = &results[0].plugins;
+        assert_eq!(verdicts.len(), 2);
+        assert_eq!(verdicts[0].verdict, Verdict::Warn);
+        assert_eq!(verdicts[0].message.as_deref(), Some("1.1.1.1"));
+        assert_eq!(verdicts[1].verdict, Verdict::Error);
+    }
+}
@@ -0,0 +1,102 @@
+//! Push results to a remote collection endpoint.
+//!
+//! Each `speed`, `check` or periodic `serve` run can be posted as JSON to a
+//! user-specified URL, so a fleet of machines can report into one
+//! dashboard. Every report carries the reporting host and dnstest version:
+//!
+//! ```json
+//! {"kind": "speed", "host": "office-pc", "version": "0.1.4",
+//!  "timestamp": "2024-01-01T00:00:00Z", "results": [...]}
+//! ```
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::error::Result;
+use crate::http;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Timeout for one push.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What kind of run a report comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunKind {
+    /// `dnstest speed`
+    Speed,
+    /// `dnstest check`
+    Check,
+    /// `dnstest monitor`, or the periodic speed test of `dnstest serve`
+    Monitor,
+}
+
+/// Body of a pushed report.
+#[derive(Debug, Serialize)]
+struct Report<'a, T> {
+    kind: RunKind,
+    host: &'a str,
+    version: &'a str,
+    timestamp: DateTime<Utc>,
+    results: &'a T,
+}
+
+/// A remote collection endpoint.
+///
+/// # Example
+///
+/// ```ignore
+/// let pusher = Pusher::new("https://collector.example/dnstest", Some(token));
+/// pusher.push(RunKind::Speed, &results).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pusher {
+    url: String,
+    token: Option<String>,
+    host: String,
+}
+
+impl Pusher {
+    /// Create a pusher for an endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - Endpoint to POST reports to
+    /// * `token` - Optional bearer token
+    pub fn new(url: impl Into<String>, token: Option<String>) -> Self {
+        let host = hostname::get()
+            .map(|h| h.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            url: url.into(),
+            token,
+            host,
+        }
+    }
+
+    /// Endpoint reports are sent to.
+    #[must_use]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// POST the results of a run.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - What kind of run produced the results
+    /// * `results` - Results to send
+    pub async fn push<T: Serialize + Sync>(&self, kind: RunKind, results: &T) -> Result<()> {
+        let report = Report {
+            kind,
+            host: &self.host,
+            version: env!("CARGO_PKG_VERSION"),
+            timestamp: Utc::now(),
+            results,
+        };
+        http::post_json(&self.url, &report, self.token.as_deref(), PUSH_TIMEOUT).await?;
+        tracing::debug!("Pushed {kind:?} results to {}", self.url);
+        Ok(())
+    }
+}
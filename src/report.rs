@@ -0,0 +1,273 @@
+//! Shareable report generation.
+//!
+//! This module renders speed test and pollution check results into a
+//! single self-contained HTML page (inline CSS and JavaScript, no external
+//! assets), suitable for attaching to a support ticket or sharing with a
+//! team.
+
+use crate::dns::{PollutionResult, SpeedTestResult, SpeedTester, TestSummary};
+use serde::Serialize;
+
+/// Data embedded into the report page.
+#[derive(Debug, Serialize)]
+struct ReportData<'a> {
+    generated_at: String,
+    version: &'static str,
+    summary: TestSummary,
+    results: &'a [SpeedTestResult],
+    pollution: &'a [PollutionResult],
+}
+
+/// Render a self-contained HTML report.
+///
+/// # Arguments
+///
+/// * `results` - Speed test results
+/// * `pollution` - Pollution check results (may be empty)
+///
+/// # Returns
+///
+/// Returns the complete HTML document.
+///
+/// # Example
+///
+/// ```ignore
+/// let html = report::render_html(&results, &pollution);
+/// std::fs::write("report.html", html)?;
+/// ```
+#[must_use]
+pub fn render_html(results: &[SpeedTestResult], pollution: &[PollutionResult]) -> String {
+    let data = ReportData {
+        generated_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        version: env!("CARGO_PKG_VERSION"),
+        summary: SpeedTester::summarize(results),
+        results,
+        pollution,
+    };
+
+    // Serializing plain structs cannot fail; `</` is escaped so server
+    // names cannot close the script element early
+    let json = serde_json::to_string(&data)
+        .unwrap_or_else(|_| "{}".to_string())
+        .replace("</", "<\\/");
+
+    HTML_TEMPLATE.replace("/*DATA*/", &json)
+}
+
+/// Report page template. `/*DATA*/` is replaced with the JSON payload.
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>dnstest 报告</title>
+<style>
+  body { font-family: -apple-system, "Segoe UI", "PingFang SC", "Microsoft YaHei", sans-serif;
+         margin: 2rem auto; max-width: 1100px; padding: 0 1rem; color: #222; }
+  h1 { margin-bottom: .2rem; }
+  .meta { color: #888; margin-top: 0; }
+  .cards { display: flex; flex-wrap: wrap; gap: .8rem; }
+  .card { border: 1px solid #ddd; border-radius: 8px; padding: .6rem 1rem; min-width: 110px; }
+  .card b { display: block; font-size: 1.4rem; }
+  table { border-collapse: collapse; width: 100%; margin-top: .5rem; }
+  th, td { border-bottom: 1px solid #eee; padding: .35rem .5rem; text-align: left; }
+  th { cursor: pointer; user-select: none; background: #f7f7f7; }
+  th.sorted::after { content: attr(data-dir); color: #888; }
+  tr.highlight { background: #fff6d5; }
+  .ok { color: #2e7d32; } .fail { color: #c62828; } .warn { color: #ef6c00; }
+  #chart svg { width: 100%; }
+  #chart rect { fill: #4f9dde; cursor: pointer; }
+  #chart rect:hover, #chart rect.active { fill: #1565c0; }
+  #chart text { font-size: 11px; fill: #444; }
+  label { color: #555; font-size: .9rem; }
+</style>
+</head>
+<body>
+<h1>DNS 测速报告</h1>
+<p class="meta" id="meta"></p>
+
+<h2>统计</h2>
+<div class="cards" id="summary"></div>
+
+<h2>延迟分布</h2>
+<label><input type="checkbox" id="top-only" checked> 仅显示最快的 30 个</label>
+<div id="chart"></div>
+
+<h2>服务器</h2>
+<table id="servers">
+  <thead><tr>
+    <th data-key="rank">#</th><th data-key="name">名称</th><th data-key="ip">IP</th>
+    <th data-key="latency">延迟 (ms)</th><th data-key="loss">丢包</th><th data-key="status">状态</th>
+  </tr></thead>
+  <tbody></tbody>
+</table>
+
+<h2>污染检测</h2>
+<table id="pollution">
+  <thead><tr><th>域名</th><th>结论</th><th>重合度</th><th>系统DNS</th><th>公共DNS</th><th>判定依据</th></tr></thead>
+  <tbody></tbody>
+</table>
+
+<script>
+const DATA = /*DATA*/;
+
+const fmt = (v, d = 1) => (v === null || v === undefined) ? "-" : Number(v).toFixed(d);
+const el = (tag, attrs = {}, text) => {
+  const e = document.createElement(tag);
+  for (const [k, v] of Object.entries(attrs)) e.setAttribute(k, v);
+  if (text !== undefined) e.textContent = text;
+  return e;
+};
+
+document.getElementById("meta").textContent =
+  `生成时间: ${DATA.generated_at} · dnstest ${DATA.version}`;
+
+const s = DATA.summary;
+const cards = [
+  ["服务器", s.total], ["成功", s.success], ["失败/超时", s.failed + s.timeout],
+  ["平均延迟", fmt(s.avg_latency) + " ms"], ["最低延迟", fmt(s.min_latency) + " ms"],
+  ["最高延迟", fmt(s.max_latency) + " ms"],
+];
+for (const [label, value] of cards) {
+  const c = el("div", { class: "card" }, label);
+  c.appendChild(el("b", {}, String(value)));
+  document.getElementById("summary").appendChild(c);
+}
+
+const rows = DATA.results.map((r, i) => ({
+  rank: i + 1, name: r.server.name, ip: r.server.IP,
+  latency: r.latency_ms, loss: r.packet_loss, status: r.success ? "成功" : (r.error || "失败"),
+  success: r.success,
+}));
+
+function highlight(ip) {
+  document.querySelectorAll("#servers tr").forEach(tr =>
+    tr.classList.toggle("highlight", tr.dataset.ip === ip));
+  document.querySelectorAll("#chart rect").forEach(rect =>
+    rect.classList.toggle("active", rect.dataset.ip === ip));
+}
+
+function drawChart() {
+  const ok = rows.filter(r => r.success).sort((a, b) => a.latency - b.latency);
+  const shown = document.getElementById("top-only").checked ? ok.slice(0, 30) : ok;
+  const box = document.getElementById("chart");
+  box.innerHTML = "";
+  if (shown.length === 0) { box.textContent = "没有成功的测试结果"; return; }
+
+  const ns = "http://www.w3.org/2000/svg";
+  const barH = 18, gap = 4, labelW = 220, width = 1000;
+  const max = Math.max(...shown.map(r => r.latency));
+  const svg = document.createElementNS(ns, "svg");
+  svg.setAttribute("viewBox", `0 0 ${width} ${shown.length * (barH + gap)}`);
+  shown.forEach((r, i) => {
+    const y = i * (barH + gap);
+    const w = Math.max(1, (r.latency / max) * (width - labelW - 70));
+    const label = document.createElementNS(ns, "text");
+    label.setAttribute("x", 0); label.setAttribute("y", y + 13);
+    label.textContent = `${r.name} (${r.ip})`.slice(0, 36);
+    const rect = document.createElementNS(ns, "rect");
+    rect.setAttribute("x", labelW); rect.setAttribute("y", y);
+    rect.setAttribute("width", w); rect.setAttribute("height", barH);
+    rect.dataset.ip = r.ip;
+    const tip = document.createElementNS(ns, "title");
+    tip.textContent = `${r.name} ${r.ip}: ${fmt(r.latency)} ms`;
+    rect.appendChild(tip);
+    rect.addEventListener("click", () => highlight(r.ip));
+    const value = document.createElementNS(ns, "text");
+    value.setAttribute("x", labelW + w + 6); value.setAttribute("y", y + 13);
+    value.textContent = `${fmt(r.latency)} ms`;
+    svg.append(label, rect, value);
+  });
+  box.appendChild(svg);
+}
+
+let sortKey = "rank", sortAsc = true;
+function drawTable() {
+  const body = document.querySelector("#servers tbody");
+  body.innerHTML = "";
+  const sorted = [...rows].sort((a, b) => {
+    let x = a[sortKey], y = b[sortKey];
+    if (x === null || x === undefined) x = Infinity;
+    if (y === null || y === undefined) y = Infinity;
+    const c = typeof x === "string" ? x.localeCompare(y) : x - y;
+    return sortAsc ? c : -c;
+  });
+  for (const r of sorted) {
+    const tr = el("tr");
+    tr.dataset.ip = r.ip;
+    tr.append(el("td", {}, r.rank), el("td", {}, r.name), el("td", {}, r.ip),
+      el("td", {}, fmt(r.latency)), el("td", {}, fmt(r.loss * 100, 0) + "%"),
+      el("td", { class: r.success ? "ok" : "fail" }, r.status));
+    tr.addEventListener("click", () => highlight(r.ip));
+    body.appendChild(tr);
+  }
+  document.querySelectorAll("#servers th").forEach(th => {
+    th.classList.toggle("sorted", th.dataset.key === sortKey);
+    th.dataset.dir = sortAsc ? " ▲" : " ▼";
+  });
+}
+document.querySelectorAll("#servers th").forEach(th => th.addEventListener("click", () => {
+  sortAsc = sortKey === th.dataset.key ? !sortAsc : true;
+  sortKey = th.dataset.key;
+  drawTable();
+}));
+
+function drawPollution() {
+  const body = document.querySelector("#pollution tbody");
+  if (DATA.pollution.length === 0) {
+    const tr = el("tr");
+    tr.appendChild(el("td", { colspan: 6 }, "未进行污染检测"));
+    body.appendChild(tr);
+    return;
+  }
+  for (const p of DATA.pollution) {
+    const verdict = p.is_polluted ? "可能污染" : (p.censorship_suspected ? "疑似审查" : "正常");
+    const cls = p.is_polluted ? "fail" : (p.censorship_suspected ? "warn" : "ok");
+    const tr = el("tr");
+    tr.append(el("td", {}, p.domain), el("td", { class: cls }, verdict),
+      el("td", {}, fmt(p.overlap_ratio * 100, 0) + "%"),
+      el("td", {}, p.system_ips.join(", ")), el("td", {}, p.public_ips.join(", ")),
+      el("td", {}, p.reasons.join(", ")));
+    body.appendChild(tr);
+  }
+}
+
+document.getElementById("top-only").addEventListener("change", drawChart);
+drawChart();
+drawTable();
+drawPollution();
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::DnsServer;
+
+    #[test]
+    fn test_render_html_embeds_results() {
+        let results = vec![
+            SpeedTestResult::success(DnsServer::new("Cloudflare", "1.1.1.1"), 12.5, 0.0),
+            SpeedTestResult::failure(DnsServer::new("Dead", "192.0.2.1"), "timeout"),
+        ];
+        let html = render_html(&results, &[]);
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(!html.contains("/*DATA*/"));
+        assert!(html.contains("\"Cloudflare\""));
+        assert!(html.contains("\"total\":2"));
+    }
+
+    #[test]
+    fn test_render_html_escapes_script_end() {
+        let results = vec![SpeedTestResult::success(
+            DnsServer::new("</script><b>x", "1.1.1.1"),
+            1.0,
+            0.0,
+        )];
+        let html = render_html(&results, &[]);
+        assert!(!html.contains("</script><b>"));
+    }
+}
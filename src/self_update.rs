@@ -0,0 +1,263 @@
+//! Self-update from GitHub releases.
+//!
+//! Release archives are built by `.github/workflows/release.yml`, one per
+//! target triple, next to a `SHA256SUMS` file. Updating downloads the
+//! archive for the running platform, checks it against `SHA256SUMS`,
+//! unpacks it with the system `tar` (which also reads zip on Windows) and
+//! swaps the running executable. A running executable cannot be overwritten
+//! on Windows, so the swap goes through `self_replace`, which moves it aside
+//! and deletes it once the process exits.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::error::{Error, Result};
+use crate::http;
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// GitHub repository releases are published to.
+pub const REPOSITORY: &str = "wjsoj/dnstest";
+
+/// Name of the checksum file attached to every release.
+const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// Timeout for the release metadata request.
+const API_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Timeout for downloading an archive.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A published release.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    /// Git tag, e.g. `v0.1.5`
+    pub tag_name: String,
+    /// Release page URL
+    pub html_url: String,
+    /// Attached files
+    pub assets: Vec<Asset>,
+}
+
+/// A file attached to a release.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Asset {
+    /// File name
+    pub name: String,
+    /// Direct download URL
+    pub browser_download_url: String,
+}
+
+impl Release {
+    /// Version of the release, parsed from its tag.
+    pub fn version(&self) -> Result<Version> {
+        Version::parse(self.tag_name.trim_start_matches('v'))
+            .map_err(|e| Error::parse(format!("Invalid release tag {}: {e}", self.tag_name)))
+    }
+
+    /// Whether the release is newer than the running binary.
+    pub fn is_newer(&self) -> Result<bool> {
+        Ok(self.version()? > current_version())
+    }
+
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .ok_or_else(|| Error::network(format!("Release {} has no {name}", self.tag_name)))
+    }
+}
+
+/// Version of the running binary.
+#[must_use]
+pub fn current_version() -> Version {
+    // Cargo guarantees the package version is valid semver
+    Version::parse(env!("CARGO_PKG_VERSION")).unwrap_or_else(|_| Version::new(0, 0, 0))
+}
+
+/// Target triple release archives are built for on this platform.
+///
+/// Returns `None` on platforms without prebuilt binaries.
+#[must_use]
+pub const fn target() -> Option<&'static str> {
+    if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+        Some("x86_64-unknown-linux-gnu")
+    } else if cfg!(all(target_os = "linux", target_arch = "aarch64")) {
+        Some("aarch64-unknown-linux-gnu")
+    } else if cfg!(all(target_os = "macos", target_arch = "x86_64")) {
+        Some("x86_64-apple-darwin")
+    } else if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+        Some("aarch64-apple-darwin")
+    } else if cfg!(all(target_os = "windows", target_arch = "x86_64")) {
+        Some("x86_64-pc-windows-msvc")
+    } else {
+        None
+    }
+}
+
+/// Archive file name for a target triple.
+#[must_use]
+pub fn archive_name(target: &str) -> String {
+    let extension = if target.contains("windows") {
+        "zip"
+    } else {
+        "tar.gz"
+    };
+    format!("dnstest-{target}.{extension}")
+}
+
+/// Fetch the latest published release.
+pub async fn latest_release() -> Result<Release> {
+    http::get_json(
+        &format!("https://api.github.com/repos/{REPOSITORY}/releases/latest"),
+        API_TIMEOUT,
+    )
+    .await
+}
+
+/// Download, verify and install a release over the running binary.
+///
+/// # Arguments
+///
+/// * `release` - Release to install
+///
+/// # Returns
+///
+/// Path of the replaced executable.
+///
+/// # Example
+///
+/// ```ignore
+/// let release = self_update::latest_release().await?;
+/// if release.is_newer()? {
+///     self_update::install(&release).await?;
+/// }
+/// ```
+pub async fn install(release: &Release) -> Result<PathBuf> {
+    let target = target().ok_or_else(|| Error::config("No prebuilt binaries for this platform"))?;
+    let name = archive_name(target);
+    let archive = release.asset(&name)?;
+    let checksums = release.asset(CHECKSUMS_FILE)?;
+
+    let checksums = http::get(&checksums.browser_download_url, API_TIMEOUT).await?;
+    let expected = find_checksum(&String::from_utf8_lossy(&checksums), &name)
+        .ok_or_else(|| Error::network(format!("{CHECKSUMS_FILE} does not list {name}")))?;
+
+    tracing::info!("Downloading {}", archive.browser_download_url);
+    let data = http::get(&archive.browser_download_url, DOWNLOAD_TIMEOUT).await?;
+    let actual = sha256_hex(&data);
+    if !actual.eq_ignore_ascii_case(&expected) {
+        return Err(Error::network(format!(
+            "Checksum mismatch for {name}: expected {expected}, got {actual}"
+        )));
+    }
+
+    // A new directory with a random name that only this user can enter,
+    // so that no one can swap the binary between the checksum and the
+    // replacement; removed when dropped
+    let staging = tempfile::Builder::new()
+        .prefix("dnstest-update-")
+        .tempdir()?;
+    unpack_and_replace(staging.path(), &name, &data).await
+}
+
+async fn unpack_and_replace(staging: &Path, name: &str, data: &[u8]) -> Result<PathBuf> {
+    let archive = staging.join(name);
+    std::fs::write(&archive, data)?;
+
+    let status = tokio::process::Command::new("tar")
+        .arg("-xf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(staging)
+        .status()
+        .await
+        .map_err(|e| Error::config(format!("Failed to run tar: {e}")))?;
+    if !status.success() {
+        return Err(Error::parse(format!("Failed to unpack {name}")));
+    }
+
+    let binary = staging.join(format!("dnstest{}", std::env::consts::EXE_SUFFIX));
+    if !binary.is_file() {
+        return Err(Error::parse(format!("{name} does not contain dnstest")));
+    }
+
+    let exe = std::env::current_exe()?;
+    self_replace::self_replace(&binary)?;
+    Ok(exe)
+}
+
+/// Look up a file's digest in `sha256sum` output.
+fn find_checksum(checksums: &str, name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let (digest, file) = line.trim().split_once(char::is_whitespace)?;
+        // Binary mode entries are prefixed with '*'
+        let file = file.trim_start().trim_start_matches('*');
+        (file == name).then(|| digest.to_string())
+    })
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_name() {
+        assert_eq!(
+            archive_name("x86_64-unknown-linux-gnu"),
+            "dnstest-x86_64-unknown-linux-gnu.tar.gz"
+        );
+        assert_eq!(
+            archive_name("x86_64-pc-windows-msvc"),
+            "dnstest-x86_64-pc-windows-msvc.zip"
+        );
+    }
+
+    #[test]
+    fn test_find_checksum() {
+        let sums = "\
+aaaa  dnstest-x86_64-apple-darwin.tar.gz
+bbbb *dnstest-x86_64-pc-windows-msvc.zip
+";
+        assert_eq!(
+            find_checksum(sums, "dnstest-x86_64-apple-darwin.tar.gz").as_deref(),
+            Some("aaaa")
+        );
+        assert_eq!(
+            find_checksum(sums, "dnstest-x86_64-pc-windows-msvc.zip").as_deref(),
+            Some("bbbb")
+        );
+        assert_eq!(find_checksum(sums, "dnstest.tar.gz"), None);
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_release_version() {
+        let release = Release {
+            tag_name: "v999.0.0".to_string(),
+            html_url: String::new(),
+            assets: Vec::new(),
+        };
+        assert_eq!(release.version().unwrap(), Version::new(999, 0, 0));
+        assert!(release.is_newer().unwrap());
+    }
+}
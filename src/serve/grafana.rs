@@ -0,0 +1,329 @@
+//! Grafana JSON datasource endpoints.
+//!
+//! Implements the `/search` and `/query` API of the `SimpleJSON` datasource
+//! (also understood by the JSON API and Infinity plugins) over the speed
+//! test history, so Grafana can chart measurements without extra glue.
+//!
+//! Targets are server IP addresses (latency in milliseconds), or one of
+//! the aggregates [`AVG_LATENCY`] and [`SUCCESS_RATE`]. Table queries
+//! return one row per measurement; the `*` target selects every server.
+
+use crate::dns::SpeedTester;
+use crate::history::HistoryRun;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Target for the average latency of each run.
+pub const AVG_LATENCY: &str = "avg_latency";
+
+/// Target for the percentage of servers that responded in each run.
+pub const SUCCESS_RATE: &str = "success_rate";
+
+/// Target matching every server in table queries.
+pub const ALL_SERVERS: &str = "*";
+
+/// Body of a `/search` request.
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchRequest {
+    /// Text typed into the query editor, used as a substring filter
+    #[serde(default)]
+    pub target: String,
+}
+
+/// Body of a `/query` request.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryRequest {
+    /// Dashboard time range; all history when absent
+    #[serde(default)]
+    pub range: Option<TimeRange>,
+    /// Requested series
+    #[serde(default)]
+    pub targets: Vec<QueryTarget>,
+    /// Maximum number of points per series
+    #[serde(default)]
+    pub max_data_points: Option<usize>,
+}
+
+/// Time range of a query.
+#[derive(Debug, Deserialize)]
+pub struct TimeRange {
+    /// Range start
+    pub from: DateTime<Utc>,
+    /// Range end
+    pub to: DateTime<Utc>,
+}
+
+/// A single requested series.
+#[derive(Debug, Deserialize)]
+pub struct QueryTarget {
+    /// Target name as returned by `/search`
+    pub target: String,
+    /// Response shape
+    #[serde(default, rename = "type")]
+    pub kind: TargetKind,
+}
+
+/// Response shape of a target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetKind {
+    /// Time series of `[value, timestamp_ms]` points
+    #[default]
+    #[serde(alias = "timeseries")]
+    Timeserie,
+    /// Table of individual measurements
+    Table,
+}
+
+/// A single entry of a `/query` response.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum QueryResponse {
+    /// Time series response
+    Series {
+        /// Series name shown in the legend
+        target: String,
+        /// `[value, unix_timestamp_ms]` pairs
+        datapoints: Vec<(f64, i64)>,
+    },
+    /// Table response
+    Table {
+        /// Always `"table"`
+        #[serde(rename = "type")]
+        kind: &'static str,
+        /// Column definitions
+        columns: Vec<Column>,
+        /// Row values, in column order
+        rows: Vec<Vec<Value>>,
+    },
+}
+
+/// A table column definition.
+#[derive(Debug, Serialize)]
+pub struct Column {
+    /// Column title
+    pub text: &'static str,
+    /// Column type (`time`, `string` or `number`)
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+}
+
+/// List the available targets matching `filter`.
+///
+/// Aggregates come first, followed by every server IP seen in history.
+#[must_use]
+pub fn search(runs: &[HistoryRun], filter: &str) -> Vec<String> {
+    let servers: BTreeSet<&str> = runs
+        .iter()
+        .flat_map(|run| &run.results)
+        .map(|r| r.server.ip.as_str())
+        .collect();
+
+    [AVG_LATENCY, SUCCESS_RATE]
+        .into_iter()
+        .chain(servers)
+        .filter(|target| target.contains(filter))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Answer a `/query` request.
+#[must_use]
+pub fn query(runs: &[HistoryRun], request: &QueryRequest) -> Vec<QueryResponse> {
+    let in_range: Vec<&HistoryRun> = runs
+        .iter()
+        .filter(|run| {
+            request
+                .range
+                .as_ref()
+                .map_or(true, |r| run.timestamp >= r.from && run.timestamp <= r.to)
+        })
+        .collect();
+
+    request
+        .targets
+        .iter()
+        .map(|target| match target.kind {
+            TargetKind::Timeserie => {
+                let mut series = series(&in_range, &target.target);
+                if let QueryResponse::Series { datapoints, .. } = &mut series {
+                    if let Some(max) = request.max_data_points {
+                        downsample(datapoints, max);
+                    }
+                }
+                series
+            }
+            TargetKind::Table => table(&in_range, &target.target),
+        })
+        .collect()
+}
+
+/// Build the time series for one target.
+fn series(runs: &[&HistoryRun], target: &str) -> QueryResponse {
+    let mut name = target.to_string();
+    let datapoints = runs
+        .iter()
+        .filter_map(|run| {
+            let value = match target {
+                AVG_LATENCY => SpeedTester::summarize(&run.results).avg_latency,
+                SUCCESS_RATE if !run.results.is_empty() => {
+                    let summary = SpeedTester::summarize(&run.results);
+                    Some(summary.success as f64 / summary.total as f64 * 100.0)
+                }
+                SUCCESS_RATE => None,
+                ip => run
+                    .results
+                    .iter()
+                    .find(|r| r.server.ip == ip)
+                    .and_then(|r| {
+                        name = format!("{} ({})", r.server.name, r.server.ip);
+                        r.latency_ms
+                    }),
+            };
+            value.map(|v| (v, run.timestamp.timestamp_millis()))
+        })
+        .collect();
+
+    QueryResponse::Series {
+        target: name,
+        datapoints,
+    }
+}
+
+/// Build the measurement table for one target.
+fn table(runs: &[&HistoryRun], target: &str) -> QueryResponse {
+    let rows = runs
+        .iter()
+        .flat_map(|run| run.results.iter().map(move |r| (run.timestamp, r)))
+        .filter(|(_, r)| target == ALL_SERVERS || r.server.ip == target)
+        .map(|(timestamp, r)| {
+            vec![
+                Value::from(timestamp.timestamp_millis()),
+                Value::from(r.server.name.clone()),
+                Value::from(r.server.ip.clone()),
+                r.latency_ms.map_or(Value::Null, Value::from),
+                Value::from(r.packet_loss * 100.0),
+                Value::from(r.success),
+            ]
+        })
+        .collect();
+
+    QueryResponse::Table {
+        kind: "table",
+        columns: vec![
+            Column {
+                text: "Time",
+                kind: "time",
+            },
+            Column {
+                text: "Server",
+                kind: "string",
+            },
+            Column {
+                text: "IP",
+                kind: "string",
+            },
+            Column {
+                text: "Latency (ms)",
+                kind: "number",
+            },
+            Column {
+                text: "Loss (%)",
+                kind: "number",
+            },
+            Column {
+                text: "Success",
+                kind: "string",
+            },
+        ],
+        rows,
+    }
+}
+
+/// Thin out `points` to at most `max` evenly spaced entries.
+fn downsample(points: &mut Vec<(f64, i64)>, max: usize) {
+    if max == 0 || points.len() <= max {
+        return;
+    }
+    let step = points.len().div_ceil(max);
+    let kept = points.iter().step_by(step).copied().collect();
+    *points = kept;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{DnsServer, SpeedTestResult};
+
+    fn runs() -> Vec<HistoryRun> {
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        vec![
+            HistoryRun {
+                timestamp: at("2024-01-01T00:00:00Z"),
+                results: vec![
+                    SpeedTestResult::success(DnsServer::new("Cloudflare", "1.1.1.1"), 10.0, 0.0),
+                    SpeedTestResult::failure(DnsServer::new("Google", "8.8.8.8"), "timeout"),
+                ],
+            },
+            HistoryRun {
+                timestamp: at("2024-01-02T00:00:00Z"),
+                results: vec![
+                    SpeedTestResult::success(DnsServer::new("Cloudflare", "1.1.1.1"), 20.0, 0.0),
+                    SpeedTestResult::success(DnsServer::new("Google", "8.8.8.8"), 30.0, 0.0),
+                ],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_search() {
+        let runs = runs();
+        assert_eq!(
+            search(&runs, ""),
+            vec![AVG_LATENCY, SUCCESS_RATE, "1.1.1.1", "8.8.8.8"]
+        );
+        assert_eq!(search(&runs, "8.8"), vec!["8.8.8.8"]);
+    }
+
+    #[test]
+    fn test_query_series_and_range() {
+        let request: QueryRequest = serde_json::from_str(
+            r#"{
+                "range": {"from": "2024-01-01T12:00:00Z", "to": "2024-01-03T00:00:00Z"},
+                "targets": [{"target": "1.1.1.1", "type": "timeserie"}, {"target": "success_rate"}],
+                "maxDataPoints": 100
+            }"#,
+        )
+        .unwrap();
+
+        let response = serde_json::to_value(query(&runs(), &request)).unwrap();
+        assert_eq!(response[0]["target"], "Cloudflare (1.1.1.1)");
+        assert_eq!(
+            response[0]["datapoints"],
+            serde_json::json!([[20.0, 1_704_153_600_000_i64]])
+        );
+        assert_eq!(response[1]["datapoints"][0][0], 100.0);
+    }
+
+    #[test]
+    fn test_query_table() {
+        let request: QueryRequest =
+            serde_json::from_str(r#"{"targets": [{"target": "*", "type": "table"}]}"#).unwrap();
+
+        let response = serde_json::to_value(query(&runs(), &request)).unwrap();
+        assert_eq!(response[0]["type"], "table");
+        assert_eq!(response[0]["rows"].as_array().unwrap().len(), 4);
+        assert_eq!(response[0]["rows"][1][3], Value::Null);
+    }
+
+    #[test]
+    fn test_downsample() {
+        let mut points: Vec<(f64, i64)> = (0..10).map(|i| (f64::from(i), i64::from(i))).collect();
+        downsample(&mut points, 5);
+        assert_eq!(points.len(), 5);
+        assert_eq!(points[1], (2.0, 2));
+    }
+}
@@ -0,0 +1,278 @@
+//! HTTP server mode.
+//!
+//! `dnstest serve` exposes the speed test history, and the status of the
+//! domain [`watch`]list if one is configured, over a small HTTP/1.1
+//! server. It only needs to answer dashboards polling a handful of JSON
+//! endpoints, so requests are parsed by hand instead of pulling in a web
+//! framework; every connection is closed after one response.
+
+#![allow(clippy::missing_errors_doc)]
+
+pub mod grafana;
+pub mod watch;
+
+use crate::error::{Error, Result};
+use crate::history::History;
+use crate::serve::watch::Watcher;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Default listen address.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8053";
+
+/// Largest request body accepted.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// A parsed HTTP request.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// An HTTP response ready to be written.
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    reason: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json<T: Serialize>(value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self {
+                status: 200,
+                reason: "OK",
+                content_type: "application/json",
+                body,
+            },
+            Err(e) => Self::error(500, "Internal Server Error", &e.to_string()),
+        }
+    }
+
+    fn html(body: String) -> Self {
+        Self {
+            status: 200,
+            reason: "OK",
+            content_type: "text/html; charset=utf-8",
+            body: body.into_bytes(),
+        }
+    }
+
+    fn text(body: &str) -> Self {
+        Self {
+            status: 200,
+            reason: "OK",
+            content_type: "text/plain; charset=utf-8",
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn error(status: u16, reason: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            reason,
+            content_type: "text/plain; charset=utf-8",
+            body: message.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// What the server answers from.
+struct State {
+    history: History,
+    /// Watchlist whose status is served, if any
+    watcher: Option<Arc<Watcher>>,
+}
+
+/// Serve the history over HTTP until the process exits.
+///
+/// # Arguments
+///
+/// * `listen` - Address to listen on
+/// * `history` - History store to serve
+/// * `watcher` - Watchlist whose latest status is served at `/status`
+///
+/// # Example
+///
+/// ```ignore
+/// serve::run("127.0.0.1:8053".parse()?, History::open_default(), None).await?;
+/// ```
+pub async fn run(
+    listen: SocketAddr,
+    history: History,
+    watcher: Option<Arc<Watcher>>,
+) -> Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    let state = Arc::new(State { history, watcher });
+    tracing::info!("Serving on http://{listen}");
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &state).await {
+                tracing::debug!("Connection from {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: &State) -> Result<()> {
+    let response = match read_request(&mut stream).await {
+        Ok(request) => {
+            tracing::debug!("{} {}", request.method, request.path);
+            route(&request, state)
+        }
+        Err(e) => Response::error(400, "Bad Request", &e.to_string()),
+    };
+    write_response(&mut stream, &response).await
+}
+
+/// Read a request line, headers and a `Content-Length` body.
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(Error::parse("Malformed request line"));
+    };
+    let method = method.to_string();
+    let path = target.split('?').next().unwrap_or("/").to_string();
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::parse("Invalid Content-Length"))?;
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(Error::parse("Request body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Request { method, path, body })
+}
+
+async fn write_response(stream: &mut TcpStream, response: &Response) -> Result<()> {
+    // CORS headers let browser-side datasources (Infinity) call us directly
+    let head = format!(
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
+         Access-Control-Allow-Headers: accept, content-type\r\n\
+         Connection: close\r\n\r\n",
+        response.status,
+        response.reason,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn route(request: &Request, state: &State) -> Response {
+    let history = &state.history;
+    match (request.method.as_str(), request.path.as_str()) {
+        ("OPTIONS", _) => Response::text(""),
+        // Grafana's "Save & test" probes the root path
+        ("GET", "/") => Response::text("dnstest"),
+        ("POST", "/search") => {
+            let filter = serde_json::from_slice::<grafana::SearchRequest>(&request.body)
+                .map(|r| r.target)
+                .unwrap_or_default();
+            with_history(history, |runs| {
+                Response::json(&grafana::search(runs, &filter))
+            })
+        }
+        ("POST", "/query") => match serde_json::from_slice(&request.body) {
+            Ok(query) => with_history(history, |runs| {
+                Response::json(&grafana::query(runs, &query))
+            }),
+            Err(e) => Response::error(400, "Bad Request", &e.to_string()),
+        },
+        // Annotations are not supported; an empty list keeps Grafana quiet
+        ("POST", "/annotations") => Response::json(&Vec::<()>::new()),
+        ("GET", "/status" | "/status.json") => match &state.watcher {
+            Some(watcher) if request.path == "/status" => {
+                Response::html(watcher.status().to_html())
+            }
+            Some(watcher) => Response::json(&watcher.status()),
+            None => Response::error(404, "Not Found", "No [watch] domains configured"),
+        },
+        _ => Response::error(404, "Not Found", "Not found"),
+    }
+}
+
+fn with_history(
+    history: &History,
+    f: impl FnOnce(&[crate::history::HistoryRun]) -> Response,
+) -> Response {
+    match history.load() {
+        Ok(runs) => f(&runs),
+        Err(e) => Response::error(500, "Internal Server Error", &e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_route() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = State {
+            history: History::new(dir.path().join("history.ndjson")),
+            watcher: None,
+        };
+
+        assert_eq!(route(&request("GET", "/", ""), &state).status, 200);
+        assert_eq!(route(&request("GET", "/missing", ""), &state).status, 404);
+        assert_eq!(
+            route(&request("POST", "/query", "not json"), &state).status,
+            400
+        );
+
+        let search = route(&request("POST", "/search", "{}"), &state);
+        assert_eq!(search.status, 200);
+        assert_eq!(search.body, br#"["avg_latency","success_rate"]"#);
+
+        assert_eq!(route(&request("GET", "/status", ""), &state).status, 404);
+        state.watcher = Some(Arc::new(Watcher::new(vec!["example.com".into()])));
+        let status = route(&request("GET", "/status.json", ""), &state);
+        assert_eq!(status.body, br#"{"checked_at":null,"entries":[]}"#);
+        let page = route(&request("GET", "/status", ""), &state);
+        assert_eq!(page.content_type, "text/html; charset=utf-8");
+    }
+}
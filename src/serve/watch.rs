@@ -0,0 +1,383 @@
+//! Domain watchlist.
+//!
+//! With a `[watch]` table in `config.toml`, `dnstest serve` checks the
+//! listed domains for pollution against each listed resolver every
+//! `interval` seconds. The outcome of the latest round is served as a
+//! status page at `/status` and as JSON at `/status.json`, showing which
+//! domains are polluted on which resolvers right now.
+//!
+//! ```toml
+//! [watch]
+//! domains = ["google.com", "twitter.com"]
+//! resolvers = ["114.114.114.114#114DNS", "223.5.5.5#AliDNS"]
+//! interval = 600
+//! ```
+
+#![allow(clippy::missing_panics_doc)]
+
+use crate::dns::{CheckVerdict, PollutionChecker, PollutionResult};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// Default seconds between two rounds.
+pub const DEFAULT_INTERVAL_SECS: u64 = 600;
+
+/// Default number of domain and resolver pairs checked at the same time.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Name of the system resolver on the status page.
+pub const SYSTEM_RESOLVER: &str = "System";
+
+/// Domains `serve` keeps checking (`[watch]` table).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Watchlist {
+    /// Domains to check
+    pub domains: Vec<String>,
+    /// Resolvers checked in place of the system resolver (format:
+    /// IP#Name); the system resolver if empty
+    pub resolvers: Vec<String>,
+    /// Seconds between two rounds
+    pub interval: u64,
+}
+
+impl Default for Watchlist {
+    fn default() -> Self {
+        Self {
+            domains: Vec::new(),
+            resolvers: Vec::new(),
+            interval: DEFAULT_INTERVAL_SECS,
+        }
+    }
+}
+
+/// Outcome of one domain on one resolver.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchEntry {
+    /// Domain checked
+    pub domain: String,
+    /// Name of the resolver checked
+    pub resolver: String,
+    /// Outcome, `None` if the check failed
+    pub verdict: Option<CheckVerdict>,
+    /// Addresses returned by the resolver
+    pub system_ips: Vec<IpAddr>,
+    /// Addresses returned by the reference resolvers
+    pub public_ips: Vec<IpAddr>,
+    /// Why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl WatchEntry {
+    fn new(domain: &str, resolver: &str, result: crate::error::Result<PollutionResult>) -> Self {
+        match result {
+            Ok(result) => Self {
+                domain: domain.to_string(),
+                resolver: resolver.to_string(),
+                verdict: Some(result.verdict()),
+                system_ips: result.system_ips,
+                public_ips: result.public_ips,
+                error: None,
+            },
+            Err(e) => Self {
+                domain: domain.to_string(),
+                resolver: resolver.to_string(),
+                verdict: None,
+                system_ips: Vec::new(),
+                public_ips: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Outcome of the latest round.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WatchStatus {
+    /// When the round finished, `None` before the first one
+    pub checked_at: Option<DateTime<Utc>>,
+    /// One entry per domain and resolver, by domain
+    pub entries: Vec<WatchEntry>,
+}
+
+impl WatchStatus {
+    /// Entries found polluted.
+    pub fn polluted(&self) -> impl Iterator<Item = &WatchEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.verdict == Some(CheckVerdict::Polluted))
+    }
+
+    /// Domains checked, in the order they were first seen.
+    #[must_use]
+    pub fn domains(&self) -> Vec<&str> {
+        let mut domains: Vec<&str> = Vec::new();
+        for entry in &self.entries {
+            if !domains.contains(&entry.domain.as_str()) {
+                domains.push(&entry.domain);
+            }
+        }
+        domains
+    }
+
+    /// Resolvers checked, in the order they were first seen.
+    #[must_use]
+    pub fn resolvers(&self) -> Vec<&str> {
+        let mut resolvers: Vec<&str> = Vec::new();
+        for entry in &self.entries {
+            if !resolvers.contains(&entry.resolver.as_str()) {
+                resolvers.push(&entry.resolver);
+            }
+        }
+        resolvers
+    }
+
+    /// Outcome of `domain` on `resolver`, `None` if it was not checked.
+    #[must_use]
+    pub fn get(&self, domain: &str, resolver: &str) -> Option<&WatchEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.domain == domain && e.resolver == resolver)
+    }
+
+    /// Render the status page: a table of domains by resolvers.
+    #[must_use]
+    pub fn to_html(&self) -> String {
+        let resolvers = self.resolvers();
+
+        let mut rows = String::new();
+        for domain in self.domains() {
+            let _ = write!(rows, "<tr><th>{}</th>", escape(domain));
+            for resolver in &resolvers {
+                let entry = self.get(domain, resolver);
+                let (class, label, title) = match entry {
+                    Some(WatchEntry {
+                        verdict: Some(verdict),
+                        system_ips,
+                        ..
+                    }) => {
+                        let (class, label) = match verdict {
+                            CheckVerdict::Clean => ("ok", "正常"),
+                            CheckVerdict::Suspicious => ("warn", "可疑"),
+                            CheckVerdict::Polluted => ("fail", "污染"),
+                        };
+                        let ips: Vec<String> = system_ips.iter().map(ToString::to_string).collect();
+                        (class, label, ips.join(", "))
+                    }
+                    Some(entry) => ("err", "失败", entry.error.clone().unwrap_or_default()),
+                    None => ("", "-", String::new()),
+                };
+                let _ = write!(
+                    rows,
+                    "<td class=\"{class}\" title=\"{}\">{label}</td>",
+                    escape(&title)
+                );
+            }
+            rows.push_str("</tr>\n");
+        }
+
+        let mut header = String::new();
+        for resolver in &resolvers {
+            let _ = write!(header, "<th>{}</th>", escape(resolver));
+        }
+        let checked_at = self.checked_at.map_or_else(
+            || "尚未完成首轮检测".to_string(),
+            |at| {
+                format!(
+                    "检测时间: {}",
+                    at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S")
+                )
+            },
+        );
+        STATUS_TEMPLATE
+            .replace("<!--CHECKED_AT-->", &checked_at)
+            .replace("<!--POLLUTED-->", &self.polluted().count().to_string())
+            .replace("<!--HEADER-->", &header)
+            .replace("<!--ROWS-->", &rows)
+    }
+}
+
+/// Checks the watchlist and keeps the outcome of the latest round.
+///
+/// # Example
+///
+/// ```ignore
+/// let watcher = Watcher::new(vec!["google.com".into()])
+///     .with_resolver("AliDNS", PollutionChecker::new()?.with_system_resolver(&[ip]));
+/// let status = watcher.check().await;
+/// println!("{} polluted", status.polluted().count());
+/// ```
+pub struct Watcher {
+    domains: Vec<String>,
+    /// Checkers by resolver name
+    resolvers: Vec<(String, PollutionChecker)>,
+    /// Pairs checked at the same time
+    concurrency: usize,
+    status: Mutex<WatchStatus>,
+}
+
+impl Watcher {
+    /// Watch `domains`, on no resolver yet.
+    #[must_use]
+    pub fn new(domains: Vec<String>) -> Self {
+        Self {
+            domains,
+            resolvers: Vec::new(),
+            concurrency: DEFAULT_CONCURRENCY,
+            status: Mutex::new(WatchStatus::default()),
+        }
+    }
+
+    /// Check at most `concurrency` domain and resolver pairs at the same
+    /// time.
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Also check the domains with `checker`, shown as `name`.
+    #[must_use]
+    pub fn with_resolver(mut self, name: impl Into<String>, checker: PollutionChecker) -> Self {
+        self.resolvers.push((name.into(), checker));
+        self
+    }
+
+    /// Number of domains watched.
+    #[must_use]
+    pub fn domains(&self) -> usize {
+        self.domains.len()
+    }
+
+    /// Number of resolvers checked.
+    #[must_use]
+    pub fn resolvers(&self) -> usize {
+        self.resolvers.len()
+    }
+
+    /// Check every domain on every resolver and keep the outcome as the
+    /// current status.
+    pub async fn check(&self) -> WatchStatus {
+        let mut checks = Vec::new();
+        for domain in &self.domains {
+            for (name, checker) in &self.resolvers {
+                let index = checks.len();
+                checks.push(async move {
+                    (
+                        index,
+                        WatchEntry::new(domain, name, checker.check(domain).await),
+                    )
+                });
+            }
+        }
+        let mut entries: Vec<_> = futures::stream::iter(checks)
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+        entries.sort_by_key(|(index, _)| *index);
+        for (_, checker) in &self.resolvers {
+            checker.flush_reference_cache();
+        }
+        let status = WatchStatus {
+            checked_at: Some(Utc::now()),
+            entries: entries.into_iter().map(|(_, entry)| entry).collect(),
+        };
+        *self.status.lock().unwrap() = status.clone();
+        status
+    }
+
+    /// Outcome of the latest round.
+    #[must_use]
+    pub fn status(&self) -> WatchStatus {
+        self.status.lock().unwrap().clone()
+    }
+}
+
+/// Escape text for HTML content and attribute values.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Status page template. `<!--NAME-->` comments are replaced with the
+/// content.
+const STATUS_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="60">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>dnstest 污染监测</title>
+<style>
+  body { font-family: -apple-system, "Segoe UI", "PingFang SC", "Microsoft YaHei", sans-serif;
+         margin: 2rem auto; max-width: 1100px; padding: 0 1rem; color: #222; }
+  .meta { color: #888; }
+  table { border-collapse: collapse; }
+  th, td { border-bottom: 1px solid #eee; padding: .35rem .8rem; text-align: left; }
+  thead th { background: #f7f7f7; }
+  .ok { color: #2e7d32; } .fail { color: #c62828; font-weight: bold; }
+  .warn { color: #ef6c00; } .err { color: #888; }
+</style>
+</head>
+<body>
+<h1>DNS 污染监测</h1>
+<p class="meta"><!--CHECKED_AT--> · 污染: <!--POLLUTED--> · <a href="/status.json">JSON</a></p>
+<table>
+<thead><tr><th>域名</th><!--HEADER--></tr></thead>
+<tbody>
+<!--ROWS--></tbody>
+</table>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_page() {
+        let entry = |domain: &str, resolver: &str, verdict| WatchEntry {
+            domain: domain.to_string(),
+            resolver: resolver.to_string(),
+            verdict,
+            system_ips: vec![IpAddr::from([31, 13, 88, 26])],
+            public_ips: Vec::new(),
+            error: verdict.is_none().then(|| "timed out".to_string()),
+        };
+        let status = WatchStatus {
+            checked_at: Some(Utc::now()),
+            entries: vec![
+                entry("google.com", "AliDNS", Some(CheckVerdict::Polluted)),
+                entry("google.com", "<Office>", None),
+                entry("example.com", "AliDNS", Some(CheckVerdict::Clean)),
+            ],
+        };
+        assert_eq!(status.polluted().count(), 1);
+        assert_eq!(status.domains(), ["google.com", "example.com"]);
+        assert_eq!(status.resolvers(), ["AliDNS", "<Office>"]);
+        assert!(status
+            .get("google.com", "<Office>")
+            .unwrap()
+            .error
+            .is_some());
+        assert!(status.get("example.com", "<Office>").is_none());
+
+        let html = status.to_html();
+        assert!(html.contains("污染: 1"));
+        assert!(html.contains("<th>AliDNS</th><th>&lt;Office&gt;</th>"));
+        assert!(html.contains(
+            "<tr><th>google.com</th><td class=\"fail\" title=\"31.13.88.26\">污染</td>\
+             <td class=\"err\" title=\"timed out\">失败</td></tr>"
+        ));
+        // No check of example.com on the second resolver
+        assert!(html.contains("<td class=\"\" title=\"\">-</td>"));
+    }
+}
@@ -0,0 +1,162 @@
+//! OpenTelemetry export of traces and metrics.
+//!
+//! The speed test and pollution check pipelines are instrumented with
+//! `tracing` spans and record their outcomes as metrics. With the `otel`
+//! feature enabled, [`Telemetry`] ships both to an OTLP/HTTP collector;
+//! without it, the metric helpers compile to nothing.
+//!
+//! Metrics:
+//!
+//! - `dnstest.probe.latency` (histogram, ms): latency of answering servers
+//! - `dnstest.probes` (counter): probed servers, by `outcome`
+//! - `dnstest.pollution.checks` (counter): checked domains, by `polluted`
+
+use crate::dns::{PollutionResult, SpeedTestResult};
+
+#[cfg(feature = "otel")]
+pub use exporter::Telemetry;
+
+/// Record the outcome of a single server probe.
+pub(crate) fn record_probe(result: &SpeedTestResult) {
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::KeyValue;
+
+        let meter = opentelemetry::global::meter("dnstest");
+        let server = [
+            KeyValue::new("server.name", result.server.name.clone()),
+            KeyValue::new("server.address", result.server.ip.clone()),
+        ];
+        if let Some(latency) = result.latency_ms {
+            meter
+                .f64_histogram("dnstest.probe.latency")
+                .with_unit("ms")
+                .build()
+                .record(latency, &server);
+        }
+        let outcome = if result.success { "success" } else { "failure" };
+        meter.u64_counter("dnstest.probes").build().add(
+            1,
+            &[server.as_slice(), &[KeyValue::new("outcome", outcome)]].concat(),
+        );
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = result;
+}
+
+/// Record the verdict of a pollution check.
+pub(crate) fn record_pollution(result: &PollutionResult) {
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::KeyValue;
+
+        opentelemetry::global::meter("dnstest")
+            .u64_counter("dnstest.pollution.checks")
+            .build()
+            .add(
+                1,
+                &[
+                    KeyValue::new("domain", result.domain.clone()),
+                    KeyValue::new("polluted", result.is_polluted),
+                    KeyValue::new("censorship_suspected", result.censorship_suspected),
+                ],
+            );
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = result;
+}
+
+#[cfg(feature = "otel")]
+mod exporter {
+    use crate::error::{Error, Result};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::metrics::SdkMeterProvider;
+    use opentelemetry_sdk::trace::{SdkTracerProvider, Tracer};
+    use opentelemetry_sdk::Resource;
+    use tracing_opentelemetry::OpenTelemetryLayer;
+    use tracing_subscriber::registry::LookupSpan;
+
+    /// Installed OTLP exporters.
+    ///
+    /// Buffered spans and metrics are flushed when this is dropped, so keep
+    /// it alive until the program exits.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let telemetry = Telemetry::init("http://localhost:4318")?;
+    /// tracing_subscriber::registry().with(telemetry.layer()).init();
+    /// ```
+    pub struct Telemetry {
+        tracer_provider: SdkTracerProvider,
+        meter_provider: SdkMeterProvider,
+    }
+
+    impl Telemetry {
+        /// Set up OTLP/HTTP export and install the global meter provider.
+        ///
+        /// The standard `OTEL_EXPORTER_OTLP_*` environment variables take
+        /// precedence over `endpoint`.
+        ///
+        /// # Arguments
+        ///
+        /// * `endpoint` - Collector base URL (e.g. `http://localhost:4318`)
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if an exporter cannot be created.
+        pub fn init(endpoint: &str) -> Result<Self> {
+            let endpoint = endpoint.trim_end_matches('/');
+            let resource = Resource::builder()
+                .with_service_name(env!("CARGO_PKG_NAME"))
+                .build();
+
+            let spans = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(format!("{endpoint}/v1/traces"))
+                .build()
+                .map_err(|e| Error::config(format!("OTLP trace exporter: {e}")))?;
+            let tracer_provider = SdkTracerProvider::builder()
+                .with_batch_exporter(spans)
+                .with_resource(resource.clone())
+                .build();
+
+            let metrics = MetricExporter::builder()
+                .with_http()
+                .with_endpoint(format!("{endpoint}/v1/metrics"))
+                .build()
+                .map_err(|e| Error::config(format!("OTLP metric exporter: {e}")))?;
+            let meter_provider = SdkMeterProvider::builder()
+                .with_periodic_exporter(metrics)
+                .with_resource(resource)
+                .build();
+            opentelemetry::global::set_meter_provider(meter_provider.clone());
+
+            Ok(Self {
+                tracer_provider,
+                meter_provider,
+            })
+        }
+
+        /// A `tracing` layer that exports spans through this exporter.
+        #[must_use]
+        pub fn layer<S>(&self) -> OpenTelemetryLayer<S, Tracer>
+        where
+            S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+        {
+            tracing_opentelemetry::layer().with_tracer(self.tracer_provider.tracer("dnstest"))
+        }
+    }
+
+    impl Drop for Telemetry {
+        fn drop(&mut self) {
+            if let Err(e) = self.tracer_provider.shutdown() {
+                eprintln!("Failed to flush traces: {e}");
+            }
+            if let Err(e) = self.meter_provider.shutdown() {
+                eprintln!("Failed to flush metrics: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Terminal text layout.
+//!
+//! `format!("{:<20}", name)` pads to 20 characters, but Chinese characters
+//! take two columns in a terminal, so rows with Chinese server names end up
+//! wider than the others and the columns after them shift. [`Wide`] pads
+//! and truncates by display width instead:
+//!
+//! ```ignore
+//! use dnstest::text::wide;
+//!
+//! assert_eq!(format!("{:<8}|", wide("阿里DNS")), "阿里DNS |");
+//! assert_eq!(format!("{:.6}|", wide("腾讯公共DNS")), "腾讯公|");
+//! ```
+
+use std::fmt;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Text formatted by display width: the width of a format spec pads it to
+/// that many terminal columns, and the precision truncates it to at most
+/// that many.
+#[derive(Debug, Clone, Copy)]
+pub struct Wide<'a>(pub &'a str);
+
+/// Format `text` by display width, see [`Wide`].
+#[must_use]
+pub const fn wide(text: &str) -> Wide<'_> {
+    Wide(text)
+}
+
+/// Number of terminal columns `text` takes.
+#[must_use]
+pub fn width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// The longest prefix of `text` that fits in `columns` terminal columns.
+#[must_use]
+pub fn truncate(text: &str, columns: usize) -> &str {
+    let mut used = 0;
+    for (idx, c) in text.char_indices() {
+        used += c.width().unwrap_or(0);
+        if used > columns {
+            return &text[..idx];
+        }
+    }
+    text
+}
+
+impl fmt::Display for Wide<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = f
+            .precision()
+            .map_or(self.0, |columns| truncate(self.0, columns));
+        let padding = f.width().unwrap_or(0).saturating_sub(width(text));
+        let (before, after) = match f.align() {
+            Some(fmt::Alignment::Right) => (padding, 0),
+            Some(fmt::Alignment::Center) => (padding / 2, padding - padding / 2),
+            Some(fmt::Alignment::Left) | None => (0, padding),
+        };
+        let fill = f.fill();
+        for _ in 0..before {
+            write!(f, "{fill}")?;
+        }
+        f.write_str(text)?;
+        for _ in 0..after {
+            write!(f, "{fill}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wide_padding() {
+        assert_eq!(format!("{:<6}|", wide("名称")), "名称  |");
+        assert_eq!(format!("{:>6}|", wide("延迟")), "  延迟|");
+        assert_eq!(format!("{:^7}|", wide("ab")), "  ab   |");
+        // Wider than the column: kept whole unless truncated
+        assert_eq!(format!("{:<4}|", wide("香港宽频")), "香港宽频|");
+        assert_eq!(format!("{:<5.5}|", wide("香港宽频")), "香港 |");
+        assert_eq!(width("Cloudflare 中国"), 15);
+    }
+}
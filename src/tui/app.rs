@@ -5,14 +5,25 @@
 #![allow(clippy::cast_sign_loss)]
 #![allow(clippy::manual_let_else)]
 
-use crate::dns::{DnsServer, PollutionResult, SpeedTestResult};
+use super::servers::ServerEditor;
+use crate::dns::progress::ProgressEvent;
+use crate::dns::{
+    CheckVerdict, DnsServer, Hop, IpFamily, PollutionResult, SpeedTestResult, Tracer,
+};
 use crate::error::Result as ColorResult;
+use crate::monitor::{self, Heatmap, Monitor};
+use crate::serve::watch::{WatchEntry, WatchStatus, Watcher};
+use crate::text;
+use crate::update_check::{self, UpdateStatus};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    text::{Line, Span},
     widgets::{Block, BorderType, Cell, Gauge, Paragraph, Row, Table, TableState},
     Frame,
 };
+use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::time::Duration;
 
@@ -20,12 +31,18 @@ use tokio::time::Duration;
 #[derive(Debug)]
 #[allow(dead_code)]
 enum AppMessage {
-    /// A single speed test result.
-    Result(SpeedTestResult),
-    /// Progress update.
-    Progress { tested: usize, total: usize },
-    /// All tests completed.
-    Completed,
+    /// Progress of the speed test, including each result.
+    Speed(ProgressEvent),
+    /// Background update check finished.
+    UpdateStatus(UpdateStatus),
+    /// A traceroute hop completed.
+    RouteHop(Hop),
+    /// Traceroute finished, with the error if it failed.
+    RouteDone(Option<String>),
+    /// Time for the next monitor round.
+    MonitorTick,
+    /// A round of pollution checks finished.
+    Watch(WatchStatus),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -35,12 +52,88 @@ enum SortMode {
     Status,
 }
 
+/// Servers tested at the same time, unless configured.
+pub const DEFAULT_CONCURRENCY: usize = 20;
+
+/// Longest a test run may take, unless configured.
+pub const DEFAULT_RUN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Terminals smaller than this get the compact layout.
+const COMPACT_SIZE: (u16, u16) = (80, 30);
+
+/// Terminals smaller than this get the minimal layout.
+const MINIMAL_SIZE: (u16, u16) = (60, 16);
+
+/// How much of the interface fits in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Density {
+    /// Title bar, tabs, statistics panel and progress gauge
+    #[default]
+    Full,
+    /// One line each for the title, tabs and statistics, and shorter
+    /// table columns
+    Compact,
+    /// The current view and a one-line status only
+    Minimal,
+}
+
+impl Density {
+    /// The density that fits a terminal of this size.
+    fn for_area(area: Rect) -> Self {
+        let smaller = |(width, height): (u16, u16)| area.width < width || area.height < height;
+        if smaller(MINIMAL_SIZE) {
+            Self::Minimal
+        } else if smaller(COMPACT_SIZE) {
+            Self::Compact
+        } else {
+            Self::Full
+        }
+    }
+
+    /// Height of the status header above a view's table.
+    const fn header_height(self) -> u16 {
+        match self {
+            Self::Full => 3,
+            Self::Compact | Self::Minimal => 1,
+        }
+    }
+}
+
+/// Servers failing this many runs in a row are pruned, as by
+/// `dnstest prune`.
+const PRUNE_AFTER: u64 = 3;
+
+/// Text being typed in the Servers tab.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Input {
+    /// Server being edited, a new one if `None`
+    editing: Option<usize>,
+    text: String,
+}
+
+/// Which results the speed test table shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Filter {
+    #[default]
+    All,
+    /// Servers that answered
+    Answered,
+    /// Servers that failed or timed out
+    Failed,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 enum View {
     #[default]
     SpeedTest,
     PollutionCheck,
+    /// Loaded servers, to enable, edit and remove
+    Servers,
     Help,
+    /// Traceroute to the selected server
+    Route,
+    /// Provider latency over completed runs
+    Heatmap,
 }
 
 pub struct App {
@@ -51,6 +144,8 @@ pub struct App {
     current_view: View,
     tab_index: usize,
     sort_mode: SortMode,
+    /// Results shown in the speed test table.
+    filter: Filter,
     testing: bool,
     tested_count: usize,
     total_count: usize,
@@ -59,6 +154,52 @@ pub struct App {
     message_tx: Option<mpsc::UnboundedSender<AppMessage>>,
     /// Table state for scrolling.
     table_state: TableState,
+    /// Whether to check for new releases and DNS lists.
+    check_updates: bool,
+    /// Update notice shown in the statistics bar.
+    update_notice: Option<String>,
+    /// Server being traced in the route view.
+    route_server: Option<DnsServer>,
+    /// Hops of the current trace so far.
+    route_hops: Vec<Hop>,
+    /// Whether a trace is running.
+    tracing: bool,
+    /// Why the last trace failed.
+    route_error: Option<String>,
+    /// Rolling statistics while monitor mode repeats runs.
+    monitor: Option<Monitor>,
+    /// Best latency of each provider in every completed run.
+    heatmap: Heatmap,
+    /// Address family to test, both if `None`.
+    family: Option<IpFamily>,
+    /// Where the results were loaded from instead of testing.
+    loaded_from: Option<String>,
+    /// Layout for the current terminal size, updated on every draw.
+    density: Density,
+    /// Servers tested at the same time.
+    concurrency: usize,
+    /// Longest a test run may take before the rest is abandoned.
+    run_timeout: Duration,
+    /// Changes made in the Servers tab.
+    server_editor: ServerEditor,
+    /// Selected row of the Servers tab.
+    server_index: usize,
+    /// Table state of the Servers tab.
+    server_table_state: TableState,
+    /// Server being added or edited.
+    input: Option<Input>,
+    /// Outcome of the last action in the Servers tab.
+    server_message: Option<String>,
+    /// List file the servers came from, the saved lists if `None`.
+    list_file: Option<PathBuf>,
+    /// Checks the watched domains on every watched resolver.
+    watcher: Option<Arc<Watcher>>,
+    /// Outcome of the latest pollution round.
+    watch_status: WatchStatus,
+    /// Pollution round running, if any.
+    watching: Option<tokio::task::JoinHandle<()>>,
+    /// Selected domain row and resolver column of the pollution matrix.
+    watch_cell: (usize, usize),
 }
 
 impl App {
@@ -71,12 +212,36 @@ impl App {
             current_view: View::default(),
             tab_index: 0,
             sort_mode: SortMode::Latency,
+            filter: Filter::default(),
             testing: false,
             tested_count: 0,
             total_count: 0,
             selected_index: 0,
             message_tx: None,
             table_state: TableState::default(),
+            check_updates: false,
+            update_notice: None,
+            route_server: None,
+            route_hops: Vec::new(),
+            tracing: false,
+            route_error: None,
+            monitor: None,
+            heatmap: Heatmap::new(),
+            family: None,
+            loaded_from: None,
+            density: Density::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+            run_timeout: DEFAULT_RUN_TIMEOUT,
+            server_editor: ServerEditor::default(),
+            server_index: 0,
+            server_table_state: TableState::default(),
+            input: None,
+            server_message: None,
+            list_file: None,
+            watcher: None,
+            watch_status: WatchStatus::default(),
+            watching: None,
+            watch_cell: (0, 0),
         }
     }
 
@@ -84,20 +249,72 @@ impl App {
         self.dns_servers = servers;
     }
 
+    /// Save changes from the Servers tab to `path` instead of the saved
+    /// lists.
+    pub fn set_list_file(&mut self, path: PathBuf) {
+        self.list_file = Some(path);
+    }
+
+    /// Check the domains of `watcher` on its resolvers in the Pollution
+    /// tab.
+    pub fn set_watcher(&mut self, watcher: Watcher) {
+        self.watcher = Some(Arc::new(watcher));
+    }
+
+    pub fn set_check_updates(&mut self, enabled: bool) {
+        self.check_updates = enabled;
+    }
+
+    /// Test only servers of `family`, or of both families if `None`.
+    pub fn set_family(&mut self, family: Option<IpFamily>) {
+        self.family = family;
+    }
+
+    /// Test `concurrency` servers at the same time and give up on the
+    /// servers not tested after `run_timeout`.
+    pub fn set_limits(&mut self, concurrency: usize, run_timeout: Duration) {
+        self.concurrency = concurrency.max(1);
+        self.run_timeout = run_timeout;
+    }
+
+    /// Browse previously captured results instead of starting empty.
+    ///
+    /// `source` names where they came from, for the status line. Their
+    /// servers become the list a new test runs against.
+    pub fn set_results(&mut self, results: Vec<SpeedTestResult>, source: impl Into<String>) {
+        self.dns_servers = results.iter().map(|r| r.server.clone()).collect();
+        self.tested_count = results.len();
+        self.results = results;
+        self.loaded_from = Some(source.into());
+        self.sort_results();
+    }
+
     pub async fn run(&mut self) -> ColorResult<()> {
         // Create channel for async task communication
         let (tx, mut rx) = mpsc::unbounded_channel();
-        self.message_tx = Some(tx);
+        self.message_tx = Some(tx.clone());
+
+        if self.check_updates {
+            self.start_update_check(tx);
+        }
 
         // Initialize terminal with raw mode and alternate screen
         let mut terminal = ratatui::init();
 
-        // Load DNS server list
-        if let Ok(lists) = crate::config::ConfigLoader::load_all() {
-            let merged = crate::config::ConfigLoader::merge(lists);
-            self.dns_servers = merged.servers;
+        // Load DNS server list, unless one was given
+        if self.dns_servers.is_empty() {
+            if let Ok(lists) = crate::config::ConfigLoader::load_all() {
+                let merged = crate::config::ConfigLoader::merge(lists);
+                self.dns_servers = merged.servers;
+            }
         }
-        self.total_count = self.dns_servers.len();
+        if let Some(family) = self.family {
+            self.dns_servers.retain(|s| s.in_family(family));
+            self.results.retain(|r| r.server.in_family(family));
+            self.tested_count = self.results.len();
+        }
+        self.total_count = self.dns_servers.iter().filter(|s| !s.disabled).count();
+        self.server_editor = ServerEditor::new(&self.dns_servers);
 
         let res = self.run_loop(&mut terminal, &mut rx).await;
 
@@ -136,25 +353,83 @@ impl App {
 
     fn handle_message(&mut self, msg: AppMessage) {
         match msg {
-            AppMessage::Result(result) => {
-                self.results.push(result);
-                self.tested_count += 1;
+            AppMessage::Speed(ProgressEvent::ServerDone { done, result, .. }) => {
+                if let Some(monitor) = &mut self.monitor {
+                    monitor.record_result(&result);
+                }
+                self.results.push(*result);
+                self.tested_count = self.tested_count.max(done);
                 // Real-time sorting during test
                 self.sort_results();
             }
-            AppMessage::Progress { tested, .. } => {
-                self.tested_count = tested;
+            AppMessage::Speed(ProgressEvent::Started { .. } | ProgressEvent::BatchDelay { .. }) => {
             }
-            AppMessage::Completed => {
+            AppMessage::Speed(ProgressEvent::Finished { .. }) => {
                 self.testing = false;
                 // Final sort
                 self.sort_results();
+                self.heatmap.record(&self.results);
+                if let Some(monitor) = &mut self.monitor {
+                    monitor.finish_round();
+                    self.schedule_monitor_tick();
+                }
+            }
+            AppMessage::MonitorTick => {
+                if self.monitor.is_some() && !self.testing {
+                    self.start_speed_test();
+                }
+            }
+            AppMessage::UpdateStatus(status) => {
+                self.update_notice = Self::update_notice(&status);
+            }
+            AppMessage::RouteHop(hop) => {
+                self.route_hops.push(hop);
             }
+            AppMessage::RouteDone(error) => {
+                self.tracing = false;
+                self.route_error = error;
+            }
+            AppMessage::Watch(status) => {
+                self.watching = None;
+                self.watch_status = status;
+                self.move_watch_cell(0, 0);
+            }
+        }
+    }
+
+    /// Show the cached update status and refresh it in the background if stale.
+    fn start_update_check(&mut self, tx: mpsc::UnboundedSender<AppMessage>) {
+        let cached = UpdateStatus::load();
+        if let Some(status) = &cached {
+            self.update_notice = Self::update_notice(status);
+        }
+        if cached.as_ref().map_or(true, UpdateStatus::is_stale) {
+            tokio::spawn(async move {
+                if let Ok(status) = update_check::refresh().await {
+                    let _ = tx.send(AppMessage::UpdateStatus(status));
+                }
+            });
+        }
+    }
+
+    fn update_notice(status: &UpdateStatus) -> Option<String> {
+        let mut notices = Vec::new();
+        if let Some(tag) = status.newer_release() {
+            notices.push(format!("dnstest {tag} available (self-update)"));
+        }
+        if status.list_outdated() {
+            notices.push("New DNS list available (update)".to_string());
         }
+        (!notices.is_empty()).then(|| format!(" {} ", notices.join(" | ")))
     }
 
     fn handle_key(&mut self, key: crossterm::event::KeyEvent) -> bool {
-        use crossterm::event::KeyCode;
+        use crossterm::event::{KeyCode, KeyModifiers};
+
+        if self.input.is_some() && !key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.handle_input(key.code);
+            return true;
+        }
 
         match key.code {
             KeyCode::Char('c')
@@ -166,10 +441,11 @@ impl App {
             }
 
             KeyCode::Tab => {
-                self.tab_index = (self.tab_index + 1) % 3;
+                self.tab_index = (self.tab_index + 1) % 4;
                 self.current_view = match self.tab_index {
                     0 => View::SpeedTest,
                     1 => View::PollutionCheck,
+                    2 => View::Servers,
                     _ => View::Help,
                 };
                 return true;
@@ -187,6 +463,11 @@ impl App {
             }
             KeyCode::Char('3') => {
                 self.tab_index = 2;
+                self.current_view = View::Servers;
+                return true;
+            }
+            KeyCode::Char('4') => {
+                self.tab_index = 3;
                 self.current_view = View::Help;
                 return true;
             }
@@ -198,6 +479,80 @@ impl App {
                 return true;
             }
 
+            KeyCode::Char(' ') if self.current_view == View::PollutionCheck => {
+                if self.watching.is_none() {
+                    self.start_watch();
+                }
+                return true;
+            }
+            KeyCode::Up | KeyCode::Char('k') if self.current_view == View::PollutionCheck => {
+                self.move_watch_cell(-1, 0);
+                return true;
+            }
+            KeyCode::Down | KeyCode::Char('j') if self.current_view == View::PollutionCheck => {
+                self.move_watch_cell(1, 0);
+                return true;
+            }
+            KeyCode::Left | KeyCode::Char('h') if self.current_view == View::PollutionCheck => {
+                self.move_watch_cell(0, -1);
+                return true;
+            }
+            KeyCode::Right | KeyCode::Char('l') if self.current_view == View::PollutionCheck => {
+                self.move_watch_cell(0, 1);
+                return true;
+            }
+
+            KeyCode::Up | KeyCode::Char('k') if self.current_view == View::Servers => {
+                self.server_index = self.server_index.saturating_sub(1);
+                self.server_table_state.select(Some(self.server_index));
+                return true;
+            }
+            KeyCode::Down | KeyCode::Char('j') if self.current_view == View::Servers => {
+                let max = self.server_editor.len().saturating_sub(1);
+                self.server_index = (self.server_index + 1).min(max);
+                self.server_table_state.select(Some(self.server_index));
+                return true;
+            }
+
+            KeyCode::Char(' ') if self.current_view == View::Servers => {
+                self.server_editor.toggle(self.server_index);
+                self.servers_changed();
+                return true;
+            }
+            KeyCode::Enter | KeyCode::Char('e') if self.current_view == View::Servers => {
+                if let Some(server) = self.server_editor.get(self.server_index) {
+                    self.input = Some(Input {
+                        editing: Some(self.server_index),
+                        text: format!("{}#{}", server.spec(), server.name),
+                    });
+                }
+                return true;
+            }
+            KeyCode::Char('a') if self.current_view == View::Servers => {
+                self.input = Some(Input {
+                    editing: None,
+                    text: String::new(),
+                });
+                return true;
+            }
+            KeyCode::Char('d') | KeyCode::Delete if self.current_view == View::Servers => {
+                self.server_editor.remove(self.server_index);
+                self.servers_changed();
+                return true;
+            }
+            KeyCode::Char('p') if self.current_view == View::Servers => {
+                self.prune_servers();
+                return true;
+            }
+            KeyCode::Char('w') if self.current_view == View::Servers => {
+                self.server_message =
+                    Some(match self.server_editor.save(self.list_file.as_deref()) {
+                        Ok(()) => "Saved".to_string(),
+                        Err(e) => format!("Save failed: {e}"),
+                    });
+                return true;
+            }
+
             KeyCode::Up | KeyCode::Char('k') => {
                 if self.selected_index > 0 {
                     self.selected_index -= 1;
@@ -206,7 +561,7 @@ impl App {
                 return true;
             }
             KeyCode::Down | KeyCode::Char('j') => {
-                let max = self.results.len().saturating_sub(1);
+                let max = self.visible_results().len().saturating_sub(1);
                 if self.selected_index < max {
                     self.selected_index += 1;
                     self.table_state.select(Some(self.selected_index));
@@ -224,6 +579,47 @@ impl App {
                 return true;
             }
 
+            KeyCode::Char('f') if self.current_view == View::SpeedTest => {
+                self.filter = match self.filter {
+                    Filter::All => Filter::Answered,
+                    Filter::Answered => Filter::Failed,
+                    Filter::Failed => Filter::All,
+                };
+                self.selected_index = 0;
+                self.table_state.select(Some(0));
+                return true;
+            }
+
+            KeyCode::Char('m') if self.current_view == View::SpeedTest => {
+                self.monitor = match self.monitor {
+                    Some(_) => None,
+                    None => Some(Monitor::new()),
+                };
+                if self.monitor.is_some() && !self.testing {
+                    self.start_speed_test();
+                }
+                return true;
+            }
+
+            KeyCode::Char('t') if self.current_view == View::SpeedTest => {
+                if !self.tracing {
+                    self.start_route();
+                }
+                return true;
+            }
+
+            KeyCode::Char('h') if self.current_view == View::SpeedTest => {
+                self.current_view = View::Heatmap;
+                return true;
+            }
+
+            KeyCode::Esc | KeyCode::Char('q')
+                if matches!(self.current_view, View::Route | View::Heatmap) =>
+            {
+                self.current_view = View::SpeedTest;
+                return true;
+            }
+
             KeyCode::Char('q') if self.current_view != View::Help => {
                 self.testing = false;
                 return false;
@@ -241,13 +637,84 @@ impl App {
         true
     }
 
+    /// Apply a key to the server being added or edited.
+    fn handle_input(&mut self, code: crossterm::event::KeyCode) {
+        use crossterm::event::KeyCode;
+
+        let Some(input) = &mut self.input else {
+            return;
+        };
+        match code {
+            KeyCode::Char(c) => input.text.push(c),
+            KeyCode::Backspace => {
+                input.text.pop();
+            }
+            KeyCode::Esc => self.input = None,
+            KeyCode::Enter => {
+                let result = match input.editing {
+                    Some(index) => self.server_editor.edit(index, input.text.trim()),
+                    None => self.server_editor.add(input.text.trim()),
+                };
+                match result {
+                    Ok(()) => {
+                        if input.editing.is_none() {
+                            self.server_index = self.server_editor.len().saturating_sub(1);
+                            self.server_table_state.select(Some(self.server_index));
+                        }
+                        self.input = None;
+                        self.server_message = None;
+                        self.servers_changed();
+                    }
+                    // Keep the text to fix it
+                    Err(e) => self.server_message = Some(e.to_string()),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Remove the servers that failed the latest runs in a row.
+    fn prune_servers(&mut self) {
+        self.server_message = Some(match crate::history::History::open_default().load() {
+            Ok(runs) => {
+                let failing = crate::history::failure_streaks(&runs)
+                    .into_iter()
+                    .filter(|(_, streak)| *streak >= PRUNE_AFTER)
+                    .map(|(spec, _)| spec)
+                    .collect();
+                let removed = self.server_editor.remove_specs(&failing);
+                self.servers_changed();
+                format!("Removed {removed} servers that failed {PRUNE_AFTER} runs in a row")
+            }
+            Err(e) => format!("Failed to load history: {e}"),
+        });
+    }
+
+    /// Test the servers as edited in the Servers tab from now on.
+    fn servers_changed(&mut self) {
+        self.dns_servers = self.server_editor.servers().cloned().collect();
+        self.server_index = self
+            .server_index
+            .min(self.server_editor.len().saturating_sub(1));
+        self.server_table_state.select(Some(self.server_index));
+        if !self.testing {
+            self.total_count = self.dns_servers.iter().filter(|s| !s.disabled).count();
+        }
+    }
+
     fn start_speed_test(&mut self) {
         self.testing = true;
+        self.loaded_from = None;
         self.results.clear();
         self.tested_count = 0;
         self.selected_index = 0;
 
-        let servers: Vec<DnsServer> = self.dns_servers.clone();
+        let servers: Vec<DnsServer> = self
+            .dns_servers
+            .iter()
+            .filter(|s| !s.disabled)
+            .cloned()
+            .collect();
         self.total_count = servers.len();
 
         let Some(tx) = self.message_tx.clone() else {
@@ -256,16 +723,16 @@ impl App {
         };
 
         let total = servers.len();
+        let (concurrency, run_timeout) = (self.concurrency, self.run_timeout);
 
         // Spawn async speed test task
         tokio::spawn(async move {
             use tokio::sync::Semaphore;
 
-            const MAX_CONCURRENT: usize = 20;
-            const TOTAL_TIMEOUT_SECS: u64 = 120;
-
-            let semaphore = std::sync::Arc::new(Semaphore::new(MAX_CONCURRENT));
+            let semaphore = std::sync::Arc::new(Semaphore::new(concurrency));
             let tested = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let results_ok = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let _ = tx.send(AppMessage::Speed(ProgressEvent::Started { total }));
 
             let mut handles = Vec::new();
 
@@ -277,6 +744,7 @@ impl App {
 
                 let tx = tx.clone();
                 let tested = tested.clone();
+                let results_ok = results_ok.clone();
 
                 let handle = tokio::spawn(async move {
                     let tester = match crate::dns::SpeedTester::new() {
@@ -288,14 +756,15 @@ impl App {
                     };
 
                     let result = tester.test_latency(&server).await;
-                    let count = tested.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
-
-                    // Send result and progress
-                    let _ = tx.send(AppMessage::Result(result));
-                    let _ = tx.send(AppMessage::Progress {
-                        tested: count,
+                    let done = tested.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+                    if result.success {
+                        results_ok.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                    let _ = tx.send(AppMessage::Speed(ProgressEvent::ServerDone {
+                        done,
                         total,
-                    });
+                        result: Box::new(result),
+                    }));
 
                     drop(permit);
                 });
@@ -304,21 +773,101 @@ impl App {
             }
 
             // Wait for all tasks with timeout
-            let timeout_result = tokio::time::timeout(
-                Duration::from_secs(TOTAL_TIMEOUT_SECS),
-                futures::future::join_all(handles),
-            )
-            .await;
+            let timeout_result =
+                tokio::time::timeout(run_timeout, futures::future::join_all(handles)).await;
 
             if timeout_result.is_err() {
                 tracing::warn!("Speed test timed out");
             }
 
             // Signal completion
-            let _ = tx.send(AppMessage::Completed);
+            let succeeded = results_ok.load(std::sync::atomic::Ordering::Relaxed);
+            let _ = tx.send(AppMessage::Speed(ProgressEvent::Finished {
+                total,
+                succeeded,
+            }));
+        });
+    }
+
+    /// Check the watched domains on every watched resolver.
+    fn start_watch(&mut self) {
+        let (Some(watcher), Some(tx)) = (self.watcher.clone(), self.message_tx.clone()) else {
+            return;
+        };
+        self.watching = Some(tokio::spawn(async move {
+            let _ = tx.send(AppMessage::Watch(watcher.check().await));
+        }));
+    }
+
+    /// Move the selected cell of the pollution matrix by rows and columns,
+    /// staying inside the matrix.
+    fn move_watch_cell(&mut self, rows: isize, columns: isize) {
+        let bound = |index: usize, delta: isize, len: usize| {
+            index
+                .saturating_add_signed(delta)
+                .min(len.saturating_sub(1))
+        };
+        let (row, column) = self.watch_cell;
+        self.watch_cell = (
+            bound(row, rows, self.watch_status.domains().len()),
+            bound(column, columns, self.watch_status.resolvers().len()),
+        );
+    }
+
+    /// Start the next monitor round once the interval has passed.
+    fn schedule_monitor_tick(&self) {
+        let Some(tx) = self.message_tx.clone() else {
+            return;
+        };
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(monitor::DEFAULT_INTERVAL_SECS)).await;
+            let _ = tx.send(AppMessage::MonitorTick);
         });
     }
 
+    /// Trace the route to the selected server and switch to the route view.
+    fn start_route(&mut self) {
+        let Some(result) = self.visible_results().get(self.selected_index).copied() else {
+            return;
+        };
+        let Some(tx) = self.message_tx.clone() else {
+            return;
+        };
+        let server = result.server.clone();
+
+        self.current_view = View::Route;
+        self.route_hops.clear();
+        self.route_error = None;
+        self.route_server = Some(server.clone());
+
+        let Some(ip) = server.ip_addr() else {
+            self.route_error = Some("Invalid IP address".to_string());
+            return;
+        };
+        self.tracing = true;
+
+        tokio::spawn(async move {
+            let result = Tracer::new()
+                .trace(ip, |hop| {
+                    let _ = tx.send(AppMessage::RouteHop(hop.clone()));
+                })
+                .await;
+            let _ = tx.send(AppMessage::RouteDone(result.err().map(|e| e.to_string())));
+        });
+    }
+
+    /// Results that pass the filter, in table order.
+    fn visible_results(&self) -> Vec<&SpeedTestResult> {
+        self.results
+            .iter()
+            .filter(|r| match self.filter {
+                Filter::All => true,
+                Filter::Answered => r.success,
+                Filter::Failed => !r.success,
+            })
+            .collect()
+    }
+
     fn sort_results(&mut self) {
         match self.sort_mode {
             SortMode::Latency => {
@@ -375,26 +924,52 @@ impl App {
     }
 
     fn draw(&mut self, f: &mut Frame) {
+        self.density = Density::for_area(f.area());
+        let (title, tabs, stats) = match self.density {
+            Density::Full => (3, 2, 6),
+            Density::Compact => (1, 1, 1),
+            Density::Minimal => (0, 0, 1),
+        };
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
-                Constraint::Length(3),
-                Constraint::Length(2),
-                Constraint::Min(10),
-                Constraint::Length(6),
+                Constraint::Length(title),
+                Constraint::Length(tabs),
+                Constraint::Min(1),
+                Constraint::Length(stats),
             ])
             .split(f.area());
 
-        self.draw_title_bar(f, chunks[0]);
-        self.draw_tabs(f, chunks[1]);
+        if self.density != Density::Minimal {
+            self.draw_title_bar(f, chunks[0]);
+            self.draw_tabs(f, chunks[1]);
+        }
 
         match self.current_view {
             View::SpeedTest => self.draw_speed_test(f, chunks[2]),
             View::PollutionCheck => self.draw_pollution_check(f, chunks[2]),
+            View::Servers => self.draw_servers(f, chunks[2]),
             View::Help => self.draw_help(f, chunks[2]),
+            View::Route => self.draw_route(f, chunks[2]),
+            View::Heatmap => self.draw_heatmap(f, chunks[2]),
+        }
+
+        if self.density == Density::Full {
+            self.draw_stats_bar(f, chunks[3]);
+        } else {
+            self.draw_status_line(f, chunks[3]);
         }
+    }
 
-        self.draw_stats_bar(f, chunks[3]);
+    /// Split a view into its status header and the area below.
+    fn split_header(&self, area: Rect) -> std::rc::Rc<[Rect]> {
+        Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(self.density.header_height()),
+                Constraint::Min(1),
+            ])
+            .split(area)
     }
 
     fn draw_title_bar(&self, f: &mut Frame, area: Rect) {
@@ -426,7 +1001,7 @@ impl App {
     }
 
     fn draw_tabs(&self, f: &mut Frame, area: Rect) {
-        let titles = ["Speed Test", "Pollution", "Help"];
+        let titles = ["Speed Test", "Pollution", "Servers", "Help"];
         let mut tab_text = String::new();
         for (i, title) in titles.iter().enumerate() {
             if i == self.tab_index {
@@ -442,24 +1017,47 @@ impl App {
     }
 
     fn draw_speed_test(&mut self, f: &mut Frame, area: Rect) {
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Length(3), Constraint::Min(10)])
-            .split(area);
+        let chunks = self.split_header(area);
 
         let sort_indicator = match self.sort_mode {
             SortMode::Latency => "Latency",
             SortMode::Name => "Name",
             SortMode::Status => "Status",
         };
-        let status_text = if self.testing {
+        let filter_indicator = match self.filter {
+            Filter::All => "All",
+            Filter::Answered => "Answered",
+            Filter::Failed => "Failed",
+        };
+        let limits = format!(
+            "Concurrency: {} | Run limit: {}s",
+            self.concurrency,
+            self.run_timeout.as_secs()
+        );
+        let mut status_text = if self.testing {
             format!(
-                "Testing... ({}/{}) | Sort by: {} [s]",
-                self.tested_count, self.total_count, sort_indicator
+                "Testing... ({}/{}) | {} | Sort by: {} [s] | Show: {} [f]",
+                self.tested_count, self.total_count, limits, sort_indicator, filter_indicator
             )
         } else {
-            format!("Sort by: {} [s]", sort_indicator)
+            format!(
+                "{} | Sort by: {} [s] | Show: {} [f]",
+                limits, sort_indicator, filter_indicator
+            )
         };
+        if let Some(source) = &self.loaded_from {
+            status_text.push_str(&format!(" | From: {source}"));
+        }
+        if let Some(monitor) = &self.monitor {
+            status_text.push_str(&format!(
+                " | Monitoring, round {} [m]",
+                monitor.rounds() + u64::from(self.testing)
+            ));
+        }
+        let visible = self.visible_results();
+        if let Some(source_ip) = visible.get(self.selected_index).and_then(|r| r.source_ip) {
+            status_text.push_str(&format!(" | Source: {}", source_ip));
+        }
         let header = Paragraph::new(status_text).style(Style::default().fg(Color::DarkGray));
         f.render_widget(header, chunks[0]);
 
@@ -476,8 +1074,7 @@ impl App {
             return;
         }
 
-        let rows: Vec<Row> = self
-            .results
+        let rows: Vec<Row> = visible
             .iter()
             .enumerate()
             .map(|(idx, r)| {
@@ -504,12 +1101,173 @@ impl App {
                     Style::default()
                 };
 
+                let cells = if let Some(monitor) = &self.monitor {
+                    Self::monitor_cells(monitor, idx, r, selected, latency_style)
+                } else {
+                    vec![
+                        Cell::from(format!("{}", idx + 1)).style(selected),
+                        Cell::from(r.server.name.clone()).style(selected),
+                        Cell::from(r.server.ip.clone()).style(selected),
+                        Cell::from(latency_bar).style(latency_style),
+                        Cell::from(latency_text).style(latency_style),
+                        Cell::from(r.hops.map_or_else(String::new, |h| format!("{h} hops")))
+                            .style(selected),
+                    ]
+                };
+                Row::new(self.visible_columns(cells))
+            })
+            .collect();
+
+        // Names shrink before anything else on narrow terminals
+        let name = if self.density == Density::Full {
+            Constraint::Length(25)
+        } else {
+            Constraint::Min(8)
+        };
+        let table = if self.monitor.is_some() {
+            Table::new(
+                rows,
+                self.visible_columns(vec![
+                    Constraint::Length(4),
+                    name,
+                    Constraint::Length(18),
+                    Constraint::Length(6),
+                    Constraint::Length(5),
+                    Constraint::Length(8),
+                    Constraint::Length(8),
+                    Constraint::Length(8),
+                    Constraint::Length(8),
+                    Constraint::Length(8),
+                ]),
+            )
+            .header(
+                Row::new(self.visible_columns(vec![
+                    "#", "Name", "IP", "Loss%", "Snt", "Last", "Avg", "Best", "Wrst", "StDev",
+                ]))
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+        } else {
+            Table::new(
+                rows,
+                self.visible_columns(vec![
+                    Constraint::Length(4),
+                    name,
+                    Constraint::Length(18),
+                    Constraint::Length(22),
+                    Constraint::Length(12),
+                    Constraint::Length(8),
+                ]),
+            )
+        }
+        .block(Block::default().border_type(BorderType::Rounded))
+        .row_highlight_style(Style::default().bg(Color::Blue));
+
+        // Use stateful rendering for scroll support
+        f.render_stateful_widget(table, chunks[1], &mut self.table_state);
+    }
+
+    /// Keep the speed test columns that fit the terminal.
+    ///
+    /// Compact layouts drop the latency bar and hop count, or the send
+    /// count and worst latency in monitor mode; minimal layouts also drop
+    /// the IP and the standard deviation.
+    fn visible_columns<T>(&self, columns: Vec<T>) -> Vec<T> {
+        let dropped: &[usize] = match (self.density, self.monitor.is_some()) {
+            (Density::Full, _) => &[],
+            (Density::Compact, false) => &[3, 5],
+            (Density::Minimal, false) => &[2, 3, 5],
+            (Density::Compact, true) => &[4, 8],
+            (Density::Minimal, true) => &[2, 4, 8, 9],
+        };
+        columns
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| !dropped.contains(idx))
+            .map(|(_, column)| column)
+            .collect()
+    }
+
+    /// Cells of a result row with the server's rolling statistics,
+    /// mtr-style.
+    fn monitor_cells(
+        monitor: &Monitor,
+        idx: usize,
+        r: &SpeedTestResult,
+        selected: Style,
+        latency_style: Style,
+    ) -> Vec<Cell<'static>> {
+        let stats = monitor.get(&r.server.spec()).copied().unwrap_or_default();
+        let ms =
+            |value: Option<f64>| value.map_or_else(|| "-".to_string(), |ms| format!("{ms:.1}"));
+        let loss_style = if stats.loss() > 0.0 {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+
+        vec![
+            Cell::from(format!("{}", idx + 1)).style(selected),
+            Cell::from(r.server.name.clone()).style(selected),
+            Cell::from(r.server.ip.clone()).style(selected),
+            Cell::from(format!("{:.1}", stats.loss() * 100.0)).style(loss_style),
+            Cell::from(stats.sent.to_string()),
+            Cell::from(ms(r.latency_ms)).style(latency_style),
+            Cell::from(ms(stats.avg_ms())),
+            Cell::from(ms(stats.best_ms)),
+            Cell::from(ms(stats.worst_ms)),
+            Cell::from(ms(stats.stddev_ms())),
+        ]
+    }
+
+    fn draw_route(&self, f: &mut Frame, area: Rect) {
+        let chunks = self.split_header(area);
+
+        let target = self
+            .route_server
+            .as_ref()
+            .map_or_else(String::new, |s| format!("{} ({})", s.name, s.ip));
+        let status_text = if self.tracing {
+            format!("Tracing route to {target}... | [Esc] back")
+        } else if let Some(error) = &self.route_error {
+            format!("Route to {target} failed: {error} | [Esc] back")
+        } else {
+            format!("Route to {target} | [Esc] back")
+        };
+        let header = Paragraph::new(status_text).style(Style::default().fg(Color::DarkGray));
+        f.render_widget(header, chunks[0]);
+
+        let rows: Vec<Row> = self
+            .route_hops
+            .iter()
+            .map(|hop| {
+                let style = if hop.reached {
+                    Style::default().fg(Color::Green)
+                } else if hop.address.is_none() {
+                    Style::default().fg(Color::Yellow)
+                } else {
+                    Style::default()
+                };
+                let rtts = hop
+                    .rtts_ms
+                    .iter()
+                    .map(|rtt| rtt.map_or_else(|| "*".to_string(), |ms| format!("{ms:.1}ms")))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+
                 Row::new(vec![
-                    Cell::from(format!("{}", idx + 1)).style(selected),
-                    Cell::from(r.server.name.clone()).style(selected),
-                    Cell::from(r.server.ip.clone()).style(selected),
-                    Cell::from(latency_bar).style(latency_style),
-                    Cell::from(latency_text).style(latency_style),
+                    Cell::from(hop.ttl.to_string()),
+                    Cell::from(
+                        hop.address
+                            .map_or_else(|| "*".to_string(), |ip| ip.to_string()),
+                    )
+                    .style(style),
+                    Cell::from(
+                        hop.avg_ms()
+                            .map_or_else(String::new, |ms| format!("{ms:.1}ms")),
+                    )
+                    .style(style),
+                    Cell::from(format!("{:.0}%", hop.loss() * 100.0)),
+                    Cell::from(rtts).style(Style::default().fg(Color::DarkGray)),
                 ])
             })
             .collect();
@@ -518,24 +1276,320 @@ impl App {
             rows,
             [
                 Constraint::Length(4),
-                Constraint::Length(25),
                 Constraint::Length(18),
-                Constraint::Length(22),
-                Constraint::Length(12),
+                Constraint::Length(10),
+                Constraint::Length(6),
+                Constraint::Min(20),
             ],
         )
-        .block(Block::default().border_type(BorderType::Rounded))
-        .row_highlight_style(Style::default().bg(Color::Blue));
+        .header(
+            Row::new(vec!["Hop", "Address", "Avg", "Loss", "Probes"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().border_type(BorderType::Rounded));
+        f.render_widget(table, chunks[1]);
+    }
 
-        // Use stateful rendering for scroll support
-        f.render_stateful_widget(table, chunks[1], &mut self.table_state);
+    fn draw_heatmap(&self, f: &mut Frame, area: Rect) {
+        const COLORS: [Color; 5] = [
+            Color::Green,
+            Color::LightGreen,
+            Color::Yellow,
+            Color::LightRed,
+            Color::Red,
+        ];
+
+        let chunks = self.split_header(area);
+
+        let mut legend = vec![Span::styled(
+            format!("{} runs | ", self.heatmap.rounds()),
+            Style::default().fg(Color::DarkGray),
+        )];
+        let mut lower = 0.0;
+        for (bucket, color) in COLORS.iter().enumerate() {
+            let label = monitor::HEATMAP_BUCKETS_MS.get(bucket).map_or_else(
+                || format!(">={lower:.0}ms"),
+                |upper| format!("<{upper:.0}ms"),
+            );
+            legend.push(Span::styled("█ ", Style::default().fg(*color)));
+            legend.push(Span::raw(format!("{label}  ")));
+            if let Some(upper) = monitor::HEATMAP_BUCKETS_MS.get(bucket) {
+                lower = *upper;
+            }
+        }
+        legend.push(Span::styled("· ", Style::default().fg(Color::DarkGray)));
+        legend.push(Span::styled(
+            "no answer | [Esc] back",
+            Style::default().fg(Color::DarkGray),
+        ));
+        f.render_widget(Paragraph::new(Line::from(legend)), chunks[0]);
+
+        if self.heatmap.rounds() == 0 {
+            let msg = Paragraph::new("Run a speed test (or monitor mode) to fill the heatmap")
+                .style(Style::default().fg(Color::DarkGray))
+                .alignment(ratatui::layout::Alignment::Center);
+            f.render_widget(msg, chunks[1]);
+            return;
+        }
+
+        let name_width = self
+            .heatmap
+            .rows()
+            .map(|(name, _)| text::width(name))
+            .max()
+            .unwrap_or(0)
+            .min(20);
+        // Latest runs that fit, one cell each
+        let columns = (chunks[1].width as usize).saturating_sub(name_width + 1);
+        let lines: Vec<Line> =
+            self.heatmap
+                .rows()
+                .map(|(name, cells)| {
+                    let name = text::wide(name);
+                    let mut spans = vec![Span::raw(format!("{name:<name_width$.name_width$} "))];
+                    spans.extend(cells.iter().skip(cells.len().saturating_sub(columns)).map(
+                        |cell| match cell {
+                            Some(ms) => Span::styled(
+                                "█",
+                                Style::default().fg(COLORS[monitor::heatmap_bucket(*ms)]),
+                            ),
+                            None => Span::styled("·", Style::default().fg(Color::DarkGray)),
+                        },
+                    ));
+                    Line::from(spans)
+                })
+                .collect();
+        f.render_widget(Paragraph::new(lines), chunks[1]);
     }
 
+    fn draw_servers(&mut self, f: &mut Frame, area: Rect) {
+        let chunks = self.split_header(area);
+
+        let header = if let Some(input) = &self.input {
+            let action = if input.editing.is_some() {
+                "Edit"
+            } else {
+                "Add"
+            };
+            let mut spans = vec![
+                Span::styled(
+                    format!("{action} server (IP#Name): "),
+                    Style::default().fg(Color::Cyan),
+                ),
+                Span::raw(format!("{}_", input.text)),
+                Span::styled(
+                    "  [Enter] done [Esc] cancel",
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ];
+            if let Some(message) = &self.server_message {
+                spans.push(Span::styled(
+                    format!(" | {message}"),
+                    Style::default().fg(Color::Red),
+                ));
+            }
+            Line::from(spans)
+        } else {
+            let mut status_text = "[Space] enable/disable [e] edit [a] add [d] delete \
+                                   [p] prune failing [w] save"
+                .to_string();
+            if self.server_editor.is_dirty() {
+                status_text.push_str(" | Unsaved changes");
+            }
+            if let Some(message) = &self.server_message {
+                status_text.push_str(&format!(" | {message}"));
+            }
+            Line::styled(status_text, Style::default().fg(Color::DarkGray))
+        };
+        f.render_widget(Paragraph::new(header), chunks[0]);
+
+        if self.server_editor.len() == 0 {
+            let msg = Paragraph::new("No servers loaded, press [a] to add one")
+                .style(Style::default().fg(Color::DarkGray))
+                .alignment(ratatui::layout::Alignment::Center);
+            f.render_widget(msg, chunks[1]);
+            return;
+        }
+
+        let rows: Vec<Row> = self
+            .server_editor
+            .servers()
+            .enumerate()
+            .map(|(idx, server)| {
+                let style = if idx == self.server_index {
+                    Style::default().bg(Color::Blue)
+                } else if server.disabled {
+                    Style::default().fg(Color::DarkGray)
+                } else {
+                    Style::default()
+                };
+                let state = if server.disabled {
+                    Cell::from("Disabled").style(Style::default().fg(Color::Yellow))
+                } else {
+                    Cell::from("Enabled").style(Style::default().fg(Color::Green))
+                };
+                Row::new(vec![
+                    Cell::from(format!("{}", idx + 1)).style(style),
+                    Cell::from(server.name.clone()).style(style),
+                    Cell::from(server.spec()).style(style),
+                    Cell::from(server.provider.clone().unwrap_or_default()).style(style),
+                    Cell::from(server.country.clone().unwrap_or_default()).style(style),
+                    state,
+                ])
+            })
+            .collect();
+
+        let name = if self.density == Density::Full {
+            Constraint::Length(25)
+        } else {
+            Constraint::Min(8)
+        };
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(4),
+                name,
+                Constraint::Length(28),
+                Constraint::Length(14),
+                Constraint::Length(7),
+                Constraint::Length(8),
+            ],
+        )
+        .header(
+            Row::new(vec!["#", "Name", "Address", "Provider", "Country", "State"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().border_type(BorderType::Rounded));
+        f.render_stateful_widget(table, chunks[1], &mut self.server_table_state);
+    }
+
+    /// Label and color of a cell of the pollution matrix.
+    fn verdict_label(entry: Option<&WatchEntry>) -> (&'static str, Color) {
+        match entry.map(|e| e.verdict) {
+            Some(Some(CheckVerdict::Clean)) => ("Clean", Color::Green),
+            Some(Some(CheckVerdict::Suspicious)) => ("Suspicious", Color::Yellow),
+            Some(Some(CheckVerdict::Polluted)) => ("Polluted", Color::Red),
+            Some(None) => ("Failed", Color::DarkGray),
+            None => ("-", Color::DarkGray),
+        }
+    }
+
+    /// Watched domains by watched resolvers, with the selected cell's
+    /// addresses below.
     fn draw_pollution_check(&self, f: &mut Frame, area: Rect) {
-        let msg = Paragraph::new("Pollution check feature coming soon...")
-            .style(Style::default().fg(Color::DarkGray))
-            .alignment(ratatui::layout::Alignment::Center);
-        f.render_widget(msg, area);
+        let chunks = self.split_header(area);
+
+        let Some(watcher) = &self.watcher else {
+            let msg = Paragraph::new("No domains to watch, add them to [watch] in the config")
+                .style(Style::default().fg(Color::DarkGray))
+                .alignment(ratatui::layout::Alignment::Center);
+            f.render_widget(msg, area);
+            return;
+        };
+
+        let mut status_text = if self.watching.is_some() {
+            format!(
+                "Checking {} domains on {} resolvers...",
+                watcher.domains(),
+                watcher.resolvers()
+            )
+        } else {
+            "[Space] check [arrows] select".to_string()
+        };
+        if let Some(at) = self.watch_status.checked_at {
+            status_text.push_str(&format!(
+                " | Polluted: {} | Checked at {}",
+                self.watch_status.polluted().count(),
+                at.with_timezone(&chrono::Local).format("%H:%M:%S")
+            ));
+        }
+        let header = Paragraph::new(status_text).style(Style::default().fg(Color::DarkGray));
+        f.render_widget(header, chunks[0]);
+
+        let domains = self.watch_status.domains();
+        let resolvers = self.watch_status.resolvers();
+        if domains.is_empty() {
+            let msg = if self.watching.is_some() {
+                "Checking..."
+            } else {
+                "Press [Space] to check the watched domains"
+            };
+            let msg = Paragraph::new(msg)
+                .style(Style::default().fg(Color::DarkGray))
+                .alignment(ratatui::layout::Alignment::Center);
+            f.render_widget(msg, chunks[1]);
+            return;
+        }
+
+        let body = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(2)])
+            .split(chunks[1]);
+
+        let (selected_row, selected_column) = self.watch_cell;
+        let rows: Vec<Row> = domains
+            .iter()
+            .enumerate()
+            .map(|(row, domain)| {
+                let mut cells = vec![Cell::from(*domain)];
+                cells.extend(resolvers.iter().enumerate().map(|(column, resolver)| {
+                    let (label, color) =
+                        Self::verdict_label(self.watch_status.get(domain, resolver));
+                    let mut style = Style::default().fg(color);
+                    if (row, column) == (selected_row, selected_column) {
+                        style = style.add_modifier(Modifier::REVERSED);
+                    }
+                    Cell::from(label).style(style)
+                }));
+                Row::new(cells)
+            })
+            .collect();
+
+        let domain_width = domains.iter().map(|d| text::width(d)).max().unwrap_or(0);
+        let mut widths = vec![Constraint::Length(domain_width.min(30) as u16)];
+        widths.extend(
+            resolvers
+                .iter()
+                .map(|r| Constraint::Length(text::width(r).clamp(10, 20) as u16)),
+        );
+        let mut header = vec![Cell::from("Domain")];
+        header.extend(resolvers.iter().map(|r| Cell::from(*r)));
+        let table = Table::new(rows, widths)
+            .header(Row::new(header).style(Style::default().add_modifier(Modifier::BOLD)))
+            .block(Block::default().border_type(BorderType::Rounded));
+        f.render_widget(table, body[0]);
+
+        let detail = domains
+            .get(selected_row)
+            .zip(resolvers.get(selected_column))
+            .and_then(|(domain, resolver)| self.watch_status.get(domain, resolver))
+            .map_or_else(String::new, |entry| {
+                let ips = |ips: &[std::net::IpAddr]| {
+                    ips.iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                let (label, _) = Self::verdict_label(Some(entry));
+                match &entry.error {
+                    Some(error) => {
+                        format!("{} on {}: {label} | {error}", entry.domain, entry.resolver)
+                    }
+                    None => format!(
+                        "{} on {}: {label} | Resolver: {} | Reference: {}",
+                        entry.domain,
+                        entry.resolver,
+                        ips(&entry.system_ips),
+                        ips(&entry.public_ips)
+                    ),
+                }
+            });
+        f.render_widget(
+            Paragraph::new(detail)
+                .style(Style::default().fg(Color::White))
+                .wrap(ratatui::widgets::Wrap { trim: true }),
+            body[1],
+        );
     }
 
     fn draw_help(&self, f: &mut Frame, area: Rect) {
@@ -567,8 +1621,24 @@ impl App {
         let help_items = [
             ("Space", "Start speed test"),
             ("s", "Cycle sort mode (Latency/Name/Status)"),
+            ("f", "Cycle filter (All/Answered/Failed)"),
+            ("m", "Toggle monitor mode (repeat runs, rolling stats)"),
+            ("t", "Trace route to selected server"),
+            ("h", "Provider latency heatmap"),
+            (
+                "Space (Pollution)",
+                "Check the watched domains on every resolver",
+            ),
+            (
+                "arrows (Pollution)",
+                "Select a domain and resolver for details",
+            ),
             ("j/k or Up/Down", "Navigate results"),
-            ("1/2/3", "Switch tabs (Speed/Pollution/Help)"),
+            ("Space (Servers)", "Enable/disable the selected server"),
+            ("e / a / d", "Edit, add or delete a server (Servers)"),
+            ("p (Servers)", "Remove servers that failed 3 runs in a row"),
+            ("w (Servers)", "Save server changes to the DNS list"),
+            ("1/2/3/4", "Switch tabs (Speed/Pollution/Servers/Help)"),
             ("Tab", "Cycle through tabs"),
             ("q", "Quit application"),
         ];
@@ -601,12 +1671,8 @@ impl App {
         f.render_widget(footer, chunks[2]);
     }
 
-    fn draw_stats_bar(&self, f: &mut Frame, area: Rect) {
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Length(3), Constraint::Length(3)])
-            .split(area);
-
+    /// Statistics of the results, e.g. "Total: 20  |  Success: 18  |  ...".
+    fn stats_text(&self) -> String {
         let (total, success, failed, timeout, avg, min, max) = self.get_stats();
 
         let mut stats_parts = vec![format!("Total: {}", total), format!("Success: {}", success)];
@@ -626,16 +1692,49 @@ impl App {
         if let Some(max_lat) = max {
             stats_parts.push(format!("Max: {:.1}ms", max_lat));
         }
+        stats_parts.join("  |  ")
+    }
+
+    /// One line with the statistics and progress, for small terminals.
+    fn draw_status_line(&self, f: &mut Frame, area: Rect) {
+        let mut spans = Vec::new();
+        if self.testing {
+            spans.push(Span::styled(
+                format!("[{}/{}] ", self.tested_count, self.total_count),
+                Style::default().fg(Color::Cyan),
+            ));
+        }
+        spans.push(Span::raw(self.stats_text().replace("  |  ", " | ")));
+        if let Some(notice) = &self.update_notice {
+            spans.push(Span::styled(
+                format!(" |{notice}"),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        f.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    fn draw_stats_bar(&self, f: &mut Frame, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Length(3)])
+            .split(area);
 
-        let stats_text = stats_parts.join("  |  ");
+        let stats_text = self.stats_text();
 
+        let mut block = Block::default()
+            .title(" Statistics ")
+            .border_type(BorderType::Rounded);
+        if let Some(notice) = &self.update_notice {
+            block = block.title(
+                Line::from(notice.as_str())
+                    .style(Style::default().fg(Color::Yellow))
+                    .right_aligned(),
+            );
+        }
         let stats = Paragraph::new(stats_text)
             .style(Style::default().fg(Color::White))
-            .block(
-                Block::default()
-                    .title(" Statistics ")
-                    .border_type(BorderType::Rounded),
-            );
+            .block(block);
         f.render_widget(stats, chunks[0]);
 
         let progress = if self.total_count > 0 {
@@ -664,3 +1763,56 @@ impl Default for App {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_density() {
+        let density = |width, height| Density::for_area(Rect::new(0, 0, width, height));
+        assert_eq!(density(120, 40), Density::Full);
+        assert_eq!(density(79, 40), Density::Compact);
+        assert_eq!(density(120, 20), Density::Compact);
+        assert_eq!(density(50, 40), Density::Minimal);
+
+        let mut app = App::new();
+        app.density = Density::Minimal;
+        let columns = app.visible_columns(vec!["#", "Name", "IP", "Bar", "Latency", "Hops"]);
+        assert_eq!(columns, ["#", "Name", "Latency"]);
+    }
+
+    #[test]
+    fn test_watch_cell() {
+        let entry = |domain: &str, resolver: &str| WatchEntry {
+            domain: domain.to_string(),
+            resolver: resolver.to_string(),
+            verdict: Some(CheckVerdict::Clean),
+            system_ips: Vec::new(),
+            public_ips: Vec::new(),
+            error: None,
+        };
+        let mut app = App::new();
+        app.watch_status = WatchStatus {
+            checked_at: None,
+            entries: vec![
+                entry("google.com", "System"),
+                entry("google.com", "AliDNS"),
+                entry("example.com", "System"),
+                entry("example.com", "AliDNS"),
+            ],
+        };
+
+        app.move_watch_cell(-1, -1);
+        assert_eq!(app.watch_cell, (0, 0));
+        app.move_watch_cell(1, 5);
+        assert_eq!(app.watch_cell, (1, 1));
+        app.move_watch_cell(0, -1);
+        assert_eq!(app.watch_cell, (1, 0));
+
+        // A smaller round keeps the selection inside the matrix
+        app.watch_status.entries.truncate(2);
+        app.move_watch_cell(0, 0);
+        assert_eq!(app.watch_cell, (0, 0));
+    }
+}
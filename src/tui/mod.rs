@@ -4,5 +4,6 @@
 //! for DNS testing operations using the `ratatui` library.
 
 mod app;
+mod servers;
 
-pub use app::App;
+pub use app::{App, DEFAULT_CONCURRENCY, DEFAULT_RUN_TIMEOUT};
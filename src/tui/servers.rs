@@ -0,0 +1,259 @@
+//! Server list editing for the Servers tab.
+//!
+//! [`ServerEditor`] keeps the loaded servers together with what became of
+//! them, so that saving only touches the entries that were added, edited
+//! or removed and leaves the rest of the list files alone.
+
+use crate::config::{ConfigLoader, LIST_FILE, LIST_V6_FILE};
+use crate::dns::DnsServer;
+use crate::error::{Error, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// A server in the editor.
+#[derive(Debug, Clone)]
+struct Entry {
+    /// The server as it is now
+    server: DnsServer,
+    /// The server as loaded, `None` if added since
+    loaded: Option<DnsServer>,
+}
+
+/// Edits to the server list, saved on request.
+#[derive(Debug, Clone, Default)]
+pub struct ServerEditor {
+    entries: Vec<Entry>,
+    /// Specs of loaded servers that were removed (see [`DnsServer::spec`])
+    removed: HashSet<String>,
+}
+
+impl ServerEditor {
+    /// Edit `servers`, as loaded from the lists.
+    pub fn new(servers: &[DnsServer]) -> Self {
+        Self {
+            entries: servers
+                .iter()
+                .map(|server| Entry {
+                    server: server.clone(),
+                    loaded: Some(server.clone()),
+                })
+                .collect(),
+            removed: HashSet::new(),
+        }
+    }
+
+    /// Servers in list order, disabled ones included.
+    pub fn servers(&self) -> impl Iterator<Item = &DnsServer> {
+        self.entries.iter().map(|entry| &entry.server)
+    }
+
+    /// Number of servers.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Server at `index`.
+    pub fn get(&self, index: usize) -> Option<&DnsServer> {
+        self.entries.get(index).map(|entry| &entry.server)
+    }
+
+    /// Whether anything changed since loading or the last save.
+    pub fn is_dirty(&self) -> bool {
+        !self.removed.is_empty()
+            || self
+                .entries
+                .iter()
+                .any(|entry| entry.loaded.as_ref() != Some(&entry.server))
+    }
+
+    /// Enable the server at `index` if disabled, disable it otherwise.
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.server.disabled = !entry.server.disabled;
+        }
+    }
+
+    /// Remove the server at `index`.
+    pub fn remove(&mut self, index: usize) {
+        if index < self.entries.len() {
+            if let Some(loaded) = self.entries.remove(index).loaded {
+                self.removed.insert(loaded.spec());
+            }
+        }
+    }
+
+    /// Add a server given as on the command line (`IP#Name`).
+    pub fn add(&mut self, spec: &str) -> Result<()> {
+        let server = parse(spec)?;
+        if self
+            .entries
+            .iter()
+            .any(|entry| entry.server.spec() == server.spec())
+        {
+            return Err(Error::config(format!(
+                "{} is already listed",
+                server.spec()
+            )));
+        }
+        self.entries.push(Entry {
+            server,
+            loaded: None,
+        });
+        Ok(())
+    }
+
+    /// Change the address and name of the server at `index` to `spec`
+    /// (`IP#Name`), keeping its other details.
+    pub fn edit(&mut self, index: usize, spec: &str) -> Result<()> {
+        let server = parse(spec)?;
+        let taken = self
+            .entries
+            .iter()
+            .enumerate()
+            .any(|(i, entry)| i != index && entry.server.spec() == server.spec());
+        if taken {
+            return Err(Error::config(format!(
+                "{} is already listed",
+                server.spec()
+            )));
+        }
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.server.name = server.name;
+            entry.server.ip = server.ip;
+            entry.server.port = server.port;
+            entry.server.protocol = server.protocol;
+        }
+        Ok(())
+    }
+
+    /// Remove the servers whose [`DnsServer::spec`] is in `specs`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of removed servers.
+    pub fn remove_specs(&mut self, specs: &HashSet<String>) -> usize {
+        let before = self.entries.len();
+        let mut index = 0;
+        while index < self.entries.len() {
+            if specs.contains(&self.entries[index].server.spec()) {
+                self.remove(index);
+            } else {
+                index += 1;
+            }
+        }
+        before - self.entries.len()
+    }
+
+    /// Write the changes to `file`, or else to the saved lists, with added
+    /// servers going to the list of their address family.
+    pub fn save(&mut self, file: Option<&Path>) -> Result<()> {
+        let edits: Vec<(DnsServer, DnsServer)> = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let loaded = entry.loaded.as_ref()?;
+                (*loaded != entry.server).then(|| (loaded.clone(), entry.server.clone()))
+            })
+            .collect();
+        let added: Vec<DnsServer> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.loaded.is_none())
+            .map(|entry| entry.server.clone())
+            .collect();
+
+        let paths: Vec<PathBuf> = file.map_or_else(
+            || {
+                [LIST_FILE, LIST_V6_FILE]
+                    .into_iter()
+                    .map(ConfigLoader::list_path)
+                    .filter(|path| path.exists())
+                    .collect()
+            },
+            |file| vec![file.to_path_buf()],
+        );
+        for path in &paths {
+            if !self.removed.is_empty() {
+                ConfigLoader::remove_specs(path, &self.removed)?;
+            }
+            if !edits.is_empty() {
+                ConfigLoader::update_servers(path, &edits)?;
+            }
+        }
+        if let Some(file) = file {
+            ConfigLoader::add_servers(file, &added)?;
+        } else {
+            let (v6, v4): (Vec<_>, Vec<_>) = added.into_iter().partition(DnsServer::is_ipv6);
+            for (name, servers) in [(LIST_FILE, v4), (LIST_V6_FILE, v6)] {
+                if !servers.is_empty() {
+                    ConfigLoader::add_servers(ConfigLoader::list_path(name), &servers)?;
+                }
+            }
+        }
+
+        for entry in &mut self.entries {
+            entry.loaded = Some(entry.server.clone());
+        }
+        self.removed.clear();
+        Ok(())
+    }
+}
+
+/// Parse a single server given as on the command line.
+fn parse(spec: &str) -> Result<DnsServer> {
+    let mut servers = ConfigLoader::from_args(vec![spec.to_string()])?.servers;
+    if servers.len() != 1 {
+        return Err(Error::config(format!("{spec} is not a single server")));
+    }
+    Ok(servers.remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LIST_FILE);
+        let servers = [
+            DnsServer::new("Google", "8.8.8.8"),
+            DnsServer::new("Quad9", "9.9.9.9"),
+            DnsServer::new("Broken", "192.0.2.1"),
+        ];
+        std::fs::write(
+            &path,
+            serde_json::to_string(&crate::dns::DnsList::from_servers(servers.to_vec())).unwrap(),
+        )
+        .unwrap();
+
+        let mut editor = ServerEditor::new(&servers);
+        assert!(!editor.is_dirty());
+        editor.toggle(1);
+        editor.edit(0, "8.8.4.4#Google Secondary").unwrap();
+        assert!(editor.edit(0, "9.9.9.9").is_err());
+        editor.add("1.1.1.1#Cloudflare").unwrap();
+        assert!(editor.add("1.1.1.1").is_err());
+        assert_eq!(
+            editor.remove_specs(&HashSet::from(["192.0.2.1".to_string()])),
+            1
+        );
+        assert!(editor.is_dirty());
+
+        editor.save(Some(&path)).unwrap();
+        assert!(!editor.is_dirty());
+        let saved = ConfigLoader::load_from_file(&path).unwrap().servers;
+        let summary: Vec<_> = saved
+            .iter()
+            .map(|s| (s.name.as_str(), s.ip.as_str(), s.disabled))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Google Secondary", "8.8.4.4", false),
+                ("Quad9", "9.9.9.9", true),
+                ("Cloudflare", "1.1.1.1", false),
+            ]
+        );
+    }
+}
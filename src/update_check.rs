@@ -0,0 +1,195 @@
+//! Daily check for new releases and DNS lists.
+//!
+//! The check is opt-in (`--check-updates`). Its result is cached in
+//! `update-check.json` in the cache directory and refreshed at most once a
+//! day, so the network is never on the startup path: callers show the
+//! cached status right away and refresh it in the background.
+
+#![allow(clippy::missing_errors_doc)]
+
+use crate::config::{ConfigLoader, DEFAULT_LIST_URL, DEFAULT_LIST_V6_URL};
+use crate::dns::DnsList;
+use crate::error::Result;
+use crate::{http, self_update};
+use chrono::{DateTime, Duration, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// Cache file name inside the cache directory.
+const CACHE_FILE: &str = "update-check.json";
+
+/// Minimum time between two checks.
+const CHECK_INTERVAL: Duration = Duration::hours(24);
+
+/// Timeout for each request made by a check.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Result of the last update check.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateStatus {
+    /// When the check ran
+    pub checked_at: Option<DateTime<Utc>>,
+    /// Tag of the latest release, if it could be fetched
+    pub latest_release: Option<String>,
+    /// Digest of the published DNS lists, if they could be fetched
+    pub list_digest: Option<String>,
+}
+
+impl UpdateStatus {
+    /// Load the cached status, if any.
+    #[must_use]
+    pub fn load() -> Option<Self> {
+        let content = std::fs::read_to_string(cache_path()).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Whether the status is older than the check interval.
+    #[must_use]
+    pub fn is_stale(&self) -> bool {
+        self.checked_at
+            .map_or(true, |at| Utc::now() - at >= CHECK_INTERVAL)
+    }
+
+    /// The latest release, if it is newer than the running binary.
+    #[must_use]
+    pub fn newer_release(&self) -> Option<&str> {
+        let tag = self.latest_release.as_deref()?;
+        let version = Version::parse(tag.trim_start_matches('v')).ok()?;
+        (version > self_update::current_version()).then_some(tag)
+    }
+
+    /// Whether the published DNS lists differ from the installed ones.
+    ///
+    /// Compared against the lists in the config directory, so running
+    /// `dnstest update` clears this without another check.
+    #[must_use]
+    pub fn list_outdated(&self) -> bool {
+        let Some(remote) = &self.list_digest else {
+            return false;
+        };
+        ConfigLoader::load_all().map_or(true, |lists| list_digest(&lists) != *remote)
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = cache_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Run a check now and cache the result.
+///
+/// Failing requests leave the corresponding field empty rather than
+/// failing the check, so an offline machine does not retry until the
+/// next interval.
+///
+/// # Example
+///
+/// ```ignore
+/// let status = match UpdateStatus::load() {
+///     Some(status) if !status.is_stale() => status,
+///     _ => update_check::refresh().await?,
+/// };
+/// if let Some(tag) = status.newer_release() {
+///     println!("dnstest {tag} is available");
+/// }
+/// ```
+pub async fn refresh() -> Result<UpdateStatus> {
+    let (release, v4, v6) = tokio::join!(
+        self_update::latest_release(),
+        http::get_json::<DnsList>(DEFAULT_LIST_URL, REQUEST_TIMEOUT),
+        http::get_json::<DnsList>(DEFAULT_LIST_V6_URL, REQUEST_TIMEOUT),
+    );
+
+    let status = UpdateStatus {
+        checked_at: Some(Utc::now()),
+        latest_release: release
+            .map_err(|e| tracing::debug!("Release check failed: {e}"))
+            .ok()
+            .map(|r| r.tag_name),
+        list_digest: v4
+            .and_then(|v4| Ok(vec![v4, v6?]))
+            .map_err(|e| tracing::debug!("DNS list check failed: {e}"))
+            .ok()
+            .map(|lists| list_digest(&lists)),
+    };
+    status.save()?;
+    Ok(status)
+}
+
+fn cache_path() -> PathBuf {
+    ConfigLoader::cache_dir().join(CACHE_FILE)
+}
+
+/// Order-independent digest of the server addresses in a set of lists.
+///
+/// Addresses are compared in canonical form, as loaded lists are
+/// normalized but the downloaded ones are not.
+fn list_digest(lists: &[DnsList]) -> String {
+    let mut ips: Vec<String> = lists
+        .iter()
+        .flat_map(|list| &list.servers)
+        .map(|server| {
+            server
+                .ip_addr()
+                .map_or_else(|| server.ip.clone(), |ip| ip.to_string())
+        })
+        .collect();
+    ips.sort_unstable();
+    ips.dedup();
+
+    let mut hasher = Sha256::new();
+    for ip in ips {
+        hasher.update(ip.as_bytes());
+        hasher.update(b"\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::DnsServer;
+
+    fn list(ips: &[&str]) -> DnsList {
+        DnsList {
+            servers: ips.iter().map(|ip| DnsServer::new(*ip, *ip)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_list_digest_ignores_order() {
+        assert_eq!(
+            list_digest(&[list(&["1.1.1.1", "8.8.8.8"])]),
+            list_digest(&[list(&["8.8.8.8"]), list(&["1.1.1.1"])])
+        );
+        assert_ne!(
+            list_digest(&[list(&["1.1.1.1"])]),
+            list_digest(&[list(&["1.1.1.1", "8.8.8.8"])])
+        );
+        assert_eq!(
+            list_digest(&[list(&["2001:db8::1"])]),
+            list_digest(&[list(&["2001:DB8:0:0:0:0:0:1"])])
+        );
+    }
+
+    #[test]
+    fn test_newer_release() {
+        let mut status = UpdateStatus {
+            checked_at: Some(Utc::now()),
+            latest_release: Some("v0.0.1".to_string()),
+            list_digest: None,
+        };
+        assert!(!status.is_stale());
+        assert_eq!(status.newer_release(), None);
+
+        status.latest_release = Some("v999.0.0".to_string());
+        assert_eq!(status.newer_release(), Some("v999.0.0"));
+        assert!(UpdateStatus::default().is_stale());
+    }
+}